@@ -14,6 +14,7 @@ const TEST_SCRIPTS: &[(&str, &str)] = &[
     ("rsi", include_str!("../test_data/rsi.pine")),
     ("macd", include_str!("../test_data/macd.pine")),
     ("complex", include_str!("../test_data/complex.pine")),
+    ("functions", include_str!("../test_data/functions.pine")),
 ];
 
 fn bench_single_bar(c: &mut Criterion) {
@@ -31,6 +32,23 @@ fn bench_single_bar(c: &mut Criterion) {
     group.finish();
 }
 
+/// A run long enough for per-bar execution, not setup, to dominate.
+fn bench_long_run(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter/long_run");
+    group.sample_size(10);
+    let data = generate_bars(5000);
+
+    for (name, source) in TEST_SCRIPTS {
+        group.bench_with_input(BenchmarkId::from_parameter(name), source, |b, source| {
+            b.iter(|| {
+                execute(black_box(source), data.clone()).unwrap();
+            });
+        });
+    }
+
+    group.finish();
+}
+
 fn bench_compile_only(c: &mut Criterion) {
     let mut group = c.benchmark_group("interpreter/compile");
 
@@ -48,5 +66,10 @@ fn bench_compile_only(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_compile_only, bench_single_bar);
+criterion_group!(
+    benches,
+    bench_compile_only,
+    bench_single_bar,
+    bench_long_run
+);
 criterion_main!(benches);
@@ -56,7 +56,7 @@ impl PartialEq for Loc {
 
 /// Type qualifier for variables and parameters
 /// Hierarchy: const < input < simple < series (const is the weakest)
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum TypeQualifier {
     Const,
     Input,
@@ -308,6 +308,12 @@ pub enum Stmt {
         #[serde(skip)]
         loc: Loc,
     },
+    /// A statement that failed to parse, standing in for it in the partial
+    /// program a recovering parse returns. Never executed.
+    Error {
+        #[serde(skip)]
+        loc: Loc,
+    },
 }
 
 /// An item that can be exported from a library
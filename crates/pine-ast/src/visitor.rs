@@ -102,7 +102,8 @@ pub fn walk_stmt<V: Visitor + ?Sized>(v: &mut V, stmt: &Stmt) {
         | Stmt::TypeDecl { .. }
         | Stmt::EnumDecl { .. }
         | Stmt::Export { .. }
-        | Stmt::Import { .. } => {}
+        | Stmt::Import { .. }
+        | Stmt::Error { .. } => {}
     }
 }
 
@@ -5,8 +5,8 @@
 //! is what `strategy.closedtrades` reports.
 
 use crate::{
-    Broker, Commission, Direction, EntryFilter, Exit, FillModel, OcaType, Order, OrderKind,
-    Position, RiskRule, RiskType, Sizing, Trade,
+    rest_of_bar, Broker, CloseEntriesRule, Commission, Direction, EntryFilter, Exit, Fill,
+    FillModel, OcaType, Order, OrderKind, Position, RiskRule, RiskType, Sizing, Trade, MARGIN_CALL,
 };
 use pine_core::Bar;
 use std::collections::HashMap;
@@ -20,6 +20,18 @@ pub struct BarBroker<F: FillModel> {
     max_entries: usize,
     /// Tick size, so `strategy.exit` distances given in ticks become prices.
     mintick: f64,
+    /// Fill again at the close of each bar (`process_orders_on_close`).
+    process_orders_on_close: bool,
+    /// Ask for the script to be run again after a fill (`calc_on_order_fills`).
+    calc_on_order_fills: bool,
+    close_entries_rule: CloseEntriesRule,
+    /// Margin held against long and short positions, as a fraction of their
+    /// value; 0 trades without margin.
+    margin_long: f64,
+    margin_short: f64,
+    /// What one unit of the symbol's currency is worth in the account's.
+    /// Prices stay in the symbol's currency; money is converted when booked.
+    fx_rate: f64,
     /// Starting capital, kept so `strategy.netprofit` can be derived from the
     /// equity identity.
     initial: f64,
@@ -37,6 +49,15 @@ pub struct BarBroker<F: FillModel> {
 
     open: Vec<Trade>,
     closed: Vec<Trade>,
+    /// Fills so far, so `advance` can tell whether it filled anything.
+    fill_count: u64,
+    /// Fills not yet collected by `take_fills`.
+    reported: Vec<Fill>,
+    /// The price of the last fill, for the run `calc_on_order_fills` asks for.
+    last_fill_price: f64,
+    /// An order filled in the last `advance` and the script wants to be run
+    /// again for it, as of this price; cleared by `take_recalc`.
+    recalc: Option<f64>,
 
     bar_index: u64,
 
@@ -76,6 +97,12 @@ impl<F: FillModel> BarBroker<F> {
             sizing: Sizing::Contracts(1.0),
             max_entries: 1,
             mintick: 0.0,
+            process_orders_on_close: false,
+            calc_on_order_fills: false,
+            close_entries_rule: CloseEntriesRule::Fifo,
+            margin_long: 0.0,
+            margin_short: 0.0,
+            fx_rate: 1.0,
             initial: initial_capital,
             cash: initial_capital,
             realized: 0.0,
@@ -84,6 +111,10 @@ impl<F: FillModel> BarBroker<F> {
             exits: Vec::new(),
             open: Vec::new(),
             closed: Vec::new(),
+            fill_count: 0,
+            reported: Vec::new(),
+            last_fill_price: 0.0,
+            recalc: None,
             bar_index: 0,
             entry_filter: EntryFilter::All,
             max_position_size: None,
@@ -124,6 +155,28 @@ impl<F: FillModel> BarBroker<F> {
         self
     }
 
+    pub fn with_process_orders_on_close(mut self, enabled: bool) -> Self {
+        self.process_orders_on_close = enabled;
+        self
+    }
+
+    pub fn with_calc_on_order_fills(mut self, enabled: bool) -> Self {
+        self.calc_on_order_fills = enabled;
+        self
+    }
+
+    pub fn with_close_entries_rule(mut self, rule: CloseEntriesRule) -> Self {
+        self.close_entries_rule = rule;
+        self
+    }
+
+    /// Hold `long` and `short` percent of a position's value as margin.
+    pub fn with_margin(mut self, long: f64, short: f64) -> Self {
+        self.margin_long = long / 100.0;
+        self.margin_short = short / 100.0;
+        self
+    }
+
     fn open_lots_toward(&self, direction: Direction) -> usize {
         self.open
             .iter()
@@ -160,15 +213,26 @@ impl<F: FillModel> BarBroker<F> {
         }
     }
 
+    /// The commission on `qty` at `price`, in the account currency.
     fn commission_on(&self, qty: f64, price: f64) -> f64 {
-        self.commission.map_or(0.0, |c| c.charge(qty, price))
+        self.commission
+            .map_or(0.0, |c| c.charge(qty, price * self.fx_rate))
     }
 
     /// Apply a fill of `signed_qty` contracts at `price`: close opposing lots
     /// first (FIFO), then open a lot with whatever direction remains. `target`
-    /// restricts which lots may be closed to those from that entry — a reducing
-    /// order leaves the remainder unopened, so it only ever shrinks them.
+    /// names the entry a reducing order exits — it leaves the remainder
+    /// unopened, so it only ever shrinks the position. Under the `ANY` rule only
+    /// that entry's lots close; under `FIFO` the oldest lots do, whichever entry
+    /// opened them.
     fn apply_fill(&mut self, mut signed_qty: f64, price: f64, id: &str, target: Option<&str>) {
+        self.fill_count += 1;
+        self.last_fill_price = price;
+        let closes = match self.close_entries_rule {
+            CloseEntriesRule::Any => target,
+            CloseEntriesRule::Fifo => None,
+        };
+
         // This fill's commission, split across the portions it closes and opens
         // by contract count, so each closed trade carries its exit commission
         // and each opened lot its entry commission.
@@ -182,7 +246,7 @@ impl<F: FillModel> BarBroker<F> {
         while signed_qty != 0.0 {
             let Some(index) = self.open.iter().position(|t| {
                 t.size.signum() != signed_qty.signum()
-                    && target.is_none_or(|want| t.entry_id == want)
+                    && closes.is_none_or(|want| t.entry_id == want)
             }) else {
                 break;
             };
@@ -193,22 +257,33 @@ impl<F: FillModel> BarBroker<F> {
             let entry_share = lot.commission * closed / lot.size.abs();
             let exit_share = order_commission * closed / order_qty_abs;
 
-            self.realized += (price - lot.entry_price) * closed_signed;
+            self.realized += (price - lot.entry_price) * closed_signed * self.fx_rate;
             signed_qty += closed_signed; // moves signed_qty toward zero
 
-            self.closed.push(Trade {
+            // The exited portion takes its share of the lot's excursions.
+            let runup_share = lot.max_runup * closed / lot.size.abs();
+            let drawdown_share = lot.max_drawdown * closed / lot.size.abs();
+            let mut trade = Trade {
                 entry_id: lot.entry_id.clone(),
                 size: closed_signed,
                 entry_price: lot.entry_price,
                 entry_bar: lot.entry_bar,
                 exit_price: Some(price),
                 exit_bar: Some(self.bar_index),
+                exit_id: Some(id.to_string()),
                 commission: entry_share + exit_share,
-            });
+                fx_rate: self.fx_rate,
+                max_runup: runup_share,
+                max_drawdown: drawdown_share,
+            };
+            trade.track(price);
+            self.closed.push(trade);
 
             let lot = &mut self.open[index];
             lot.size -= closed_signed;
             lot.commission -= entry_share;
+            lot.max_runup -= runup_share;
+            lot.max_drawdown -= drawdown_share;
             if lot.size == 0.0 {
                 self.open.remove(index);
             }
@@ -224,11 +299,27 @@ impl<F: FillModel> BarBroker<F> {
                 entry_bar: self.bar_index,
                 exit_price: None,
                 exit_bar: None,
+                exit_id: None,
                 commission: order_commission * signed_qty.abs() / order_qty_abs,
+                fx_rate: self.fx_rate,
+                max_runup: 0.0,
+                max_drawdown: 0.0,
             });
         }
     }
 
+    /// Record a fill of `signed_qty` at `price` for [`Broker::take_fills`].
+    fn report(&mut self, id: &str, signed_qty: f64, price: f64, comment: &str, alert: &str) {
+        self.reported.push(Fill {
+            id: id.to_string(),
+            qty: signed_qty,
+            price,
+            position_size: self.net_size(),
+            comment: comment.to_string(),
+            alert_message: alert.to_string(),
+        });
+    }
+
     /// The signed quantity an order actually trades at `price`, resolving the
     /// default quantity and, for a reducing or reversing order, the position.
     fn resolve_qty(&self, order: &Order, price: f64) -> f64 {
@@ -252,7 +343,7 @@ impl<F: FillModel> BarBroker<F> {
                 // was generated on; fall back to the fill price if unstamped.
                 let sizing_price = order.sizing_price.unwrap_or(price);
                 self.sizing
-                    .contracts(sizing_price, self.equity(sizing_price))
+                    .contracts(sizing_price * self.fx_rate, self.equity(sizing_price))
             }
         };
         let net = self.net_size();
@@ -266,9 +357,13 @@ impl<F: FillModel> BarBroker<F> {
     }
 
     /// Evaluate every exit bracket against `bar`: for a matched position, fill
-    /// the stop-loss, trailing stop or take-profit if the bar reaches it (a stop
-    /// wins when several do, the conservative assumption), then retire it.
+    /// the stop-loss, trailing stop or take-profit if the bar reaches it, then
+    /// retire it. The legs are tested on each of the fill model's
+    /// [`steps`](FillModel::steps) in turn. When several are reached on one
+    /// step the fill model says which came first; on the bar alone, the stop
+    /// wins — the conservative assumption.
     fn evaluate_exits(&mut self, bar: &Bar) {
+        let steps = self.fills.steps(bar).to_vec();
         let ids: Vec<String> = self.exits.iter().map(|e| e.id.clone()).collect();
         for id in ids {
             let Some(exit) = self.exits.iter().find(|e| e.id == id).cloned() else {
@@ -297,41 +392,75 @@ impl<F: FillModel> BarBroker<F> {
                 .stop
                 .or_else(|| exit.loss_ticks.map(|t| entry_avg - dir * t * mintick));
 
-            // Arm and advance the trailing stop with this bar: the reference
-            // trails "each time the trade's profit reaches a new high", so it
-            // follows the peak within the bar and can fill the same one.
-            let trail_stop = self.advance_trail(&id, dir, entry_avg, bar);
-
-            // A stop wins over the take-profit when a bar reaches both. The
-            // trailing stop fills at its level — price set the peak this bar,
-            // then retraced to the stop.
-            let hit = sl
-                .and_then(|p| self.leg_fill(OrderKind::Stop(p), exit_dir, bar))
-                .or_else(|| {
-                    trail_stop.filter(|&ts| {
-                        if dir > 0.0 {
-                            bar.low <= ts
-                        } else {
-                            bar.high >= ts
-                        }
-                    })
+            // The stop is listed before the take-profit, so it wins whenever
+            // the fill model cannot tell them apart.
+            let legs: Vec<Order> = [sl.map(OrderKind::Stop), tp.map(OrderKind::Limit)]
+                .into_iter()
+                .flatten()
+                .map(|kind| Order {
+                    kind,
+                    ..Order::market("", exit_dir, None)
                 })
-                .or_else(|| tp.and_then(|p| self.leg_fill(OrderKind::Limit(p), exit_dir, bar)));
+                .collect();
+
+            // Walk the price through the bar, intrabar by intrabar when the fill
+            // model has them, until a leg is hit.
+            let mut hit = None;
+            for step in &steps {
+                // Arm and advance the trailing stop with this step: the
+                // reference trails "each time the trade's profit reaches a new
+                // high", so it follows the peak within the step and can fill
+                // the same one.
+                let trail_stop = self.advance_trail(&id, dir, entry_avg, step);
+
+                // The trailing stop fills at its level — price set the peak
+                // this step, then retraced to the stop — and beats a
+                // take-profit but not the stop-loss. Each hit carries the
+                // alert message of its leg.
+                let first = self.fills.first_fill(&legs, step);
+                hit = match first {
+                    Some((i, price)) if matches!(legs[i].kind, OrderKind::Stop(_)) => {
+                        Some((price, &exit.alert_loss))
+                    }
+                    _ => trail_stop
+                        .filter(|&ts| {
+                            if dir > 0.0 {
+                                step.low <= ts
+                            } else {
+                                step.high >= ts
+                            }
+                        })
+                        .map(|ts| (ts, &exit.alert_trailing))
+                        .or(first.map(|(_, price)| (price, &exit.alert_profit))),
+                };
+                if hit.is_some() {
+                    break;
+                }
+            }
 
-            if let Some(price) = hit {
+            if let Some((price, alert)) = hit {
                 let requested = match (exit.qty, exit.qty_percent) {
                     (Some(q), _) => pos.abs().min(q.abs()),
                     (None, Some(pct)) => pos.abs() * (pct / 100.0),
                     (None, None) => pos.abs(),
                 };
                 self.apply_fill(-dir * requested, price, &exit.id, target);
+                if !exit.disable_alert {
+                    let alert = if alert.is_empty() {
+                        &exit.alert_message
+                    } else {
+                        alert
+                    };
+                    self.report(&exit.id, -dir * requested, price, &exit.comment, alert);
+                }
                 self.exits.retain(|e| e.id != id);
             }
         }
     }
 
-    /// Arm a trailing exit and advance its peak from `bar`, returning the stop
-    /// price if it is active — `trail_offset` ticks behind the best price seen.
+    /// Arm a trailing exit and advance its peak from `bar` (one step of the
+    /// chart bar), returning the stop price if it is active — `trail_offset`
+    /// ticks behind the best price seen.
     fn advance_trail(&mut self, id: &str, dir: f64, entry_avg: f64, bar: &Bar) -> Option<f64> {
         let mintick = self.mintick;
         let exit = self.exits.iter_mut().find(|e| e.id == id)?;
@@ -362,15 +491,6 @@ impl<F: FillModel> BarBroker<F> {
         exit.peak.map(|pk| pk - dir * offset * mintick)
     }
 
-    /// The fill price of one exit leg against `bar`, or `None` if unreached.
-    fn leg_fill(&self, kind: OrderKind, direction: Direction, bar: &Bar) -> Option<f64> {
-        let leg = Order {
-            kind,
-            ..Order::market("", direction, None)
-        };
-        self.fills.fill(&leg, bar)
-    }
-
     /// Whether an entry order is blocked by the pyramiding limit: it would add a
     /// new lot to an already-full stack on its own side.
     fn pyramiding_blocks(&self, order: &Order) -> bool {
@@ -505,12 +625,134 @@ impl<F: FillModel> BarBroker<F> {
         }
     }
 
+    /// The margin held against a position of signed `size`, as a fraction of
+    /// its value.
+    fn margin_rate(&self, size: f64) -> f64 {
+        if size > 0.0 {
+            self.margin_long
+        } else if size < 0.0 {
+            self.margin_short
+        } else {
+            0.0
+        }
+    }
+
+    /// Shrink an entry `qty` to what the account can margin at `price`: the
+    /// position it leaves may tie up at most the equity on hand. Returns 0 when
+    /// nothing more can be afforded.
+    fn clamp_to_margin(&self, order: &Order, qty: f64, price: f64) -> f64 {
+        let size = self.position().size;
+        let after = size + qty;
+        let margin = self.margin_rate(after);
+        // Only an order that leaves a position on its own side needs margin;
+        // one that merely shrinks the position frees it.
+        if order.reduce_only || margin == 0.0 || price <= 0.0 || after.signum() != qty.signum() {
+            return qty;
+        }
+        let equity = self.equity(price) - self.commission_on(qty, price);
+        let max = (equity / (price * self.fx_rate * margin)).max(0.0);
+        if after.abs() <= max {
+            return qty;
+        }
+        let clamped = max * after.signum() - size;
+        if clamped.signum() != qty.signum() {
+            0.0
+        } else {
+            clamped
+        }
+    }
+
+    /// Issue a margin call if equity no longer covers the position's margin at
+    /// the bar's adverse extreme: part of the position is liquidated at that
+    /// price. As TradingView does, four times the contracts the shortfall is
+    /// worth are closed, or the whole position if that is less, until what
+    /// remains is within margin.
+    fn check_margin(&mut self, bar: &Bar) {
+        // One liquidation may leave the rest still short of margin — the
+        // price hasn't moved but equity has — so call again until it isn't.
+        loop {
+            let size = self.net_size();
+            let margin = self.margin_rate(size);
+            if size == 0.0 || margin == 0.0 {
+                return;
+            }
+            let price = if size > 0.0 { bar.low } else { bar.high };
+            let value = price * self.fx_rate;
+            let shortfall = size.abs() * value * margin - self.equity(price);
+            if shortfall <= 0.0 || value <= 0.0 {
+                return;
+            }
+            let qty = ((shortfall / value).ceil() * 4.0).min(size.abs());
+            self.apply_fill(-size.signum() * qty, price, MARGIN_CALL, None);
+            self.report(MARGIN_CALL, -size.signum() * qty, price, "", "");
+        }
+    }
+
     /// Close the whole position at `price` — the forced exit a breached drawdown
     /// or intraday-loss rule performs.
     fn flatten(&mut self, price: f64) {
         let size = self.position().size;
         if size != 0.0 {
             self.apply_fill(-size, price, "risk_flatten", None);
+            self.report("risk_flatten", -size, price, "", "");
+        }
+    }
+
+    /// Fill pending orders against `bar` in submission order; a filled order
+    /// leaves the book.
+    fn fill_pending(&mut self, bar: &Bar) {
+        let ids: Vec<String> = self.order.clone();
+        for id in ids {
+            let Some(order) = self.pending.get(&id).cloned() else {
+                continue;
+            };
+            // While halted (for the run or the day), drop new entries; a
+            // reduce-only exit still fills so an open position can be closed.
+            if (self.halted || self.halted_today) && !order.reduce_only {
+                self.pending.remove(&id);
+                self.order.retain(|o| o != &id);
+                continue;
+            }
+            if self.pyramiding_blocks(&order) {
+                // The stack is full: drop the entry, as Pine rejects it.
+                self.pending.remove(&id);
+                self.order.retain(|o| o != &id);
+                continue;
+            }
+            // Once the day's fill cap is reached, no more orders fill — except a
+            // reduce-only exit of the current position. Enforced here, per fill,
+            // so orders already pending when the bar opens are capped too.
+            if !order.reduce_only {
+                if let Some(cap) = self.max_intraday_filled_orders {
+                    if self.filled_today >= cap {
+                        self.pending.remove(&id);
+                        self.order.retain(|o| o != &id);
+                        continue;
+                    }
+                }
+            }
+            if let Some(price) = self.fills.fill(&order, bar) {
+                let qty = self.clamp_to_max_position(&order, self.resolve_qty(&order, price));
+                let qty = self.clamp_to_margin(&order, qty, price);
+                if qty != 0.0 {
+                    self.apply_fill(qty, price, &order.id, order.close_target.as_deref());
+                    if !order.disable_alert {
+                        self.report(&order.id, qty, price, &order.comment, &order.alert_message);
+                    }
+                    self.apply_oca(&order, qty);
+                    self.filled_today += 1;
+                }
+                self.pending.remove(&id);
+                self.order.retain(|o| o != &id);
+            }
+        }
+    }
+
+    /// Stretch the open trades' run-ups and drawdowns over the bar's range.
+    fn track_excursions(&mut self, bar: &Bar) {
+        for trade in &mut self.open {
+            trade.track(bar.high);
+            trade.track(bar.low);
         }
     }
 
@@ -603,56 +845,70 @@ impl<F: FillModel> Broker for BarBroker<F> {
         self.bar_index = bar.index;
         self.roll_day(bar.time);
 
-        // Fill in submission order; a filled order leaves the book.
-        let ids: Vec<String> = self.order.clone();
-        for id in ids {
-            let Some(order) = self.pending.get(&id).cloned() else {
-                continue;
-            };
-            // While halted (for the run or the day), drop new entries; a
-            // reduce-only exit still fills so an open position can be closed.
-            if (self.halted || self.halted_today) && !order.reduce_only {
-                self.pending.remove(&id);
-                self.order.retain(|o| o != &id);
-                continue;
-            }
-            if self.pyramiding_blocks(&order) {
-                // The stack is full: drop the entry, as Pine rejects it.
-                self.pending.remove(&id);
-                self.order.retain(|o| o != &id);
-                continue;
-            }
-            // Once the day's fill cap is reached, no more orders fill — except a
-            // reduce-only exit of the current position. Enforced here, per fill,
-            // so orders already pending when the bar opens are capped too.
-            if !order.reduce_only {
-                if let Some(cap) = self.max_intraday_filled_orders {
-                    if self.filled_today >= cap {
-                        self.pending.remove(&id);
-                        self.order.retain(|o| o != &id);
-                        continue;
-                    }
-                }
-            }
-            if let Some(price) = self.fills.fill(&order, bar) {
-                let qty = self.clamp_to_max_position(&order, self.resolve_qty(&order, price));
-                if qty != 0.0 {
-                    self.apply_fill(qty, price, &order.id, order.close_target.as_deref());
-                    self.apply_oca(&order, qty);
-                    self.filled_today += 1;
-                }
-                self.pending.remove(&id);
-                self.order.retain(|o| o != &id);
-            }
-        }
+        let fill_count = self.fill_count;
+        self.fill_pending(bar);
 
-        // Then the protective exits, against the position those fills produced.
+        // Then the protective exits, against the position those fills produced,
+        // and a margin call if what is left cannot be carried.
         self.evaluate_exits(bar);
+        self.check_margin(bar);
+        self.recalc = (self.calc_on_order_fills && self.fill_count > fill_count)
+            .then_some(self.last_fill_price);
+        self.track_excursions(bar);
 
         // Finally settle equity for the bar and enforce the equity-drop rules.
         self.mark_and_check_risk(bar);
     }
 
+    fn resume(&mut self, bar: &Bar, price: f64) {
+        // Step through the rest of the bar only. The excursions and risk marks
+        // it takes are a subset of those the bar already took, except for the
+        // position the run's fills produced.
+        let steps = self.fills.rest(bar, price);
+        let fill_count = self.fill_count;
+        for step in &steps {
+            self.fill_pending(step);
+            self.evaluate_exits(step);
+            self.check_margin(step);
+            self.track_excursions(step);
+        }
+        self.recalc = (self.calc_on_order_fills && self.fill_count > fill_count)
+            .then_some(self.last_fill_price);
+
+        let rest = Bar {
+            high: steps.iter().fold(price, |a, s| a.max(s.high)),
+            low: steps.iter().fold(price, |a, s| a.min(s.low)),
+            ..rest_of_bar(bar, price)
+        };
+        self.mark_and_check_risk(&rest);
+    }
+
+    fn close(&mut self, bar: &Bar) {
+        if !self.process_orders_on_close {
+            return;
+        }
+        // The close is the only price left: market orders fill at it, and
+        // price orders and exits only if it reaches them.
+        let at_close = Bar {
+            open: bar.close,
+            high: bar.close,
+            low: bar.close,
+            ..bar.clone()
+        };
+        self.fill_pending(&at_close);
+        self.evaluate_exits(&at_close);
+        self.check_margin(&at_close);
+        self.mark_and_check_risk(&at_close);
+    }
+
+    fn take_recalc(&mut self) -> Option<f64> {
+        self.recalc.take()
+    }
+
+    fn take_fills(&mut self) -> Vec<Fill> {
+        std::mem::take(&mut self.reported)
+    }
+
     fn position(&self) -> Position {
         let size = self.net_size();
         if size == 0.0 {
@@ -681,7 +937,8 @@ impl<F: FillModel> Broker for BarBroker<F> {
             .open
             .iter()
             .map(|t| (price - t.entry_price) * t.size)
-            .sum();
+            .sum::<f64>()
+            * self.fx_rate;
         self.cash + self.realized + unrealized
     }
 
@@ -696,12 +953,33 @@ impl<F: FillModel> Broker for BarBroker<F> {
     fn halted_bar(&self) -> Option<u64> {
         self.halted_bar
     }
+
+    fn set_fx_rate(&mut self, rate: f64) {
+        self.fx_rate = rate;
+        for trade in &mut self.open {
+            trade.fx_rate = rate;
+        }
+    }
+
+    fn margin_liquidation_price(&self) -> Option<f64> {
+        // Equity is linear in price, `base + size * price`, and the margin
+        // held is `|size| * price * rate`; the call comes where they meet.
+        let size = self.net_size();
+        let margin = self.margin_rate(size);
+        if margin == 0.0 {
+            return None;
+        }
+        let base = self.equity(0.0);
+        let slope = (size.abs() * margin - size) * self.fx_rate;
+        let price = base / slope;
+        (slope != 0.0 && price > 0.0).then_some(price)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Commission, Direction, OrderKind, PineFills};
+    use crate::{Commission, Direction, OrderKind, PineFills, MARGIN_CALL};
 
     fn bar(index: u64, open: f64, high: f64, low: f64, close: f64) -> Bar {
         Bar {
@@ -860,6 +1138,26 @@ mod tests {
         assert_eq!(trade.profit(0.0), 20.0); // (110 - 100) * 2, price ignored once closed
     }
 
+    #[test]
+    fn a_trade_records_its_run_up_and_drawdown() {
+        let mut b = broker();
+        b.submit(Order::market("L", Direction::Long, Some(2.0)));
+        b.advance(&bar(0, 100.0, 104.0, 97.0, 101.0));
+        b.advance(&bar(1, 101.0, 108.0, 99.0, 105.0));
+        b.submit(Order {
+            reduce_only: true,
+            ..Order::market("L", Direction::Short, Some(1.0))
+        });
+        b.advance(&bar(2, 103.0, 103.0, 90.0, 92.0));
+
+        // Half closed at the open of bar 2: up 8 and down 3 a contract before it.
+        let closed = &b.closed_trades()[0];
+        assert_eq!((closed.max_runup, closed.max_drawdown), (8.0, 3.0));
+        // The other half also rode bar 2 down to 90.
+        let open = b.open_trades()[0];
+        assert_eq!((open.max_runup, open.max_drawdown), (8.0, 10.0));
+    }
+
     #[test]
     fn closing_realises_profit_and_flattens() {
         let mut b = broker();
@@ -992,6 +1290,177 @@ mod tests {
         assert_eq!(b.position().avg_price, 101.0); // B's entry
     }
 
+    /// Two long lots, A at 100 then B at 101, and a close of B filled at 102.
+    fn close_b_of_two(rule: CloseEntriesRule) -> BarBroker<PineFills> {
+        let mut b = broker().with_close_entries_rule(rule);
+        for (i, id) in ["A", "B"].into_iter().enumerate() {
+            b.submit(Order {
+                reverses: false,
+                ..Order::market(id, Direction::Long, Some(1.0))
+            });
+            let price = 100.0 + i as f64;
+            b.advance(&bar(i as u64, price, price, price, price));
+        }
+        b.submit(Order {
+            reduce_only: true,
+            close_target: Some("B".into()),
+            ..Order::market("B", Direction::Long, None)
+        });
+        b.advance(&bar(2, 102.0, 102.0, 102.0, 102.0));
+        b
+    }
+
+    #[test]
+    fn fifo_closes_the_oldest_trade_whichever_entry_is_named() {
+        let b = close_b_of_two(CloseEntriesRule::Fifo);
+        assert_eq!(b.closed_trades()[0].entry_id, "A");
+        assert_eq!(b.position().avg_price, 101.0);
+    }
+
+    #[test]
+    fn any_closes_the_named_entry() {
+        let b = close_b_of_two(CloseEntriesRule::Any);
+        assert_eq!(b.closed_trades()[0].entry_id, "B");
+        assert_eq!(b.position().avg_price, 100.0);
+    }
+
+    #[test]
+    fn process_orders_on_close_fills_at_the_close() {
+        let mut b = broker().with_process_orders_on_close(true);
+        b.advance(&bar(0, 100.0, 105.0, 95.0, 102.0));
+        b.submit(Order::market("L", Direction::Long, Some(1.0)));
+        // A buy limit the close has not come down to waits for the next bar.
+        b.submit(Order {
+            kind: OrderKind::Limit(97.0),
+            ..Order::market("M", Direction::Long, Some(1.0))
+        });
+        b.close(&bar(0, 100.0, 105.0, 95.0, 102.0));
+        assert_eq!(b.position().size, 1.0);
+        assert_eq!(b.open_trades()[0].entry_price, 102.0);
+        assert_eq!(b.open_trades()[0].entry_bar, 0);
+    }
+
+    #[test]
+    fn without_process_orders_on_close_the_close_fills_nothing() {
+        let mut b = broker();
+        b.submit(Order::market("L", Direction::Long, Some(1.0)));
+        b.close(&bar(0, 100.0, 105.0, 95.0, 102.0));
+        assert!(b.position().is_flat());
+    }
+
+    #[test]
+    fn calc_on_order_fills_asks_for_a_recalc_after_a_fill() {
+        let mut b = broker().with_calc_on_order_fills(true);
+        b.advance(&bar(0, 100.0, 100.0, 100.0, 100.0));
+        assert_eq!(b.take_recalc(), None);
+
+        b.submit(Order::market("L", Direction::Long, Some(1.0)));
+        b.advance(&bar(1, 101.0, 101.0, 101.0, 101.0));
+        assert_eq!(b.take_recalc(), Some(101.0));
+        assert_eq!(b.take_recalc(), None); // reading clears it
+    }
+
+    #[test]
+    fn a_resumed_bar_ignores_prices_from_before_the_fill() {
+        let mut b = broker().with_calc_on_order_fills(true);
+        // Open 100, nearer the low, so the path is 100 → 90 → 112 → 105: the
+        // low printed before the rise through 108.
+        let bar = bar(0, 100.0, 112.0, 90.0, 105.0);
+        b.submit(Order {
+            kind: OrderKind::Stop(108.0),
+            ..Order::market("L", Direction::Long, Some(1.0))
+        });
+        b.advance(&bar);
+        assert_eq!(b.take_recalc(), Some(108.0));
+
+        // A stop the run placed under the entry is not hit by the earlier low.
+        b.submit_exit(Exit {
+            stop: Some(95.0),
+            ..Exit::resting("X", Some("L".to_string()), None, None)
+        });
+        b.resume(&bar, 108.0);
+        assert_eq!(b.position().size, 1.0);
+        assert_eq!(b.take_recalc(), None);
+    }
+
+    #[test]
+    fn the_rest_of_a_bar_follows_the_assumed_path() {
+        // 100 → 110 → 90 → 105: past 95 on the fall, only the low and close.
+        let rest = rest_of_bar(&bar(0, 100.0, 110.0, 90.0, 105.0), 95.0);
+        assert_eq!(
+            (rest.open, rest.high, rest.low, rest.close),
+            (95.0, 105.0, 90.0, 105.0)
+        );
+    }
+
+    #[test]
+    fn margin_caps_the_entry_to_what_equity_covers() {
+        // At 50% margin 10_000 of equity carries 20_000 of position: 200 at 100.
+        let mut b = broker().with_margin(50.0, 50.0);
+        b.submit(Order::market("L", Direction::Long, Some(500.0)));
+        b.advance(&bar(0, 100.0, 100.0, 100.0, 100.0));
+        assert_eq!(b.position().size, 200.0);
+    }
+
+    #[test]
+    fn a_margin_call_liquidates_four_times_the_shortfall() {
+        let mut b = broker().with_margin(25.0, 25.0);
+        b.submit(Order::market("L", Direction::Long, Some(400.0)));
+        b.advance(&bar(0, 100.0, 100.0, 100.0, 100.0));
+        assert_eq!(b.position().size, 400.0);
+
+        // At the low of 99 equity is 9_600 against 9_900 of margin: a 300
+        // shortfall is worth 4 contracts, so 16 are closed at 99.
+        b.advance(&bar(1, 100.0, 100.0, 99.0, 99.5));
+        assert_eq!(b.position().size, 384.0);
+        let call = &b.closed_trades()[0];
+        assert_eq!(call.exit_id.as_deref(), Some(MARGIN_CALL));
+        assert_eq!(call.size, 16.0);
+        assert_eq!(call.exit_price, Some(99.0));
+    }
+
+    #[test]
+    fn margin_calls_repeat_until_the_position_is_within_margin() {
+        let mut b = broker().with_margin(10.0, 10.0);
+        b.submit(Order::market("L", Direction::Long, Some(1_000.0)));
+        b.advance(&bar(0, 100.0, 100.0, 100.0, 100.0));
+
+        // At 10% margin one call (40 contracts) covers only part of the 900
+        // shortfall at 99; five more follow before 908 are left.
+        b.advance(&bar(1, 100.0, 100.0, 99.0, 99.5));
+        assert_eq!(b.position().size, 908.0);
+        assert_eq!(b.closed_trades().len(), 6);
+        assert!(b.equity(99.0) >= 908.0 * 99.0 * 0.1);
+    }
+
+    #[test]
+    fn the_liquidation_price_is_where_equity_meets_the_margin() {
+        let mut b = broker().with_margin(50.0, 100.0);
+        assert_eq!(b.margin_liquidation_price(), None);
+
+        b.submit(Order::market("L", Direction::Long, Some(150.0)));
+        b.advance(&bar(0, 100.0, 100.0, 100.0, 100.0));
+        let price = b.margin_liquidation_price().unwrap();
+        assert!((price - 200.0 / 3.0).abs() < 1e-9);
+
+        // Fully margined, a short is called once it has lost a third.
+        let mut b = broker().with_margin(50.0, 100.0);
+        b.submit(Order::market("S", Direction::Short, Some(50.0)));
+        b.advance(&bar(0, 100.0, 100.0, 100.0, 100.0));
+        assert_eq!(b.margin_liquidation_price(), Some(150.0));
+    }
+
+    #[test]
+    fn without_margin_equity_may_go_negative() {
+        let mut b = broker();
+        b.submit(Order::market("L", Direction::Long, Some(1_000.0)));
+        b.advance(&bar(0, 100.0, 100.0, 100.0, 100.0));
+        b.advance(&bar(1, 80.0, 80.0, 80.0, 80.0));
+        assert_eq!(b.position().size, 1_000.0);
+        assert_eq!(b.equity(80.0), -10_000.0);
+        assert_eq!(b.margin_liquidation_price(), None);
+    }
+
     #[test]
     fn cash_sizing_buys_contracts_worth_the_cash() {
         let mut b = broker().with_sizing(Sizing::Cash(1_000.0));
@@ -1099,4 +1568,34 @@ mod tests {
         assert_eq!(b.closed_trades().len(), 1);
         assert_eq!(b.equity(104.0), 10_004.0);
     }
+
+    #[test]
+    fn profit_and_commission_are_converted_to_the_account_currency() {
+        // One symbol-currency unit is worth 0.5 in the account currency.
+        let mut b = broker().with_commission(Commission::Percent(1.0));
+        b.set_fx_rate(0.5);
+        b.submit(Order::market("L", Direction::Long, Some(10.0)));
+        b.advance(&bar(0, 100.0, 100.0, 100.0, 100.0)); // 1% of 500 = 5
+        b.submit(Order {
+            reduce_only: true,
+            close_target: Some("L".into()),
+            ..Order::market("L", Direction::Long, None)
+        });
+        b.advance(&bar(1, 110.0, 110.0, 110.0, 110.0)); // 1% of 550 = 5.5
+
+        let trade = &b.closed_trades()[0];
+        assert_eq!(trade.cost(), 500.0);
+        assert_eq!(trade.profit(0.0), 50.0 - 5.0 - 5.5);
+        assert_eq!(b.equity(110.0), 10_039.5);
+    }
+
+    #[test]
+    fn open_trades_are_marked_at_the_latest_rate() {
+        let mut b = broker();
+        b.submit(Order::market("L", Direction::Long, Some(10.0)));
+        b.advance(&bar(0, 100.0, 100.0, 100.0, 100.0));
+        b.set_fx_rate(2.0);
+        assert_eq!(b.open_trades()[0].fx_rate, 2.0);
+        assert_eq!(b.equity(105.0), 10_100.0);
+    }
 }
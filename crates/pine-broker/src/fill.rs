@@ -10,6 +10,60 @@ use pine_core::Bar;
 /// Decides whether `order` fills against `bar`, returning the fill price.
 pub trait FillModel {
     fn fill(&self, order: &Order, bar: &Bar) -> Option<f64>;
+
+    /// Of `orders` competing on one bar — the legs of an exit bracket — the
+    /// one that fills first, by index, with its price. A bar's OHLC does not
+    /// say which price came first, so by default the earliest listed that
+    /// fills wins; callers list the conservative leg first.
+    fn first_fill(&self, orders: &[Order], bar: &Bar) -> Option<(usize, f64)> {
+        orders
+            .iter()
+            .enumerate()
+            .find_map(|(i, order)| self.fill(order, bar).map(|price| (i, price)))
+    }
+
+    /// The bars `bar`'s price moved through, oldest first, for state that
+    /// follows the price within a bar (a trailing stop's peak). Filling against
+    /// one of them fills against that step alone. By default the bar itself.
+    fn steps<'a>(&'a self, bar: &'a Bar) -> &'a [Bar] {
+        std::slice::from_ref(bar)
+    }
+
+    /// The steps price moved through in `bar` after trading at `price`,
+    /// oldest first — what is left of the bar once an order filled there. By
+    /// default the [`rest_of_bar`].
+    fn rest(&self, bar: &Bar, price: f64) -> Vec<Bar> {
+        vec![rest_of_bar(bar, price)]
+    }
+}
+
+/// What is left of `bar` after price traded at `price`, along the path
+/// TradingView assumes within a bar: open, then whichever of the high and low
+/// is nearer the open, then the other, then the close. It opens at `price` and
+/// spans only the legs from there on, so nothing printed before the fill
+/// reaches it. It covers none of the bar's intrabars, so a magnifier fills
+/// against it as it is.
+pub fn rest_of_bar(bar: &Bar, price: f64) -> Bar {
+    let (first, second) = if bar.high - bar.open <= bar.open - bar.low {
+        (bar.high, bar.low)
+    } else {
+        (bar.low, bar.high)
+    };
+    let path = [bar.open, first, second, bar.close];
+    // The first leg that passes through the price; one outside the bar (a
+    // slipped fill) is taken to be on the first.
+    let leg = path
+        .windows(2)
+        .position(|w| w[0].min(w[1]) <= price && price <= w[0].max(w[1]))
+        .unwrap_or(0);
+    let rest = &path[leg + 1..];
+    Bar {
+        open: price,
+        high: rest.iter().fold(price, |a, &b| a.max(b)),
+        low: rest.iter().fold(price, |a, &b| a.min(b)),
+        next_time: Some(bar.time),
+        ..bar.clone()
+    }
 }
 
 /// TradingView's default assumptions:
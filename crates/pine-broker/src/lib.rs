@@ -15,9 +15,11 @@ use pine_core::Bar;
 
 mod broker;
 mod fill;
+mod magnifier;
 
 pub use broker::BarBroker;
-pub use fill::{FillModel, PineFills};
+pub use fill::{rest_of_bar, FillModel, PineFills};
+pub use magnifier::{magnifier_timeframe, BarMagnifier, MagnifierFactory};
 
 /// Long or short.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -192,6 +194,29 @@ impl From<&str> for OcaType {
     }
 }
 
+/// Which open trades an exit closes when a position holds several, from the
+/// `strategy` declaration's `close_entries_rule`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CloseEntriesRule {
+    /// The oldest trades first, whichever entry the exit names; the name only
+    /// bounds how much it closes (`"FIFO"`).
+    #[default]
+    Fifo,
+    /// Only the trades of the entry the exit names (`"ANY"`).
+    Any,
+}
+
+impl From<&str> for CloseEntriesRule {
+    /// From the declaration's string; anything but `"ANY"` is FIFO.
+    fn from(tag: &str) -> Self {
+        if tag.eq_ignore_ascii_case("any") {
+            CloseEntriesRule::Any
+        } else {
+            CloseEntriesRule::Fifo
+        }
+    }
+}
+
 /// A submitted order, before it fills. Replaces any pending order with the same
 /// `id`, as Pine's order commands do.
 #[derive(Debug, Clone)]
@@ -221,6 +246,11 @@ pub struct Order {
     pub oca_name: Option<String>,
     pub oca_type: OcaType,
     pub comment: String,
+    /// The message its fill alerts with (`alert_message`); empty for the
+    /// default.
+    pub alert_message: String,
+    /// Fill without alerting (`disable_alert`).
+    pub disable_alert: bool,
 }
 
 impl Order {
@@ -239,6 +269,8 @@ impl Order {
             oca_name: None,
             oca_type: OcaType::None,
             comment: String::new(),
+            alert_message: String::new(),
+            disable_alert: false,
         }
     }
 }
@@ -269,6 +301,14 @@ pub struct Exit {
     pub trail_price: Option<f64>,
     pub trail_points: Option<f64>,
     pub trail_offset: Option<f64>,
+    pub comment: String,
+    /// The message a fill alerts with: `alert_profit`, `alert_loss` or
+    /// `alert_trailing` for the leg that filled if set, else `alert_message`.
+    pub alert_message: String,
+    pub alert_profit: String,
+    pub alert_loss: String,
+    pub alert_trailing: String,
+    pub disable_alert: bool,
     /// Runtime state of the trailing stop, carried across bars: whether it has
     /// activated and the best price seen since.
     pub activated: bool,
@@ -295,6 +335,12 @@ impl Exit {
             trail_price: None,
             trail_points: None,
             trail_offset: None,
+            comment: String::new(),
+            alert_message: String::new(),
+            alert_profit: String::new(),
+            alert_loss: String::new(),
+            alert_trailing: String::new(),
+            disable_alert: false,
             activated: false,
             peak: None,
         }
@@ -303,7 +349,7 @@ impl Exit {
 
 /// One trade: an entry, and its exit once closed. `size` is signed — positive is
 /// long, negative short — matching `strategy.*trades.size`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Trade {
     pub entry_id: String,
     pub size: f64,
@@ -311,20 +357,66 @@ pub struct Trade {
     pub entry_bar: u64,
     pub exit_price: Option<f64>,
     pub exit_bar: Option<u64>,
-    /// Commission on entry, plus exit once closed.
+    /// The order or exit that closed the trade, or [`MARGIN_CALL`] for a
+    /// forced liquidation.
+    pub exit_id: Option<String>,
+    /// Commission on entry, plus exit once closed, in the account currency.
     pub commission: f64,
+    /// What one unit of the symbol's currency was worth in the account's when
+    /// the trade closed — or, while open, as of the latest bar.
+    pub fx_rate: f64,
+    /// The largest open profit the trade showed (its maximum favourable
+    /// excursion), in the account currency, before commission. Measured at
+    /// each bar's high and low while the trade was open, and at its exit.
+    pub max_runup: f64,
+    /// The largest open loss the trade showed (its maximum adverse
+    /// excursion), as a positive amount, measured as `max_runup` is.
+    pub max_drawdown: f64,
 }
 
+/// The [`Trade::exit_id`] of a trade closed by a margin call, as TradingView's
+/// trade list labels it.
+pub const MARGIN_CALL: &str = "Margin call";
+
 impl Trade {
-    /// Realised profit once closed, or profit at `price` while open.
+    /// Realised profit once closed, or profit at `price` while open, in the
+    /// account currency.
     pub fn profit(&self, price: f64) -> f64 {
         let exit = self.exit_price.unwrap_or(price);
-        (exit - self.entry_price) * self.size - self.commission
+        (exit - self.entry_price) * self.size * self.fx_rate - self.commission
+    }
+
+    /// The account-currency value of the trade at entry: what it tied up.
+    pub fn cost(&self) -> f64 {
+        self.entry_price * self.size.abs() * self.fx_rate
     }
 
     pub fn is_open(&self) -> bool {
         self.exit_price.is_none()
     }
+
+    /// Widen the trade's run-up and drawdown to take in the price reaching
+    /// `price`.
+    pub(crate) fn track(&mut self, price: f64) {
+        let excursion = (price - self.entry_price) * self.size * self.fx_rate;
+        self.max_runup = self.max_runup.max(excursion);
+        self.max_drawdown = self.max_drawdown.max(-excursion);
+    }
+}
+
+/// An order or exit filling, as reported for its alert.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    /// The order or exit that filled, or [`MARGIN_CALL`].
+    pub id: String,
+    /// Signed contracts: positive bought, negative sold.
+    pub qty: f64,
+    pub price: f64,
+    /// The net position the fill left.
+    pub position_size: f64,
+    pub comment: String,
+    /// The message to alert with; empty for the default.
+    pub alert_message: String,
 }
 
 /// The current net position: signed size and the average price it was opened at.
@@ -364,6 +456,27 @@ pub trait Broker {
     /// Fill whatever `bar` allows, updating the position and trade log.
     fn advance(&mut self, bar: &Bar);
 
+    /// The script has run on `bar`'s close: fill what the close allows, for a
+    /// broker that processes orders on close (`process_orders_on_close`).
+    /// Otherwise orders wait for the next bar, and this does nothing.
+    fn close(&mut self, _bar: &Bar) {}
+
+    /// The price of the fill in the last [`advance`](Broker::advance) the
+    /// script should run again for (`calc_on_order_fills`), or `None` when no
+    /// run is due. The orders that run places fill on the rest of the bar, by
+    /// [`resume`](Broker::resume). Reading it clears it.
+    fn take_recalc(&mut self) -> Option<f64> {
+        None
+    }
+
+    /// Fill what `bar` allows after price traded at `price` — the rest of the
+    /// bar a `calc_on_order_fills` run left, which may ask for another. What
+    /// the bar did before that price was already processed and is not
+    /// revisited. By default an `advance` over the [`rest_of_bar`].
+    fn resume(&mut self, bar: &Bar, price: f64) {
+        self.advance(&rest_of_bar(bar, price));
+    }
+
     /// The current net position.
     fn position(&self) -> Position;
 
@@ -383,15 +496,37 @@ pub trait Broker {
     /// The bar the run halted on if a rest-of-run risk rule fired
     /// (`max_drawdown`, `max_cons_loss_days`), else `None`.
     fn halted_bar(&self) -> Option<u64>;
+
+    /// The price at which the open position would be margin called
+    /// (`strategy.margin_liquidation_price`); `None` when flat, trading
+    /// without margin, or when no price can trigger one.
+    fn margin_liquidation_price(&self) -> Option<f64> {
+        None
+    }
+
+    /// What one unit of the symbol's currency is worth in the account's, for
+    /// the fills and marks that follow. A broker that keeps its books in the
+    /// symbol's currency ignores it.
+    fn set_fx_rate(&mut self, _rate: f64) {}
+
+    /// The fills since the last call, oldest first, leaving out orders placed
+    /// with `disable_alert`. Reading them clears them.
+    fn take_fills(&mut self) -> Vec<Fill> {
+        Vec::new()
+    }
 }
 
 /// The account settings a `strategy()` declaration configures its broker with,
 /// so a custom [`BrokerFactory`] can honour the script's parameters rather than
 /// inventing its own.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BrokerConfig {
-    /// Starting capital (`strategy.initial_capital`).
+    /// Starting capital (`strategy.initial_capital`), in `currency`.
     pub initial_capital: f64,
+    /// The account currency (`currency`): capital, equity, profit and cash
+    /// commission are kept in it, converted from the symbol's currency at the
+    /// rate the host gives [`Broker::set_fx_rate`].
+    pub currency: String,
     /// The symbol's tick size, or 0 when unknown.
     pub mintick: f64,
     /// How an order's absent `qty` is sized.
@@ -402,6 +537,22 @@ pub struct BrokerConfig {
     pub commission: Option<Commission>,
     /// Slippage applied to fills, in ticks.
     pub slippage: f64,
+    /// Fill against lower-timeframe bars where the factory can get them
+    /// (`use_bar_magnifier`); see [`MagnifierFactory`].
+    pub use_bar_magnifier: bool,
+    /// Fill again at each bar's close, after the script ran on it
+    /// (`process_orders_on_close`).
+    pub process_orders_on_close: bool,
+    /// Run the script again on a bar once an order fills on it
+    /// (`calc_on_order_fills`).
+    pub calc_on_order_fills: bool,
+    /// Which trades an exit closes (`close_entries_rule`).
+    pub close_entries_rule: CloseEntriesRule,
+    /// The share of a long position's value held as margin, in percent
+    /// (`margin_long`); 0 trades without margin.
+    pub margin_long: f64,
+    /// The same for short positions (`margin_short`).
+    pub margin_short: f64,
 }
 
 /// Builds the [`Broker`] a `strategy` trades against. The default,
@@ -418,17 +569,30 @@ pub struct DefaultBrokerFactory;
 
 impl BrokerFactory for DefaultBrokerFactory {
     fn build(&self, config: &BrokerConfig) -> Box<dyn Broker> {
-        let fills = PineFills {
-            slippage: config.slippage,
-            mintick: config.mintick,
-        };
-        let mut broker = BarBroker::new(fills, config.initial_capital)
-            .with_mintick(config.mintick)
-            .with_sizing(config.sizing)
-            .with_pyramiding(config.pyramiding);
-        if let Some(commission) = config.commission {
-            broker = broker.with_commission(commission);
-        }
-        Box::new(broker)
+        Box::new(configured(pine_fills(config), config))
+    }
+}
+
+/// Pine's default fill model with the script's slippage.
+fn pine_fills(config: &BrokerConfig) -> PineFills {
+    PineFills {
+        slippage: config.slippage,
+        mintick: config.mintick,
+    }
+}
+
+/// A [`BarBroker`] over `fills`, set up as `config` asks.
+fn configured<F: FillModel>(fills: F, config: &BrokerConfig) -> BarBroker<F> {
+    let mut broker = BarBroker::new(fills, config.initial_capital)
+        .with_mintick(config.mintick)
+        .with_sizing(config.sizing)
+        .with_pyramiding(config.pyramiding)
+        .with_process_orders_on_close(config.process_orders_on_close)
+        .with_calc_on_order_fills(config.calc_on_order_fills)
+        .with_close_entries_rule(config.close_entries_rule)
+        .with_margin(config.margin_long, config.margin_short);
+    if let Some(commission) = config.commission {
+        broker = broker.with_commission(commission);
     }
+    broker
 }
@@ -0,0 +1,285 @@
+//! TradingView's bar magnifier: fills decided against the intrabars of each
+//! chart bar rather than its OHLC.
+//!
+//! One bar's OHLC cannot say whether its high came before its low, so a bar
+//! that reaches both legs of an exit bracket needs a tie-break (the stop wins).
+//! With the chart bar's lower-timeframe bars in hand the order of events is
+//! mostly known: the leg reached on the earliest intrabar filled first. Only a
+//! single intrabar reaching both still falls back to the tie-break.
+
+use crate::{Broker, BrokerConfig, BrokerFactory, DefaultBrokerFactory, FillModel, Order};
+use pine_core::{intrabar_range, Bar, DataProvider, Timeframe, TimeframeUnit};
+use std::rc::Rc;
+
+/// A [`FillModel`] that replays `inner` over the intrabars of each bar.
+///
+/// A chart bar's intrabars are those opening from its open time until the next
+/// chart bar's (see [`intrabar_range`]); `period` stands in for the last bar.
+/// A bar without intrabars is filled by `inner` as usual.
+pub struct BarMagnifier<F: FillModel> {
+    inner: F,
+    /// Oldest first.
+    intrabars: Vec<Bar>,
+    /// The chart timeframe in milliseconds.
+    period: i64,
+}
+
+impl<F: FillModel> BarMagnifier<F> {
+    pub fn new(inner: F, mut intrabars: Vec<Bar>, period: i64) -> Self {
+        // Each intrabar ends where the next opens, so one handed back by
+        // `steps` is magnified to itself alone.
+        for i in 1..intrabars.len() {
+            intrabars[i - 1].next_time = Some(intrabars[i].time);
+        }
+        Self {
+            inner,
+            intrabars,
+            period: period.max(1),
+        }
+    }
+
+    /// The intrabars of `bar`, oldest first.
+    fn intrabars_of(&self, bar: &Bar) -> &[Bar] {
+        let range = intrabar_range(
+            &self.intrabars,
+            |intrabar| intrabar.time,
+            bar.time,
+            bar.next_time,
+            Some(self.period),
+        );
+        &self.intrabars[range]
+    }
+}
+
+impl<F: FillModel> FillModel for BarMagnifier<F> {
+    fn fill(&self, order: &Order, bar: &Bar) -> Option<f64> {
+        let intrabars = self.intrabars_of(bar);
+        if intrabars.is_empty() {
+            return self.inner.fill(order, bar);
+        }
+        intrabars
+            .iter()
+            .find_map(|intrabar| self.inner.fill(order, intrabar))
+    }
+
+    fn first_fill(&self, orders: &[Order], bar: &Bar) -> Option<(usize, f64)> {
+        let intrabars = self.intrabars_of(bar);
+        if intrabars.is_empty() {
+            return self.inner.first_fill(orders, bar);
+        }
+        intrabars
+            .iter()
+            .find_map(|intrabar| self.inner.first_fill(orders, intrabar))
+    }
+
+    fn steps<'a>(&'a self, bar: &'a Bar) -> &'a [Bar] {
+        match self.intrabars_of(bar) {
+            [] => std::slice::from_ref(bar),
+            intrabars => intrabars,
+        }
+    }
+
+    fn rest(&self, bar: &Bar, price: f64) -> Vec<Bar> {
+        // The rest of the first intrabar that traded at the price, then the
+        // intrabars after it.
+        let intrabars = self.intrabars_of(bar);
+        let Some(i) = intrabars
+            .iter()
+            .position(|intrabar| intrabar.low <= price && price <= intrabar.high)
+        else {
+            return self.inner.rest(bar, price);
+        };
+        let mut rest = self.inner.rest(&intrabars[i], price);
+        rest.extend_from_slice(&intrabars[i + 1..]);
+        rest
+    }
+}
+
+/// The intrabar timeframe the magnifier requests for a `chart` timeframe: the
+/// coarsest of 1 day, 60, 15, 5, 1 minutes and 1 second that is at least five
+/// times finer and divides the chart bar evenly. `None` when nothing finer is
+/// available, as for a chart already at seconds.
+pub fn magnifier_timeframe(chart: &Timeframe) -> Option<Timeframe> {
+    const CANDIDATES: [(u32, TimeframeUnit); 6] = [
+        (1, TimeframeUnit::Daily),
+        (60, TimeframeUnit::Minutes),
+        (15, TimeframeUnit::Minutes),
+        (5, TimeframeUnit::Minutes),
+        (1, TimeframeUnit::Minutes),
+        (1, TimeframeUnit::Seconds),
+    ];
+    let period = chart.to_millis()?;
+    CANDIDATES
+        .into_iter()
+        .map(|(multiplier, unit)| Timeframe { multiplier, unit })
+        .find(|tf| {
+            tf.to_millis()
+                .is_some_and(|ms| ms * 5 <= period && period % ms == 0)
+        })
+}
+
+/// Builds the same broker as [`DefaultBrokerFactory`], magnified when the
+/// script sets `use_bar_magnifier`: the intrabars come from `provider` at
+/// [`magnifier_timeframe`] of the chart. Without intrabars to be had, the
+/// broker is built unmagnified.
+pub struct MagnifierFactory {
+    provider: Rc<dyn DataProvider>,
+    symbol: String,
+    timeframe: Timeframe,
+}
+
+impl MagnifierFactory {
+    /// Magnify fills on `symbol`'s chart at `timeframe`, with intrabars from
+    /// `provider`.
+    pub fn new(provider: Rc<dyn DataProvider>, symbol: String, timeframe: Timeframe) -> Self {
+        Self {
+            provider,
+            symbol,
+            timeframe,
+        }
+    }
+
+    fn intrabars(&self) -> Option<(Vec<Bar>, i64)> {
+        let period = self.timeframe.to_millis()?;
+        let lower = magnifier_timeframe(&self.timeframe)?;
+        let data = self.provider.request(&self.symbol, lower).ok()?;
+        Some((data.bars, period))
+    }
+}
+
+impl BrokerFactory for MagnifierFactory {
+    fn build(&self, config: &BrokerConfig) -> Box<dyn Broker> {
+        if config.use_bar_magnifier {
+            if let Some((intrabars, period)) = self.intrabars() {
+                let fills = BarMagnifier::new(crate::pine_fills(config), intrabars, period);
+                return Box::new(crate::configured(fills, config));
+            }
+        }
+        DefaultBrokerFactory.build(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BarBroker, Direction, Exit, OrderKind, PineFills};
+
+    fn bar(time: i64, open: f64, high: f64, low: f64, close: f64) -> Bar {
+        Bar {
+            open,
+            high,
+            low,
+            close,
+            time,
+            ..Bar::default()
+        }
+    }
+
+    /// A long entered at 100 with a stop at 95 and a take-profit at 105, then a
+    /// bar reaching both.
+    fn bracket<F: FillModel>(fills: F) -> BarBroker<F> {
+        let mut b = BarBroker::new(fills, 10_000.0);
+        b.submit(Order::market("L", Direction::Long, Some(1.0)));
+        b.submit_exit(Exit {
+            stop: Some(95.0),
+            limit: Some(105.0),
+            ..Exit::resting("X", Some("L".to_string()), None, None)
+        });
+        b.advance(&bar(0, 100.0, 101.0, 99.0, 100.0));
+        b.advance(&bar(300_000, 100.0, 106.0, 94.0, 100.0));
+        b
+    }
+
+    #[test]
+    fn without_intrabars_the_stop_wins() {
+        let b = bracket(PineFills::default());
+        assert_eq!(b.closed_trades()[0].exit_price, Some(95.0));
+    }
+
+    #[test]
+    fn the_leg_reached_first_intrabar_fills() {
+        // The second chart bar rallies to 106 before it drops to 94.
+        let intrabars = vec![
+            bar(300_000, 100.0, 103.0, 99.0, 102.0),
+            bar(360_000, 102.0, 106.0, 101.0, 104.0),
+            bar(420_000, 104.0, 104.0, 94.0, 95.0),
+        ];
+        let b = bracket(BarMagnifier::new(PineFills::default(), intrabars, 300_000));
+        assert_eq!(b.closed_trades()[0].exit_price, Some(105.0));
+    }
+
+    #[test]
+    fn a_trailing_stop_trails_intrabar_by_intrabar() {
+        // Long at 100, trailing 1.0 behind the peak once 2.0 in profit. The
+        // second chart bar dips to 101 first, then rallies to 105 and holds
+        // above 104: on its OHLC alone the stop would fill at 104.
+        let trail = |fills| {
+            let mut b = BarBroker::new(fills, 10_000.0).with_mintick(0.5);
+            b.submit(Order::market("L", Direction::Long, Some(1.0)));
+            b.submit_exit(Exit {
+                trail_points: Some(4.0),
+                trail_offset: Some(2.0),
+                ..Exit::resting("X", Some("L".into()), None, None)
+            });
+            b.advance(&bar(0, 100.0, 101.0, 99.0, 100.0));
+            b.advance(&bar(300_000, 100.0, 105.0, 101.0, 105.0));
+            b
+        };
+        let intrabars = vec![
+            bar(300_000, 100.0, 101.5, 101.0, 101.5),
+            bar(360_000, 101.5, 105.0, 104.5, 105.0),
+        ];
+        let magnified = trail(BarMagnifier::new(PineFills::default(), intrabars, 300_000));
+        assert_eq!(magnified.position().size, 1.0);
+
+        let unmagnified = trail(BarMagnifier::new(PineFills::default(), Vec::new(), 300_000));
+        assert!(unmagnified.position().is_flat());
+        assert_eq!(unmagnified.closed_trades()[0].exit_price, Some(104.0));
+    }
+
+    #[test]
+    fn intrabars_are_bounded_by_the_chart_bars() {
+        // A chart bar opening mid-bucket, as on a session-aligned chart: its
+        // intrabars run to the next chart bar's open, not to the epoch bucket.
+        let intrabars = vec![
+            bar(120_000, 100.0, 101.0, 99.0, 100.0),
+            bar(300_000, 100.0, 106.0, 100.0, 105.0),
+            bar(420_000, 105.0, 105.0, 94.0, 95.0),
+        ];
+        let magnifier = BarMagnifier::new(PineFills::default(), intrabars, 300_000);
+        let chart_bar = Bar {
+            next_time: Some(420_000),
+            ..bar(120_000, 100.0, 106.0, 99.0, 105.0)
+        };
+        assert_eq!(magnifier.intrabars_of(&chart_bar).len(), 2);
+    }
+
+    #[test]
+    fn a_limit_fills_on_the_intrabar_that_reaches_it() {
+        // The intrabar gapping below the limit fills at its open.
+        let intrabars = vec![
+            bar(0, 100.0, 101.0, 99.0, 100.0),
+            bar(60_000, 96.0, 97.0, 95.0, 96.0),
+        ];
+        let magnifier = BarMagnifier::new(PineFills::default(), intrabars, 300_000);
+        let order = Order {
+            kind: OrderKind::Limit(98.0),
+            ..Order::market("L", Direction::Long, Some(1.0))
+        };
+        let chart_bar = bar(0, 100.0, 101.0, 95.0, 96.0);
+        assert_eq!(magnifier.fill(&order, &chart_bar), Some(96.0));
+        assert_eq!(PineFills::default().fill(&order, &chart_bar), Some(98.0));
+    }
+
+    #[test]
+    fn intrabar_timeframe_is_a_fine_divisor_of_the_chart() {
+        let lower = |chart: &str| {
+            magnifier_timeframe(&chart.parse::<Timeframe>().unwrap()).map(|tf| tf.period())
+        };
+        assert_eq!(lower("5").as_deref(), Some("1"));
+        assert_eq!(lower("60").as_deref(), Some("5"));
+        assert_eq!(lower("1D").as_deref(), Some("60"));
+        assert_eq!(lower("1").as_deref(), Some("1S"));
+        assert_eq!(lower("1S"), None);
+    }
+}
@@ -125,6 +125,8 @@ pub fn builtin_function_derive(input: TokenStream) -> TokenStream {
                 quote! { #name: #ty }
             })
             .collect();
+        let state_types: Vec<_> = state_fields.iter().map(|f| &f.ty).collect();
+        let state_indices: Vec<_> = (0..state_fields.len()).map(syn::Index::from).collect();
         let slot_name = format_ident!("{}Slot", struct_name);
 
         quote! {
@@ -132,12 +134,18 @@ pub fn builtin_function_derive(input: TokenStream) -> TokenStream {
             /// fields, plus the bar it last advanced on and what it returned
             /// then, so re-entering a call site within one bar cannot advance
             /// the state twice.
+            ///
+            /// While the interpreter holds a checkpoint, `base` keeps the state
+            /// the bar was entered with, so a later tick of that bar starts
+            /// over from it rather than from what the previous tick left.
             #[derive(Default)]
             #[doc(hidden)]
             pub struct #slot_name<O: ::pine_core::PineOutput> {
                 #(#state_decls,)*
                 bar_seq: u64,
+                update_seq: u64,
                 memo: Option<::pine_interpreter::Value<O>>,
+                base: Option<(#(#state_types,)*)>,
             }
 
             impl #impl_generics #struct_name #ty_generics #where_clause {
@@ -156,13 +164,14 @@ pub fn builtin_function_derive(input: TokenStream) -> TokenStream {
 
                         let call_id = call_args.call_id;
                         let bar_seq = ctx.bar_seq();
+                        let update_seq = ctx.update_seq();
 
                         // Already ran on this bar: hand back the same value
                         // rather than advancing the state again.
                         {
                             let slots = slots.borrow();
                             if let Some(slot) = slots.get(&call_id) {
-                                if slot.bar_seq == bar_seq {
+                                if slot.bar_seq == bar_seq && slot.update_seq == update_seq {
                                     if let Some(memo) = &slot.memo {
                                         return Ok(memo.clone());
                                     }
@@ -176,11 +185,20 @@ pub fn builtin_function_derive(input: TokenStream) -> TokenStream {
                             #struct_construction
                         };
 
-                        // Restore what this call site accumulated on earlier bars.
+                        // Restore what this call site accumulated on earlier
+                        // bars — on a re-run of the same bar, only up to the
+                        // bar's start.
                         {
                             let slots = slots.borrow();
                             if let Some(slot) = slots.get(&call_id) {
-                                #(instance.#state_names = slot.#state_names.clone();)*
+                                match &slot.base {
+                                    Some(base) if slot.bar_seq == bar_seq => {
+                                        #(instance.#state_names = base.#state_indices.clone();)*
+                                    }
+                                    _ => {
+                                        #(instance.#state_names = slot.#state_names.clone();)*
+                                    }
+                                }
                             }
                         }
 
@@ -188,8 +206,16 @@ pub fn builtin_function_derive(input: TokenStream) -> TokenStream {
 
                         let mut slots = slots.borrow_mut();
                         let slot = slots.entry(call_id).or_default();
+                        if slot.bar_seq != bar_seq {
+                            slot.base = if ctx.is_checkpointed() {
+                                Some((#(::std::mem::take(&mut slot.#state_names),)*))
+                            } else {
+                                None
+                            };
+                        }
                         #(slot.#state_names = instance.#state_names;)*
                         slot.bar_seq = bar_seq;
+                        slot.update_seq = update_seq;
                         slot.memo = Some(result.clone());
 
                         Ok(result)
@@ -387,6 +413,7 @@ fn generate_signature(
             let type_str = quote! { #field_ty }.to_string();
             let variadic = is_field_variadic(field);
             let lazy = is_field_lazy(field);
+            let qualifier = field_qualifier(field);
             let (has_default, _) = parse_field_default(field);
             // A defaulted, variadic or Option field may be omitted.
             let required = !has_default && !variadic && !type_str.contains("Option");
@@ -415,6 +442,7 @@ fn generate_signature(
                     required: #required,
                     variadic: #variadic,
                     lazy: #lazy,
+                    qualifier: ::pine_interpreter::TypeQualifier::#qualifier,
                 }
             }
         });
@@ -757,6 +785,28 @@ fn is_field_lazy(field: &Field) -> bool {
     false
 }
 
+/// `#[arg(simple)]`, `#[arg(input)]` or `#[arg(const)]` caps the qualifier
+/// an argument may have, as Pine's reference declares it (`simple int
+/// length`); without one a param takes a series.
+fn field_qualifier(field: &Field) -> syn::Ident {
+    let mut qualifier = "Series";
+    for attr in &field.attrs {
+        if let Meta::List(meta_list) = &attr.meta {
+            if meta_list.path.is_ident("arg") {
+                for token in meta_list.tokens.to_string().split(',') {
+                    qualifier = match token.trim() {
+                        "simple" => "Simple",
+                        "input" => "Input",
+                        "const" => "Const",
+                        _ => qualifier,
+                    };
+                }
+            }
+        }
+    }
+    syn::Ident::new(qualifier, proc_macro2::Span::call_site())
+}
+
 fn generate_value_conversion(
     field_name: &syn::Ident,
     field_type: &syn::Type,
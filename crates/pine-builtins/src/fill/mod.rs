@@ -1,20 +1,22 @@
 //! The `fill(id1, id2, color, title, transp)` global function.
 //!
 //! Fills the area between two plots or hlines. The ids come from `plot`/`hline`
-//! (which may be `na`); the fill is recorded via [`FillOutput`].
+//! (which may be `na`); the fill is recorded via [`FillOutput`], with whether
+//! they name plots or hlines.
 
+use crate::plot::plot_index;
 use pine_builtin_macro::BuiltinFunction;
-use pine_core::{Color, FillObject, FillOutput, PineOutput};
+use pine_core::{Color, FillKind, FillObject, FillOutput, PineOutput};
 use pine_interpreter::{Interpreter, RuntimeError, Value};
 
 /// fill(id1, id2, color, title, transp, ...)
 #[derive(BuiltinFunction)]
 #[builtin(name = "fill", output = FillOutput)]
-struct Fill {
-    #[arg(default = None)]
-    id1: Option<f64>,
-    #[arg(default = None)]
-    id2: Option<f64>,
+struct Fill<O: PineOutput + FillOutput> {
+    #[arg(default = Value::Na)]
+    id1: Value<O>,
+    #[arg(default = Value::Na)]
+    id2: Value<O>,
     #[arg(default = None)]
     color: Option<Color>,
     #[arg(default = "")]
@@ -23,15 +25,25 @@ struct Fill {
     transp: Option<f64>,
 }
 
-impl Fill {
-    fn execute<O: PineOutput + FillOutput>(
-        &self,
-        ctx: &mut Interpreter<O>,
-    ) -> Result<Value<O>, RuntimeError> {
+impl<O: PineOutput + FillOutput> Fill<O> {
+    fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
         let _ = self.transp;
+        let plots = plot_index(&self.id1).is_some() || plot_index(&self.id2).is_some();
+        let id = |value: &Value<O>| -> Result<Option<usize>, RuntimeError> {
+            if plots {
+                Ok(plot_index(value))
+            } else {
+                Ok(value.to_number()?.map(|x| x as usize))
+            }
+        };
         ctx.output.add_fill(FillObject {
-            id1: self.id1.map(|x| x as usize),
-            id2: self.id2.map(|x| x as usize),
+            id1: id(&self.id1)?,
+            id2: id(&self.id2)?,
+            kind: if plots {
+                FillKind::Plots
+            } else {
+                FillKind::Hlines
+            },
             color: self.color.clone(),
             title: self.title.clone(),
         });
@@ -41,5 +53,5 @@ impl Fill {
 
 /// The `fill` global function value.
 pub fn register<O: PineOutput + FillOutput>() -> Value<O> {
-    Fill::builtin_value::<O>()
+    Fill::<O>::builtin_value()
 }
@@ -1,21 +1,25 @@
 //! The `indicator(...)` declaration.
 //!
 //! A global function (not a namespace) that declares the script's identity and
-//! display settings. It records the declaration into the output via
-//! [`MetadataOutput`]; a script may have at most one (enforced by sema).
+//! display settings. It records the declaration, and the drawing limits its
+//! `max_*_count` arguments set, into the output via [`MetadataOutput`]; a
+//! script may have at most one (enforced by sema).
 
 use pine_builtin_macro::BuiltinFunction;
 use pine_core::PineVersion;
-use pine_core::{Indicator, MetadataOutput, PineOutput};
+use pine_core::{DrawingLimits, Indicator, MetadataOutput, PineOutput};
 use pine_interpreter::{Interpreter, RuntimeError, Value};
 
 /// indicator(title, shorttitle, overlay, format, precision, ...)
 #[derive(BuiltinFunction)]
 #[builtin(name = "indicator", output = MetadataOutput)]
 struct IndicatorFn {
+    #[arg(const)]
     title: String,
+    #[arg(const)]
     #[arg(default = "")]
     shorttitle: String,
+    #[arg(const)]
     #[arg(default = false)]
     overlay: bool,
     #[arg(default = "")]
@@ -24,7 +28,7 @@ struct IndicatorFn {
     precision: Option<f64>,
     #[arg(default = "")]
     timeframe: String,
-    // Accepted and ignored: chart-capacity and display hints.
+    // Accepted and ignored: a chart-capacity hint.
     #[arg(default = None)]
     max_bars_back: Option<f64>,
     #[arg(default = None)]
@@ -35,6 +39,7 @@ struct IndicatorFn {
     max_boxes_count: Option<f64>,
     #[arg(default = None)]
     max_polylines_count: Option<f64>,
+    // Accepted and ignored: chart-capacity and display hints.
     #[arg(default = None)]
     calc_bars_count: Option<f64>,
     #[arg(default = "")]
@@ -56,10 +61,6 @@ impl IndicatorFn {
     ) -> Result<Value<O>, RuntimeError> {
         let _ = (
             self.max_bars_back,
-            self.max_lines_count,
-            self.max_labels_count,
-            self.max_boxes_count,
-            self.max_polylines_count,
             self.calc_bars_count,
             &self.scale,
             self.timeframe_gaps,
@@ -75,6 +76,12 @@ impl IndicatorFn {
             precision: self.precision.map(|p| p as i64),
             timeframe: self.timeframe.clone(),
         });
+        ctx.output.set_drawing_limits(DrawingLimits {
+            labels: DrawingLimits::count(self.max_labels_count),
+            lines: DrawingLimits::count(self.max_lines_count),
+            boxes: DrawingLimits::count(self.max_boxes_count),
+            polylines: DrawingLimits::count(self.max_polylines_count),
+        });
         Ok(Value::Na)
     }
 }
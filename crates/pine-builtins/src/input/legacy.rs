@@ -6,11 +6,11 @@
 //! callable object whose members (`input.integer`, `input.bool`, ...) are type
 //! **constants**, not functions.
 //!
-//! In a headless run the type/minval/options only shape a settings UI, so
-//! `input(...)` simply returns its default and records the declaration.
+//! In a headless run `input(...)` returns the host's override or its default,
+//! checked and recorded as the v5/v6 functions are.
 
 use pine_builtin_macro::BuiltinFunction;
-use pine_core::{Input, InputOutput, InputValue, PineOutput};
+use pine_core::{Input, InputConstraints, InputOutput, InputValue, PineOutput};
 use pine_interpreter::{Builtin, BuiltinFn, Interpreter, RuntimeError, Value};
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -39,12 +39,13 @@ const GLOBAL_TYPE_TAGS: &[&str] = &["integer", "source", "symbol", "resolution",
 
 /// The overloaded v3/v4 `input(defval, title, type, ...)`.
 ///
-/// The type/constraint/options arguments only shape a settings UI, so they are
-/// accepted and ignored; the default is returned and the declaration recorded.
+/// An override is checked against the type of the default (the `type` tag is
+/// what a settings UI shows) and the range and options.
 #[derive(BuiltinFunction)]
 #[builtin(name = "input")]
 struct InputLegacy<O: PineOutput + InputOutput> {
     defval: Value<O>,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     // `type=integer`/`type=input.integer` arrive as a string tag, but v3's
@@ -66,40 +67,45 @@ struct InputLegacy<O: PineOutput + InputOutput> {
     options: Value<O>,
     #[arg(default = false)]
     confirm: bool,
+    #[arg(default = "")]
+    inline: String,
 }
 
 impl<O: PineOutput + InputOutput> InputLegacy<O> {
     fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = (self.step, &self.tooltip, self.confirm);
         let kind = match &self.r#type {
             Value::String(tag) if !tag.is_empty() => tag.clone(),
             _ => infer_kind(&self.defval),
         };
-        // Coerce a host override for this title to the default's type.
+        let constraints = InputConstraints {
+            minval: self.minval,
+            maxval: self.maxval,
+            step: self.step,
+            options: super::options_of(&self.options, &kind),
+        };
+        // Read a host override for this title as the default's type.
         let effective = match &self.defval {
-            Value::Bool(b) => Value::Bool(super::bool_input(ctx, &self.title, *b)),
-            Value::Int(n) => Value::Number(
-                super::num_input(ctx, &self.title, *n as f64, self.minval, self.maxval).trunc(),
-            ),
-            Value::Number(n) => Value::Number(super::num_input(
-                ctx,
-                &self.title,
-                *n,
-                self.minval,
-                self.maxval,
-            )),
+            Value::Bool(b) => Value::Bool(super::bool_input(ctx, &self.title, *b)?),
+            Value::Int(n) => {
+                Value::Number(super::int_input(ctx, &self.title, *n, &constraints)? as f64)
+            }
+            Value::Number(n) => {
+                Value::Number(super::float_input(ctx, &self.title, *n, &constraints)?)
+            }
             Value::String(s) => {
-                Value::String(super::string_input(ctx, &self.title, s, &self.options))
+                Value::String(super::string_input(ctx, &self.title, s, &constraints)?)
             }
+            Value::Color(c) => Value::Color(super::color_input(ctx, &self.title, c)?),
+            Value::Series(_) => super::source_input(ctx, &self.title, &self.defval)?,
             other => other.clone(),
         };
-        ctx.output.add_input(Input {
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: to_input_value(&kind, &self.defval),
-            value: to_input_value(&kind, &effective),
+        ctx.output.add_input(declared!(
+            self,
             kind,
-        });
+            constraints,
+            to_input_value(&kind, &self.defval),
+            to_input_value(&kind, &effective)
+        ));
         Ok(effective)
     }
 }
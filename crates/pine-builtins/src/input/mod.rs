@@ -5,75 +5,179 @@
 //! overloaded `input(...)` in [`legacy`].
 //!
 //! In a headless interpreter there is no settings UI, so each function returns
-//! its default value so the script can run. It also records the declaration into
+//! the host's override for its title (see `Interpreter::input`) or else its
+//! default. An override the input's type, range or options rule out is an
+//! error. Each call also records the declaration, constraints included, into
 //! the output (via [`InputOutput`]) so a host can enumerate a script's inputs
 //! without executing it.
 
+/// The [`Input`] a builtin records: the settings arguments every `input.*`
+/// shares, plus what is particular to it.
+macro_rules! declared {
+    ($self:ident, $kind:expr, $constraints:expr, $default:expr, $value:expr) => {
+        Input {
+            kind: $kind.to_string(),
+            title: $self.title.clone(),
+            group: $self.group.clone(),
+            tooltip: $self.tooltip.clone(),
+            inline: $self.inline.clone(),
+            confirm: $self.confirm,
+            constraints: $constraints,
+            default: $default,
+            value: $value,
+        }
+    };
+}
+
 mod legacy;
 
 use pine_builtin_macro::BuiltinFunction;
 use pine_core::PineVersion;
-use pine_core::{Color, Input, InputOutput, InputValue, PineOutput};
+use pine_core::{Color, Input, InputConstraints, InputOutput, InputValue, PineOutput};
 use pine_interpreter::{Builtin, BuiltinFn, Interpreter, RuntimeError, Value};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-/// The effective numeric value: a host override for `title`, clamped to
-/// `[minval, maxval]`, if present and numeric; otherwise `default`.
-fn num_input<O: PineOutput>(
+/// The bar's own series an `input.source` override may name.
+const SOURCES: [&str; 9] = [
+    "open", "high", "low", "close", "volume", "hl2", "hlc3", "hlcc4", "ohlc4",
+];
+
+/// The host override for `title`, as an input of `kind` takes it, or `None`
+/// when the host set none. An override the input cannot take is an error
+/// rather than quietly replaced by the default.
+fn checked_override<O: PineOutput>(
     ctx: &Interpreter<O>,
     title: &str,
-    default: f64,
-    minval: Option<f64>,
-    maxval: Option<f64>,
-) -> f64 {
-    let mut value = match ctx.input(title) {
-        Some(InputValue::Int(n)) => *n as f64,
-        Some(InputValue::Float(f)) => *f,
-        _ => return default,
+    kind: &str,
+    constraints: &InputConstraints,
+) -> Result<Option<InputValue>, RuntimeError> {
+    let Some(value) = ctx.input(title) else {
+        return Ok(None);
     };
-    if let Some(lo) = minval {
-        value = value.max(lo);
+    constraints
+        .check(kind, value)
+        .map(Some)
+        .map_err(|reason| RuntimeError::InvalidInput {
+            title: title.to_string(),
+            reason,
+        })
+}
+
+/// The effective integer value of an `int`-like input.
+fn int_input<O: PineOutput>(
+    ctx: &Interpreter<O>,
+    title: &str,
+    default: i64,
+    constraints: &InputConstraints,
+) -> Result<i64, RuntimeError> {
+    match checked_override(ctx, title, "int", constraints)? {
+        Some(InputValue::Int(n)) => Ok(n),
+        _ => Ok(default),
     }
-    if let Some(hi) = maxval {
-        value = value.min(hi);
+}
+
+/// The effective value of a `float`-like input.
+fn float_input<O: PineOutput>(
+    ctx: &Interpreter<O>,
+    title: &str,
+    default: f64,
+    constraints: &InputConstraints,
+) -> Result<f64, RuntimeError> {
+    match checked_override(ctx, title, "float", constraints)? {
+        Some(InputValue::Float(x)) => Ok(x),
+        _ => Ok(default),
     }
-    value
 }
 
-/// The effective boolean value: a boolean override for `title`, else `default`.
-fn bool_input<O: PineOutput>(ctx: &Interpreter<O>, title: &str, default: bool) -> bool {
-    match ctx.input(title) {
-        Some(InputValue::Bool(b)) => *b,
-        _ => default,
+/// The effective value of a `bool` input.
+fn bool_input<O: PineOutput>(
+    ctx: &Interpreter<O>,
+    title: &str,
+    default: bool,
+) -> Result<bool, RuntimeError> {
+    match checked_override(ctx, title, "bool", &InputConstraints::default())? {
+        Some(InputValue::Bool(b)) => Ok(b),
+        _ => Ok(default),
     }
 }
 
-/// The effective string value: a string override for `title` if present and, when
-/// `options` is a list, one of its members; otherwise `default`.
+/// The effective value of a string-valued input.
 fn string_input<O: PineOutput>(
     ctx: &Interpreter<O>,
     title: &str,
     default: &str,
-    options: &Value<O>,
-) -> String {
-    let InputValue::Str(value) = (match ctx.input(title) {
-        Some(v) => v,
-        None => return default.to_string(),
-    }) else {
-        return default.to_string();
-    };
-    if let Value::Array(list) = options {
-        let allowed = list
-            .borrow()
+    constraints: &InputConstraints,
+) -> Result<String, RuntimeError> {
+    match checked_override(ctx, title, "string", constraints)? {
+        Some(InputValue::Str(s)) => Ok(s),
+        _ => Ok(default.to_string()),
+    }
+}
+
+/// The effective value of a `color` input.
+fn color_input<O: PineOutput>(
+    ctx: &Interpreter<O>,
+    title: &str,
+    default: &Color,
+) -> Result<Color, RuntimeError> {
+    match checked_override(ctx, title, "color", &InputConstraints::default())? {
+        Some(InputValue::Color(color)) => Ok(color),
+        _ => Ok(default.clone()),
+    }
+}
+
+/// The constraints of a `source` input: an override names one of the bar's
+/// own series.
+fn source_constraints() -> InputConstraints {
+    InputConstraints {
+        options: SOURCES
             .iter()
-            .any(|o| matches!(o, Value::String(s) if s == value));
-        if !allowed {
-            return default.to_string();
-        }
+            .map(|source| InputValue::Str(source.to_string()))
+            .collect(),
+        ..InputConstraints::default()
+    }
+}
+
+/// The name a `source` input's value goes by: a series' id (e.g. `close`).
+fn source_id<O: PineOutput>(source: &Value<O>) -> String {
+    match source {
+        Value::Series(series) => series.id.clone(),
+        _ => "source".to_string(),
+    }
+}
+
+/// The effective series of a `source` input whose default is `default`.
+fn source_input<O: PineOutput>(
+    ctx: &Interpreter<O>,
+    title: &str,
+    default: &Value<O>,
+) -> Result<Value<O>, RuntimeError> {
+    match checked_override(ctx, title, "source", &source_constraints())? {
+        Some(InputValue::Str(id)) => Ok(ctx.get_variable(&id).cloned().unwrap_or(Value::Na)),
+        _ => Ok(default.clone()),
     }
-    value.clone()
+}
+
+/// An `options` argument as input values, numbers typed for an input of
+/// `kind`. Empty when no list was given.
+fn options_of<O: PineOutput>(options: &Value<O>, kind: &str) -> Vec<InputValue> {
+    let Value::Array(list) = options else {
+        return Vec::new();
+    };
+    list.borrow()
+        .iter()
+        .filter_map(|option| match option {
+            Value::Number(n) if kind == "int" => Some(InputValue::Int(*n as i64)),
+            Value::Int(n) if kind == "int" => Some(InputValue::Int(*n)),
+            Value::Number(n) => Some(InputValue::Float(*n)),
+            Value::Int(n) => Some(InputValue::Float(*n as f64)),
+            Value::Bool(b) => Some(InputValue::Bool(*b)),
+            Value::String(s) => Some(InputValue::Str(s.clone())),
+            _ => None,
+        })
+        .collect()
 }
 
 /// Every name the `input` namespace contributes, chosen by version: the v5/v6
@@ -87,11 +191,13 @@ pub fn register<O: PineOutput + InputOutput>(version: PineVersion) -> Vec<(Strin
     }
 }
 
-/// input.int(defval, title, minval, maxval, step, group, tooltip, options)
+/// input.int(defval, title, minval, maxval, step, group, tooltip, options, inline, confirm)
 #[derive(BuiltinFunction)]
 #[builtin(name = "input.int")]
 struct InputInt<O: PineOutput + InputOutput> {
+    #[arg(const)]
     defval: f64,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     #[arg(default = None)]
@@ -106,28 +212,40 @@ struct InputInt<O: PineOutput + InputOutput> {
     tooltip: String,
     #[arg(default = Value::Na)]
     options: Value<O>,
+    #[arg(default = "")]
+    inline: String,
+    #[arg(default = false)]
+    confirm: bool,
 }
 
 impl<O: PineOutput + InputOutput> InputInt<O> {
     fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = (self.step, &self.tooltip, &self.options);
-        let value = num_input(ctx, &self.title, self.defval, self.minval, self.maxval).trunc();
-        ctx.output.add_input(Input {
-            kind: "int".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Int(self.defval as i64),
-            value: InputValue::Int(value as i64),
-        });
-        Ok(Value::Number(value))
+        let constraints = InputConstraints {
+            minval: self.minval,
+            maxval: self.maxval,
+            step: self.step,
+            options: options_of(&self.options, "int"),
+        };
+        let default = self.defval as i64;
+        let value = int_input(ctx, &self.title, default, &constraints)?;
+        ctx.output.add_input(declared!(
+            self,
+            "int",
+            constraints,
+            InputValue::Int(default),
+            InputValue::Int(value)
+        ));
+        Ok(Value::Number(value as f64))
     }
 }
 
-/// input.float(defval, title, minval, maxval, step, group, tooltip, options)
+/// input.float(defval, title, minval, maxval, step, group, tooltip, options, inline, confirm)
 #[derive(BuiltinFunction)]
 #[builtin(name = "input.float")]
 struct InputFloat<O: PineOutput + InputOutput> {
+    #[arg(const)]
     defval: f64,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     #[arg(default = None)]
@@ -142,34 +260,49 @@ struct InputFloat<O: PineOutput + InputOutput> {
     tooltip: String,
     #[arg(default = Value::Na)]
     options: Value<O>,
+    #[arg(default = "")]
+    inline: String,
+    #[arg(default = false)]
+    confirm: bool,
 }
 
 impl<O: PineOutput + InputOutput> InputFloat<O> {
     fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = (self.step, &self.tooltip, &self.options);
-        let value = num_input(ctx, &self.title, self.defval, self.minval, self.maxval);
-        ctx.output.add_input(Input {
-            kind: "float".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Float(self.defval),
-            value: InputValue::Float(value),
-        });
+        let constraints = InputConstraints {
+            minval: self.minval,
+            maxval: self.maxval,
+            step: self.step,
+            options: options_of(&self.options, "float"),
+        };
+        let value = float_input(ctx, &self.title, self.defval, &constraints)?;
+        ctx.output.add_input(declared!(
+            self,
+            "float",
+            constraints,
+            InputValue::Float(self.defval),
+            InputValue::Float(value)
+        ));
         Ok(Value::Number(value))
     }
 }
 
-/// input.bool(defval, title, group, tooltip)
+/// input.bool(defval, title, group, tooltip, inline, confirm)
 #[derive(BuiltinFunction)]
 #[builtin(name = "input.bool", output = InputOutput)]
 struct InputBool {
+    #[arg(const)]
     defval: bool,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     #[arg(default = "")]
     group: String,
     #[arg(default = "")]
     tooltip: String,
+    #[arg(default = "")]
+    inline: String,
+    #[arg(default = false)]
+    confirm: bool,
 }
 
 impl InputBool {
@@ -177,90 +310,82 @@ impl InputBool {
         &self,
         ctx: &mut Interpreter<O>,
     ) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.tooltip;
-        let value = bool_input(ctx, &self.title, self.defval);
-        ctx.output.add_input(Input {
-            kind: "bool".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Bool(self.defval),
-            value: InputValue::Bool(value),
-        });
+        let value = bool_input(ctx, &self.title, self.defval)?;
+        ctx.output.add_input(declared!(
+            self,
+            "bool",
+            InputConstraints::default(),
+            InputValue::Bool(self.defval),
+            InputValue::Bool(value)
+        ));
         Ok(Value::Bool(value))
     }
 }
 
-/// input.string(defval, title, group, tooltip, options)
-#[derive(BuiltinFunction)]
-#[builtin(name = "input.string")]
-struct InputString<O: PineOutput + InputOutput> {
-    defval: String,
-    #[arg(default = "")]
-    title: String,
-    #[arg(default = "")]
-    group: String,
-    #[arg(default = "")]
-    tooltip: String,
-    #[arg(default = Value::Na)]
-    options: Value<O>,
-}
-
-impl<O: PineOutput + InputOutput> InputString<O> {
-    fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.tooltip;
-        let value = string_input(ctx, &self.title, &self.defval, &self.options);
-        ctx.output.add_input(Input {
-            kind: "string".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Str(self.defval.clone()),
-            value: InputValue::Str(value.clone()),
-        });
-        Ok(Value::String(value))
-    }
-}
+/// Defines a string-valued input with an `options` list (`input.string`/
+/// `input.session`) that records a `$kind` widget.
+macro_rules! input_with_options {
+    ($ident:ident, $name:literal, $kind:literal) => {
+        #[derive(BuiltinFunction)]
+        #[builtin(name = $name)]
+        struct $ident<O: PineOutput + InputOutput> {
+            #[arg(const)]
+            defval: String,
+            #[arg(const)]
+            #[arg(default = "")]
+            title: String,
+            #[arg(default = "")]
+            group: String,
+            #[arg(default = "")]
+            tooltip: String,
+            #[arg(default = Value::Na)]
+            options: Value<O>,
+            #[arg(default = "")]
+            inline: String,
+            #[arg(default = false)]
+            confirm: bool,
+        }
 
-/// input.session(defval, title, group, tooltip, options)
-#[derive(BuiltinFunction)]
-#[builtin(name = "input.session")]
-struct InputSession<O: PineOutput + InputOutput> {
-    defval: String,
-    #[arg(default = "")]
-    title: String,
-    #[arg(default = "")]
-    group: String,
-    #[arg(default = "")]
-    tooltip: String,
-    #[arg(default = Value::Na)]
-    options: Value<O>,
+        impl<O: PineOutput + InputOutput> $ident<O> {
+            fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
+                let constraints = InputConstraints {
+                    options: options_of(&self.options, $kind),
+                    ..InputConstraints::default()
+                };
+                let value = string_input(ctx, &self.title, &self.defval, &constraints)?;
+                ctx.output.add_input(declared!(
+                    self,
+                    $kind,
+                    constraints,
+                    InputValue::Str(self.defval.clone()),
+                    InputValue::Str(value.clone())
+                ));
+                Ok(Value::String(value))
+            }
+        }
+    };
 }
 
-impl<O: PineOutput + InputOutput> InputSession<O> {
-    fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.tooltip;
-        let value = string_input(ctx, &self.title, &self.defval, &self.options);
-        ctx.output.add_input(Input {
-            kind: "session".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Str(self.defval.clone()),
-            value: InputValue::Str(value.clone()),
-        });
-        Ok(Value::String(value))
-    }
-}
+input_with_options!(InputString, "input.string", "string");
+input_with_options!(InputSession, "input.session", "session");
 
-/// input.color(defval, title, group, tooltip)
+/// input.color(defval, title, group, tooltip, inline, confirm)
 #[derive(BuiltinFunction)]
 #[builtin(name = "input.color", output = InputOutput)]
 struct InputColor {
+    #[arg(const)]
     defval: Color,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     #[arg(default = "")]
     group: String,
     #[arg(default = "")]
     tooltip: String,
+    #[arg(default = "")]
+    inline: String,
+    #[arg(default = false)]
+    confirm: bool,
 }
 
 impl InputColor {
@@ -268,29 +393,35 @@ impl InputColor {
         &self,
         ctx: &mut Interpreter<O>,
     ) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.tooltip;
-        ctx.output.add_input(Input {
-            kind: "color".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Color(self.defval.clone()),
-            value: InputValue::Color(self.defval.clone()),
-        });
-        Ok(Value::Color(self.defval.clone()))
+        let value = color_input(ctx, &self.title, &self.defval)?;
+        ctx.output.add_input(declared!(
+            self,
+            "color",
+            InputConstraints::default(),
+            InputValue::Color(self.defval.clone()),
+            InputValue::Color(value.clone())
+        ));
+        Ok(Value::Color(value))
     }
 }
 
-/// input.time(defval, title, group, tooltip)
+/// input.time(defval, title, group, tooltip, inline, confirm)
 #[derive(BuiltinFunction)]
 #[builtin(name = "input.time", output = InputOutput)]
 struct InputTime {
+    #[arg(const)]
     defval: f64,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     #[arg(default = "")]
     group: String,
     #[arg(default = "")]
     tooltip: String,
+    #[arg(default = "")]
+    inline: String,
+    #[arg(default = false)]
+    confirm: bool,
 }
 
 impl InputTime {
@@ -298,56 +429,58 @@ impl InputTime {
         &self,
         ctx: &mut Interpreter<O>,
     ) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.tooltip;
-        let value = num_input(ctx, &self.title, self.defval, None, None);
-        ctx.output.add_input(Input {
-            kind: "time".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Int(self.defval as i64),
-            value: InputValue::Int(value as i64),
-        });
-        Ok(Value::Number(value))
+        let default = self.defval as i64;
+        let value = int_input(ctx, &self.title, default, &InputConstraints::default())?;
+        ctx.output.add_input(declared!(
+            self,
+            "time",
+            InputConstraints::default(),
+            InputValue::Int(default),
+            InputValue::Int(value)
+        ));
+        Ok(Value::Number(value as f64))
     }
 }
 
-/// input.source(defval, title, group, tooltip)
+/// input.source(defval, title, group, tooltip, inline, confirm)
 #[derive(BuiltinFunction)]
 #[builtin(name = "input.source")]
 struct InputSource<O: PineOutput + InputOutput> {
     defval: Value<O>,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     #[arg(default = "")]
     group: String,
     #[arg(default = "")]
     tooltip: String,
+    #[arg(default = "")]
+    inline: String,
+    #[arg(default = false)]
+    confirm: bool,
 }
 
 impl<O: PineOutput + InputOutput> InputSource<O> {
     fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.tooltip;
-        // The default is a series (e.g. `close`); record its id for reference.
-        let series_id = match &self.defval {
-            Value::Series(series) => series.id.clone(),
-            _ => "source".to_string(),
-        };
-        ctx.output.add_input(Input {
-            kind: "source".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Str(series_id.clone()),
-            value: InputValue::Str(series_id),
-        });
-        Ok(self.defval.clone())
+        let value = source_input(ctx, &self.title, &self.defval)?;
+        ctx.output.add_input(declared!(
+            self,
+            "source",
+            source_constraints(),
+            InputValue::Str(source_id(&self.defval)),
+            InputValue::Str(source_id(&value))
+        ));
+        Ok(value)
     }
 }
 
-/// input.price(defval, ...) - A price input; returns its default.
+/// input.price(defval, title, group, tooltip, options, inline, confirm)
 #[derive(BuiltinFunction)]
 #[builtin(name = "input.price")]
 struct InputPrice<O: PineOutput + InputOutput> {
+    #[arg(const)]
     defval: f64,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     #[arg(default = "")]
@@ -356,37 +489,50 @@ struct InputPrice<O: PineOutput + InputOutput> {
     tooltip: String,
     #[arg(default = Value::Na)]
     options: Value<O>,
+    #[arg(default = "")]
+    inline: String,
+    #[arg(default = false)]
+    confirm: bool,
 }
 
 impl<O: PineOutput + InputOutput> InputPrice<O> {
     fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = (&self.tooltip, &self.options);
-        let value = num_input(ctx, &self.title, self.defval, None, None);
-        ctx.output.add_input(Input {
-            kind: "price".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Float(self.defval),
-            value: InputValue::Float(value),
-        });
+        let constraints = InputConstraints {
+            options: options_of(&self.options, "price"),
+            ..InputConstraints::default()
+        };
+        let value = float_input(ctx, &self.title, self.defval, &constraints)?;
+        ctx.output.add_input(declared!(
+            self,
+            "price",
+            constraints,
+            InputValue::Float(self.defval),
+            InputValue::Float(value)
+        ));
         Ok(Value::Number(value))
     }
 }
 
 /// Defines a string-valued input (`input.symbol`/`input.timeframe`/
-/// `input.text_area`) that records a `$kind` widget and returns its default.
+/// `input.text_area`) that records a `$kind` widget.
 macro_rules! input_string_like {
     ($ident:ident, $name:literal, $kind:literal) => {
         #[derive(BuiltinFunction)]
         #[builtin(name = $name, output = InputOutput)]
         struct $ident {
+            #[arg(const)]
             defval: String,
+            #[arg(const)]
             #[arg(default = "")]
             title: String,
             #[arg(default = "")]
             group: String,
             #[arg(default = "")]
             tooltip: String,
+            #[arg(default = "")]
+            inline: String,
+            #[arg(default = false)]
+            confirm: bool,
         }
 
         impl $ident {
@@ -394,15 +540,15 @@ macro_rules! input_string_like {
                 &self,
                 ctx: &mut Interpreter<O>,
             ) -> Result<Value<O>, RuntimeError> {
-                let _ = &self.tooltip;
-                let value = string_input(ctx, &self.title, &self.defval, &Value::Na);
-                ctx.output.add_input(Input {
-                    kind: $kind.to_string(),
-                    title: self.title.clone(),
-                    group: self.group.clone(),
-                    default: InputValue::Str(self.defval.clone()),
-                    value: InputValue::Str(value.clone()),
-                });
+                let constraints = InputConstraints::default();
+                let value = string_input(ctx, &self.title, &self.defval, &constraints)?;
+                ctx.output.add_input(declared!(
+                    self,
+                    $kind,
+                    constraints,
+                    InputValue::Str(self.defval.clone()),
+                    InputValue::Str(value.clone())
+                ));
                 Ok(Value::String(value))
             }
         }
@@ -413,34 +559,74 @@ input_string_like!(InputSymbol, "input.symbol", "symbol");
 input_string_like!(InputTimeframe, "input.timeframe", "timeframe");
 input_string_like!(InputTextArea, "input.text_area", "text_area");
 
-/// input.enum(defval, ...) - An enum input; returns its default member.
+/// input.enum(defval, title, group, tooltip, inline, confirm) - An enum input;
+/// returns its default member, or the one an override names.
 #[derive(BuiltinFunction)]
 #[builtin(name = "input.enum")]
 struct InputEnum<O: PineOutput + InputOutput> {
+    #[arg(const)]
     defval: Value<O>,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     #[arg(default = "")]
     group: String,
     #[arg(default = "")]
     tooltip: String,
+    #[arg(default = "")]
+    inline: String,
+    #[arg(default = false)]
+    confirm: bool,
 }
 
 impl<O: PineOutput + InputOutput> InputEnum<O> {
     fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.tooltip;
-        let member = match &self.defval {
-            Value::Enum { field_name, .. } => field_name.clone(),
-            _ => String::new(),
+        let (enum_name, member) = match &self.defval {
+            Value::Enum {
+                enum_name,
+                field_name,
+                ..
+            } => (enum_name.clone(), field_name.clone()),
+            _ => (String::new(), String::new()),
+        };
+        let members = ctx
+            .enum_members(&enum_name)
+            .map(<[_]>::to_vec)
+            .unwrap_or_default();
+        let constraints = InputConstraints {
+            options: members
+                .iter()
+                .map(|(name, _)| InputValue::Str(name.clone()))
+                .collect(),
+            ..InputConstraints::default()
+        };
+        let (effective, value) = match checked_override(ctx, &self.title, "enum", &constraints)? {
+            Some(InputValue::Str(name)) => {
+                // Without the declaration at hand there is nothing to check
+                // the name against, nor a member to return.
+                let Some((_, title)) = members.iter().find(|(member, _)| *member == name) else {
+                    return Err(RuntimeError::InvalidInput {
+                        title: self.title.clone(),
+                        reason: format!("{name:?} is not a member of {enum_name}"),
+                    });
+                };
+                let value = Value::Enum {
+                    enum_name,
+                    field_name: name.clone(),
+                    title: title.clone(),
+                };
+                (name, value)
+            }
+            _ => (member.clone(), self.defval.clone()),
         };
-        ctx.output.add_input(Input {
-            kind: "enum".to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default: InputValue::Str(member.clone()),
-            value: InputValue::Str(member),
-        });
-        Ok(self.defval.clone())
+        ctx.output.add_input(declared!(
+            self,
+            "enum",
+            constraints,
+            InputValue::Str(member),
+            InputValue::Str(effective)
+        ));
+        Ok(value)
     }
 }
 
@@ -450,21 +636,27 @@ impl<O: PineOutput + InputOutput> InputEnum<O> {
 #[builtin(name = "input")]
 struct InputAuto<O: PineOutput + InputOutput> {
     defval: Value<O>,
+    #[arg(const)]
     #[arg(default = "")]
     title: String,
     #[arg(default = "")]
     group: String,
     #[arg(default = "")]
     tooltip: String,
+    #[arg(default = "")]
+    inline: String,
+    #[arg(default = false)]
+    confirm: bool,
 }
 
 impl<O: PineOutput + InputOutput> InputAuto<O> {
     fn execute(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.tooltip;
-        // The type is inferred from the default, and the override coerced to it.
+        // The type is inferred from the default, and the override checked
+        // against it.
+        let unconstrained = InputConstraints::default();
         let (kind, default, effective, effective_value) = match &self.defval {
             Value::Bool(b) => {
-                let v = bool_input(ctx, &self.title, *b);
+                let v = bool_input(ctx, &self.title, *b)?;
                 (
                     "bool",
                     InputValue::Bool(*b),
@@ -473,16 +665,16 @@ impl<O: PineOutput + InputOutput> InputAuto<O> {
                 )
             }
             Value::Int(n) => {
-                let v = num_input(ctx, &self.title, *n as f64, None, None).trunc();
+                let v = int_input(ctx, &self.title, *n, &unconstrained)?;
                 (
                     "int",
                     InputValue::Int(*n),
-                    Value::Number(v),
-                    InputValue::Int(v as i64),
+                    Value::Number(v as f64),
+                    InputValue::Int(v),
                 )
             }
             Value::Number(n) => {
-                let v = num_input(ctx, &self.title, *n, None, None);
+                let v = float_input(ctx, &self.title, *n, &unconstrained)?;
                 (
                     "float",
                     InputValue::Float(*n),
@@ -491,7 +683,7 @@ impl<O: PineOutput + InputOutput> InputAuto<O> {
                 )
             }
             Value::String(s) => {
-                let v = string_input(ctx, &self.title, s, &Value::Na);
+                let v = string_input(ctx, &self.title, s, &unconstrained)?;
                 (
                     "string",
                     InputValue::Str(s.clone()),
@@ -499,26 +691,32 @@ impl<O: PineOutput + InputOutput> InputAuto<O> {
                     InputValue::Str(v),
                 )
             }
-            Value::Color(c) => (
-                "color",
-                InputValue::Color(c.clone()),
-                Value::Color(c.clone()),
-                InputValue::Color(c.clone()),
-            ),
-            other => (
-                "source",
-                InputValue::Str(String::new()),
-                other.clone(),
-                InputValue::Str(String::new()),
-            ),
+            Value::Color(c) => {
+                let v = color_input(ctx, &self.title, c)?;
+                (
+                    "color",
+                    InputValue::Color(c.clone()),
+                    Value::Color(v.clone()),
+                    InputValue::Color(v),
+                )
+            }
+            other => {
+                let v = source_input(ctx, &self.title, other)?;
+                (
+                    "source",
+                    InputValue::Str(source_id(other)),
+                    v.clone(),
+                    InputValue::Str(source_id(&v)),
+                )
+            }
+        };
+        let constraints = if kind == "source" {
+            source_constraints()
+        } else {
+            unconstrained
         };
-        ctx.output.add_input(Input {
-            kind: kind.to_string(),
-            title: self.title.clone(),
-            group: self.group.clone(),
-            default,
-            value: effective_value,
-        });
+        ctx.output
+            .add_input(declared!(self, kind, constraints, default, effective_value));
         Ok(effective)
     }
 }
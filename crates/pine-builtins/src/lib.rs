@@ -14,6 +14,7 @@ pub use pine_core::DefaultPineOutput;
 pub use pine_core::LogLevel;
 pub use pine_interpreter::BuiltinFn;
 pub use pine_interpreter::EvaluatedArg;
+pub use strategy::account_rate;
 
 // Namespace modules
 mod alertcondition;
@@ -198,6 +199,21 @@ fn callable_namespace<O: PineOutput>(namespace: Value<O>) -> Value<O> {
     }
 }
 
+/// A string field of a namespace object, e.g. `syminfo.currency`.
+pub fn object_string<O: PineOutput>(
+    ctx: &Interpreter<O>,
+    object: &str,
+    field: &str,
+) -> Option<String> {
+    match ctx.get_variable(object) {
+        Some(Value::Object { fields, .. }) => match fields.borrow().get(field) {
+            Some(Value::String(s)) => Some(s.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Register all builtin namespaces as objects and global functions
 /// Returns namespace objects to be loaded as variables (e.g., "array", "str", "ta")
 /// and global builtin functions (e.g., "na")
@@ -232,10 +248,9 @@ pub fn register_namespace_objects<
 
     // `syminfo` and `timeframe` are always present in Pine, so an absent one
     // falls back to defaults.
-    namespaces.insert(
-        "syminfo".to_string(),
-        syminfo::create_syminfo(syminfo.unwrap_or_default()),
-    );
+    let syminfo = syminfo.unwrap_or_default();
+    let (session_ns, session_advance) = session::register(&syminfo);
+    namespaces.insert("syminfo".to_string(), syminfo::create_syminfo(syminfo));
     namespaces.insert(
         "timeframe".to_string(),
         timeframe::register(timeframe.unwrap_or_default()),
@@ -247,7 +262,8 @@ pub fn register_namespace_objects<
     namespaces.insert("chart".to_string(), chart::register());
     namespaces.insert("color".to_string(), callable_namespace(color::register()));
     namespaces.insert("map".to_string(), map::register());
-    namespaces.insert("session".to_string(), session::register());
+    namespaces.insert("session".to_string(), session_ns);
+    advances.push(session_advance);
     namespaces.insert("runtime".to_string(), runtime::register());
     namespaces.insert("alert".to_string(), alertcondition::register_alert());
     namespaces.insert("ticker".to_string(), ticker::register());
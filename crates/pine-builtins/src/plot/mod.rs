@@ -67,8 +67,35 @@ impl<O: PineOutput + PlotOutput> Plot<O> {
             linestyle: self.linestyle.clone(),
         };
 
+        // The handle `fill` takes: which of the bar's plots this is.
+        let index = ctx.output.plots().len();
         ctx.output.add_plot(plot);
-        Ok(Value::Na)
+        Ok(plot_handle(index))
+    }
+}
+
+/// A `plot(...)` call's return value: a `plot` object holding the plot's index
+/// among the bar's plots, so `fill` can tell it from an `hline` id.
+fn plot_handle<O: PineOutput>(index: usize) -> Value<O> {
+    let fields = HashMap::from([("index".to_string(), Value::Int(index as i64))]);
+    Value::Object {
+        type_name: "plot".to_string(),
+        fields: Rc::new(RefCell::new(fields)),
+        call: None,
+        value: None,
+    }
+}
+
+/// The plot index a [`plot_handle`] holds, or `None` for anything else.
+pub fn plot_index<O: PineOutput>(value: &Value<O>) -> Option<usize> {
+    match value {
+        Value::Object {
+            type_name, fields, ..
+        } if type_name == "plot" => match fields.borrow().get("index") {
+            Some(Value::Int(index)) => Some(*index as usize),
+            _ => None,
+        },
+        _ => None,
     }
 }
 
@@ -4,8 +4,10 @@
 //! The expression is a lazy argument — captured unevaluated — and replayed in a
 //! secondary interpreter over the requested feed's bars, seeded with a snapshot
 //! of the chart's variables so its namespaces and builtins are already in place.
-//! The resulting series is merged back non-repainting: each bar sees the last
-//! *closed* requested bar.
+//! The resulting series is merged back onto the chart per `barmerge`: by
+//! default each bar sees the last *closed* requested bar, `lookahead_on` lets it
+//! see the requested bar it falls inside, and `gaps_on` shows a value only on
+//! the chart bar it first appears on.
 //!
 //! The feed comes from `ctx.request_provider` and the chart's bar spacing from
 //! `ctx.chart_period`, set by the host — the same way `strategy.*` reaches the
@@ -17,7 +19,7 @@ use std::rc::Rc;
 
 use pine_ast::{Expr, Program, Stmt, VarKind};
 use pine_builtin_macro::BuiltinFunction;
-use pine_core::{Data, PineOutput, Timeframe};
+use pine_core::{intrabar_range, Data, PineOutput, Timeframe};
 use pine_interpreter::{Interpreter, RuntimeError, Series, Value};
 
 /// One requested series, cached per call site so the secondary run happens once.
@@ -151,10 +153,11 @@ impl<O: PineOutput> RequestCurrencyRate<O> {
         if self.from == self.to {
             return Ok(Value::Number(1.0));
         }
+        let now = current_time(ctx);
         Ok(ctx
             .request_provider
             .as_ref()
-            .and_then(|p| p.currency_rate(&self.from, &self.to))
+            .and_then(|p| p.currency_rate(&self.from, &self.to, now))
             .map_or(Value::Na, Value::Number))
     }
 }
@@ -268,8 +271,9 @@ pub fn register<O: PineOutput>() -> Value<O> {
     }
 }
 
-/// `request.security(symbol, timeframe, expression, …)` — the last confirmed
-/// value of `expression` on the requested feed, merged back non-repainting.
+/// `request.security(symbol, timeframe, expression, …)` — the value of
+/// `expression` on the requested feed, merged back according to `gaps` and
+/// `lookahead` (see [`Merge`]).
 #[derive(BuiltinFunction)]
 #[builtin(name = "request.security", stateful)]
 struct RequestSecurity<O: PineOutput> {
@@ -289,18 +293,15 @@ struct RequestSecurity<O: PineOutput> {
     calc_bars_count: Option<f64>,
     #[state]
     series: Option<SecondarySeries<O>>,
+    /// The requested bar last merged, so `gaps_on` shows each one once.
+    #[state]
+    merged: Option<usize>,
 }
 
 impl<O: PineOutput> RequestSecurity<O> {
     fn execute(&mut self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        // Accepted, but their repainting/currency behaviour is not applied yet.
-        let _ = (
-            &self.gaps,
-            &self.lookahead,
-            &self.ignore_invalid_symbol,
-            &self.currency,
-            &self.calc_bars_count,
-        );
+        // Accepted, but their currency behaviour is not applied yet.
+        let _ = (&self.ignore_invalid_symbol, &self.currency);
         // A tuple expression (`[high, low]`) always resolves to a tuple, so a
         // `[h, l] = request.security(...)` destructuring works even when the
         // feed is unavailable — each element is then na.
@@ -311,23 +312,107 @@ impl<O: PineOutput> RequestSecurity<O> {
             return Ok(na_shaped(arity));
         };
         let expr = Rc::clone(expr);
+        let period = timeframe.to_millis();
 
         // Build the requested series once, then reuse it every bar.
         if self.series.is_none() {
-            self.series = Some(Rc::new(request_series(ctx, &self.symbol, timeframe, &expr)));
+            let limit = bars_limit(self.calc_bars_count);
+            self.series = Some(Rc::new(request_series(
+                ctx,
+                &self.symbol,
+                timeframe,
+                &expr,
+                limit,
+            )));
         }
         let series = self.series.as_ref().expect("series built above");
-        Ok(match aligned(series, current_time(ctx)) {
+        let merge = Merge {
+            gaps: barmerge_on(self.gaps.as_ref(), "gaps_on"),
+            lookahead: barmerge_on(self.lookahead.as_ref(), "lookahead_on"),
+            period,
+        };
+        let now = current_time(ctx);
+        let chart_end = now + ctx.chart_period.unwrap_or(0);
+        let Some(index) = merge.index(series, now, chart_end) else {
+            return Ok(na_shaped(arity));
+        };
+        if merge.gaps && self.merged.replace(index) == Some(index) {
+            return Ok(na_shaped(arity));
+        }
+        Ok(match &series[index].1 {
             Value::Na => na_shaped(arity),
-            value => value,
+            value => value.clone(),
         })
     }
 }
 
+/// How a requested series is laid onto chart bars — `request.security`'s
+/// `gaps` and `lookahead` arguments.
+struct Merge {
+    /// `barmerge.gaps_on`: a requested value shows on the first chart bar that
+    /// sees it, and the bars after it read na until the next one arrives.
+    gaps: bool,
+    /// `barmerge.lookahead_on`: a chart bar sees the requested bar it falls
+    /// inside, before that bar has closed. Repaints on history unless the
+    /// expression is offset (`close[1]`).
+    lookahead: bool,
+    /// The requested timeframe in milliseconds; `None` for months, whose
+    /// length varies.
+    period: Option<i64>,
+}
+
+impl Merge {
+    /// The requested bar the chart bar at `now` sees, if any. `chart_end` is
+    /// when that chart bar closes.
+    ///
+    /// Bars are stamped with their open time. A requested bar closes when the
+    /// next one opens, or one `period` after its own open if that is sooner
+    /// (a daily bar closes on Friday, not at Monday's open).
+    fn index<O: PineOutput>(
+        &self,
+        series: &[(i64, Value<O>)],
+        now: i64,
+        chart_end: i64,
+    ) -> Option<usize> {
+        if self.lookahead {
+            return series
+                .partition_point(|(time, _)| *time <= now)
+                .checked_sub(1);
+        }
+        // Every bar opened before the chart bar closes; all but the last of
+        // them have closed too.
+        let opened = series.partition_point(|(time, _)| *time < chart_end);
+        let last = opened.checked_sub(1)?;
+        let next_open = series.get(opened).map(|(time, _)| *time);
+        let end = match (next_open, self.period) {
+            (Some(next), Some(period)) => next.min(series[last].0 + period),
+            (Some(next), None) => next,
+            (None, Some(period)) => series[last].0 + period,
+            (None, None) => i64::MAX,
+        };
+        if end <= chart_end {
+            Some(last)
+        } else {
+            last.checked_sub(1)
+        }
+    }
+}
+
+/// Whether a `barmerge` argument selects `on`, the non-default mode. Legacy
+/// scripts pass a bool instead of the constant.
+fn barmerge_on<O: PineOutput>(arg: Option<&Value<O>>, on: &str) -> bool {
+    match arg {
+        Some(Value::String(mode)) => mode == on,
+        Some(Value::Bool(flag)) => *flag,
+        _ => false,
+    }
+}
+
 /// `request.security_lower_tf(symbol, timeframe, expression, …)` — each bar, the
-/// array of `expression`'s values across the intrabars of the current chart bar.
-/// This engine's chart is already the finest feed, so a same-timeframe request
-/// yields a one-element array; a request coarser than the chart is rejected.
+/// array of `expression`'s values across the intrabars of the current chart bar,
+/// drawn from the provider's feed at the finer `timeframe`. A same-timeframe
+/// request yields a one-element array; a request coarser than the chart is
+/// rejected.
 #[derive(BuiltinFunction)]
 #[builtin(name = "request.security_lower_tf", stateful)]
 struct RequestSecurityLowerTf<O: PineOutput> {
@@ -347,11 +432,7 @@ struct RequestSecurityLowerTf<O: PineOutput> {
 
 impl<O: PineOutput> RequestSecurityLowerTf<O> {
     fn execute(&mut self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = (
-            &self.ignore_invalid_symbol,
-            &self.currency,
-            &self.calc_bars_count,
-        );
+        let _ = (&self.ignore_invalid_symbol, &self.currency);
         let (Value::Expr(expr), Ok(tf)) = (&self.expression, self.timeframe.parse::<Timeframe>())
         else {
             return Ok(Value::Na);
@@ -369,50 +450,72 @@ impl<O: PineOutput> RequestSecurityLowerTf<O> {
         }
 
         if self.series.is_none() {
-            self.series = Some(Rc::new(request_series(ctx, &self.symbol, tf, &expr)));
+            let limit = bars_limit(self.calc_bars_count);
+            self.series = Some(Rc::new(request_series(ctx, &self.symbol, tf, &expr, limit)));
         }
         let series = self.series.as_ref().expect("series built above");
 
-        // The intrabars of the current chart bar: those in `[time, time + period)`.
-        let now = current_time(ctx);
-        let end = ctx.chart_period.map_or(i64::MAX, |period| now + period);
-        let values: Vec<Value<O>> = series
+        let range = intrabar_range(
+            series,
+            |(time, _)| *time,
+            current_time(ctx),
+            ctx.next_bar_time,
+            ctx.chart_period,
+        );
+        let values = series[range]
             .iter()
-            .filter(|(time, _)| *time >= now && *time < end)
             .map(|(_, value)| value.clone())
             .collect();
         Ok(Value::Array(Rc::new(RefCell::new(values))))
     }
 }
 
+/// `calc_bars_count` as a bar limit; `None` (every bar) when absent or not
+/// positive.
+fn bars_limit(calc_bars_count: Option<f64>) -> Option<usize> {
+    calc_bars_count
+        .filter(|count| *count >= 1.0)
+        .map(|count| count as usize)
+}
+
 /// Fetch `symbol` at `timeframe` from the host provider and replay `expr` over
-/// its bars, or an empty series when there is no provider or the symbol is
-/// unavailable.
+/// its bars — only the most recent `limit` of them, when given — or an empty
+/// series when there is no provider or the symbol is unavailable.
 fn request_series<O: PineOutput>(
     ctx: &Interpreter<O>,
     symbol: &str,
     timeframe: Timeframe,
     expr: &Expr,
+    limit: Option<usize>,
 ) -> Vec<(i64, Value<O>)> {
     let data = ctx
         .request_provider
         .clone()
         .and_then(|provider| provider.request(symbol, timeframe).ok());
-    data.map_or_else(Vec::new, |data| {
-        secondary_series(&ctx.snapshot(), expr, data)
+    data.map_or_else(Vec::new, |mut data| {
+        if let Some(limit) = limit {
+            let skip = data.bars.len().saturating_sub(limit);
+            data.bars.drain(..skip);
+        }
+        secondary_series(ctx, expr, data)
     })
 }
 
-/// Replay `expr` over `data`'s bars in an interpreter seeded from `base_vars`
-/// (the chart's namespaces and builtins), taking its value at each bar's close.
+/// Replay `expr` over `data`'s bars in an interpreter seeded from a snapshot
+/// of `ctx` (the chart's namespaces and builtins), taking its value at each
+/// bar's close.
 fn secondary_series<O: PineOutput>(
-    base_vars: &HashMap<String, Value<O>>,
+    ctx: &Interpreter<O>,
     expr: &Expr,
     data: Data,
 ) -> Vec<(i64, Value<O>)> {
-    let mut interp = Interpreter::<O>::new();
-    for (name, value) in base_vars {
-        interp.set_variable(name, value.clone());
+    let mut interp = ctx.child();
+    // The chart's own OHLCV values are left out: the first requested bar would
+    // otherwise see them as its `close[1]`.
+    for (name, value) in ctx.snapshot() {
+        if !BAR_SERIES.contains(&name.as_str()) {
+            interp.set_variable(&name, value);
+        }
     }
 
     // A one-statement program that computes the captured expression each bar.
@@ -424,11 +527,12 @@ fn secondary_series<O: PineOutput>(
         var_kind: VarKind::Plain,
         loc: Default::default(),
     }]);
+    let program = interp.compile(&program);
 
     let mut series = Vec::with_capacity(data.bars.len());
     for bar in &data.bars {
         bind_bar(&mut interp, bar);
-        if interp.execute(&program).is_err() {
+        if interp.run(&program).is_err() {
             break;
         }
         // A bare series (`close`) yields the wrapper; take its scalar value.
@@ -442,19 +546,25 @@ fn secondary_series<O: PineOutput>(
     series
 }
 
+/// The per-bar series [`bind_bar`] sets on the secondary run.
+const BAR_SERIES: [&str; 9] = [
+    "open", "high", "low", "close", "volume", "hl2", "hlc3", "hlcc4", "ohlc4",
+];
+
 /// Bind the secondary run's per-bar OHLCV series and `bar_index`/`time`.
 fn bind_bar<O: PineOutput>(interp: &mut Interpreter<O>, bar: &pine_core::Bar) {
-    for (id, value) in [
-        ("open", bar.open),
-        ("high", bar.high),
-        ("low", bar.low),
-        ("close", bar.close),
-        ("volume", bar.volume),
-        ("hl2", (bar.high + bar.low) / 2.0),
-        ("hlc3", (bar.high + bar.low + bar.close) / 3.0),
-        ("hlcc4", (bar.high + bar.low + bar.close * 2.0) / 4.0),
-        ("ohlc4", (bar.open + bar.high + bar.low + bar.close) / 4.0),
-    ] {
+    let values = [
+        bar.open,
+        bar.high,
+        bar.low,
+        bar.close,
+        bar.volume,
+        (bar.high + bar.low) / 2.0,
+        (bar.high + bar.low + bar.close) / 3.0,
+        (bar.high + bar.low + bar.close * 2.0) / 4.0,
+        (bar.open + bar.high + bar.low + bar.close) / 4.0,
+    ];
+    for (id, value) in BAR_SERIES.into_iter().zip(values) {
         interp.advance_series(
             id,
             Value::Series(Series {
@@ -470,17 +580,6 @@ fn bind_bar<O: PineOutput>(interp: &mut Interpreter<O>, bar: &pine_core::Bar) {
     }
 }
 
-/// Non-repainting merge: a requested bar's `time` is when it is confirmed (its
-/// last constituent bar), so the value is the last one confirmed at or before
-/// `now`.
-fn aligned<O: PineOutput>(series: &[(i64, Value<O>)], now: i64) -> Value<O> {
-    let confirmed = series.partition_point(|(time, _)| *time <= now);
-    confirmed
-        .checked_sub(1)
-        .and_then(|i| series.get(i))
-        .map_or(Value::Na, |(_, value)| value.clone())
-}
-
 fn current_time<O: PineOutput>(ctx: &Interpreter<O>) -> i64 {
     ctx.current_time.unwrap_or(0)
 }
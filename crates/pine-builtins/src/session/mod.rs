@@ -1,16 +1,30 @@
 //! The `session.*` namespace: session-type constants and session-state variables.
 //!
-//! Headless there is no intraday session calendar, so the state variables take
-//! sensible defaults — the bar is treated as inside the regular market session.
+//! The state variables are worked out per bar from `syminfo.session` in
+//! `syminfo.timezone`. The chart is taken to be regular-hours: a bar outside
+//! the session is extended-hours, pre- or post-market by which side of the
+//! day's session it falls on. A symbol without a session string trades around
+//! the clock.
 
-use pine_core::PineOutput;
-use pine_interpreter::Value;
-use std::cell::RefCell;
+use pine_core::{PineOutput, Session, SymInfo, Timezone};
+use pine_interpreter::{Interpreter, PerBarAdvance, Value};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 
-/// Register the `session.*` namespace object.
-pub fn register<O: PineOutput>() -> Value<O> {
+const STATE: [&str; 7] = [
+    "ismarket",
+    "ispremarket",
+    "ispostmarket",
+    "isfirstbar",
+    "isfirstbar_regular",
+    "islastbar",
+    "islastbar_regular",
+];
+
+/// Register the `session.*` namespace object, and the advance that refreshes
+/// its state variables each bar.
+pub fn register<O: PineOutput>(syminfo: &SymInfo) -> (Value<O>, PerBarAdvance<O>) {
     let mut fields: HashMap<String, Value<O>> = HashMap::new();
 
     // Session-type constants (arguments to `ticker.new`/`request.security`).
@@ -20,23 +34,138 @@ pub fn register<O: PineOutput>() -> Value<O> {
         Value::String("extended".to_string()),
     );
 
-    // Session-state variables: treat every bar as regular-session, first/last off.
-    fields.insert("ismarket".to_string(), Value::Bool(true));
-    for off in [
-        "ispremarket",
-        "ispostmarket",
-        "isfirstbar",
-        "isfirstbar_regular",
-        "islastbar",
-        "islastbar_regular",
-    ] {
-        fields.insert(off.to_string(), Value::Bool(false));
+    // Before the first bar: in the market, first/last off.
+    for name in STATE {
+        fields.insert(name.to_string(), Value::Bool(name == "ismarket"));
     }
 
-    Value::Object {
+    let fields = Rc::new(RefCell::new(fields));
+    let session =
+        Session::parse(&syminfo.session).unwrap_or_else(|| Session::parse("24x7").unwrap());
+    // The host rejects a symbol whose timezone does not resolve; an unset one
+    // parses as UTC.
+    let tz = Timezone::parse(&syminfo.timezone).unwrap_or(Timezone::UTC);
+    let advance = advance_state(Rc::clone(&fields), session, tz);
+
+    let namespace = Value::Object {
         type_name: "session".to_string(),
-        fields: Rc::new(RefCell::new(fields)),
+        fields,
         call: None,
         value: None,
+    };
+    (namespace, advance)
+}
+
+/// Each bar, recompute the state variables for the bar's time.
+fn advance_state<O: PineOutput>(
+    fields: Rc<RefCell<HashMap<String, Value<O>>>>,
+    session: Session,
+    tz: Timezone,
+) -> PerBarAdvance<O> {
+    // The bar last advanced on, its time, and the time of the bar before it —
+    // kept across a re-run of the same bar (see `Interpreter::rollback`).
+    let bar = Cell::new(0u64);
+    let time = Cell::new(None::<i64>);
+    let previous = Cell::new(None::<i64>);
+    Rc::new(move |ctx: &mut Interpreter<O>| {
+        let Some(now) = ctx.current_time else {
+            return;
+        };
+        let seq = ctx.bar_seq();
+        if bar.replace(seq) != seq {
+            previous.set(time.replace(Some(now)));
+        }
+        let period = ctx.chart_period.unwrap_or(0);
+        let state = state_at(
+            &session,
+            tz.to_local(now),
+            previous.get().map(|t| tz.to_local(t)),
+            period,
+        );
+        let mut fields = fields.borrow_mut();
+        for (name, flag) in STATE.into_iter().zip(state) {
+            fields.insert(name.to_string(), Value::Bool(flag));
+        }
+    })
+}
+
+/// The state variables, in [`STATE`] order, for a bar at wall-clock `local`
+/// lasting `period` ms, after one at `previous`.
+fn state_at(session: &Session, local: i64, previous: Option<i64>, period: i64) -> [bool; 7] {
+    let occurrence = session.occurrence(local);
+    let ismarket = occurrence.is_some();
+    let isfirstbar_regular =
+        occurrence.is_some_and(|(start, _)| previous.is_none_or(|p| p < start));
+    let islastbar_regular = occurrence.is_some_and(|(_, end)| local + period >= end);
+
+    let day = session.trading_day(local);
+    let bounds = day.and_then(|day| session.bounds(day));
+    let ispremarket = !ismarket && bounds.is_some_and(|(open, _)| local < open);
+    let ispostmarket = !ismarket && bounds.is_some_and(|(_, close)| local >= close);
+    let isfirstbar = bounds.is_some() && previous.is_none_or(|p| session.trading_day(p) != day);
+    let islastbar = bounds.is_some_and(|(_, close)| {
+        if ismarket {
+            local + period >= close
+        } else {
+            session.trading_day(local + period) != day
+        }
+    });
+    [
+        ismarket,
+        ispremarket,
+        ispostmarket,
+        isfirstbar,
+        isfirstbar_regular,
+        islastbar,
+        islastbar_regular,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60_000;
+
+    /// Tuesday 2 January 2024 at `h:mi`, wall clock.
+    fn at(h: i64, mi: i64) -> i64 {
+        1_704_153_600_000 + (h * 60 + mi) * MINUTE
+    }
+
+    #[test]
+    fn first_and_last_bars_of_the_regular_session() {
+        let session = Session::parse("0930-1600:23456").unwrap();
+        let state = |local, previous| state_at(&session, local, previous, 30 * MINUTE);
+        // ismarket, ispremarket, ispostmarket, isfirstbar, isfirstbar_regular,
+        // islastbar, islastbar_regular
+        assert_eq!(
+            state(at(9, 0), Some(at(8, 30))),
+            [false, true, false, false, false, false, false]
+        );
+        assert_eq!(
+            state(at(9, 30), Some(at(9, 0))),
+            [true, false, false, false, true, false, false]
+        );
+        assert_eq!(
+            state(at(12, 0), Some(at(11, 30))),
+            [true, false, false, false, false, false, false]
+        );
+        assert_eq!(
+            state(at(15, 30), Some(at(15, 0))),
+            [true, false, false, false, false, true, true]
+        );
+        assert_eq!(
+            state(at(16, 0), Some(at(15, 30))),
+            [false, false, true, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn a_gap_makes_the_next_bar_first() {
+        let session = Session::parse("0930-1600:23456").unwrap();
+        // The previous bar was Monday's last.
+        let monday_close = at(15, 30) - 86_400_000;
+        let state = state_at(&session, at(9, 30), Some(monday_close), 30 * MINUTE);
+        assert!(state[3] && state[4]);
     }
 }
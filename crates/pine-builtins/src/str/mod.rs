@@ -23,8 +23,8 @@ fn java_to_chrono(fmt: &str) -> String {
         .replace('\'', "")
 }
 
-/// str.format_time(time, format, timezone) - Format a UNIX-ms timestamp. The
-/// timezone is ignored (times are formatted in UTC).
+/// str.format_time(time, format, timezone) - Format a UNIX-ms timestamp as a
+/// wall-clock time in `timezone` (default `syminfo.timezone`).
 #[derive(BuiltinFunction)]
 #[builtin(name = "str.format_time")]
 struct StrFormatTime {
@@ -36,11 +36,16 @@ struct StrFormatTime {
 }
 
 impl StrFormatTime {
-    fn execute<O: PineOutput>(&self, _ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.timezone;
-        let Some(dt) = chrono::DateTime::from_timestamp_millis(self.time as i64) else {
+    fn execute<O: PineOutput>(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
+        let tz = crate::time::resolve_timezone(ctx, &self.timezone)?;
+        let ms = self.time as i64;
+        let Some(dt) = chrono::DateTime::from_timestamp_millis(ms) else {
+            return Ok(Value::Na);
+        };
+        let Some(offset) = chrono::FixedOffset::east_opt(tz.offset_at(ms)) else {
             return Ok(Value::Na);
         };
+        let dt = dt.with_timezone(&offset);
         Ok(Value::String(
             dt.format(&java_to_chrono(&self.format)).to_string(),
         ))
@@ -275,10 +280,10 @@ fn render_value<O: PineOutput>(value: &Value<O>) -> String {
         }
         Value::Series(series) => render_value(&series.current),
         Value::Object { type_name, .. } => format!("[Object:{}]", type_name),
-        Value::Function { .. } => "[Function]".to_string(),
+        Value::Function(_) => "[Function]".to_string(),
         Value::BuiltinFunction(_) => "[BuiltinFunction]".to_string(),
         Value::Expr(_) => "[Expr]".to_string(),
-        Value::Type { name, .. } => format!("[Type:{}]", name),
+        Value::Type(ty) => format!("[Type:{}]", ty.name()),
         Value::Enum {
             enum_name,
             field_name,
@@ -12,30 +12,37 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+use crate::object_string;
 use pine_broker::{
     BrokerConfig, Commission, Direction, EntryFilter, Exit, OcaType, Order, OrderKind, RiskRule,
     RiskType, Sizing, Trade,
 };
 use pine_builtin_macro::BuiltinFunction;
-use pine_core::{PineOutput, PineVersion};
+use pine_core::{DrawingLimits, MetadataOutput, PineOutput, PineVersion};
 use pine_interpreter::{Builtin, BuiltinFn, EvaluatedArg, Interpreter, RuntimeError, Value};
 
 /// TradingView's default starting capital.
 const DEFAULT_INITIAL_CAPITAL: f64 = 1_000_000.0;
 
 /// strategy(title, shorttitle, overlay, ..., default_qty_type, default_qty_value,
-/// initial_capital, ..., slippage, commission_type, commission_value, ...)
+/// initial_capital, ..., slippage, commission_type, commission_value,
+/// process_orders_on_close, close_entries_rule, margin_long, margin_short, ...,
+/// use_bar_magnifier, ...)
 ///
-/// Only the parameters that shape the simulated broker are honoured; display
-/// and reporting-only parameters are accepted and ignored. Runs every bar, but
-/// only builds the broker on the first, so state persists across the backtest.
+/// Only the parameters that shape the simulated broker, and the drawing
+/// limits, are honoured; display and reporting-only parameters are accepted
+/// and ignored. Runs every bar, but only builds the broker on the first, so
+/// state persists across the backtest.
 #[derive(BuiltinFunction)]
-#[builtin(name = "strategy")]
+#[builtin(name = "strategy", output = MetadataOutput)]
 struct StrategyFn {
     #[allow(dead_code)]
+    #[arg(const)]
     title: String,
+    #[arg(const)]
     #[arg(default = "")]
     shorttitle: String,
+    #[arg(const)]
     #[arg(default = false)]
     overlay: bool,
     #[arg(default = "")]
@@ -60,22 +67,55 @@ struct StrategyFn {
     commission_type: String,
     #[arg(default = 0.0)]
     commission_value: f64,
+    #[arg(default = false)]
+    use_bar_magnifier: bool,
+    #[arg(default = false)]
+    calc_on_order_fills: bool,
+    #[arg(default = false)]
+    process_orders_on_close: bool,
+    #[arg(default = "FIFO")]
+    close_entries_rule: String,
+    #[arg(default = None)]
+    margin_long: Option<f64>,
+    #[arg(default = None)]
+    margin_short: Option<f64>,
+    #[arg(default = None)]
+    max_lines_count: Option<f64>,
+    #[arg(default = None)]
+    max_labels_count: Option<f64>,
+    #[arg(default = None)]
+    max_boxes_count: Option<f64>,
+    #[arg(default = None)]
+    max_polylines_count: Option<f64>,
 }
 
 impl StrategyFn {
-    fn execute<O: PineOutput>(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
+    fn execute<O: PineOutput + MetadataOutput>(
+        &self,
+        ctx: &mut Interpreter<O>,
+    ) -> Result<Value<O>, RuntimeError> {
         let _ = (
             &self.shorttitle,
             self.overlay,
             &self.format,
             self.precision,
             &self.scale,
-            &self.currency,
         );
+        ctx.output.set_drawing_limits(DrawingLimits {
+            labels: DrawingLimits::count(self.max_labels_count),
+            lines: DrawingLimits::count(self.max_lines_count),
+            boxes: DrawingLimits::count(self.max_boxes_count),
+            polylines: DrawingLimits::count(self.max_polylines_count),
+        });
 
         // Runs every bar; build the broker only once so trades accumulate.
         if ctx.broker.is_none() {
             let initial_capital = self.initial_capital.unwrap_or(DEFAULT_INITIAL_CAPITAL);
+            // `currency.NONE`, the default, keeps the books in the symbol's.
+            let currency = match self.currency.as_str() {
+                "" | "NONE" => object_string(ctx, "syminfo", "currency").unwrap_or_default(),
+                declared => declared.to_string(),
+            };
             let commission =
                 (self.commission_value != 0.0).then_some(match self.commission_type.as_str() {
                     "cash_per_contract" => Commission::CashPerContract(self.commission_value),
@@ -85,17 +125,28 @@ impl StrategyFn {
                 });
             let config = BrokerConfig {
                 initial_capital,
+                currency: currency.clone(),
                 mintick: mintick_of(ctx),
                 sizing: self.sizing(),
                 pyramiding: self.pyramiding.unwrap_or(0.0) as usize,
                 commission,
                 slippage: self.slippage,
+                use_bar_magnifier: self.use_bar_magnifier,
+                process_orders_on_close: self.process_orders_on_close,
+                calc_on_order_fills: self.calc_on_order_fills,
+                close_entries_rule: self.close_entries_rule.as_str().into(),
+                // Unset before v6 (see `declaration`): no margin.
+                margin_long: self.margin_long.unwrap_or(0.0),
+                margin_short: self.margin_short.unwrap_or(0.0),
             };
 
             let factory = ctx.broker_factory.as_ref().ok_or_else(|| {
                 RuntimeError::TypeError("strategy() has no broker configured".to_string())
             })?;
-            ctx.broker = Some(factory.build(&config));
+            let mut broker = factory.build(&config);
+            ctx.set_object_field("strategy", "account_currency", Value::String(currency));
+            broker.set_fx_rate(account_rate(ctx));
+            ctx.broker = Some(broker);
             ctx.set_object_field(
                 "strategy",
                 "initial_capital",
@@ -118,6 +169,23 @@ impl StrategyFn {
     }
 }
 
+/// What one unit of the symbol's currency is worth in the strategy's account
+/// currency, at the request provider's `currency_rate` for the current bar. 1
+/// when the two are the same, or when the provider has no rate for the pair.
+pub fn account_rate<O: PineOutput>(ctx: &Interpreter<O>) -> f64 {
+    let symbol = object_string(ctx, "syminfo", "currency").unwrap_or_default();
+    let account = object_string(ctx, "strategy", "account_currency").unwrap_or_default();
+    if symbol.is_empty() || account.is_empty() || symbol == account {
+        return 1.0;
+    }
+    ctx.request_provider
+        .as_ref()
+        .and_then(|provider| {
+            provider.currency_rate(&symbol, &account, ctx.current_time.unwrap_or(0))
+        })
+        .unwrap_or(1.0)
+}
+
 /// The symbol's tick size from `syminfo.mintick`, or 0 (which disables tick-based
 /// slippage and exit distances) when it is unknown.
 fn mintick_of<O: PineOutput>(ctx: &Interpreter<O>) -> f64 {
@@ -184,6 +252,10 @@ struct StrategyEntry {
     oca_type: String,
     #[arg(default = "")]
     comment: String,
+    #[arg(default = "")]
+    alert_message: String,
+    #[arg(default = false)]
+    disable_alert: bool,
 }
 
 impl StrategyEntry {
@@ -203,6 +275,8 @@ impl StrategyEntry {
                 oca_name: non_empty(&self.oca_name),
                 oca_type: OcaType::from(self.oca_type.as_str()),
                 comment: self.comment.clone(),
+                alert_message: self.alert_message.clone(),
+                disable_alert: self.disable_alert,
             });
         }
         Ok(Value::Na)
@@ -230,6 +304,10 @@ struct StrategyOrder {
     oca_type: String,
     #[arg(default = "")]
     comment: String,
+    #[arg(default = "")]
+    alert_message: String,
+    #[arg(default = false)]
+    disable_alert: bool,
 }
 
 impl StrategyOrder {
@@ -249,6 +327,8 @@ impl StrategyOrder {
                 oca_name: non_empty(&self.oca_name),
                 oca_type: OcaType::from(self.oca_type.as_str()),
                 comment: self.comment.clone(),
+                alert_message: self.alert_message.clone(),
+                disable_alert: self.disable_alert,
             });
         }
         Ok(Value::Na)
@@ -269,10 +349,19 @@ struct StrategyClose {
     qty: Option<f64>,
     #[arg(default = None)]
     qty_percent: Option<f64>,
+    #[arg(default = "")]
+    alert_message: String,
+    #[arg(default = false)]
+    immediately: bool,
+    #[arg(default = false)]
+    disable_alert: bool,
 }
 
 impl StrategyClose {
     fn execute<O: PineOutput>(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
+        // Closing on this bar's close is not modelled; the order waits for
+        // the next fill like any other market order.
+        let _ = self.immediately;
         if let Some(broker) = ctx.broker.as_mut() {
             // Direction is ignored for a reduce-only order — the broker closes
             // against whatever side is open — so Long is just a placeholder. The
@@ -290,13 +379,15 @@ impl StrategyClose {
                 oca_name: None,
                 oca_type: OcaType::None,
                 comment: self.comment.clone(),
+                alert_message: self.alert_message.clone(),
+                disable_alert: self.disable_alert,
             });
         }
         Ok(Value::Na)
     }
 }
 
-/// strategy.close_all(comment, alert_message)
+/// strategy.close_all(comment, alert_message, immediately, disable_alert)
 ///
 /// Flattens the position with a market order.
 #[derive(BuiltinFunction)]
@@ -304,10 +395,19 @@ impl StrategyClose {
 struct StrategyCloseAll {
     #[arg(default = "")]
     comment: String,
+    #[arg(default = "")]
+    alert_message: String,
+    #[arg(default = false)]
+    immediately: bool,
+    #[arg(default = false)]
+    disable_alert: bool,
 }
 
 impl StrategyCloseAll {
     fn execute<O: PineOutput>(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
+        // Closing on this bar's close is not modelled; the order waits for
+        // the next fill like any other market order.
+        let _ = self.immediately;
         if let Some(broker) = ctx.broker.as_mut() {
             broker.submit(Order {
                 id: "Close all".to_string(),
@@ -322,6 +422,8 @@ impl StrategyCloseAll {
                 oca_name: None,
                 oca_type: OcaType::None,
                 comment: self.comment.clone(),
+                alert_message: self.alert_message.clone(),
+                disable_alert: self.disable_alert,
             });
         }
         Ok(Value::Na)
@@ -520,11 +622,20 @@ struct StrategyExit {
     trail_offset: Option<f64>,
     #[arg(default = "")]
     comment: String,
+    #[arg(default = "")]
+    alert_message: String,
+    #[arg(default = "")]
+    alert_profit: String,
+    #[arg(default = "")]
+    alert_loss: String,
+    #[arg(default = "")]
+    alert_trailing: String,
+    #[arg(default = false)]
+    disable_alert: bool,
 }
 
 impl StrategyExit {
     fn execute<O: PineOutput>(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        let _ = &self.comment;
         if let Some(broker) = ctx.broker.as_mut() {
             broker.submit_exit(Exit {
                 limit: self.limit,
@@ -534,6 +645,12 @@ impl StrategyExit {
                 trail_price: self.trail_price,
                 trail_points: self.trail_points,
                 trail_offset: self.trail_offset,
+                comment: self.comment.clone(),
+                alert_message: self.alert_message.clone(),
+                alert_profit: self.alert_profit.clone(),
+                alert_loss: self.alert_loss.clone(),
+                alert_trailing: self.alert_trailing.clone(),
+                disable_alert: self.disable_alert,
                 ..Exit::resting(
                     self.id.clone(),
                     non_empty(&self.from_entry),
@@ -546,9 +663,8 @@ impl StrategyExit {
     }
 }
 
-/// strategy.convert_to_account(value) - Convert a value to the account currency.
-/// The account and symbol currency are the same here (no FX feed), so this is
-/// the identity.
+/// strategy.convert_to_account(value) - Convert a value in the symbol's currency
+/// to the account currency, at the provider's current rate.
 #[derive(BuiltinFunction)]
 #[builtin(name = "strategy.convert_to_account")]
 struct StrategyConvertToAccount {
@@ -556,13 +672,13 @@ struct StrategyConvertToAccount {
 }
 
 impl StrategyConvertToAccount {
-    fn execute<O: PineOutput>(&self, _ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        Ok(Value::Number(self.value))
+    fn execute<O: PineOutput>(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
+        Ok(Value::Number(self.value * account_rate(ctx)))
     }
 }
 
-/// strategy.convert_to_symbol(value) - Convert a value to the symbol currency
-/// (identity without an FX feed).
+/// strategy.convert_to_symbol(value) - Convert a value in the account currency
+/// to the symbol's, at the provider's current rate.
 #[derive(BuiltinFunction)]
 #[builtin(name = "strategy.convert_to_symbol")]
 struct StrategyConvertToSymbol {
@@ -570,8 +686,8 @@ struct StrategyConvertToSymbol {
 }
 
 impl StrategyConvertToSymbol {
-    fn execute<O: PineOutput>(&self, _ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
-        Ok(Value::Number(self.value))
+    fn execute<O: PineOutput>(&self, ctx: &mut Interpreter<O>) -> Result<Value<O>, RuntimeError> {
+        Ok(Value::Number(self.value / account_rate(ctx)))
     }
 }
 
@@ -592,7 +708,7 @@ impl StrategyDefaultEntryQty {
 
 /// A trade's profit as a percentage of the capital it tied up at entry.
 fn profit_percent(trade: &Trade, close: f64) -> f64 {
-    let cost = trade.entry_price * trade.size.abs();
+    let cost = trade.cost();
     if cost == 0.0 {
         0.0
     } else {
@@ -600,6 +716,41 @@ fn profit_percent(trade: &Trade, close: f64) -> f64 {
     }
 }
 
+/// A trade's excursion as a percentage of what it tied up.
+fn excursion_percent(trade: &Trade, excursion: f64) -> f64 {
+    let cost = trade.cost();
+    if cost == 0.0 {
+        0.0
+    } else {
+        excursion / cost * 100.0
+    }
+}
+
+/// The `max_runup`/`max_drawdown` accessors, in money and percent, over the
+/// open or closed log.
+fn insert_excursions<O: PineOutput>(m: &mut HashMap<String, Value<O>>, open: bool) {
+    m.insert(
+        "max_runup".into(),
+        trade_field(open, |t, _| Value::Number(t.max_runup)),
+    );
+    m.insert(
+        "max_runup_percent".into(),
+        trade_field(open, |t, _| {
+            Value::Number(excursion_percent(t, t.max_runup))
+        }),
+    );
+    m.insert(
+        "max_drawdown".into(),
+        trade_field(open, |t, _| Value::Number(t.max_drawdown)),
+    );
+    m.insert(
+        "max_drawdown_percent".into(),
+        trade_field(open, |t, _| {
+            Value::Number(excursion_percent(t, t.max_drawdown))
+        }),
+    );
+}
+
 /// A `strategy.*trades.<field>(trade_num)` accessor: reads one field off trade
 /// `trade_num` of the open or closed log (via the interpreter's broker), or `na`
 /// when the index is out of range. `select` also gets the current close, for the
@@ -641,8 +792,8 @@ fn trade_field<O: PineOutput>(open: bool, select: fn(&Trade, f64) -> Value<O>) -
 }
 
 /// The `strategy.closedtrades` object: bare, the number of closed trades; as a
-/// namespace, per-trade accessors over the closed log. Time/comment/exit-id and
-/// per-trade drawdown/runup are not modelled by the broker, so they are `na`.
+/// namespace, per-trade accessors over the closed log. Times and comments are
+/// not modelled by the broker, so they are `na`.
 fn register_closedtrades<O: PineOutput>() -> Value<O> {
     let mut m: HashMap<String, Value<O>> = HashMap::new();
     m.insert(
@@ -687,17 +838,14 @@ fn register_closedtrades<O: PineOutput>() -> Value<O> {
         "profit_percent".into(),
         trade_field(false, |t, close| Value::Number(profit_percent(t, close))),
     );
-    for name in [
-        "entry_time",
-        "entry_comment",
-        "exit_time",
-        "exit_id",
-        "exit_comment",
-        "max_drawdown",
-        "max_drawdown_percent",
-        "max_runup",
-        "max_runup_percent",
-    ] {
+    m.insert(
+        "exit_id".into(),
+        trade_field(false, |t, _| {
+            t.exit_id.clone().map(Value::String).unwrap_or(Value::Na)
+        }),
+    );
+    insert_excursions(&mut m, false);
+    for name in ["entry_time", "entry_comment", "exit_time", "exit_comment"] {
         m.insert(name.into(), trade_field(false, |_, _| Value::Na));
     }
     // The trade number of the first closed trade in the set.
@@ -749,19 +897,13 @@ fn register_opentrades<O: PineOutput>() -> Value<O> {
         "profit_percent".into(),
         trade_field(true, |t, close| Value::Number(profit_percent(t, close))),
     );
-    // Capital the open trade ties up: entry price times absolute size.
+    // Capital the open trade ties up: its value at entry.
     m.insert(
         "capital_held".into(),
-        trade_field(true, |t, _| Value::Number(t.entry_price * t.size.abs())),
+        trade_field(true, |t, _| Value::Number(t.cost())),
     );
-    for name in [
-        "entry_time",
-        "entry_comment",
-        "max_drawdown",
-        "max_drawdown_percent",
-        "max_runup",
-        "max_runup_percent",
-    ] {
+    insert_excursions(&mut m, true);
+    for name in ["entry_time", "entry_comment"] {
         m.insert(name.into(), trade_field(true, |_, _| Value::Na));
     }
     Value::Object {
@@ -779,10 +921,41 @@ fn register_opentrades<O: PineOutput>() -> Value<O> {
     }
 }
 
+/// The `strategy(...)` declaration as `version` reads it. From v6 a position
+/// is held on 100% margin each side unless the declaration says otherwise;
+/// before, the strategy trades without margin.
+fn declaration<O: PineOutput + MetadataOutput>(version: PineVersion) -> BuiltinFn<O> {
+    if version < PineVersion::V6 {
+        return Rc::new(StrategyFn::builtin_fn);
+    }
+    Rc::new(|ctx, mut call| {
+        let positional = call
+            .args
+            .iter()
+            .filter(|arg| matches!(arg, EvaluatedArg::Positional(_)))
+            .count();
+        for name in ["margin_long", "margin_short"] {
+            let given = StrategyFn::signature()
+                .position(name)
+                .is_some_and(|position| positional > position)
+                || call.args.iter().any(
+                    |arg| matches!(arg, EvaluatedArg::Named { name: given, .. } if given == name),
+                );
+            if !given {
+                call.args.push(EvaluatedArg::Named {
+                    name: name.to_string(),
+                    value: Value::Number(100.0),
+                });
+            }
+        }
+        StrategyFn::builtin_fn(ctx, call)
+    })
+}
+
 /// Build the `strategy` namespace object: the callable declaration, the order
 /// commands, the direction and sizing constants, and the read-only values the
 /// host refreshes each bar (seeded to a flat, zero-profit account).
-pub fn register<O: PineOutput>(_version: PineVersion) -> Value<O> {
+pub fn register<O: PineOutput + MetadataOutput>(version: PineVersion) -> Value<O> {
     let mut fields: HashMap<String, Value<O>> = HashMap::new();
 
     // Order commands.
@@ -965,9 +1138,7 @@ pub fn register<O: PineOutput>(_version: PineVersion) -> Value<O> {
     Value::Object {
         type_name: "strategy".to_string(),
         fields: Rc::new(RefCell::new(fields)),
-        call: Some(Builtin::untyped(
-            Rc::new(StrategyFn::builtin_fn) as BuiltinFn<O>
-        )),
+        call: Some(Builtin::untyped(declaration(version))),
         value: None,
     }
 }
@@ -2,7 +2,7 @@ use pine_builtin_macro::BuiltinFunction;
 use pine_core::{PineOutput, PineVersion, MAX_LOOKBACK};
 use pine_interpreter::{Builtin, Interpreter, PerBarAdvance, RuntimeError, Series, Value};
 use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
 
 mod comparison;
@@ -121,7 +121,7 @@ pub fn register<O: PineOutput>(
             Value::Series(Series {
                 id: format!("ta.{name}"),
                 current: Box::new(Value::Number(seed)),
-                history: Some(Rc::new(RefCell::new(Vec::new()))),
+                history: Some(Rc::new(RefCell::new(VecDeque::new()))),
             }),
         );
     }
@@ -132,7 +132,7 @@ pub fn register<O: PineOutput>(
     let vwap_series = Rc::new(RefCell::new(Value::Series(Series {
         id: "ta.vwap".to_string(),
         current: Box::new(Value::Na),
-        history: Some(Rc::new(RefCell::new(Vec::new()))),
+        history: Some(Rc::new(RefCell::new(VecDeque::new()))),
     })));
     ta_ns.insert("vwap".to_string(), {
         let cell = Rc::clone(&vwap_series);
@@ -174,7 +174,7 @@ fn series_now<O: PineOutput>(ctx: &Interpreter<O>, name: &str) -> Option<f64> {
 }
 
 fn series_prev<O: PineOutput>(ctx: &Interpreter<O>, name: &str) -> Option<f64> {
-    ctx.user_series_history.get(name)?.last()?.as_number().ok()
+    ctx.series_history(name)?.back()?.as_number().ok()
 }
 
 /// The current bar's OHLCV plus the previous bar's `close`/`volume` — everything
@@ -302,10 +302,9 @@ fn step_series<O: PineOutput>(series: &mut Value<O>, push: bool, next: f64) {
         if push {
             if let Some(history) = &s.history {
                 let mut history = history.borrow_mut();
-                history.push((*s.current).clone());
+                history.push_back((*s.current).clone());
                 if history.len() > MAX_LOOKBACK {
-                    let excess = history.len() - MAX_LOOKBACK;
-                    history.drain(..excess);
+                    history.pop_front();
                 }
             }
         }
@@ -325,6 +324,37 @@ fn commit_series<O: PineOutput>(
     }
 }
 
+/// Undo a [`step_series`]: drop what it pushed and put `current` back.
+fn unstep_series<O: PineOutput>(series: &mut Value<O>, pushed: bool, current: &Value<O>) {
+    if let Value::Series(s) = series {
+        if pushed {
+            if let Some(history) = &s.history {
+                history.borrow_mut().pop_back();
+            }
+        }
+        *s.current = current.clone();
+    }
+}
+
+/// The current value of a builtin-owned series, as it stands.
+fn series_value<O: PineOutput>(series: &Value<O>) -> Value<O> {
+    match series {
+        Value::Series(s) => (*s.current).clone(),
+        other => other.clone(),
+    }
+}
+
+/// What the accumulators held when a bar began, kept while the interpreter
+/// may roll that bar back and re-run it on the next tick.
+struct AccumulatorBase<O: PineOutput> {
+    values: Vec<Value<O>>,
+    vwap: Value<O>,
+    cum_pv: f64,
+    cum_v: f64,
+    session: i64,
+    advanced: bool,
+}
+
 /// The trading-day bucket a UNIX-ms timestamp falls in (UTC midnight boundaries).
 /// Session-anchored series (`vwap`, pivot levels) reset when it changes.
 fn day_bucket(millis: i64) -> i64 {
@@ -341,10 +371,46 @@ fn advance_accumulators<O: PineOutput>(
     let cum_pv = Cell::new(0.0f64);
     let cum_v = Cell::new(0.0f64);
     let session = Cell::new(i64::MIN);
+    // The bar last advanced on, and the state it started from when it may be
+    // re-run (see `Interpreter::rollback`).
+    let bar = Cell::new(0u64);
+    let base: RefCell<Option<AccumulatorBase<O>>> = RefCell::new(None);
     Rc::new(move |ctx: &mut Interpreter<O>| {
         let Some(bars) = read_bars(ctx) else {
             return;
         };
+        let seq = ctx.bar_seq();
+        if bar.replace(seq) == seq {
+            // Another tick of the same bar: start over from where it began.
+            if let Some(base) = base.borrow().as_ref() {
+                let mut fields = fields.borrow_mut();
+                for ((name, _, _), value) in ACCUMULATORS.iter().zip(&base.values) {
+                    if let Some(series) = fields.get_mut(*name) {
+                        unstep_series(series, base.advanced, value);
+                    }
+                }
+                unstep_series(&mut vwap_series.borrow_mut(), base.advanced, &base.vwap);
+                cum_pv.set(base.cum_pv);
+                cum_v.set(base.cum_v);
+                session.set(base.session);
+                advanced.set(base.advanced);
+            }
+        } else {
+            *base.borrow_mut() = ctx.is_checkpointed().then(|| {
+                let fields = fields.borrow();
+                AccumulatorBase {
+                    values: ACCUMULATORS
+                        .iter()
+                        .map(|(name, _, _)| fields.get(*name).map_or(Value::Na, series_value))
+                        .collect(),
+                    vwap: series_value(&vwap_series.borrow()),
+                    cum_pv: cum_pv.get(),
+                    cum_v: cum_v.get(),
+                    session: session.get(),
+                    advanced: advanced.get(),
+                }
+            });
+        }
         // The previous bar's value only exists to be pushed from the second bar on.
         let push = advanced.replace(true);
         for &(name, seed, formula) in ACCUMULATORS {
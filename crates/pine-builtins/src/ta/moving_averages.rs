@@ -101,6 +101,7 @@ impl TaSma {
 pub struct TaEma {
     source: f64,
     #[length_check]
+    #[arg(simple)]
     length: f64,
     /// Holds the first `length` values, which seed the recursion.
     #[state]
@@ -137,6 +138,7 @@ impl TaEma {
 pub struct TaRma {
     source: f64,
     #[length_check]
+    #[arg(simple)]
     length: f64,
     #[state]
     window: SeriesBuffer<f64>,
@@ -250,6 +252,7 @@ impl TaVwma {
 pub struct TaHma {
     source: f64,
     #[length_check]
+    #[arg(simple)]
     length: f64,
     #[state]
     window: SeriesBuffer<f64>,
@@ -14,6 +14,7 @@ use pine_interpreter::{Interpreter, RuntimeError, Value};
 pub struct TaRsi {
     source: f64,
     #[length_check]
+    #[arg(simple)]
     length: f64,
     /// Last bar's source, to difference against. `None` on the first bar, when
     /// there is no change to measure yet.
@@ -376,8 +377,10 @@ impl TaLinreg {
 pub struct TaTsi {
     source: f64,
     #[length_check]
+    #[arg(simple)]
     short_length: f64,
     #[length_check]
+    #[arg(simple)]
     long_length: f64,
     #[state]
     previous_source: Option<f64>,
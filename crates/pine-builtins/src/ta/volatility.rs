@@ -67,6 +67,7 @@ impl TaTr {
 #[builtin(name = "ta.atr", stateful)]
 pub struct TaAtr {
     #[length_check]
+    #[arg(simple)]
     length: f64,
     #[state]
     previous_close: Option<f64>,
@@ -325,8 +326,10 @@ fn pair<O: PineOutput>(a: Value<O>, b: Value<O>) -> Value<O> {
 #[builtin(name = "ta.dmi", stateful)]
 pub struct TaDmi {
     #[length_check]
+    #[arg(simple)]
     di_length: f64,
     #[length_check]
+    #[arg(simple)]
     adx_smoothing: f64,
     #[state]
     previous_high: Option<f64>,
@@ -422,6 +425,7 @@ impl TaDmi {
 pub struct TaSupertrend {
     factor: f64,
     #[length_check]
+    #[arg(simple)]
     atr_period: f64,
     #[state]
     previous_close: Option<f64>,
@@ -501,10 +505,13 @@ fn pair3<O: PineOutput>(a: Value<O>, b: Value<O>, c: Value<O>) -> Value<O> {
 pub struct TaMacd {
     source: f64,
     #[length_check]
+    #[arg(simple)]
     fast: f64,
     #[length_check]
+    #[arg(simple)]
     slow: f64,
     #[length_check]
+    #[arg(simple)]
     signal: f64,
     #[state]
     fast_win: SeriesBuffer<f64>,
@@ -589,6 +596,7 @@ impl TaBbw {
 pub struct TaKc {
     series: f64,
     #[length_check]
+    #[arg(simple)]
     length: f64,
     mult: f64,
     #[arg(default = true)]
@@ -646,6 +654,7 @@ impl TaKc {
 pub struct TaKcw {
     series: f64,
     #[length_check]
+    #[arg(simple)]
     length: f64,
     mult: f64,
     #[arg(default = true)]
@@ -1,8 +1,9 @@
-use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Timelike, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
 use pine_builtin_macro::BuiltinFunction;
-use pine_core::PineOutput;
+use pine_core::{PineOutput, Session, Timezone};
 use pine_interpreter::{
-    Builtin, BuiltinFn, BuiltinSignature, EvaluatedArg, Interpreter, RuntimeError, Value,
+    Builtin, BuiltinFn, BuiltinSignature, EvaluatedArg, FunctionCallArgs, Interpreter,
+    RuntimeError, Value,
 };
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -19,16 +20,47 @@ pub fn register_timenow<O: PineOutput>() -> Value<O> {
     }
 }
 
-/// UNIX milliseconds (UTC) for the given date parts, or `None` if out of range.
-fn ymd_to_millis(y: i64, mo: i64, d: i64, h: i64, mi: i64, s: i64) -> Option<i64> {
+/// The timezone a date builtin works in: `name` when the script gives one,
+/// otherwise the symbol's exchange timezone (`syminfo.timezone`). A name that
+/// does not resolve is an error, as in TradingView.
+pub(crate) fn resolve_timezone<O: PineOutput>(
+    ctx: &Interpreter<O>,
+    name: &str,
+) -> Result<Timezone, RuntimeError> {
+    let name = if name.is_empty() {
+        crate::object_string(ctx, "syminfo", "timezone").unwrap_or_default()
+    } else {
+        name.to_string()
+    };
+    Timezone::parse(&name).ok_or(RuntimeError::InvalidTimezone(name))
+}
+
+/// UNIX milliseconds for the given date parts read as a wall-clock time in
+/// `tz`, or `None` if out of range.
+fn ymd_to_millis(tz: Timezone, y: i64, mo: i64, d: i64, h: i64, mi: i64, s: i64) -> Option<i64> {
     let date = NaiveDate::from_ymd_opt(y as i32, mo as u32, d as u32)?;
     let dt = date.and_hms_opt(h as u32, mi as u32, s as u32)?;
-    Some(dt.and_utc().timestamp_millis())
+    Some(tz.from_local(dt.and_utc().timestamp_millis()))
 }
 
 /// Parse a date string (the `timestamp("01 Jan 2019 00:00")` form) to UNIX ms.
+/// RFC 2822 and ISO 8601 strings carry their own offset; otherwise a trailing
+/// timezone (`"2019-01-01 09:30 GMT+2"`) is honoured, and UTC assumed.
 fn parse_date_string(s: &str) -> Option<i64> {
     let s = s.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s).or_else(|_| DateTime::parse_from_rfc2822(s)) {
+        return Some(dt.timestamp_millis());
+    }
+    if let Some((rest, zone)) = s.rsplit_once(' ') {
+        if let (Some(tz), Some(local)) = (Timezone::parse(zone), parse_naive_date(rest)) {
+            return Some(tz.from_local(local));
+        }
+    }
+    parse_naive_date(s)
+}
+
+/// A date string without a timezone, as wall-clock UNIX ms.
+fn parse_naive_date(s: &str) -> Option<i64> {
     const DATETIME_FORMATS: &[&str] = &[
         "%Y-%m-%dT%H:%M:%S",
         "%Y-%m-%d %H:%M:%S",
@@ -44,7 +76,7 @@ fn parse_date_string(s: &str) -> Option<i64> {
     const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%d %b %Y", "%d %B %Y"];
     for fmt in DATE_FORMATS {
         if let Ok(date) = NaiveDate::parse_from_str(s, fmt) {
-            return Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp_millis());
+            return Some(date.and_time(NaiveTime::MIN).and_utc().timestamp_millis());
         }
     }
     None
@@ -53,11 +85,11 @@ fn parse_date_string(s: &str) -> Option<i64> {
 /// `timestamp(...)` — build a UNIX timestamp (ms) from date parts.
 ///
 /// Handles the numeric form `timestamp(year, month, day, hour, minute, second)`,
-/// the timezone form `timestamp(tz, year, month, ...)` (the timezone string is
-/// ignored and the remaining numbers read as `[year, month, day, hour?, minute?,
-/// second?]`), and the single date-string form `timestamp("01 Jan 2019 00:00")`.
+/// read in `syminfo.timezone`; the timezone form `timestamp(tz, year, month,
+/// ...)`, read in `tz`; and the single date-string form
+/// `timestamp("01 Jan 2019 00:00")`.
 fn timestamp_fn<O: PineOutput>() -> BuiltinFn<O> {
-    Rc::new(|_ctx, call_args| {
+    Rc::new(|ctx, call_args| {
         let values: Vec<&Value<O>> = call_args
             .args
             .iter()
@@ -74,8 +106,12 @@ fn timestamp_fn<O: PineOutput>() -> BuiltinFn<O> {
                 .unwrap_or(Value::Na));
         }
 
-        // Numeric (optionally timezone-prefixed) form: drop any non-numeric
-        // argument (the timezone string) and read the remaining numbers.
+        // Numeric form, optionally led by the timezone string.
+        let zone = match values.first() {
+            Some(Value::String(name)) => name.as_str(),
+            _ => "",
+        };
+        let tz = resolve_timezone(ctx, zone)?;
         let nums: Vec<i64> = values
             .iter()
             .filter_map(|v| v.as_number().ok().map(|n| n as i64))
@@ -85,60 +121,65 @@ fn timestamp_fn<O: PineOutput>() -> BuiltinFn<O> {
         }
         let get = |i: usize| nums.get(i).copied().unwrap_or(0);
         Ok(
-            ymd_to_millis(get(0), get(1), get(2), get(3), get(4), get(5))
+            ymd_to_millis(tz, get(0), get(1), get(2), get(3), get(4), get(5))
                 .map(|ms| Value::Number(ms as f64))
                 .unwrap_or(Value::Na),
         )
     })
 }
 
-/// The UTC datetime for a UNIX-ms timestamp, or `None` if out of range.
-fn datetime_of(millis: f64) -> Option<DateTime<Utc>> {
-    DateTime::from_timestamp_millis(millis as i64)
+/// The wall-clock datetime in `tz` for a UNIX-ms timestamp, or `None` if out
+/// of range.
+fn datetime_of(millis: f64, tz: Timezone) -> Option<NaiveDateTime> {
+    tz.local_datetime(millis as i64)
 }
 
 // The date-part extractors, shared by the `x(time)` functions and the bare-value
 // forms so the two can never disagree. `sunday = 1 … saturday = 7`, matching Pine.
-fn year_of(ms: f64) -> Option<i64> {
-    datetime_of(ms).map(|d| d.year() as i64)
+fn year_of(ms: f64, tz: Timezone) -> Option<i64> {
+    datetime_of(ms, tz).map(|d| d.year() as i64)
 }
-fn month_of(ms: f64) -> Option<i64> {
-    datetime_of(ms).map(|d| d.month() as i64)
+fn month_of(ms: f64, tz: Timezone) -> Option<i64> {
+    datetime_of(ms, tz).map(|d| d.month() as i64)
 }
-fn dayofmonth_of(ms: f64) -> Option<i64> {
-    datetime_of(ms).map(|d| d.day() as i64)
+fn dayofmonth_of(ms: f64, tz: Timezone) -> Option<i64> {
+    datetime_of(ms, tz).map(|d| d.day() as i64)
 }
-fn dayofweek_of(ms: f64) -> Option<i64> {
-    datetime_of(ms).map(|d| d.weekday().num_days_from_sunday() as i64 + 1)
+fn dayofweek_of(ms: f64, tz: Timezone) -> Option<i64> {
+    datetime_of(ms, tz).map(|d| d.weekday().num_days_from_sunday() as i64 + 1)
 }
-fn hour_of(ms: f64) -> Option<i64> {
-    datetime_of(ms).map(|d| d.hour() as i64)
+fn hour_of(ms: f64, tz: Timezone) -> Option<i64> {
+    datetime_of(ms, tz).map(|d| d.hour() as i64)
 }
-fn minute_of(ms: f64) -> Option<i64> {
-    datetime_of(ms).map(|d| d.minute() as i64)
+fn minute_of(ms: f64, tz: Timezone) -> Option<i64> {
+    datetime_of(ms, tz).map(|d| d.minute() as i64)
 }
-fn second_of(ms: f64) -> Option<i64> {
-    datetime_of(ms).map(|d| d.second() as i64)
+fn second_of(ms: f64, tz: Timezone) -> Option<i64> {
+    datetime_of(ms, tz).map(|d| d.second() as i64)
 }
-fn weekofyear_of(ms: f64) -> Option<i64> {
-    datetime_of(ms).map(|d| d.iso_week().week() as i64)
+fn weekofyear_of(ms: f64, tz: Timezone) -> Option<i64> {
+    datetime_of(ms, tz).map(|d| d.iso_week().week() as i64)
 }
 
-/// Defines a `name(time)` date function returning an integer part (or `na`).
+/// Defines a `name(time, timezone)` date function returning an integer part
+/// (or `na`).
 macro_rules! date_fn {
     ($ident:ident, $name:literal, $extract:ident) => {
         #[derive(BuiltinFunction)]
         #[builtin(name = $name)]
         struct $ident {
             time: f64,
+            #[arg(default = "")]
+            timezone: String,
         }
 
         impl $ident {
             fn execute<O: PineOutput>(
                 &self,
-                _ctx: &mut Interpreter<O>,
+                ctx: &mut Interpreter<O>,
             ) -> Result<Value<O>, RuntimeError> {
-                Ok($extract(self.time).map(Value::Int).unwrap_or(Value::Na))
+                let tz = resolve_timezone(ctx, &self.timezone)?;
+                Ok($extract(self.time, tz).map(Value::Int).unwrap_or(Value::Na))
             }
         }
     };
@@ -154,21 +195,22 @@ date_fn!(Second, "second", second_of);
 date_fn!(Weekofyear, "weekofyear", weekofyear_of);
 
 /// A date name that is both a function (`year(t)`) and a bare value (the current
-/// bar's year, from the interpreter's `current_time`).
+/// bar's year, from the interpreter's `current_time`, in `syminfo.timezone`).
 fn date_dual<O: PineOutput>(
     name: &str,
     call: BuiltinFn<O>,
     signature: &'static BuiltinSignature,
-    extract: fn(f64) -> Option<i64>,
+    extract: fn(f64, Timezone) -> Option<i64>,
 ) -> Value<O> {
     Value::Object {
         type_name: name.to_string(),
         fields: Rc::new(RefCell::new(HashMap::new())),
         call: Some(Builtin { call, signature }),
         value: Some(Rc::new(move |ctx: &mut Interpreter<O>| {
+            let tz = resolve_timezone(ctx, "")?;
             Ok(ctx
                 .current_time
-                .and_then(|ms| extract(ms as f64))
+                .and_then(|ms| extract(ms as f64, tz))
                 .map(Value::Int)
                 .unwrap_or(Value::Na))
         })),
@@ -190,10 +232,52 @@ macro_rules! dual {
     };
 }
 
-/// `time(timeframe, session, timezone)` — the bar's time. Timeframe/session
-/// resolution is not modelled, so it returns the current bar's time regardless.
+/// The string argument at positional `index` or named `name`, if one was given.
+fn string_arg<O: PineOutput>(args: &[EvaluatedArg<O>], index: usize, name: &str) -> Option<String> {
+    let named = args.iter().find_map(|arg| match arg {
+        EvaluatedArg::Named { name: n, value } if n == name => Some(value),
+        _ => None,
+    });
+    let positional = args
+        .iter()
+        .filter_map(|arg| match arg {
+            EvaluatedArg::Positional(value) => Some(value),
+            EvaluatedArg::Named { .. } => None,
+        })
+        .nth(index);
+    match named.or(positional)? {
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// Whether the current bar lies inside the `session` argument of a
+/// `time(timeframe, session, timezone)` call, read in its `timezone`. Without
+/// a session every bar does.
+fn in_session<O: PineOutput>(
+    ctx: &Interpreter<O>,
+    args: &[EvaluatedArg<O>],
+) -> Result<bool, RuntimeError> {
+    let spec = string_arg(args, 1, "session").unwrap_or_default();
+    if spec.is_empty() {
+        return Ok(true);
+    }
+    let session = Session::parse(&spec)
+        .ok_or_else(|| RuntimeError::TypeError(format!("invalid session \"{spec}\"")))?;
+    let tz = resolve_timezone(ctx, &string_arg(args, 2, "timezone").unwrap_or_default())?;
+    Ok(ctx
+        .current_time
+        .is_some_and(|ms| session.occurrence(tz.to_local(ms)).is_some()))
+}
+
+/// `time(timeframe, session, timezone)` — the bar's time, or `na` when the bar
+/// is outside `session`. The timeframe is not resampled: it is the bar's own
+/// time regardless.
 fn time_fn<O: PineOutput>() -> BuiltinFn<O> {
-    Rc::new(|ctx: &mut Interpreter<O>, _call_args| {
+    Rc::new(|ctx: &mut Interpreter<O>, call_args| {
+        if !in_session(ctx, &call_args.args)? {
+            return Ok(Value::Na);
+        }
         Ok(ctx
             .current_time
             .map(|ms| Value::Number(ms as f64))
@@ -226,29 +310,47 @@ fn close_time<O: PineOutput>(ctx: &Interpreter<O>) -> Value<O> {
     }
 }
 
-/// The `time_close` name: a value (the bar's closing UNIX ms) and a function.
+/// The `time_close` name: a value (the bar's closing UNIX ms) and a function
+/// (`time_close(timeframe, session, timezone)`, `na` outside the session).
 pub fn register_time_close<O: PineOutput>() -> Value<O> {
     Value::Object {
         type_name: "time_close".to_string(),
         fields: Rc::new(RefCell::new(HashMap::new())),
         call: Some(Builtin::untyped(Rc::new(
-            |ctx: &mut Interpreter<O>, _args| Ok(close_time(ctx)),
+            |ctx: &mut Interpreter<O>, call_args: FunctionCallArgs<O>| {
+                if !in_session(ctx, &call_args.args)? {
+                    return Ok(Value::Na);
+                }
+                Ok(close_time(ctx))
+            },
         ))),
         value: Some(Rc::new(|ctx: &mut Interpreter<O>| Ok(close_time(ctx)))),
     }
 }
 
-/// The `time_tradingday` value: the start of the bar's trading day (UTC midnight).
+/// The `time_tradingday` value: the start of the bar's trading day (midnight
+/// in `syminfo.timezone`). An overnight session's evening belongs to the next
+/// day's trading.
 pub fn register_time_tradingday<O: PineOutput>() -> Value<O> {
     Value::Object {
         type_name: "time_tradingday".to_string(),
         fields: Rc::new(RefCell::new(HashMap::new())),
         call: None,
         value: Some(Rc::new(|ctx: &mut Interpreter<O>| {
-            const DAY_MS: i64 = 86_400_000;
+            let tz = resolve_timezone(ctx, "")?;
+            let session = crate::object_string(ctx, "syminfo", "session")
+                .and_then(|spec| Session::parse(&spec));
+            let midnight = |local: i64| {
+                let day = match &session {
+                    Some(session) => session.trading_day(local)?,
+                    None => DateTime::from_timestamp_millis(local)?.date_naive(),
+                };
+                Some(day.and_time(NaiveTime::MIN).and_utc().timestamp_millis())
+            };
             Ok(ctx
                 .current_time
-                .map(|ms| Value::Number((ms.div_euclid(DAY_MS) * DAY_MS) as f64))
+                .and_then(|ms| midnight(tz.to_local(ms)))
+                .map(|local| Value::Number(tz.from_local(local) as f64))
                 .unwrap_or(Value::Na))
         })),
     }
@@ -294,9 +396,10 @@ pub fn register_dayofweek<O: PineOutput>() -> Value<O> {
             signature: DayOfWeek::signature(),
         }),
         value: Some(Rc::new(|ctx: &mut Interpreter<O>| {
+            let tz = resolve_timezone(ctx, "")?;
             Ok(ctx
                 .current_time
-                .and_then(|ms| dayofweek_of(ms as f64))
+                .and_then(|ms| dayofweek_of(ms as f64, tz))
                 .map(Value::Int)
                 .unwrap_or(Value::Na))
         })),
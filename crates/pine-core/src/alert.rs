@@ -0,0 +1,91 @@
+//! Alerts as they fire: the events a host forwards to whatever acts on them.
+//!
+//! A script raises alerts three ways — `alert(...)`, an `alertcondition(...)`
+//! whose condition holds, and a strategy order filling — and each becomes one
+//! [`AlertEvent`] stamped with the bar it fired on. The output of a bar carries
+//! its events (see [`AlertConditionOutput`](crate::AlertConditionOutput)); a
+//! host that wants them as they happen installs an [`AlertSink`].
+
+use crate::Frequency;
+
+/// What raised an [`AlertEvent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSource {
+    /// An `alert(...)` call.
+    Alert,
+    /// An `alertcondition(...)` whose condition held on the bar.
+    AlertCondition,
+    /// A strategy order or exit filling; the message is its `alert_message`.
+    OrderFill,
+}
+
+/// One alert firing, with its message's `{{...}}` placeholders expanded.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct AlertEvent {
+    /// The open time of the bar it fired on (UNIX ms).
+    pub time: i64,
+    pub bar_index: u64,
+    pub source: AlertSource,
+    /// The `alert(...)` frequency; `None` for the other sources.
+    pub frequency: Option<Frequency>,
+    /// The `alertcondition` title, or the id of the order that filled.
+    pub title: String,
+    pub message: String,
+}
+
+/// Receives alerts as the script raises them, bar by bar — the hook an
+/// execution bridge hangs off.
+pub trait AlertSink {
+    fn send(&mut self, event: &AlertEvent);
+}
+
+/// Replace each `{{name}}` in `template` with `value(name)`. A placeholder
+/// `value` does not know is left as written, braces and all.
+pub fn expand_placeholders(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        expanded.push_str(&rest[..start]);
+        match value(name.trim()) {
+            Some(v) => expanded.push_str(&v),
+            None => expanded.push_str(&rest[start..start + len + 4]),
+        }
+        rest = &rest[start + len + 4..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "close" => Some("101.5".to_string()),
+            "ticker" => Some("AAPL".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn known_placeholders_are_replaced() {
+        assert_eq!(
+            expand_placeholders("{{ticker}} closed at {{close}}", lookup),
+            "AAPL closed at 101.5"
+        );
+    }
+
+    #[test]
+    fn unknown_and_unterminated_placeholders_are_kept() {
+        assert_eq!(
+            expand_placeholders("{{plot_0}} {{close}} {{open", lookup),
+            "{{plot_0}} 101.5 {{open"
+        );
+    }
+}
@@ -1,6 +1,7 @@
 //! A bar of market data, and the series a script runs over.
 
 use crate::SymInfo;
+use std::ops::Range;
 
 /// Represents a single bar/candle of market data
 #[derive(Debug, Clone, Default)]
@@ -29,6 +30,33 @@ pub struct Bar {
     pub is_realtime: bool,
     /// The last historical bar before real-time (`barstate.islastconfirmedhistory`).
     pub is_last_confirmed_history: bool,
+    /// The open time of the bar after this one, when the host knows it. `None`
+    /// for the last bar and for one still forming.
+    pub next_time: Option<i64>,
+}
+
+/// The range of `intrabars` (oldest first, each opening at `time_of` it) that
+/// make up the chart bar opening at `open`: those opening in `[open, next)`,
+/// where `next` is the next chart bar's open time. Without it, `period` (the
+/// chart's bar spacing) after `open` stands in; without either, every
+/// intrabar from `open` on.
+///
+/// Bounding by the chart bars themselves, rather than by `period`-aligned
+/// buckets of the epoch, keeps weekly, session-aligned and gap-started bars
+/// whole.
+pub fn intrabar_range<T>(
+    intrabars: &[T],
+    time_of: impl Fn(&T) -> i64,
+    open: i64,
+    next: Option<i64>,
+    period: Option<i64>,
+) -> Range<usize> {
+    let start = intrabars.partition_point(|bar| time_of(bar) < open);
+    let end = match next.or_else(|| period.filter(|p| *p > 0).map(|p| open + p)) {
+        Some(end) => intrabars.partition_point(|bar| time_of(bar) < end),
+        None => intrabars.len(),
+    };
+    start..end.max(start)
 }
 
 /// One row of raw market data, before it is placed in a series.
@@ -94,6 +122,7 @@ impl Data {
                 is_history: true,
                 is_realtime: false,
                 is_last_confirmed_history: index == last,
+                next_time: None,
             })
             .collect();
 
@@ -146,4 +175,17 @@ mod tests {
     fn an_empty_series_has_no_bars() {
         assert!(Data::from_ohlcv([]).bars.is_empty());
     }
+
+    #[test]
+    fn intrabars_run_until_the_next_bar_opens() {
+        // A 7-unit bar opening at 3, off the epoch's 7-unit buckets.
+        let times = [1, 3, 5, 9, 10, 12];
+        let range = |open, next| intrabar_range(&times, |t| *t, open, next, Some(7));
+        assert_eq!(range(3, Some(10)), 1..4);
+        // The last bar ends one period after it opens.
+        assert_eq!(range(10, None), 4..6);
+        // A bar after a gap has none.
+        assert_eq!(range(20, Some(27)), 6..6);
+        assert_eq!(intrabar_range(&times, |t| *t, 9, None, None), 3..6);
+    }
 }
@@ -1,23 +1,30 @@
+mod alert;
 mod bar;
 mod library;
 mod output;
 mod series_buffer;
+mod session;
 mod syminfo;
 mod timeframe;
+mod timezone;
 mod version;
 
-pub use bar::{Bar, Data, Ohlcv};
+pub use alert::{expand_placeholders, AlertEvent, AlertSink, AlertSource};
+pub use bar::{intrabar_range, Bar, Data, Ohlcv};
 pub use library::{DirLoader, FileResolver, LibraryLoader};
 pub use output::{
-    AlertCondition, AlertConditionOutput, BoxOutput, Color, DefaultPineOutput, DrawingOutput,
-    FillObject, FillOutput, Frequency, GlobalContext, GlobalOutput, Indicator, Input, InputOutput,
-    InputValue, Label, LabelOutput, Library, LineObject, LineOutput, LinefillObject, LogEntry,
-    LogLevel, LogOutput, MetadataOutput, PineBox, PineOutput, Plot, PlotOutput, Plotarrow, Plotbar,
-    Plotcandle, Plotchar, Plotshape, PolylineObject, Table, TableCell, TableOutput,
+    AlertCondition, AlertConditionOutput, BoxOutput, Color, DefaultPineOutput, DrawingLimits,
+    DrawingOutput, FillKind, FillObject, FillOutput, Frequency, GlobalContext, GlobalOutput,
+    Indicator, Input, InputConstraints, InputOutput, InputValue, Label, LabelOutput, Library,
+    LineObject, LineOutput, LinefillObject, LogEntry, LogLevel, LogOutput, MetadataOutput, PineBox,
+    PineOutput, Plot, PlotOutput, Plotarrow, Plotbar, Plotcandle, Plotchar, Plotshape,
+    PolylineObject, Table, TableCell, TableOutput,
 };
 pub use series_buffer::{SeriesBuffer, MAX_LOOKBACK};
+pub use session::Session;
 pub use syminfo::SymInfo;
 pub use timeframe::{Timeframe, TimeframeError, TimeframeUnit};
+pub use timezone::Timezone;
 pub use version::{PineVersion, VersionError};
 
 /// The error a [`DataProvider`] fails with.
@@ -73,9 +80,11 @@ pub trait DataProvider {
     fn economic(&self, _country: &str, _field: &str) -> Option<f64> {
         None
     }
-    /// The exchange rate `from`→`to` (`request.currency_rate`). Same-currency
-    /// pairs are answered as `1.0` by the builtin without consulting the feed.
-    fn currency_rate(&self, _from: &str, _to: &str) -> Option<f64> {
+    /// The exchange rate `from`→`to` in effect at `time`, the current bar's
+    /// open (`request.currency_rate`, and a strategy's conversion to its
+    /// account currency). Same-currency pairs are answered as `1.0` by the
+    /// builtin without consulting the feed.
+    fn currency_rate(&self, _from: &str, _to: &str, _time: i64) -> Option<f64> {
         None
     }
 }
@@ -1,9 +1,10 @@
 // Output-related types, traits, and implementations
 
-use std::collections::HashMap;
+use crate::AlertEvent;
+use std::collections::{BTreeMap, HashMap};
 
 /// Represents a color with RGBA components
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub struct Color {
     pub r: u8, // Red component (0-255)
     pub g: u8, // Green component (0-255)
@@ -18,7 +19,7 @@ impl Color {
 }
 
 /// Represents a label drawable object
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub struct Label {
     pub x: f64,
     pub y: f64,
@@ -34,7 +35,6 @@ pub struct Label {
     pub text_font_family: String,
 }
 
-/// Represents a box drawable object
 /// A `fill(...)` between two plots or hlines.
 #[derive(Clone, Debug)]
 pub struct FillObject {
@@ -42,10 +42,21 @@ pub struct FillObject {
     pub id1: Option<usize>,
     /// Id of the second plot/hline.
     pub id2: Option<usize>,
+    /// Which the ids name.
+    pub kind: FillKind,
     pub color: Option<Color>,
     pub title: String,
 }
 
+/// What a [`FillObject`]'s ids refer to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillKind {
+    /// Plots, by their index among the bar's [`PlotOutput::plots`].
+    Plots,
+    /// Hlines, by the id of the line each drew into [`LineOutput`].
+    Hlines,
+}
+
 /// Chart-wide settings written by global functions like `bgcolor`/`barcolor`.
 #[derive(Clone, Debug, Default)]
 pub struct GlobalContext {
@@ -56,7 +67,8 @@ pub struct GlobalContext {
 }
 
 /// How often an `alert(...)` call re-fires within a bar / across bars.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Frequency {
     /// Every time the call is reached (`alert.freq_all`).
     All,
@@ -80,7 +92,7 @@ impl Frequency {
 /// An `alertcondition(...)` declaration or an `alert(...)` fire — a named alert
 /// with a message. `frequency` is `None` for `alertcondition` and `Some` for
 /// `alert`, which specifies one.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, serde::Serialize)]
 pub struct AlertCondition {
     pub title: String,
     pub message: String,
@@ -88,7 +100,7 @@ pub struct AlertCondition {
 }
 
 /// The `indicator(...)` declaration — a script's identity and display settings.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, serde::Serialize)]
 pub struct Indicator {
     pub title: String,
     pub shorttitle: String,
@@ -98,6 +110,42 @@ pub struct Indicator {
     pub timeframe: String,
 }
 
+/// How many drawings of each kind a script keeps at once, as its
+/// `indicator`/`strategy` declaration sets them with `max_labels_count`,
+/// `max_lines_count`, `max_boxes_count` and `max_polylines_count`. Creating
+/// one past its kind's limit deletes the oldest of that kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawingLimits {
+    pub labels: usize,
+    pub lines: usize,
+    pub boxes: usize,
+    pub polylines: usize,
+}
+
+impl DrawingLimits {
+    /// The limit of a kind the declaration leaves unset.
+    pub const DEFAULT: usize = 50;
+    /// The largest limit a declaration may set.
+    pub const MAX: usize = 500;
+
+    /// The limit a declared `max_*_count` sets: the default when absent, and
+    /// clamped to `1..=MAX`.
+    pub fn count(declared: Option<f64>) -> usize {
+        declared.map_or(Self::DEFAULT, |n| (n.max(1.0) as usize).min(Self::MAX))
+    }
+}
+
+impl Default for DrawingLimits {
+    fn default() -> Self {
+        Self {
+            labels: Self::DEFAULT,
+            lines: Self::DEFAULT,
+            boxes: Self::DEFAULT,
+            polylines: Self::DEFAULT,
+        }
+    }
+}
+
 /// The `library(...)` declaration — marks a script as a reusable library.
 #[derive(Clone, Debug, Default)]
 pub struct Library {
@@ -107,7 +155,7 @@ pub struct Library {
 }
 
 /// A trend line drawn between two points, `(x1, y1)`–`(x2, y2)`.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub struct LineObject {
     pub x1: f64,
     pub y1: f64,
@@ -121,7 +169,7 @@ pub struct LineObject {
 }
 
 /// One cell of a [`Table`].
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
 pub struct TableCell {
     pub text: String,
     pub text_color: Option<Color>,
@@ -132,16 +180,38 @@ pub struct TableCell {
 }
 
 /// A table overlay: a fixed grid of cells anchored to a chart position.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub struct Table {
     pub position: String,
     pub columns: usize,
     pub rows: usize,
     pub bgcolor: Option<Color>,
+    /// Cells that were set, by `(column, row)`. Serialized as a list, row by
+    /// row, each cell carrying its `column` and `row`.
+    #[serde(serialize_with = "serialize_cells")]
     pub cells: HashMap<(usize, usize), TableCell>,
 }
 
-#[derive(Clone, Debug)]
+fn serialize_cells<S: serde::Serializer>(
+    cells: &HashMap<(usize, usize), TableCell>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(serde::Serialize)]
+    struct Placed<'a> {
+        column: usize,
+        row: usize,
+        #[serde(flatten)]
+        cell: &'a TableCell,
+    }
+    let mut placed: Vec<Placed> = cells
+        .iter()
+        .map(|(&(column, row), cell)| Placed { column, row, cell })
+        .collect();
+    placed.sort_by_key(|p| (p.row, p.column));
+    serializer.collect_seq(placed)
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub struct PineBox {
     pub left: f64,
     pub top: f64,
@@ -278,7 +348,8 @@ pub struct Plotshape {
 }
 
 /// Log level
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum LogLevel {
     Info,
     Warning,
@@ -286,7 +357,7 @@ pub enum LogLevel {
 }
 
 /// A log entry with level and message
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct LogEntry {
     pub level: LogLevel,
     pub message: String,
@@ -296,36 +367,134 @@ pub struct LogEntry {
 /// render the right settings widget.
 ///
 /// Deserializes from a JSON scalar (untagged) so host input overrides can be read
-/// from config.
-#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+/// from config, and serializes the same way; a color serializes as its
+/// components.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 #[serde(untagged)]
 pub enum InputValue {
     Bool(bool),
     Int(i64),
     Float(f64),
     Str(String),
-    #[serde(skip)]
+    #[serde(skip_deserializing)]
     Color(Color),
 }
 
+impl std::fmt::Display for InputValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputValue::Bool(b) => write!(f, "{b}"),
+            InputValue::Int(n) => write!(f, "{n}"),
+            InputValue::Float(x) => write!(f, "{x}"),
+            InputValue::Str(s) => write!(f, "{s:?}"),
+            InputValue::Color(c) => write!(f, "{c:?}"),
+        }
+    }
+}
+
 /// A declared script input (`input.int(...)`, `input.source(...)`, ...).
 ///
 /// Recorded into the output so a host can enumerate a script's configurable
 /// settings without executing anything itself.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Input {
     /// Which `input.*` function declared it: `"int"`, `"float"`, `"bool"`,
-    /// `"string"`, `"source"`, `"color"`, `"session"`, `"time"`.
+    /// `"string"`, `"source"`, `"color"`, `"session"`, `"time"`, `"price"`,
+    /// `"symbol"`, `"timeframe"`, `"text_area"`, `"enum"`.
     pub kind: String,
     /// Display title (`title` argument), empty when none was given.
     pub title: String,
     /// Group the input belongs to (`group` argument), empty when none.
     pub group: String,
+    /// Hover text for the setting (`tooltip` argument), empty when none.
+    pub tooltip: String,
+    /// Inputs sharing an `inline` id sit on one line of the settings; empty
+    /// when the input has a line to itself.
+    pub inline: String,
+    /// Whether the script asks for the value to be confirmed before it runs.
+    pub confirm: bool,
+    /// The values the setting accepts.
+    pub constraints: InputConstraints,
     /// The `defval` the script declared.
     pub default: InputValue,
     pub value: InputValue,
 }
 
+/// The values an [`Input`] accepts: a range and increment for numbers, or a
+/// fixed list of choices. Unconstrained by default.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct InputConstraints {
+    pub minval: Option<f64>,
+    pub maxval: Option<f64>,
+    /// The settings widget's increment. Not enforced: Pine accepts values
+    /// between steps.
+    pub step: Option<f64>,
+    /// The only values allowed, when non-empty.
+    pub options: Vec<InputValue>,
+}
+
+impl InputConstraints {
+    /// `value` as an input of `kind` takes it — an integral float for an
+    /// `int`, an int for a `float` — or why it is not acceptable.
+    pub fn check(&self, kind: &str, value: &InputValue) -> Result<InputValue, String> {
+        let value = match (kind, value) {
+            ("int" | "integer" | "time", InputValue::Int(n)) => InputValue::Int(*n),
+            ("int" | "integer" | "time", InputValue::Float(x)) if x.fract() == 0.0 => {
+                InputValue::Int(*x as i64)
+            }
+            ("float" | "price", InputValue::Int(n)) => InputValue::Float(*n as f64),
+            ("float" | "price", InputValue::Float(x)) => InputValue::Float(*x),
+            ("bool", InputValue::Bool(b)) => InputValue::Bool(*b),
+            ("color", InputValue::Color(c)) => InputValue::Color(c.clone()),
+            (
+                "string" | "session" | "symbol" | "timeframe" | "text_area" | "resolution"
+                | "source" | "enum",
+                InputValue::Str(s),
+            ) => InputValue::Str(s.clone()),
+            _ => {
+                let expected = match kind {
+                    "int" | "integer" => "an integer",
+                    "time" => "a UNIX time in milliseconds",
+                    "float" | "price" => "a number",
+                    "bool" => "a bool",
+                    "color" => "a color",
+                    "source" => "a source series' name",
+                    "enum" => "an enum member's name",
+                    _ => "a string",
+                };
+                return Err(format!("expected {expected}, got {value}"));
+            }
+        };
+
+        let number = match value {
+            InputValue::Int(n) => Some(n as f64),
+            InputValue::Float(x) => Some(x),
+            _ => None,
+        };
+        if let Some(number) = number {
+            if let Some(min) = self.minval.filter(|min| number < *min) {
+                return Err(format!("{value} is below the minimum of {min}"));
+            }
+            if let Some(max) = self.maxval.filter(|max| number > *max) {
+                return Err(format!("{value} is above the maximum of {max}"));
+            }
+        }
+        let allowed = |option: &InputValue| match (option, &value) {
+            (InputValue::Int(a), InputValue::Float(b)) => *a as f64 == *b,
+            (InputValue::Float(a), InputValue::Int(b)) => *a == *b as f64,
+            (a, b) => a == b,
+        };
+        if !self.options.is_empty() && !self.options.iter().any(allowed) {
+            let options: Vec<String> = self.options.iter().map(ToString::to_string).collect();
+            return Err(format!(
+                "{value} is not one of the options ({})",
+                options.join(", ")
+            ));
+        }
+        Ok(value)
+    }
+}
+
 /// Base trait for all output implementations
 ///
 /// This trait defines the minimal contract that all output types must implement.
@@ -413,6 +582,9 @@ macro_rules! impl_output_traits_delegate {
             fn delete_label(&mut self, id: usize) -> bool {
                 self.$field.delete_label(id)
             }
+            fn labels(&self) -> Vec<(usize, &$crate::Label)> {
+                self.$field.labels()
+            }
         }
 
         impl $crate::BoxOutput for $type {
@@ -428,6 +600,9 @@ macro_rules! impl_output_traits_delegate {
             fn delete_box(&mut self, id: usize) -> bool {
                 self.$field.delete_box(id)
             }
+            fn boxes(&self) -> Vec<(usize, &$crate::PineBox)> {
+                self.$field.boxes()
+            }
         }
 
         impl $crate::InputOutput for $type {
@@ -452,6 +627,9 @@ macro_rules! impl_output_traits_delegate {
             fn library(&self) -> Option<&$crate::Library> {
                 self.$field.library()
             }
+            fn set_drawing_limits(&mut self, limits: $crate::DrawingLimits) {
+                self.$field.set_drawing_limits(limits)
+            }
         }
 
         impl $crate::AlertConditionOutput for $type {
@@ -461,6 +639,12 @@ macro_rules! impl_output_traits_delegate {
             fn alertconditions(&self) -> &[$crate::AlertCondition] {
                 self.$field.alertconditions()
             }
+            fn add_alert_event(&mut self, event: $crate::AlertEvent) {
+                self.$field.add_alert_event(event)
+            }
+            fn alert_events(&self) -> &[$crate::AlertEvent] {
+                self.$field.alert_events()
+            }
         }
 
         impl $crate::FillOutput for $type {
@@ -497,6 +681,9 @@ macro_rules! impl_output_traits_delegate {
             fn delete_line(&mut self, id: usize) -> bool {
                 self.$field.delete_line(id)
             }
+            fn lines(&self) -> Vec<(usize, &$crate::LineObject)> {
+                self.$field.lines()
+            }
         }
 
         impl $crate::DrawingOutput for $type {
@@ -512,12 +699,18 @@ macro_rules! impl_output_traits_delegate {
             fn delete_linefill(&mut self, id: usize) -> bool {
                 self.$field.delete_linefill(id)
             }
+            fn linefills(&self) -> Vec<(usize, &$crate::LinefillObject)> {
+                self.$field.linefills()
+            }
             fn add_polyline(&mut self, polyline: $crate::PolylineObject) -> usize {
                 self.$field.add_polyline(polyline)
             }
             fn delete_polyline(&mut self, id: usize) -> bool {
                 self.$field.delete_polyline(id)
             }
+            fn polylines(&self) -> Vec<(usize, &$crate::PolylineObject)> {
+                self.$field.polylines()
+            }
         }
 
         impl $crate::TableOutput for $type {
@@ -533,6 +726,9 @@ macro_rules! impl_output_traits_delegate {
             fn delete_table(&mut self, id: usize) -> bool {
                 self.$field.delete_table(id)
             }
+            fn tables(&self) -> Vec<(usize, &$crate::Table)> {
+                self.$field.tables()
+            }
         }
     };
 }
@@ -580,6 +776,8 @@ pub trait LabelOutput: PineOutput {
     fn get_label_mut(&mut self, id: usize) -> Option<&mut Label>;
     /// Delete a label by ID and return true if it existed
     fn delete_label(&mut self, id: usize) -> bool;
+    /// Every live label with its ID, oldest first
+    fn labels(&self) -> Vec<(usize, &Label)>;
 }
 
 /// Extension trait for box output
@@ -592,6 +790,8 @@ pub trait BoxOutput: PineOutput {
     fn get_box_mut(&mut self, id: usize) -> Option<&mut PineBox>;
     /// Delete a box by ID and return true if it existed
     fn delete_box(&mut self, id: usize) -> bool;
+    /// Every live box with its ID, oldest first
+    fn boxes(&self) -> Vec<(usize, &PineBox)>;
 }
 
 /// Extension trait for table output
@@ -604,6 +804,8 @@ pub trait TableOutput: PineOutput {
     fn get_table_mut(&mut self, id: usize) -> Option<&mut Table>;
     /// Delete a table by ID and return true if it existed
     fn delete_table(&mut self, id: usize) -> bool;
+    /// Every live table with its ID, oldest first
+    fn tables(&self) -> Vec<(usize, &Table)>;
 }
 
 /// Extension trait for line output
@@ -616,10 +818,12 @@ pub trait LineOutput: PineOutput {
     fn get_line_mut(&mut self, id: usize) -> Option<&mut LineObject>;
     /// Delete a line by ID and return true if it existed
     fn delete_line(&mut self, id: usize) -> bool;
+    /// Every live line with its ID, oldest first
+    fn lines(&self) -> Vec<(usize, &LineObject)>;
 }
 
 /// A fill between two lines (`linefill.new`).
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub struct LinefillObject {
     pub line1: usize,
     pub line2: usize,
@@ -627,7 +831,7 @@ pub struct LinefillObject {
 }
 
 /// A multi-point polyline (`polyline.new`).
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
 pub struct PolylineObject {
     /// The `(x, y)` vertices, in order.
     pub points: Vec<(f64, f64)>,
@@ -647,8 +851,12 @@ pub trait DrawingOutput: PineOutput {
     fn get_linefill(&self, id: usize) -> Option<&LinefillObject>;
     fn get_linefill_mut(&mut self, id: usize) -> Option<&mut LinefillObject>;
     fn delete_linefill(&mut self, id: usize) -> bool;
+    /// Every live linefill with its ID, oldest first.
+    fn linefills(&self) -> Vec<(usize, &LinefillObject)>;
     fn add_polyline(&mut self, polyline: PolylineObject) -> usize;
     fn delete_polyline(&mut self, id: usize) -> bool;
+    /// Every live polyline with its ID, oldest first.
+    fn polylines(&self) -> Vec<(usize, &PolylineObject)>;
 }
 
 /// Extension trait for recording `fill(...)` areas.
@@ -671,6 +879,10 @@ pub trait AlertConditionOutput: PineOutput {
     fn add_alertcondition(&mut self, alert: AlertCondition);
     /// Every alert condition declared so far, in declaration order.
     fn alertconditions(&self) -> &[AlertCondition];
+    /// Record an alert that fired on this bar.
+    fn add_alert_event(&mut self, event: AlertEvent);
+    /// The alerts that fired on this bar, in the order they fired.
+    fn alert_events(&self) -> &[AlertEvent];
 }
 
 /// Extension trait for a script's declaration statement — `indicator(...)` or
@@ -684,6 +896,9 @@ pub trait MetadataOutput: PineOutput {
     fn set_library(&mut self, library: Library);
     /// The library declaration, if the script declared one.
     fn library(&self) -> Option<&Library>;
+    /// Cap how many drawings of each kind stay live, deleting the oldest of a
+    /// kind once it is over its limit.
+    fn set_drawing_limits(&mut self, limits: DrawingLimits);
 }
 
 /// Extension trait for recording declared inputs
@@ -698,19 +913,19 @@ pub trait InputOutput: PineOutput {
 #[derive(Default, Clone, Debug)]
 pub struct DefaultPineOutput {
     /// Label storage for drawable objects
-    labels: HashMap<usize, Label>,
+    labels: BTreeMap<usize, Label>,
     /// Next label ID
     next_label_id: usize,
     /// Box storage for drawable objects
-    boxes: HashMap<usize, PineBox>,
+    boxes: BTreeMap<usize, PineBox>,
     /// Next box ID
     next_box_id: usize,
     /// Line storage for drawable objects
-    lines: HashMap<usize, LineObject>,
+    lines: BTreeMap<usize, LineObject>,
     /// Next line ID
     next_line_id: usize,
     /// Table storage for drawable objects
-    tables: HashMap<usize, Table>,
+    tables: BTreeMap<usize, Table>,
     /// Next table ID
     next_table_id: usize,
     /// Plot outputs
@@ -737,16 +952,20 @@ pub struct DefaultPineOutput {
     globals: GlobalContext,
     /// Declared alert conditions.
     alertconditions: Vec<AlertCondition>,
+    /// Alerts fired on this bar.
+    alert_events: Vec<AlertEvent>,
     /// `fill(...)` areas.
     fills: Vec<FillObject>,
     /// Linefill storage for drawable objects.
-    linefills: HashMap<usize, LinefillObject>,
+    linefills: BTreeMap<usize, LinefillObject>,
     /// Next linefill ID.
     next_linefill_id: usize,
     /// Polyline storage for drawable objects.
-    polylines: HashMap<usize, PolylineObject>,
+    polylines: BTreeMap<usize, PolylineObject>,
     /// Next polyline ID.
     next_polyline_id: usize,
+    /// How many labels, lines, boxes and polylines may be live at once.
+    drawing_limits: DrawingLimits,
 }
 
 impl PineOutput for DefaultPineOutput {
@@ -764,12 +983,14 @@ impl PineOutput for DefaultPineOutput {
         self.indicator = None;
         self.globals = GlobalContext::default();
         self.alertconditions.clear();
+        self.alert_events.clear();
         self.fills.clear();
         // Labels, lines, boxes, tables, linefills and polylines are persistent
         // handle objects: a `var`-held id created on an early bar must still be
         // there to mutate on a later one (e.g. populate a table on the last
         // bar). They are not cleared, and their id counters keep rising, so ids
-        // stay unique across the whole run like Pine's.
+        // stay unique across the whole run like Pine's. The oldest of a kind
+        // goes only when a new one pushes it past its `DrawingLimits`.
     }
 }
 
@@ -838,6 +1059,7 @@ impl LabelOutput for DefaultPineOutput {
         let id = self.next_label_id;
         self.next_label_id += 1;
         self.labels.insert(id, label);
+        evict(&mut self.labels, self.drawing_limits.labels);
         id
     }
 
@@ -852,6 +1074,10 @@ impl LabelOutput for DefaultPineOutput {
     fn delete_label(&mut self, id: usize) -> bool {
         self.labels.remove(&id).is_some()
     }
+
+    fn labels(&self) -> Vec<(usize, &Label)> {
+        self.labels.iter().map(|(id, label)| (*id, label)).collect()
+    }
 }
 
 impl BoxOutput for DefaultPineOutput {
@@ -859,6 +1085,7 @@ impl BoxOutput for DefaultPineOutput {
         let id = self.next_box_id;
         self.next_box_id += 1;
         self.boxes.insert(id, box_obj);
+        evict(&mut self.boxes, self.drawing_limits.boxes);
         id
     }
 
@@ -873,6 +1100,13 @@ impl BoxOutput for DefaultPineOutput {
     fn delete_box(&mut self, id: usize) -> bool {
         self.boxes.remove(&id).is_some()
     }
+
+    fn boxes(&self) -> Vec<(usize, &PineBox)> {
+        self.boxes
+            .iter()
+            .map(|(id, box_obj)| (*id, box_obj))
+            .collect()
+    }
 }
 
 impl LineOutput for DefaultPineOutput {
@@ -880,6 +1114,7 @@ impl LineOutput for DefaultPineOutput {
         let id = self.next_line_id;
         self.next_line_id += 1;
         self.lines.insert(id, line);
+        evict(&mut self.lines, self.drawing_limits.lines);
         id
     }
 
@@ -894,6 +1129,10 @@ impl LineOutput for DefaultPineOutput {
     fn delete_line(&mut self, id: usize) -> bool {
         self.lines.remove(&id).is_some()
     }
+
+    fn lines(&self) -> Vec<(usize, &LineObject)> {
+        self.lines.iter().map(|(id, line)| (*id, line)).collect()
+    }
 }
 
 impl DrawingOutput for DefaultPineOutput {
@@ -916,16 +1155,31 @@ impl DrawingOutput for DefaultPineOutput {
         self.linefills.remove(&id).is_some()
     }
 
+    fn linefills(&self) -> Vec<(usize, &LinefillObject)> {
+        self.linefills
+            .iter()
+            .map(|(id, linefill)| (*id, linefill))
+            .collect()
+    }
+
     fn add_polyline(&mut self, polyline: PolylineObject) -> usize {
         let id = self.next_polyline_id;
         self.next_polyline_id += 1;
         self.polylines.insert(id, polyline);
+        evict(&mut self.polylines, self.drawing_limits.polylines);
         id
     }
 
     fn delete_polyline(&mut self, id: usize) -> bool {
         self.polylines.remove(&id).is_some()
     }
+
+    fn polylines(&self) -> Vec<(usize, &PolylineObject)> {
+        self.polylines
+            .iter()
+            .map(|(id, polyline)| (*id, polyline))
+            .collect()
+    }
 }
 
 impl TableOutput for DefaultPineOutput {
@@ -947,6 +1201,10 @@ impl TableOutput for DefaultPineOutput {
     fn delete_table(&mut self, id: usize) -> bool {
         self.tables.remove(&id).is_some()
     }
+
+    fn tables(&self) -> Vec<(usize, &Table)> {
+        self.tables.iter().map(|(id, table)| (*id, table)).collect()
+    }
 }
 
 impl InputOutput for DefaultPineOutput {
@@ -975,6 +1233,18 @@ impl MetadataOutput for DefaultPineOutput {
     fn library(&self) -> Option<&Library> {
         self.library.as_ref()
     }
+
+    fn set_drawing_limits(&mut self, limits: DrawingLimits) {
+        self.drawing_limits = limits;
+    }
+}
+
+/// Delete the oldest drawings — those with the lowest ids — until at most
+/// `limit` are live.
+fn evict<T>(drawings: &mut BTreeMap<usize, T>, limit: usize) {
+    while drawings.len() > limit {
+        drawings.pop_first();
+    }
 }
 
 impl AlertConditionOutput for DefaultPineOutput {
@@ -985,6 +1255,14 @@ impl AlertConditionOutput for DefaultPineOutput {
     fn alertconditions(&self) -> &[AlertCondition] {
         &self.alertconditions
     }
+
+    fn add_alert_event(&mut self, event: AlertEvent) {
+        self.alert_events.push(event);
+    }
+
+    fn alert_events(&self) -> &[AlertEvent] {
+        &self.alert_events
+    }
 }
 
 impl FillOutput for DefaultPineOutput {
@@ -1010,3 +1288,88 @@ impl GlobalOutput for DefaultPineOutput {
         &self.globals
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drawing_limits_default_and_clamp() {
+        assert_eq!(DrawingLimits::count(None), 50);
+        assert_eq!(DrawingLimits::count(Some(120.0)), 120);
+        assert_eq!(DrawingLimits::count(Some(5000.0)), 500);
+        assert_eq!(DrawingLimits::count(Some(0.0)), 1);
+
+        let mut output = DefaultPineOutput::default();
+        output.set_drawing_limits(DrawingLimits {
+            labels: 2,
+            ..DrawingLimits::default()
+        });
+        for text in ["a", "b", "c"] {
+            output.add_label(Label {
+                x: 0.0,
+                y: 0.0,
+                text: text.to_string(),
+                xloc: String::new(),
+                yloc: String::new(),
+                color: None,
+                style: String::new(),
+                textcolor: None,
+                size: String::new(),
+                textalign: String::new(),
+                tooltip: None,
+                text_font_family: String::new(),
+            });
+        }
+        let live: Vec<_> = output
+            .labels()
+            .iter()
+            .map(|(id, l)| (*id, l.text.as_str()))
+            .collect();
+        assert_eq!(live, [(1, "b"), (2, "c")]);
+    }
+
+    #[test]
+    fn constraints_coerce_and_bound_overrides() {
+        let length = InputConstraints {
+            minval: Some(1.0),
+            maxval: Some(100.0),
+            ..InputConstraints::default()
+        };
+        assert_eq!(
+            length.check("int", &InputValue::Float(20.0)),
+            Ok(InputValue::Int(20))
+        );
+        assert_eq!(
+            length.check("int", &InputValue::Int(500)),
+            Err("500 is above the maximum of 100".to_string())
+        );
+        assert_eq!(
+            length.check("int", &InputValue::Float(2.5)),
+            Err("expected an integer, got 2.5".to_string())
+        );
+
+        let mode = InputConstraints {
+            options: vec![
+                InputValue::Str("slow".to_string()),
+                InputValue::Str("fast".to_string()),
+            ],
+            ..InputConstraints::default()
+        };
+        assert!(mode
+            .check("string", &InputValue::Str("fast".to_string()))
+            .is_ok());
+        assert_eq!(
+            mode.check("string", &InputValue::Str("nope".to_string())),
+            Err(r#""nope" is not one of the options ("slow", "fast")"#.to_string())
+        );
+
+        let red = InputValue::Color(Color::new(255, 0, 0, 0));
+        let any = InputConstraints::default();
+        assert_eq!(any.check("color", &red), Ok(red.clone()));
+        assert_eq!(
+            any.check("color", &InputValue::Str("red".to_string())),
+            Err(r#"expected a color, got "red""#.to_string())
+        );
+    }
+}
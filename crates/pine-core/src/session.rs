@@ -0,0 +1,203 @@
+//! Trading sessions as Pine spells them: `"0930-1600"`, `"0930-1200,1300-1600:23456"`,
+//! `"1700-1600:23456"` (overnight), `"24x7"`.
+//!
+//! A session is one or more daily time ranges and the weekdays they trade on
+//! (`1` = Sunday … `7` = Saturday; every day when omitted). A range whose end
+//! is not after its start is overnight: it opens the evening before the day it
+//! belongs to, so `"1700-1600:23456"` trades from Sunday 17:00 to Friday 16:00.
+//! `"0000-0000"` is the whole day.
+//!
+//! Everything here works on wall-clock times: UNIX ms as they would read in
+//! UTC, as [`Timezone::to_local`](crate::Timezone::to_local) produces them.
+
+use chrono::{DateTime, Datelike, Days, NaiveDate};
+
+const MINUTE_MS: i64 = 60_000;
+const DAY_MS: i64 = 86_400_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    /// Minutes past midnight, in the order given.
+    ranges: Vec<(u32, u32)>,
+    /// Indexed by `weekday.num_days_from_sunday()`.
+    days: [bool; 7],
+}
+
+impl Session {
+    /// The session `spec` denotes, or `None` if it is not a session string.
+    pub fn parse(spec: &str) -> Option<Session> {
+        let spec = spec.trim();
+        if spec.eq_ignore_ascii_case("24x7") {
+            return Some(Session {
+                ranges: vec![(0, 0)],
+                days: [true; 7],
+            });
+        }
+        let (ranges, days) = match spec.split_once(':') {
+            Some((ranges, days)) => (ranges, Some(days)),
+            None => (spec, None),
+        };
+        let ranges = ranges
+            .split(',')
+            .map(|range| {
+                let (start, end) = range.trim().split_once('-')?;
+                Some((parse_hhmm(start)?, parse_hhmm(end)?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let days = match days {
+            None => [true; 7],
+            Some(digits) => {
+                let mut days = [false; 7];
+                for c in digits.trim().chars() {
+                    let day = c.to_digit(10).filter(|d| (1..=7).contains(d))?;
+                    days[day as usize - 1] = true;
+                }
+                days
+            }
+        };
+        Some(Session { ranges, days })
+    }
+
+    /// Whether the session trades on `date` (an overnight range counts for
+    /// the day it closes on).
+    pub fn trades_on(&self, date: NaiveDate) -> bool {
+        self.days[date.weekday().num_days_from_sunday() as usize]
+    }
+
+    /// The `[start, end)` of the range occurrence `local` falls in, if any.
+    pub fn occurrence(&self, local: i64) -> Option<(i64, i64)> {
+        let date = date_of(local)?;
+        [Some(date), date.checked_add_days(Days::new(1))]
+            .into_iter()
+            .flatten()
+            .filter(|day| self.trades_on(*day))
+            .flat_map(|day| self.occurrences_on(day))
+            .find(|(start, end)| *start <= local && local < *end)
+    }
+
+    /// The trading day `local` belongs to: its calendar date, or the next one
+    /// once an overnight range has opened for it.
+    pub fn trading_day(&self, local: i64) -> Option<NaiveDate> {
+        let date = date_of(local)?;
+        let minute = (local.rem_euclid(DAY_MS) / MINUTE_MS) as u32;
+        let overnight = self
+            .ranges
+            .iter()
+            .any(|&(start, end)| end <= start && start > 0 && minute >= start);
+        if overnight {
+            date.checked_add_days(Days::new(1))
+        } else {
+            Some(date)
+        }
+    }
+
+    /// When the regular session of trading day `date` opens and closes: the
+    /// start of its first range and the end of its last. `None` when the
+    /// session does not trade that day.
+    pub fn bounds(&self, date: NaiveDate) -> Option<(i64, i64)> {
+        if !self.trades_on(date) {
+            return None;
+        }
+        let occurrences = self.occurrences_on(date);
+        let start = occurrences.iter().map(|(start, _)| *start).min()?;
+        let end = occurrences.iter().map(|(_, end)| *end).max()?;
+        Some((start, end))
+    }
+
+    /// Each range's `[start, end)` on trading day `date`.
+    fn occurrences_on(&self, date: NaiveDate) -> Vec<(i64, i64)> {
+        let midnight = date
+            .and_time(chrono::NaiveTime::MIN)
+            .and_utc()
+            .timestamp_millis();
+        self.ranges
+            .iter()
+            .map(|&(start, end)| {
+                let start_ms = midnight + i64::from(start) * MINUTE_MS;
+                let end_ms = midnight + i64::from(end) * MINUTE_MS;
+                match (start, end) {
+                    (0, 0) => (midnight, midnight + DAY_MS),
+                    _ if end <= start => (start_ms - DAY_MS, end_ms),
+                    _ => (start_ms, end_ms),
+                }
+            })
+            .collect()
+    }
+}
+
+fn date_of(local: i64) -> Option<NaiveDate> {
+    DateTime::from_timestamp_millis(local).map(|dt| dt.date_naive())
+}
+
+/// `"0930"` as minutes past midnight.
+fn parse_hhmm(hhmm: &str) -> Option<u32> {
+    let hhmm = hhmm.trim();
+    if hhmm.len() != 4 || !hhmm.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: u32 = hhmm[..2].parse().ok()?;
+    let minutes: u32 = hhmm[2..].parse().ok()?;
+    // `2400` is accepted as the end of the day.
+    (hours < 24 && minutes < 60 || hhmm == "2400").then_some(hours * 60 + minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> i64 {
+        NaiveDate::from_ymd_opt(y, mo, d)
+            .unwrap()
+            .and_hms_opt(h, mi, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn parses_session_strings() {
+        let s = Session::parse("0930-1200,1300-1600:23456").unwrap();
+        assert_eq!(s.ranges, vec![(570, 720), (780, 960)]);
+        assert_eq!(s.days, [false, true, true, true, true, true, false]);
+        assert_eq!(Session::parse("0930-1600").unwrap().days, [true; 7]);
+        assert!(Session::parse("24x7").is_some());
+        assert_eq!(Session::parse("regular"), None);
+        assert_eq!(Session::parse("0930-1600:8"), None);
+    }
+
+    #[test]
+    fn ranges_and_days_bound_the_session() {
+        let s = Session::parse("0930-1200,1300-1600:23456").unwrap();
+        // Tuesday 2 January 2024.
+        assert_eq!(
+            s.occurrence(at(2024, 1, 2, 9, 30)),
+            Some((at(2024, 1, 2, 9, 30), at(2024, 1, 2, 12, 0)))
+        );
+        assert_eq!(s.occurrence(at(2024, 1, 2, 12, 30)), None);
+        assert_eq!(s.occurrence(at(2024, 1, 2, 16, 0)), None);
+        assert_eq!(
+            s.bounds(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()),
+            Some((at(2024, 1, 2, 9, 30), at(2024, 1, 2, 16, 0)))
+        );
+        // Saturday.
+        assert_eq!(s.occurrence(at(2024, 1, 6, 10, 0)), None);
+    }
+
+    #[test]
+    fn overnight_ranges_belong_to_the_day_they_close() {
+        let s = Session::parse("1700-1600:23456").unwrap();
+        // Sunday 7 January 2024, 18:00 is Monday's session.
+        let sunday_evening = at(2024, 1, 7, 18, 0);
+        assert_eq!(
+            s.occurrence(sunday_evening),
+            Some((at(2024, 1, 7, 17, 0), at(2024, 1, 8, 16, 0)))
+        );
+        assert_eq!(
+            s.trading_day(sunday_evening),
+            NaiveDate::from_ymd_opt(2024, 1, 8)
+        );
+        // Friday evening opens Saturday's session, which does not trade.
+        assert_eq!(s.occurrence(at(2024, 1, 12, 18, 0)), None);
+        assert_eq!(s.occurrence(at(2024, 1, 12, 16, 30)), None);
+    }
+}
@@ -57,6 +57,13 @@ pub struct Timeframe {
     pub unit: TimeframeUnit,
 }
 
+/// Serializes as its period string, e.g. `"60"`.
+impl serde::Serialize for Timeframe {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.period())
+    }
+}
+
 impl Default for Timeframe {
     fn default() -> Self {
         Self {
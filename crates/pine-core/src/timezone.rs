@@ -0,0 +1,237 @@
+//! Timezones as Pine scripts name them: `syminfo.timezone`, and the `timezone`
+//! argument of the date and time builtins.
+//!
+//! Pine accepts IANA names (`"America/New_York"`) and fixed offsets
+//! (`"GMT+3"`, `"UTC-03:30"`, `"+0530"`). Names are looked up in the IANA tz
+//! database, so a zone's offset follows every rule change it has observed —
+//! the ones a backtest over the last few decades runs into included.
+
+use chrono::{NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+
+const HOUR: i32 = 3600;
+
+/// A timezone: a fixed offset, or an IANA zone whose offset follows its rules.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timezone {
+    /// Seconds east of UTC.
+    Fixed(i32),
+    Zone(Tz),
+}
+
+impl Default for Timezone {
+    fn default() -> Self {
+        Timezone::Fixed(0)
+    }
+}
+
+impl Timezone {
+    pub const UTC: Timezone = Timezone::Fixed(0);
+
+    /// The timezone `name` denotes, or `None` if it is neither an offset nor a
+    /// zone in the tz database.
+    pub fn parse(name: &str) -> Option<Timezone> {
+        let name = name.trim();
+        // `Etc/GMT+5` is POSIX-style: five hours *west* of Greenwich.
+        if let Some(offset) = strip_prefix_ignore_case(name, "Etc/GMT") {
+            if let Some(seconds) = parse_offset(offset) {
+                return Some(Timezone::Fixed(-seconds));
+            }
+        }
+        let offset = ["UTC", "GMT", "Etc/UTC"]
+            .iter()
+            .find_map(|prefix| strip_prefix_ignore_case(name, prefix))
+            .unwrap_or(name);
+        match offset {
+            "" | "Z" | "z" => Some(Timezone::UTC),
+            _ => parse_offset(offset)
+                .map(Timezone::Fixed)
+                .or_else(|| Tz::from_str_insensitive(name).ok().map(Timezone::Zone)),
+        }
+    }
+
+    /// Seconds east of UTC at the instant `utc_ms`.
+    pub fn offset_at(&self, utc_ms: i64) -> i32 {
+        match self {
+            Timezone::Fixed(seconds) => *seconds,
+            Timezone::Zone(tz) => match chrono::DateTime::from_timestamp_millis(utc_ms) {
+                Some(instant) => tz
+                    .offset_from_utc_datetime(&instant.naive_utc())
+                    .fix()
+                    .local_minus_utc(),
+                None => 0,
+            },
+        }
+    }
+
+    /// The wall-clock time at `utc_ms`, as UNIX ms would read it in UTC.
+    pub fn to_local(&self, utc_ms: i64) -> i64 {
+        utc_ms + i64::from(self.offset_at(utc_ms)) * 1000
+    }
+
+    /// The instant the wall clock reads `local_ms`. A time skipped when clocks
+    /// go forward reads as if they had not yet; one repeated when they go back
+    /// is its first occurrence.
+    pub fn from_local(&self, local_ms: i64) -> i64 {
+        let Timezone::Zone(tz) = self else {
+            return local_ms - i64::from(self.offset_at(local_ms)) * 1000;
+        };
+        let Some(local) = chrono::DateTime::from_timestamp_millis(local_ms) else {
+            return local_ms;
+        };
+        match tz.from_local_datetime(&local.naive_utc()).earliest() {
+            Some(instant) => instant.timestamp_millis(),
+            // In the gap: read it with the offset in force the day before.
+            None => {
+                let before = self.offset_at(local_ms - i64::from(24 * HOUR) * 1000);
+                local_ms - i64::from(before) * 1000
+            }
+        }
+    }
+
+    /// The wall-clock date and time at `utc_ms`.
+    pub fn local_datetime(&self, utc_ms: i64) -> Option<NaiveDateTime> {
+        chrono::DateTime::from_timestamp_millis(self.to_local(utc_ms)).map(|dt| dt.naive_utc())
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+}
+
+/// `+5`, `-03:30`, `+0530` as seconds east of UTC.
+fn parse_offset(offset: &str) -> Option<i32> {
+    let (sign, digits) = match offset.as_bytes().first()? {
+        b'+' => (1, &offset[1..]),
+        b'-' => (-1, &offset[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match digits.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if digits.len() == 4 => digits.split_at(2),
+        None => (digits, "0"),
+    };
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * HOUR + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> i64 {
+        NaiveDate::from_ymd_opt(y, mo, d)
+            .unwrap()
+            .and_hms_opt(h, mi, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis()
+    }
+
+    fn zone(name: &str) -> Timezone {
+        Timezone::parse(name).unwrap()
+    }
+
+    #[test]
+    fn parses_offsets_and_names() {
+        assert_eq!(Timezone::parse("UTC"), Some(Timezone::UTC));
+        assert_eq!(Timezone::parse("GMT+3"), Some(Timezone::Fixed(3 * HOUR)));
+        assert_eq!(
+            Timezone::parse("UTC-03:30"),
+            Some(Timezone::Fixed(-(3 * HOUR + 1800)))
+        );
+        assert_eq!(
+            Timezone::parse("+0530"),
+            Some(Timezone::Fixed(5 * HOUR + 1800))
+        );
+        assert_eq!(
+            Timezone::parse("Etc/GMT+5"),
+            Some(Timezone::Fixed(-5 * HOUR))
+        );
+        assert!(matches!(
+            Timezone::parse("america/new_york"),
+            Some(Timezone::Zone(z)) if z.name() == "America/New_York"
+        ));
+        assert!(Timezone::parse("Asia/Jerusalem").is_some());
+        assert_eq!(Timezone::parse("Mars/Olympus_Mons"), None);
+    }
+
+    #[test]
+    fn new_york_follows_the_2007_rules() {
+        let ny = zone("America/New_York");
+        // 2024: EDT from 10 March 07:00 UTC to 3 November 06:00 UTC.
+        assert_eq!(ny.offset_at(utc(2024, 3, 10, 6, 59)), -5 * HOUR);
+        assert_eq!(ny.offset_at(utc(2024, 3, 10, 7, 0)), -4 * HOUR);
+        assert_eq!(ny.offset_at(utc(2024, 11, 3, 5, 59)), -4 * HOUR);
+        assert_eq!(ny.offset_at(utc(2024, 11, 3, 6, 0)), -5 * HOUR);
+        // Under the 1987 rules: standard time in late March, EDT in late October.
+        assert_eq!(ny.offset_at(utc(2005, 3, 20, 12, 0)), -5 * HOUR);
+        assert_eq!(ny.offset_at(utc(2005, 10, 29, 12, 0)), -4 * HOUR);
+    }
+
+    #[test]
+    fn zones_follow_their_historical_rules() {
+        // Mexico City kept daylight saving until 2022, Moscow sat at +4 from
+        // 2011 to 2014, and Cairo resumed daylight saving in 2023.
+        let mexico = zone("America/Mexico_City");
+        assert_eq!(mexico.offset_at(utc(2021, 7, 1, 12, 0)), -5 * HOUR);
+        assert_eq!(mexico.offset_at(utc(2023, 7, 1, 12, 0)), -6 * HOUR);
+        let moscow = zone("Europe/Moscow");
+        assert_eq!(moscow.offset_at(utc(2013, 1, 15, 12, 0)), 4 * HOUR);
+        assert_eq!(moscow.offset_at(utc(2016, 1, 15, 12, 0)), 3 * HOUR);
+        let cairo = zone("Africa/Cairo");
+        assert_eq!(cairo.offset_at(utc(2022, 7, 1, 12, 0)), 2 * HOUR);
+        assert_eq!(cairo.offset_at(utc(2023, 7, 1, 12, 0)), 3 * HOUR);
+    }
+
+    #[test]
+    fn london_changes_at_one_utc() {
+        let london = zone("Europe/London");
+        assert_eq!(london.offset_at(utc(2024, 3, 31, 0, 59)), 0);
+        assert_eq!(london.offset_at(utc(2024, 3, 31, 1, 0)), HOUR);
+        assert_eq!(london.offset_at(utc(2024, 10, 27, 1, 0)), 0);
+    }
+
+    #[test]
+    fn sydney_saves_daylight_over_the_new_year() {
+        let sydney = zone("Australia/Sydney");
+        assert_eq!(sydney.offset_at(utc(2024, 1, 15, 0, 0)), 11 * HOUR);
+        assert_eq!(sydney.offset_at(utc(2024, 6, 15, 0, 0)), 10 * HOUR);
+        // 7 April 2024, 03:00 AEDT = 6 April 16:00 UTC.
+        assert_eq!(sydney.offset_at(utc(2024, 4, 6, 15, 59)), 11 * HOUR);
+        assert_eq!(sydney.offset_at(utc(2024, 4, 6, 16, 0)), 10 * HOUR);
+    }
+
+    #[test]
+    fn wall_clock_times_round_trip() {
+        let ny = zone("America/New_York");
+        // 09:30 New York on a summer and a winter day.
+        assert_eq!(
+            ny.from_local(utc(2024, 7, 1, 9, 30)),
+            utc(2024, 7, 1, 13, 30)
+        );
+        assert_eq!(
+            ny.from_local(utc(2024, 1, 2, 9, 30)),
+            utc(2024, 1, 2, 14, 30)
+        );
+        let instant = utc(2024, 7, 1, 13, 30);
+        assert_eq!(ny.from_local(ny.to_local(instant)), instant);
+        // 02:30 on 10 March 2024 never happened; it reads as standard time.
+        assert_eq!(
+            ny.from_local(utc(2024, 3, 10, 2, 30)),
+            utc(2024, 3, 10, 7, 30)
+        );
+        // 01:30 on 3 November 2024 happened twice; the first is daylight time.
+        assert_eq!(
+            ny.from_local(utc(2024, 11, 3, 1, 30)),
+            utc(2024, 11, 3, 5, 30)
+        );
+    }
+}
@@ -64,9 +64,8 @@ impl DataProvider for StaticProvider {
 }
 
 /// Aggregate `bars` into `tf_ms` buckets (open of the first, high/low over all,
-/// close of the last, summed volume). A bucket's `time` is its **last**
-/// constituent bar's time — the bar it is confirmed on — so `request.security`
-/// can align it non-repainting.
+/// close of the last, summed volume). A bucket's `time` is its first
+/// constituent bar's, the open time, as a live feed stamps its bars.
 pub fn resample(bars: &[Bar], tf_ms: i64) -> Vec<Bar> {
     let mut out: Vec<Bar> = Vec::new();
     let mut current = None;
@@ -78,7 +77,6 @@ pub fn resample(bars: &[Bar], tf_ms: i64) -> Vec<Bar> {
             bucket.low = bucket.low.min(bar.low);
             bucket.close = bar.close;
             bucket.volume += bar.volume;
-            bucket.time = bar.time;
         } else {
             let mut bucket = bar.clone();
             bucket.index = out.len() as u64;
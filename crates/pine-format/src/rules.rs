@@ -207,6 +207,9 @@ impl Rules {
             }
             Stmt::Break { .. } => concat(vec![text("break"), trailing]),
             Stmt::Continue { .. } => concat(vec![text("continue"), trailing]),
+            // Only a recovering parse yields these, and the formatter never
+            // formats a program that failed to parse.
+            Stmt::Error { .. } => trailing,
             Stmt::TypeDecl {
                 name,
                 fields,
@@ -830,7 +833,8 @@ fn stmt_line(stmt: &Stmt) -> Option<u32> {
         | Stmt::MethodDecl { loc, .. }
         | Stmt::EnumDecl { loc, .. }
         | Stmt::FunctionDecl { loc, .. }
-        | Stmt::Import { loc, .. } => loc.line(),
+        | Stmt::Import { loc, .. }
+        | Stmt::Error { loc } => loc.line(),
         Stmt::Assignment { target, value } => expr_line(target).or_else(|| expr_line(value)),
         Stmt::Expression(expr) => expr_line(expr),
         Stmt::If { condition, .. } | Stmt::While { condition, .. } => expr_line(condition),
@@ -0,0 +1,910 @@
+//! Compilation of a parsed [`Program`] into the form the interpreter runs.
+//!
+//! The AST names every variable by string and leaves each call to look up its
+//! callee when it runs. Compiling resolves the names to [`Slot`]s, works out
+//! the positions a runtime error is traced to, and binds a call to a builtin
+//! namespace function (`ta.sma`, `math.max`) to that builtin once, so a bar's
+//! run neither hashes names nor searches namespaces.
+
+use std::cell::RefMut;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use pine_ast::{self as ast, Argument, BinOp, ExportItem, Literal, Loc, Program, UnOp, VarKind};
+use pine_core::PineOutput;
+
+use crate::slots::{Names, Slot};
+use crate::{trace, Builtin, Interpreter, Value};
+
+/// A program compiled by [`Interpreter::compile`], ready to
+/// [`run`](Interpreter::run) once per bar.
+pub struct Compiled<O: PineOutput> {
+    pub(crate) statements: Vec<Stmt<O>>,
+    /// The variables the script binds outside its functions, which a
+    /// debugger shows as the script's locals.
+    pub(crate) globals: Rc<[Slot]>,
+}
+
+/// A user function or method: its parameters, its compiled body, and the
+/// variables a call must save for the caller and keep for the call site.
+pub struct Function<O: PineOutput> {
+    pub(crate) params: Vec<Param<O>>,
+    pub(crate) body: Vec<Stmt<O>>,
+    /// The library it was declared in; `None` for the main script.
+    pub(crate) file: Option<Rc<str>>,
+    /// Every variable the body may bind, parameters included. A call restores
+    /// them afterwards, so the caller's scope comes back as it was.
+    pub(crate) writes: Rc<[Slot]>,
+    /// The locals the body assigns, which a call site carries from one call
+    /// to the next so `x[1]` inside the body reads the previous call's `x`.
+    pub(crate) persisted: Vec<Slot>,
+}
+
+impl<O: PineOutput> Function<O> {
+    /// How many parameters it declares.
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+}
+
+pub(crate) struct Param<O: PineOutput> {
+    pub(crate) name: String,
+    pub(crate) slot: Slot,
+    /// Evaluated in the caller's scope when the argument is omitted.
+    pub(crate) default: Option<Expr<O>>,
+    /// A `const` parameter, which only a const argument may bind.
+    pub(crate) is_const: bool,
+}
+
+/// A user-defined type: its name and fields, with their compiled defaults.
+pub struct UserType<O: PineOutput> {
+    pub(crate) name: String,
+    pub(crate) fields: Vec<Field<O>>,
+}
+
+impl<O: PineOutput> UserType<O> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+pub(crate) struct Field<O: PineOutput> {
+    pub(crate) name: String,
+    pub(crate) default: Option<Expr<O>>,
+}
+
+pub(crate) struct Stmt<O: PineOutput> {
+    pub(crate) kind: StmtKind<O>,
+    /// Where a runtime error in the statement is traced to.
+    pub(crate) loc: Option<Loc>,
+}
+
+pub(crate) enum StmtKind<O: PineOutput> {
+    VarDecl {
+        slot: Slot,
+        is_const: bool,
+        kind: VarKind,
+        initializer: Option<Expr<O>>,
+    },
+    Assign {
+        slot: Slot,
+        value: Expr<O>,
+    },
+    /// `object.member := value`; `root` is the variable `object` names, if it
+    /// is one, so a const namespace can refuse the write.
+    AssignMember {
+        object: Expr<O>,
+        member: String,
+        root: Option<Slot>,
+        value: Expr<O>,
+    },
+    /// An assignment to something that cannot be assigned; fails once its
+    /// value is evaluated.
+    AssignInvalid(Expr<O>),
+    Tuple {
+        slots: Vec<Slot>,
+        value: Expr<O>,
+    },
+    Expression(Expr<O>),
+    /// An `if`/`else if` chain: the first branch whose condition holds runs.
+    If {
+        branches: Vec<(Expr<O>, Vec<Stmt<O>>)>,
+        otherwise: Option<Vec<Stmt<O>>>,
+    },
+    For {
+        slot: Slot,
+        from: Expr<O>,
+        to: Expr<O>,
+        step: Option<Expr<O>>,
+        body: Vec<Stmt<O>>,
+    },
+    ForIn {
+        index: Option<Slot>,
+        item: Slot,
+        collection: Expr<O>,
+        body: Vec<Stmt<O>>,
+    },
+    While {
+        condition: Expr<O>,
+        body: Vec<Stmt<O>>,
+    },
+    Break,
+    Continue,
+    TypeDecl {
+        slot: Slot,
+        ty: Rc<UserType<O>>,
+        export: bool,
+    },
+    EnumDecl {
+        slot: Slot,
+        name: String,
+        /// Each member's name and title.
+        members: Vec<(String, String)>,
+        export: bool,
+    },
+    MethodDecl {
+        name: String,
+        /// The type the method extends, from its first parameter's annotation,
+        /// or why there is none.
+        type_name: Result<String, &'static str>,
+        method: Rc<Function<O>>,
+    },
+    FunctionDecl {
+        slot: Slot,
+        name: String,
+        function: Rc<Function<O>>,
+        export: bool,
+    },
+    Export {
+        slot: Slot,
+        name: String,
+    },
+    Import {
+        path: String,
+        slot: Slot,
+        alias: String,
+    },
+    Error,
+}
+
+pub(crate) struct Expr<O: PineOutput> {
+    pub(crate) kind: ExprKind<O>,
+    /// Where a runtime error in the expression is traced to.
+    pub(crate) loc: Option<Loc>,
+}
+
+pub(crate) enum ExprKind<O: PineOutput> {
+    Literal(Value<O>),
+    Variable(Slot),
+    Binary {
+        left: Box<Expr<O>>,
+        op: BinOp,
+        right: Box<Expr<O>>,
+    },
+    Unary {
+        op: UnOp,
+        expr: Box<Expr<O>>,
+    },
+    /// A ternary or an `if` used as a value: the value of the first branch
+    /// whose condition holds, else of `otherwise`, else na.
+    Conditional {
+        branches: Vec<(Expr<O>, Expr<O>)>,
+        otherwise: Option<Box<Expr<O>>>,
+    },
+    Array(Vec<Expr<O>>),
+    /// `expr[index]`; `variable` is the variable `expr` names, if it is one,
+    /// whose recorded history a lookback reads.
+    Index {
+        expr: Box<Expr<O>>,
+        index: Box<Expr<O>>,
+        id: u32,
+        variable: Option<Slot>,
+    },
+    Switch {
+        value: Box<Expr<O>>,
+        cases: Vec<Case<O>>,
+    },
+    Call(Box<Call<O>>),
+    /// `object.member`; `user_type` names the type a `Type.new`/`Type.copy`
+    /// reaches, which a variable of the same name does not shadow.
+    Member {
+        object: Box<Expr<O>>,
+        member: String,
+        user_type: Option<String>,
+    },
+    Function(Rc<Function<O>>),
+}
+
+pub(crate) struct Case<O: PineOutput> {
+    pub(crate) pattern: Expr<O>,
+    pub(crate) result: Expr<O>,
+    /// The `=>` default arm, which matches anything.
+    pub(crate) default: bool,
+}
+
+pub(crate) struct Call<O: PineOutput> {
+    pub(crate) callee: Callee<O>,
+    pub(crate) type_args: Vec<String>,
+    pub(crate) args: Vec<Arg<O>>,
+    /// The call site's stable id, which keys its builtin and local state.
+    pub(crate) id: u32,
+    /// The callee as written, for the call stack.
+    pub(crate) name: Rc<str>,
+    /// Where the call starts, for the call stack.
+    pub(crate) site: Loc,
+}
+
+pub(crate) enum Callee<O: PineOutput> {
+    /// A builtin bound when the program was compiled.
+    Builtin(Builtin<O>),
+    /// `object.member(...)`: a user method, a builtin in method form
+    /// (`arr.push(x)`) or a plain member, decided by what `object` holds.
+    Member {
+        object: Expr<O>,
+        member: String,
+        user_type: Option<String>,
+    },
+    /// Any other callee, evaluated each time the call runs.
+    Value(Expr<O>),
+}
+
+pub(crate) struct Arg<O: PineOutput> {
+    pub(crate) name: Option<String>,
+    pub(crate) value: Expr<O>,
+    pub(crate) capture: Capture,
+    pub(crate) constness: Constness,
+}
+
+/// Whether an argument is passed unevaluated, as a [`Value::Expr`], to a lazy
+/// builtin parameter.
+pub(crate) enum Capture {
+    Never,
+    Always(Rc<ast::Expr>),
+    /// The callee is only known when the call runs; captured if it turns out
+    /// to be a builtin whose parameter is lazy.
+    IfLazy(Rc<ast::Expr>),
+}
+
+/// Whether an argument is a compile-time constant, for a `const` parameter.
+pub(crate) enum Constness {
+    Always,
+    /// As constant as the variable it reads.
+    Slot(Slot),
+    Never,
+}
+
+/// Every name a program binds, and every method it declares, anywhere in it.
+#[derive(Default)]
+struct Bindings {
+    names: HashSet<String>,
+    methods: HashSet<String>,
+}
+
+impl Bindings {
+    fn bind(&mut self, name: &str) {
+        self.names.insert(name.to_string());
+    }
+}
+
+impl ast::Visitor for Bindings {
+    fn visit_stmt(&mut self, stmt: &ast::Stmt) {
+        match stmt {
+            ast::Stmt::VarDecl { name, .. }
+            | ast::Stmt::Assignment {
+                target: ast::Expr::Variable { name, .. },
+                ..
+            }
+            | ast::Stmt::For { var_name: name, .. }
+            | ast::Stmt::TypeDecl { name, .. }
+            | ast::Stmt::EnumDecl { name, .. }
+            | ast::Stmt::Import { alias: name, .. } => self.bind(name),
+            ast::Stmt::TupleAssignment { names, .. } => names.iter().for_each(|n| self.bind(n)),
+            ast::Stmt::ForIn {
+                index_var,
+                item_var,
+                ..
+            } => {
+                index_var.iter().for_each(|n| self.bind(n));
+                self.bind(item_var);
+            }
+            ast::Stmt::FunctionDecl { name, params, .. } => {
+                self.bind(name);
+                params.iter().for_each(|p| self.bind(&p.name));
+            }
+            ast::Stmt::MethodDecl { name, params, .. } => {
+                self.methods.insert(name.clone());
+                params.iter().for_each(|p| self.bind(&p.name));
+            }
+            _ => {}
+        }
+        ast::walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expr) {
+        if let ast::Expr::Function { params, .. } = expr {
+            params.iter().for_each(|p| self.bind(&p.name));
+        }
+        ast::walk_expr(self, expr);
+    }
+}
+
+/// Names a statement block ASSIGNS (declares or writes) directly — i.e. the true
+/// locals of a function body. Reads (e.g. `open`) are ignored, and nested function
+/// declarations are a separate scope so their bodies are not descended into. Used to
+/// decide which variables a call site's persistent state should carry across bars.
+fn collect_assigned_names(body: &[ast::Stmt], out: &mut Vec<String>) {
+    for s in body {
+        match s {
+            ast::Stmt::VarDecl { name, .. } => out.push(name.clone()),
+            ast::Stmt::Assignment {
+                target: ast::Expr::Variable { name: n, .. },
+                ..
+            } => out.push(n.clone()),
+            ast::Stmt::TupleAssignment { names, .. } => out.extend(names.iter().cloned()),
+            ast::Stmt::If {
+                then_branch,
+                else_if_branches,
+                else_branch,
+                ..
+            } => {
+                collect_assigned_names(then_branch, out);
+                for (_, b) in else_if_branches {
+                    collect_assigned_names(b, out);
+                }
+                if let Some(b) = else_branch {
+                    collect_assigned_names(b, out);
+                }
+            }
+            ast::Stmt::For { var_name, body, .. } => {
+                out.push(var_name.clone());
+                collect_assigned_names(body, out);
+            }
+            ast::Stmt::While { body, .. } | ast::Stmt::ForIn { body, .. } => {
+                collect_assigned_names(body, out)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Every name a statement block may bind when it runs: its assigned locals,
+/// plus loop items and declared functions, types, enums and imports. Like
+/// [`collect_assigned_names`], it leaves nested function bodies out.
+fn collect_bound_names(body: &[ast::Stmt], out: &mut Vec<String>) {
+    for s in body {
+        match s {
+            ast::Stmt::VarDecl { name, .. }
+            | ast::Stmt::Assignment {
+                target: ast::Expr::Variable { name, .. },
+                ..
+            }
+            | ast::Stmt::FunctionDecl { name, .. }
+            | ast::Stmt::TypeDecl { name, .. }
+            | ast::Stmt::EnumDecl { name, .. }
+            | ast::Stmt::Import { alias: name, .. } => out.push(name.clone()),
+            ast::Stmt::TupleAssignment { names, .. } => out.extend(names.iter().cloned()),
+            ast::Stmt::If {
+                then_branch,
+                else_if_branches,
+                else_branch,
+                ..
+            } => {
+                collect_bound_names(then_branch, out);
+                for (_, b) in else_if_branches {
+                    collect_bound_names(b, out);
+                }
+                if let Some(b) = else_branch {
+                    collect_bound_names(b, out);
+                }
+            }
+            ast::Stmt::For { var_name, body, .. } => {
+                out.push(var_name.clone());
+                collect_bound_names(body, out);
+            }
+            ast::Stmt::ForIn {
+                index_var,
+                item_var,
+                body,
+                ..
+            } => {
+                out.extend(index_var.iter().cloned());
+                out.push(item_var.clone());
+                collect_bound_names(body, out);
+            }
+            ast::Stmt::While { body, .. } => collect_bound_names(body, out),
+            _ => {}
+        }
+    }
+}
+
+pub(crate) struct Compiler<'a, O: PineOutput> {
+    interp: &'a Interpreter<O>,
+    names: RefMut<'a, Names>,
+    /// Names the program binds somewhere. A builtin under one of them may be
+    /// shadowed when the call runs, so it is not bound early.
+    bound: HashSet<String>,
+    /// Methods the program declares, which a member call may dispatch to.
+    methods: HashSet<String>,
+    /// Added to every call and subscript id, to keep the state of a separately
+    /// parsed expression apart from the script's own.
+    id_base: u32,
+}
+
+impl<'a, O: PineOutput> Compiler<'a, O> {
+    pub(crate) fn new(interp: &'a Interpreter<O>, program: &Program) -> Self {
+        let mut bindings = Bindings::default();
+        ast::walk_program(&mut bindings, program);
+        Self {
+            interp,
+            names: interp.names.borrow_mut(),
+            bound: bindings.names,
+            methods: bindings.methods,
+            id_base: 0,
+        }
+    }
+
+    /// Number the program's call and subscript sites from `id_base` on.
+    pub(crate) fn with_id_base(mut self, id_base: u32) -> Self {
+        self.id_base = id_base;
+        self
+    }
+
+    pub(crate) fn program(&mut self, program: &Program) -> Compiled<O> {
+        let mut bound = Vec::new();
+        collect_bound_names(&program.statements, &mut bound);
+        let mut globals: Vec<Slot> = bound.iter().map(|name| self.slot(name)).collect();
+        dedup(&mut globals);
+        Compiled {
+            statements: self.block(&program.statements),
+            globals: globals.into(),
+        }
+    }
+
+    pub(crate) fn expression(&mut self, expr: &ast::Expr) -> Expr<O> {
+        self.expr(expr)
+    }
+
+    fn slot(&mut self, name: &str) -> Slot {
+        self.names.slot(name)
+    }
+
+    fn block(&mut self, body: &[ast::Stmt]) -> Vec<Stmt<O>> {
+        body.iter().map(|stmt| self.stmt(stmt)).collect()
+    }
+
+    fn stmt(&mut self, stmt: &ast::Stmt) -> Stmt<O> {
+        let kind = match stmt {
+            ast::Stmt::VarDecl {
+                name,
+                type_qualifier,
+                initializer,
+                var_kind,
+                ..
+            } => StmtKind::VarDecl {
+                slot: self.slot(name),
+                is_const: matches!(type_qualifier, Some(ast::TypeQualifier::Const)),
+                kind: *var_kind,
+                initializer: initializer.as_ref().map(|e| self.expr(e)),
+            },
+            ast::Stmt::Assignment { target, value } => {
+                let value = self.expr(value);
+                match target {
+                    ast::Expr::Variable { name, .. } => StmtKind::Assign {
+                        slot: self.slot(name),
+                        value,
+                    },
+                    ast::Expr::MemberAccess { object, member, .. } => StmtKind::AssignMember {
+                        root: match object.as_ref() {
+                            ast::Expr::Variable { name, .. } => Some(self.slot(name)),
+                            _ => None,
+                        },
+                        object: self.expr(object),
+                        member: member.clone(),
+                        value,
+                    },
+                    _ => StmtKind::AssignInvalid(value),
+                }
+            }
+            ast::Stmt::TupleAssignment { names, value, .. } => StmtKind::Tuple {
+                slots: names.iter().map(|n| self.slot(n)).collect(),
+                value: self.expr(value),
+            },
+            ast::Stmt::Expression(expr) => StmtKind::Expression(self.expr(expr)),
+            ast::Stmt::If {
+                condition,
+                then_branch,
+                else_if_branches,
+                else_branch,
+            } => StmtKind::If {
+                branches: std::iter::once((condition, then_branch))
+                    .chain(else_if_branches.iter().map(|(c, b)| (c, b)))
+                    .map(|(c, b)| (self.expr(c), self.block(b)))
+                    .collect(),
+                otherwise: else_branch.as_ref().map(|b| self.block(b)),
+            },
+            ast::Stmt::For {
+                var_name,
+                from,
+                to,
+                step,
+                body,
+                ..
+            } => StmtKind::For {
+                slot: self.slot(var_name),
+                from: self.expr(from),
+                to: self.expr(to),
+                step: step.as_ref().map(|e| self.expr(e)),
+                body: self.block(body),
+            },
+            ast::Stmt::ForIn {
+                index_var,
+                item_var,
+                collection,
+                body,
+                ..
+            } => StmtKind::ForIn {
+                index: index_var.as_ref().map(|n| self.slot(n)),
+                item: self.slot(item_var),
+                collection: self.expr(collection),
+                body: self.block(body),
+            },
+            ast::Stmt::While { condition, body } => StmtKind::While {
+                condition: self.expr(condition),
+                body: self.block(body),
+            },
+            ast::Stmt::Break { .. } => StmtKind::Break,
+            ast::Stmt::Continue { .. } => StmtKind::Continue,
+            ast::Stmt::TypeDecl {
+                name,
+                fields,
+                export,
+                ..
+            } => StmtKind::TypeDecl {
+                slot: self.slot(name),
+                ty: Rc::new(UserType {
+                    name: name.clone(),
+                    fields: fields
+                        .iter()
+                        .map(|field| Field {
+                            name: field.name.clone(),
+                            default: field.default_value.as_ref().map(|e| self.expr(e)),
+                        })
+                        .collect(),
+                }),
+                export: *export,
+            },
+            ast::Stmt::EnumDecl {
+                name,
+                fields,
+                export,
+                ..
+            } => StmtKind::EnumDecl {
+                slot: self.slot(name),
+                name: name.clone(),
+                members: fields
+                    .iter()
+                    .map(|f| {
+                        (
+                            f.name.clone(),
+                            f.title.clone().unwrap_or_else(|| f.name.clone()),
+                        )
+                    })
+                    .collect(),
+                export: *export,
+            },
+            ast::Stmt::MethodDecl {
+                name, params, body, ..
+            } => {
+                let type_name = match params.first() {
+                    Some(first) => first
+                        .type_annotation
+                        .clone()
+                        .ok_or("Method's first parameter must have a type annotation"),
+                    None => Err("Method must have at least one parameter (this)"),
+                };
+                let params = params
+                    .iter()
+                    .map(|p| (p.name.as_str(), p.default_value.as_ref(), false))
+                    .collect();
+                StmtKind::MethodDecl {
+                    name: name.clone(),
+                    type_name,
+                    method: self.function(params, body),
+                }
+            }
+            ast::Stmt::FunctionDecl {
+                name,
+                params,
+                body,
+                export,
+                ..
+            } => StmtKind::FunctionDecl {
+                slot: self.slot(name),
+                name: name.clone(),
+                function: self.user_function(params, body),
+                export: *export,
+            },
+            ast::Stmt::Export { item } => {
+                let (ExportItem::Type(name) | ExportItem::Function(name)) = item;
+                StmtKind::Export {
+                    slot: self.slot(name),
+                    name: name.clone(),
+                }
+            }
+            ast::Stmt::Import { path, alias, .. } => StmtKind::Import {
+                path: path.clone(),
+                slot: self.slot(alias),
+                alias: alias.clone(),
+            },
+            ast::Stmt::Error { .. } => StmtKind::Error,
+        };
+        Stmt {
+            kind,
+            loc: trace::stmt_loc(stmt),
+        }
+    }
+
+    fn user_function(
+        &mut self,
+        params: &[ast::FunctionParam],
+        body: &[ast::Stmt],
+    ) -> Rc<Function<O>> {
+        let params = params
+            .iter()
+            .map(|p| {
+                let is_const = matches!(p.type_qualifier, Some(ast::TypeQualifier::Const));
+                (p.name.as_str(), p.default_value.as_ref(), is_const)
+            })
+            .collect();
+        self.function(params, body)
+    }
+
+    fn function(
+        &mut self,
+        params: Vec<(&str, Option<&ast::Expr>, bool)>,
+        body: &[ast::Stmt],
+    ) -> Rc<Function<O>> {
+        let params: Vec<Param<O>> = params
+            .into_iter()
+            .map(|(name, default, is_const)| Param {
+                name: name.to_string(),
+                slot: self.slot(name),
+                default: default.map(|e| self.expr(e)),
+                is_const,
+            })
+            .collect();
+        let is_param = |slot: &Slot| params.iter().any(|p| p.slot == *slot);
+
+        let mut assigned = Vec::new();
+        collect_assigned_names(body, &mut assigned);
+        let mut persisted: Vec<Slot> = assigned.iter().map(|n| self.slot(n)).collect();
+        persisted.retain(|slot| !is_param(slot));
+        dedup(&mut persisted);
+
+        let mut bound = Vec::new();
+        collect_bound_names(body, &mut bound);
+        let mut writes: Vec<Slot> = params.iter().map(|p| p.slot).collect();
+        writes.extend(bound.iter().map(|n| self.slot(n)));
+        dedup(&mut writes);
+
+        Rc::new(Function {
+            body: self.block(body),
+            params,
+            file: self.interp.current_file.clone(),
+            writes: writes.into(),
+            persisted,
+        })
+    }
+
+    fn expr(&mut self, expr: &ast::Expr) -> Expr<O> {
+        let kind = match expr {
+            ast::Expr::Literal(literal) => ExprKind::Literal(match literal {
+                Literal::Int(n) => Value::Int(*n),
+                Literal::Number(n) => Value::Number(*n),
+                Literal::String(s) | Literal::HexColor(s) => Value::String(s.clone()),
+                Literal::Bool(b) => Value::Bool(*b),
+                Literal::Na => Value::Na,
+            }),
+            ast::Expr::Variable { name, .. } => ExprKind::Variable(self.slot(name)),
+            ast::Expr::Binary {
+                left, op, right, ..
+            } => ExprKind::Binary {
+                left: Box::new(self.expr(left)),
+                op: op.clone(),
+                right: Box::new(self.expr(right)),
+            },
+            ast::Expr::Unary { op, expr } => ExprKind::Unary {
+                op: op.clone(),
+                expr: Box::new(self.expr(expr)),
+            },
+            ast::Expr::Ternary {
+                condition,
+                then_expr,
+                else_expr,
+            } => ExprKind::Conditional {
+                branches: vec![(self.expr(condition), self.expr(then_expr))],
+                otherwise: Some(Box::new(self.expr(else_expr))),
+            },
+            ast::Expr::IfExpr {
+                condition,
+                then_expr,
+                else_if_branches,
+                else_expr,
+            } => ExprKind::Conditional {
+                branches: std::iter::once((condition.as_ref(), then_expr.as_ref()))
+                    .chain(else_if_branches.iter().map(|(c, e)| (c, e)))
+                    .map(|(c, e)| (self.expr(c), self.expr(e)))
+                    .collect(),
+                otherwise: else_expr.as_ref().map(|e| Box::new(self.expr(e))),
+            },
+            ast::Expr::Array(elements) => {
+                ExprKind::Array(elements.iter().map(|e| self.expr(e)).collect())
+            }
+            ast::Expr::Index { expr, index, id } => ExprKind::Index {
+                variable: match expr.as_ref() {
+                    ast::Expr::Variable { name, .. } => Some(self.slot(name)),
+                    _ => None,
+                },
+                expr: Box::new(self.expr(expr)),
+                index: Box::new(self.expr(index)),
+                id: self.id_base + *id,
+            },
+            ast::Expr::Switch { value, cases } => ExprKind::Switch {
+                value: Box::new(self.expr(value)),
+                cases: cases
+                    .iter()
+                    .map(|(pattern, result)| Case {
+                        default: matches!(pattern, ast::Expr::Literal(Literal::Bool(true))),
+                        pattern: self.expr(pattern),
+                        result: self.expr(result),
+                    })
+                    .collect(),
+            },
+            ast::Expr::Call {
+                callee,
+                type_args,
+                args,
+                id,
+                loc,
+            } => ExprKind::Call(Box::new(self.call(
+                callee,
+                type_args,
+                args,
+                self.id_base + *id,
+                *loc,
+            ))),
+            ast::Expr::MemberAccess { object, member, .. } => ExprKind::Member {
+                user_type: user_type(object, member),
+                object: Box::new(self.expr(object)),
+                member: member.clone(),
+            },
+            ast::Expr::Function { params, body } => {
+                ExprKind::Function(self.user_function(params, body))
+            }
+        };
+        Expr {
+            kind,
+            loc: trace::expr_loc(expr),
+        }
+    }
+
+    fn call(
+        &mut self,
+        callee: &ast::Expr,
+        type_args: &[String],
+        args: &[Argument],
+        id: u32,
+        loc: Loc,
+    ) -> Call<O> {
+        let builtin = self.builtin(callee);
+        let args = args
+            .iter()
+            .enumerate()
+            .map(|(index, arg)| {
+                let (name, value) = match arg {
+                    Argument::Positional(value) => (None, value),
+                    Argument::Named { name, value } => (Some(name.clone()), value),
+                };
+                let capture = match &builtin {
+                    Some(builtin) => {
+                        let lazy = match &name {
+                            Some(name) => builtin.signature.named_is_lazy(name),
+                            None => builtin.signature.positional_is_lazy(index),
+                        };
+                        match lazy {
+                            true => Capture::Always(Rc::new(value.clone())),
+                            false => Capture::Never,
+                        }
+                    }
+                    None => Capture::IfLazy(Rc::new(value.clone())),
+                };
+                Arg {
+                    constness: self.constness(value),
+                    value: self.expr(value),
+                    name,
+                    capture,
+                }
+            })
+            .collect();
+        let name = trace::callee_name(callee).into();
+        let site = trace::call_loc(callee, loc).unwrap_or(loc);
+        let callee = match (builtin, callee) {
+            (Some(builtin), _) => Callee::Builtin(builtin),
+            (None, ast::Expr::MemberAccess { object, member, .. }) => Callee::Member {
+                user_type: user_type(object, member),
+                object: self.expr(object),
+                member: member.clone(),
+            },
+            (None, callee) => Callee::Value(self.expr(callee)),
+        };
+        Call {
+            callee,
+            type_args: type_args.to_vec(),
+            args,
+            id,
+            name,
+            site,
+        }
+    }
+
+    /// The builtin a call to `callee` reaches, when that cannot change while
+    /// the program runs: a registered (const) function or namespace member
+    /// whose name the program never binds, and no declared method shares.
+    fn builtin(&self, callee: &ast::Expr) -> Option<Builtin<O>> {
+        let (root, member) = match callee {
+            ast::Expr::Variable { name, .. } => (name, None),
+            ast::Expr::MemberAccess { object, member, .. } => match object.as_ref() {
+                ast::Expr::Variable { name, .. } => (name, Some(member)),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if self.bound.contains(root) || member.is_some_and(|m| self.methods.contains(m)) {
+            return None;
+        }
+        let var = self.interp.variables.get(self.names.get(root)?)?;
+        if !var.is_const {
+            return None;
+        }
+        match (member, &var.value) {
+            (None, Value::BuiltinFunction(builtin))
+            | (
+                None,
+                Value::Object {
+                    call: Some(builtin),
+                    ..
+                },
+            ) => Some(builtin.clone()),
+            (Some(member), Value::Object { fields, .. }) => match fields.borrow().get(member) {
+                Some(Value::BuiltinFunction(builtin)) => Some(builtin.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn constness(&mut self, expr: &ast::Expr) -> Constness {
+        match expr {
+            ast::Expr::Literal(_) => Constness::Always,
+            ast::Expr::Variable { name, .. } => Constness::Slot(self.slot(name)),
+            // A member is as constant as the object it is read from.
+            ast::Expr::MemberAccess { object, .. } => self.constness(object),
+            _ => Constness::Never,
+        }
+    }
+}
+
+/// The type `object.member` reaches when it is a `Type.new`/`Type.copy`.
+fn user_type(object: &ast::Expr, member: &str) -> Option<String> {
+    match object {
+        ast::Expr::Variable { name, .. } if member == "new" || member == "copy" => {
+            Some(name.clone())
+        }
+        _ => None,
+    }
+}
+
+fn dedup(slots: &mut Vec<Slot>) {
+    let mut seen = HashSet::new();
+    slots.retain(|slot| seen.insert(*slot));
+}
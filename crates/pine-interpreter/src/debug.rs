@@ -0,0 +1,593 @@
+//! Step debugging: breakpoints, stepping and inspection of a running script.
+//!
+//! A host registers a [`DebugHandler`] with
+//! [`set_debug_handler`](Interpreter::set_debug_handler). Before each statement
+//! the interpreter checks whether to stop there — on entry, at a breakpoint,
+//! after a step or on a pause request — and if so hands itself to the handler,
+//! which inspects the paused script and says how to go on. A runtime error
+//! stops the script once more, where it struck, before the error ends the run.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use pine_ast::{Loc, VarKind};
+use pine_core::PineOutput;
+
+use crate::compile::{Compiler, Expr};
+use crate::slots::Slot;
+use crate::{Interpreter, RuntimeError, Value};
+
+/// Where a script should stop.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Breakpoint {
+    /// Stop at the statements on this line. Without one, stop at the start of
+    /// a bar, before its first statement.
+    pub line: Option<u32>,
+    /// The library `line` is in; `None` for the main script.
+    pub file: Option<String>,
+    /// Stop only on the bar with this `bar_index`.
+    pub bar_index: Option<i64>,
+    /// Stop only when this Pine expression holds where the script would stop.
+    pub condition: Option<String>,
+}
+
+impl Breakpoint {
+    /// Stop at the statements on `line` of the main script.
+    pub fn line(line: u32) -> Self {
+        Self {
+            line: Some(line),
+            ..Self::default()
+        }
+    }
+
+    /// Stop at the start of the bar with this `bar_index`.
+    pub fn bar(bar_index: i64) -> Self {
+        Self {
+            bar_index: Some(bar_index),
+            ..Self::default()
+        }
+    }
+
+    /// Stop only on the bar with this `bar_index`.
+    pub fn on_bar(mut self, bar_index: i64) -> Self {
+        self.bar_index = Some(bar_index);
+        self
+    }
+
+    /// Stop only when `condition`, a Pine expression, holds.
+    pub fn when(mut self, condition: impl Into<String>) -> Self {
+        self.condition = Some(condition.into());
+        self
+    }
+}
+
+/// Why a script stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// At its first statement, as [`stop_on_entry`](Interpreter::stop_on_entry) asked.
+    Entry,
+    /// At the breakpoint with this index in the list last set.
+    Breakpoint(usize),
+    /// After a step.
+    Step,
+    /// Through the [`pause_handle`](Interpreter::pause_handle).
+    Pause,
+    /// Where a runtime error struck, with its message. The run ends once the
+    /// handler returns.
+    Error(String),
+}
+
+/// Where a script stopped, and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stop {
+    pub reason: StopReason,
+    /// The statement about to run, or the one that failed.
+    pub loc: Loc,
+    /// The library `loc` is in; `None` for the main script.
+    pub file: Option<String>,
+    pub bar_index: Option<i64>,
+}
+
+/// How a stopped script goes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run to the next breakpoint.
+    Continue,
+    /// Stop at the next statement, running any calls this one makes.
+    StepOver,
+    /// Stop at the next statement, inside a function this one calls if any.
+    StepIn,
+    /// Stop at the next statement once the current function has returned.
+    StepOut,
+    /// End the run with [`RuntimeError::Terminated`].
+    Terminate,
+}
+
+/// What a host does when the script it runs stops.
+pub trait DebugHandler<O: PineOutput> {
+    /// The script stopped at `stop`. Inspect it through `interp` — its
+    /// [`stack_frames`](Interpreter::stack_frames),
+    /// [`locals`](Interpreter::locals) and so on — then say how to go on.
+    fn stopped(&mut self, interp: &mut Interpreter<O>, stop: &Stop) -> Resume;
+}
+
+impl<O: PineOutput, F: FnMut(&mut Interpreter<O>, &Stop) -> Resume> DebugHandler<O> for F {
+    fn stopped(&mut self, interp: &mut Interpreter<O>, stop: &Stop) -> Resume {
+        self(interp, stop)
+    }
+}
+
+/// A frame of a stopped script's call stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    /// The user function running in it, as called; `None` for the script body.
+    pub function: Option<String>,
+    /// The statement running in it: where the script stopped in the innermost
+    /// frame, the call in progress in the others.
+    pub loc: Option<Loc>,
+    /// The library `loc` is in; `None` for the main script.
+    pub file: Option<String>,
+}
+
+/// A variable of a stopped script.
+#[derive(Debug)]
+pub struct Local<'a, O: PineOutput> {
+    pub name: String,
+    pub value: &'a Value<O>,
+    /// `Var` or `Varip` for a variable that keeps its value from bar to bar.
+    pub kind: VarKind,
+    pub is_const: bool,
+    /// What it held on earlier bars, oldest first.
+    pub history: Option<&'a VecDeque<Value<O>>>,
+}
+
+/// A statement a script ran, as [`trace_statements`](Interpreter::trace_statements)
+/// records them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Executed {
+    pub bar_index: Option<i64>,
+    pub loc: Loc,
+    /// The library `loc` is in; `None` for the main script.
+    pub file: Option<Rc<str>>,
+    /// How many user calls deep it ran.
+    pub depth: usize,
+}
+
+/// The builtins a debugger shows for the bar being run.
+const BAR_VARIABLES: [&str; 7] = [
+    "bar_index",
+    "time",
+    "open",
+    "high",
+    "low",
+    "close",
+    "volume",
+];
+
+/// Call and subscript ids from here up belong to expressions a debugger
+/// evaluates, so their state never mixes with the script's call sites.
+const EVALUATION_IDS: u32 = 1 << 31;
+
+/// Where a step ends.
+#[derive(Clone, Copy)]
+enum Stepping {
+    Run,
+    In,
+    /// At the next statement at most this many calls deep.
+    Over(usize),
+    /// At the next statement fewer than this many calls deep.
+    Out(usize),
+}
+
+impl Stepping {
+    fn ends_at(self, depth: usize) -> bool {
+        match self {
+            Stepping::Run => false,
+            Stepping::In => true,
+            Stepping::Over(from) => depth <= from,
+            Stepping::Out(from) => depth < from,
+        }
+    }
+}
+
+/// A breakpoint with its condition compiled.
+struct Armed<O: PineOutput> {
+    breakpoint: Breakpoint,
+    condition: Option<Rc<Expr<O>>>,
+    /// False when its condition failed to compile.
+    enabled: bool,
+}
+
+/// What the interpreter keeps while a script is being debugged.
+pub(crate) struct DebugState<O: PineOutput> {
+    /// Taken out while it runs, so a stop cannot re-enter it.
+    handler: Option<Box<dyn DebugHandler<O>>>,
+    breakpoints: Vec<Armed<O>>,
+    stop_on_entry: bool,
+    pause: Arc<AtomicBool>,
+    stepping: Stepping,
+    /// Whether the bar being run has reached its first statement.
+    bar_started: bool,
+    bar_index: Option<i64>,
+    /// The statement about to run.
+    loc: Option<Loc>,
+    /// The script's own variables, from the program being run.
+    globals: Rc<[Slot]>,
+    /// Whether the script already stopped for the error now unwinding.
+    error_stopped: bool,
+    trace: VecDeque<Executed>,
+    trace_capacity: usize,
+    /// Expressions compiled so far, each numbered apart from the others.
+    evaluations: u32,
+}
+
+impl<O: PineOutput> Default for DebugState<O> {
+    fn default() -> Self {
+        Self {
+            handler: None,
+            breakpoints: Vec::new(),
+            stop_on_entry: false,
+            pause: Arc::default(),
+            stepping: Stepping::Run,
+            bar_started: false,
+            bar_index: None,
+            loc: None,
+            globals: Rc::from([]),
+            error_stopped: false,
+            trace: VecDeque::new(),
+            trace_capacity: 0,
+            evaluations: 0,
+        }
+    }
+}
+
+impl<O: PineOutput> Interpreter<O> {
+    /// Debug the script: `handler` is called wherever it stops. Without a
+    /// handler, breakpoints and steps are ignored.
+    pub fn set_debug_handler(&mut self, handler: impl DebugHandler<O> + 'static) {
+        self.debug_state().handler = Some(Box::new(handler));
+    }
+
+    /// Replace the breakpoints, compiling their conditions. Each result says
+    /// whether the breakpoint at that index could be set; one that could not
+    /// is never hit.
+    pub fn set_breakpoints(
+        &mut self,
+        breakpoints: Vec<Breakpoint>,
+    ) -> Vec<Result<(), RuntimeError>> {
+        let mut results = Vec::with_capacity(breakpoints.len());
+        let mut armed = Vec::with_capacity(breakpoints.len());
+        for breakpoint in breakpoints {
+            let condition = match breakpoint
+                .condition
+                .as_deref()
+                .map(|source| self.compile_expression(source))
+            {
+                Some(Ok(condition)) => Some(Rc::new(condition)),
+                Some(Err(error)) => {
+                    results.push(Err(error));
+                    // Holds its place, so the indices `StopReason::Breakpoint`
+                    // reports follow the list the host set.
+                    armed.push(Armed {
+                        breakpoint,
+                        condition: None,
+                        enabled: false,
+                    });
+                    continue;
+                }
+                None => None,
+            };
+            results.push(Ok(()));
+            armed.push(Armed {
+                breakpoint,
+                condition,
+                enabled: true,
+            });
+        }
+        self.debug_state().breakpoints = armed;
+        results
+    }
+
+    /// Stop at the next statement the script runs.
+    pub fn stop_on_entry(&mut self) {
+        self.debug_state().stop_on_entry = true;
+    }
+
+    /// A flag another thread can raise to stop the script at its next
+    /// statement.
+    pub fn pause_handle(&mut self) -> Arc<AtomicBool> {
+        Arc::clone(&self.debug_state().pause)
+    }
+
+    /// Record the last `capacity` statements run, for
+    /// [`execution_trace`](Self::execution_trace).
+    pub fn trace_statements(&mut self, capacity: usize) {
+        let debug = self.debug_state();
+        debug.trace_capacity = capacity;
+        while debug.trace.len() > capacity {
+            debug.trace.pop_front();
+        }
+    }
+
+    /// The statements most recently run, oldest first.
+    pub fn execution_trace(&self) -> impl Iterator<Item = &Executed> {
+        self.debug.iter().flat_map(|debug| debug.trace.iter())
+    }
+
+    /// The stopped script's call stack, innermost frame first.
+    pub fn stack_frames(&self) -> Vec<StackFrame> {
+        let mut frames = Vec::with_capacity(self.call_stack.len() + 1);
+        let mut loc = self.debug.as_ref().and_then(|debug| debug.loc);
+        let mut file = self.current_file.clone();
+        for call in self.call_stack.iter().rev() {
+            frames.push(StackFrame {
+                function: Some(call.function.to_string()),
+                loc,
+                file: file.as_deref().map(str::to_string),
+            });
+            loc = Some(call.call_site);
+            file = call.file.clone();
+        }
+        frames.push(StackFrame {
+            function: None,
+            loc,
+            file: file.as_deref().map(str::to_string),
+        });
+        frames
+    }
+
+    /// The variables of the innermost frame: the running function's
+    /// parameters and locals, or the script's own variables in its body.
+    pub fn locals(&self) -> Vec<Local<'_, O>> {
+        match self.call_stack.last() {
+            Some(call) => self.debug_variables(&call.locals, self.current_call_id),
+            None => self.debug_variables(&self.debug_globals(), 0),
+        }
+    }
+
+    /// The script's own variables as a function sees them: those its locals
+    /// do not shadow. Empty in the script body, where they are the
+    /// [`locals`](Self::locals).
+    pub fn script_variables(&self) -> Vec<Local<'_, O>> {
+        let Some(call) = self.call_stack.last() else {
+            return Vec::new();
+        };
+        let visible: Vec<Slot> = self
+            .debug_globals()
+            .iter()
+            .filter(|slot| !call.locals.contains(slot))
+            .copied()
+            .collect();
+        self.debug_variables(&visible, 0)
+    }
+
+    /// The builtins describing the bar being run — `bar_index`, `time` and
+    /// its OHLCV — by name. Some are computed when read, so they come back
+    /// as values rather than [`Local`]s.
+    pub fn bar_variables(&mut self) -> Vec<(&'static str, Value<O>)> {
+        BAR_VARIABLES
+            .iter()
+            .filter_map(|&name| {
+                let value = match self.get_variable(name)?.clone() {
+                    Value::Object {
+                        value: Some(value), ..
+                    } => value(self).unwrap_or(Value::Na),
+                    Value::Series(series) => *series.current,
+                    value => value,
+                };
+                Some((name, value))
+            })
+            .collect()
+    }
+
+    /// Evaluate a Pine expression where the script stopped.
+    pub fn evaluate(&mut self, source: &str) -> Result<Value<O>, RuntimeError> {
+        let expr = self.compile_expression(source)?;
+        self.evaluate_quietly(&expr)
+    }
+
+    /// Compile `source`, a single Pine expression, against the script's
+    /// variables.
+    fn compile_expression(&mut self, source: &str) -> Result<Expr<O>, RuntimeError> {
+        let program = pine_parser::Parser::parse_source(source)
+            .map_err(|e| RuntimeError::InvalidExpression(e.to_string()))?;
+        let [pine_ast::Stmt::Expression(expr)] = program.statements.as_slice() else {
+            return Err(RuntimeError::InvalidExpression(format!(
+                "'{source}' is not a single expression"
+            )));
+        };
+        let debug = self.debug_state();
+        // 2^15 blocks of 2^16 ids; a long session reuses the oldest.
+        let id_base = EVALUATION_IDS | (debug.evaluations % (1 << 15)) << 16;
+        debug.evaluations = debug.evaluations.wrapping_add(1);
+        Ok(Compiler::new(self, &program)
+            .with_id_base(id_base)
+            .expression(expr))
+    }
+
+    /// Evaluate `expr` without stopping in it.
+    fn evaluate_quietly(&mut self, expr: &Expr<O>) -> Result<Value<O>, RuntimeError> {
+        let debug = self.debug.take();
+        let value = self.eval_expr(expr);
+        self.debug = debug;
+        value
+    }
+
+    fn debug_state(&mut self) -> &mut DebugState<O> {
+        self.debug.get_or_insert_with(Box::default)
+    }
+
+    fn debug_globals(&self) -> Rc<[Slot]> {
+        self.debug
+            .as_ref()
+            .map_or_else(|| Rc::from([]), |debug| Rc::clone(&debug.globals))
+    }
+
+    /// The bound variables among `slots`. Functions and types are declarations,
+    /// not state, and are left out.
+    fn debug_variables(&self, slots: &[Slot], call_id: u32) -> Vec<Local<'_, O>> {
+        let names = self.names.borrow();
+        slots
+            .iter()
+            .filter_map(|&slot| {
+                let var = self.variables.get(slot)?;
+                if matches!(var.value, Value::Function(_) | Value::Type(_)) {
+                    return None;
+                }
+                let kind = if self.varip_decls.contains(&(call_id, slot)) {
+                    VarKind::Varip
+                } else if var.is_var_persistent {
+                    VarKind::Var
+                } else {
+                    VarKind::Plain
+                };
+                Some(Local {
+                    name: names.name(slot).to_string(),
+                    value: &var.value,
+                    kind,
+                    is_const: var.is_const,
+                    history: self.user_series_history.get(slot),
+                })
+            })
+            .collect()
+    }
+
+    /// A bar is starting to run `globals`, the script's variables.
+    pub(crate) fn debug_bar(&mut self, globals: &Rc<[Slot]>) {
+        let bar_index = self.bar_index();
+        if let Some(debug) = &mut self.debug {
+            debug.globals = Rc::clone(globals);
+            debug.bar_index = bar_index;
+            debug.bar_started = false;
+            debug.error_stopped = false;
+        }
+    }
+
+    /// The statement at `loc` is about to run: stop there if the script
+    /// should.
+    pub(crate) fn debug_statement(&mut self, loc: Option<Loc>) -> Result<(), RuntimeError> {
+        let (Some(loc), Some(debug)) = (loc, self.debug.as_deref_mut()) else {
+            return Ok(());
+        };
+        let depth = self.call_stack.len();
+        debug.loc = Some(loc);
+        if debug.trace_capacity > 0 {
+            if debug.trace.len() == debug.trace_capacity {
+                debug.trace.pop_front();
+            }
+            debug.trace.push_back(Executed {
+                bar_index: debug.bar_index,
+                loc,
+                file: self.current_file.clone(),
+                depth,
+            });
+        }
+        let bar_start = depth == 0 && !std::mem::replace(&mut debug.bar_started, true);
+
+        let reason = if std::mem::take(&mut debug.stop_on_entry) {
+            StopReason::Entry
+        } else if debug.pause.swap(false, Ordering::SeqCst) {
+            StopReason::Pause
+        } else if debug.stepping.ends_at(depth) {
+            StopReason::Step
+        } else {
+            match self.breakpoint_hit(loc, bar_start) {
+                Some(index) => StopReason::Breakpoint(index),
+                None => return Ok(()),
+            }
+        };
+        self.stop(reason, loc)
+    }
+
+    /// A statement failed with `error`: stop where it struck, once, before
+    /// it unwinds.
+    pub(crate) fn debug_error(&mut self, error: &RuntimeError) {
+        let Some(debug) = self.debug.as_deref_mut() else {
+            return;
+        };
+        if debug.error_stopped || matches!(error.untraced(), RuntimeError::Terminated) {
+            return;
+        }
+        debug.error_stopped = true;
+        let Some(loc) = error.trace().and_then(|trace| trace.loc).or(debug.loc) else {
+            return;
+        };
+        let _ = self.stop(StopReason::Error(error.untraced().to_string()), loc);
+    }
+
+    /// The index of the first breakpoint the statement at `loc` hits.
+    fn breakpoint_hit(&mut self, loc: Loc, bar_start: bool) -> Option<usize> {
+        let count = self.debug.as_ref()?.breakpoints.len();
+        for index in 0..count {
+            let condition = {
+                let debug = self.debug.as_ref()?;
+                let Armed {
+                    breakpoint,
+                    condition,
+                    enabled,
+                } = &debug.breakpoints[index];
+                if !enabled {
+                    continue;
+                }
+                let here = match breakpoint.line {
+                    Some(line) => {
+                        loc.line == line
+                            && breakpoint.file.as_deref() == self.current_file.as_deref()
+                    }
+                    None => bar_start,
+                };
+                let on_bar = breakpoint
+                    .bar_index
+                    .is_none_or(|bar| debug.bar_index == Some(bar));
+                if !(here && on_bar) {
+                    continue;
+                }
+                condition.clone()
+            };
+            let holds = match condition {
+                None => true,
+                // An na or failing condition does not hold.
+                Some(condition) => self
+                    .evaluate_quietly(&condition)
+                    .is_ok_and(|value| value.truthy_for_condition().unwrap_or(false)),
+            };
+            if holds {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    /// Hand the stopped script to the handler and set up how it goes on.
+    fn stop(&mut self, reason: StopReason, loc: Loc) -> Result<(), RuntimeError> {
+        let Some(debug) = self.debug.as_deref_mut() else {
+            return Ok(());
+        };
+        let Some(mut handler) = debug.handler.take() else {
+            return Ok(());
+        };
+        let stop = Stop {
+            reason,
+            loc,
+            file: self.current_file.as_deref().map(str::to_string),
+            bar_index: debug.bar_index,
+        };
+        let resume = handler.stopped(self, &stop);
+
+        let depth = self.call_stack.len();
+        let debug = self.debug_state();
+        debug.handler = Some(handler);
+        debug.stepping = match resume {
+            Resume::Continue | Resume::Terminate => Stepping::Run,
+            Resume::StepIn => Stepping::In,
+            Resume::StepOver => Stepping::Over(depth),
+            Resume::StepOut => Stepping::Out(depth),
+        };
+        if resume == Resume::Terminate {
+            return Err(RuntimeError::Terminated);
+        }
+        Ok(())
+    }
+}
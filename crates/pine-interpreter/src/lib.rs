@@ -1,33 +1,45 @@
+mod compile;
+mod debug;
 mod num;
+mod rollback;
 mod signature;
+mod slots;
+mod trace;
 
+pub use compile::{Compiled, Function, UserType};
+pub use debug::{Breakpoint, DebugHandler, Executed, Local, Resume, StackFrame, Stop, StopReason};
 pub use num::Num;
+pub use pine_ast::TypeQualifier;
 pub use signature::{BuiltinSignature, Param, ParamType};
+pub use trace::{Frame, Trace};
 
 use pine_core::{Color, DefaultPineOutput, PineOutput, MAX_LOOKBACK};
 
-use pine_ast::{Argument, BinOp, Expr, Literal, MethodParam, Program, Stmt, TypeField, UnOp};
+use compile::{Arg, Call, Callee, Capture, Constness, Expr, ExprKind, Stmt, StmtKind};
+use pine_ast::{BinOp, Loc, Program, UnOp, VarKind};
+use slots::{Names, Slot, Slots};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::rc::Rc;
 use thiserror::Error;
+use trace::ActiveCall;
 
 pub use pine_core::LibraryLoader;
 
-/// Record `value` as what `name` held on a completed bar, so `name[n]` can reach
-/// it. Entries beyond [`MAX_LOOKBACK`] are dropped.
+/// Record `value` as what a variable held on a completed bar, so `name[n]` can
+/// reach it. Entries beyond [`MAX_LOOKBACK`] are dropped.
 ///
-/// Takes the history map rather than `&mut self` so callers can hold a borrow of
+/// Takes the history rather than `&mut self` so callers can hold a borrow of
 /// another interpreter field while recording.
 fn push_history<O: PineOutput>(
-    history: &mut HashMap<String, Vec<Value<O>>>,
-    name: &str,
+    history: &mut Slots<VecDeque<Value<O>>>,
+    slot: Slot,
     value: Value<O>,
 ) {
-    let entries = history.entry(name.to_string()).or_default();
-    entries.push(value);
+    let entries = history.get_or_insert_with(slot, VecDeque::new);
+    entries.push_back(value);
     if entries.len() > MAX_LOOKBACK {
-        entries.drain(..entries.len() - MAX_LOOKBACK);
+        entries.pop_front();
     }
 }
 
@@ -80,14 +92,56 @@ pub enum RuntimeError {
 
     #[error("{0}")]
     UserError(String),
+
+    #[error("Invalid value for input '{title}': {reason}")]
+    InvalidInput { title: String, reason: String },
+
+    /// A timezone that is neither a UTC offset nor a zone in the tz database.
+    #[error("Invalid timezone '{0}'")]
+    InvalidTimezone(String),
+
+    /// A [`DebugHandler`] ended the run.
+    #[error("Run terminated by the debugger")]
+    Terminated,
+
+    /// An expression given to a debugger is not a valid Pine expression.
+    #[error("Invalid expression: {0}")]
+    InvalidExpression(String),
+
+    /// Another error, with where it struck. Every error
+    /// [`Interpreter::execute`] returns is traced.
+    #[error("{error}")]
+    Traced {
+        error: Box<RuntimeError>,
+        trace: Box<Trace>,
+    },
+}
+
+impl RuntimeError {
+    /// Where the error struck, once it has left the expression that raised it.
+    pub fn trace(&self) -> Option<&Trace> {
+        match self {
+            RuntimeError::Traced { trace, .. } => Some(trace),
+            _ => None,
+        }
+    }
+
+    /// The error itself, without its trace.
+    pub fn untraced(&self) -> &RuntimeError {
+        match self {
+            RuntimeError::Traced { error, .. } => error,
+            error => error,
+        }
+    }
 }
 
-/// Control flow signals for loops
-#[derive(Debug, Clone, PartialEq)]
+/// Control flow signals for loops. A `break`/`continue` carries where it was
+/// written, for the error if no loop is there to take it.
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum LoopControl {
     None,
-    Break,
-    Continue,
+    Break(Option<Loc>),
+    Continue(Option<Loc>),
 }
 
 /// Variable storage with const qualifier tracking
@@ -104,7 +158,7 @@ struct Variable<O: PineOutput = DefaultPineOutput> {
 pub struct Series<O: PineOutput = DefaultPineOutput> {
     pub id: String,
     pub current: Box<Value<O>>,
-    pub history: Option<Rc<RefCell<Vec<Value<O>>>>>,
+    pub history: Option<Rc<RefCell<VecDeque<Value<O>>>>>,
 }
 
 /// The lazy scalar an object carries, so a single name can be *both* a namespace
@@ -134,24 +188,18 @@ pub enum Value<O: PineOutput> {
         call: Option<Builtin<O>>,
         value: Option<ObjectValueFn<O>>,
     },
-    Function {
-        params: Vec<pine_ast::FunctionParam>,
-        body: Vec<Stmt>,
-    },
+    Function(Rc<Function<O>>),
     BuiltinFunction(Builtin<O>), // Builtin callable plus the arguments it accepts
     /// An unevaluated expression, passed to a builtin that captured it (a lazy
     /// parameter) to run in another context — e.g. `request.security`.
-    Expr(Rc<Expr>),
-    Type {
-        name: String,
-        fields: Vec<TypeField>,
-    }, // User-defined type
+    Expr(Rc<pine_ast::Expr>),
+    Type(Rc<UserType<O>>), // User-defined type
     Enum {
         enum_name: String,  // The enum type name (e.g., "Signal")
         field_name: String, // The specific field/member name (e.g., "buy")
         title: String,      // The title of this enum member
     }, // Enum member value
-    Color(Color), // Color value
+    Color(Color),          // Color value
     Matrix {
         element_type: String, // Type of elements: "int", "float", "string", "bool"
         data: Rc<RefCell<Vec<Vec<Value<O>>>>>, // 2D matrix - mutable shared reference to rows of columns
@@ -193,10 +241,10 @@ impl<O: PineOutput> std::fmt::Debug for Value<O> {
             Value::Object {
                 type_name, fields, ..
             } => write!(f, "Object({}:{:?})", type_name, fields),
-            Value::Function { params, .. } => write!(f, "Function({} params)", params.len()),
+            Value::Function(function) => write!(f, "Function({} params)", function.arity()),
             Value::BuiltinFunction(_) => write!(f, "BuiltinFunction"),
             Value::Expr(_) => write!(f, "Expr"),
-            Value::Type { name, .. } => write!(f, "Type({})", name),
+            Value::Type(ty) => write!(f, "Type({})", ty.name),
             Value::Enum {
                 enum_name,
                 field_name,
@@ -239,10 +287,10 @@ impl<O: PineOutput> PartialEq for Value<O> {
             (Value::Series(a), Value::Series(b)) => a.id == b.id && *a.current == *b.current,
             (Value::Object { fields: a, .. }, Value::Object { fields: b, .. }) => Rc::ptr_eq(a, b),
             // Functions never equal (can't compare closures or function pointers)
-            (Value::Function { .. }, Value::Function { .. }) => false,
+            (Value::Function(_), Value::Function(_)) => false,
             (Value::BuiltinFunction(_), Value::BuiltinFunction(_)) => false,
             // Types compare by name
-            (Value::Type { name: a, .. }, Value::Type { name: b, .. }) => a == b,
+            (Value::Type(a), Value::Type(b)) => a.name == b.name,
             // Enums compare by enum name and field name (ensuring type safety)
             (
                 Value::Enum {
@@ -439,18 +487,18 @@ impl<O: PineOutput> Value<O> {
 
 /// Method definition stored in the interpreter
 #[derive(Clone)]
-struct MethodDef {
+struct MethodDef<O: PineOutput> {
     type_name: String, // The type this method belongs to (from first param's type annotation)
-    params: Vec<pine_ast::MethodParam>,
-    body: Vec<Stmt>,
+    method: Rc<Function<O>>,
 }
 
 /// One history-carrying subscript site — `expr[n]` where `expr` is not a plain
 /// variable (a call, arithmetic, …). Mirrors `user_series_history`, keyed by the
 /// `Expr::Index` node's stable id, so `(expr)[n]` matches `v = expr; v[n]`.
+#[derive(Clone)]
 struct SeriesSite<O: PineOutput> {
     /// Past bars, oldest first; the last entry is the previous bar.
-    history: Vec<Value<O>>,
+    history: VecDeque<Value<O>>,
     /// This bar's value, once the site has been evaluated on it.
     current: Option<Value<O>>,
     /// `bar_seq` when `current` was recorded, so history rolls once per bar.
@@ -460,7 +508,7 @@ struct SeriesSite<O: PineOutput> {
 impl<O: PineOutput> SeriesSite<O> {
     fn new() -> Self {
         Self {
-            history: Vec::new(),
+            history: VecDeque::new(),
             current: None,
             bar: 0,
         }
@@ -469,16 +517,25 @@ impl<O: PineOutput> SeriesSite<O> {
 
 /// The interpreter executes a program with a given bar
 pub struct Interpreter<O: PineOutput> {
-    /// Local variables in the current scope
-    variables: HashMap<String, Variable<O>>,
+    /// The slot each variable name resolves to, shared with the interpreters
+    /// this one spawns for libraries and `request.security`.
+    names: Rc<RefCell<Names>>,
+    /// Variables in the current scope, by slot
+    variables: Slots<Variable<O>>,
     /// User-defined types, kept separate from `variables` so a UDT and a
     /// function/variable may share a name (Pine's type and value namespaces are
     /// distinct). `Type.new` / `Type.copy` resolve here.
     user_types: HashMap<String, Value<O>>,
+    /// Each declared enum's members, name and title, in declaration order, so
+    /// `input.enum` can list its choices.
+    enums: HashMap<String, Vec<(String, String)>>,
     /// Method registry (method_name -> Vec<MethodDef>) - can have multiple methods with same name for different types
-    methods: HashMap<String, Vec<MethodDef>>,
+    methods: HashMap<String, Vec<MethodDef<O>>>,
     /// Library loader for importing external libraries
     pub library_loader: Option<Box<dyn LibraryLoader>>,
+    /// Imported libraries' namespaces by path, loaded the first time an
+    /// `import` runs and rebound on every later bar.
+    libraries: HashMap<String, Value<O>>,
     /// Exported items from this module (for library mode)
     exports: HashMap<String, Value<O>>,
     /// Output storage for plots, labels, logs, etc.
@@ -486,7 +543,7 @@ pub struct Interpreter<O: PineOutput> {
     /// Per-variable history for user-computed series (`var` declarations).
     /// history[len-1] = previous bar, history[len-2] = two bars ago, etc.
     /// Populated on each `Stmt::Assignment`; supports Pine's `name[n]` lookback.
-    pub user_series_history: HashMap<String, Vec<Value<O>>>,
+    user_series_history: Slots<VecDeque<Value<O>>>,
     /// History for subscripted non-variable series expressions (`ta.sma(..)[1]`,
     /// `(high+low)[1]`), keyed by the `Expr::Index` node's id — the same
     /// site-keyed pattern as `function_local_state`.
@@ -497,9 +554,9 @@ pub struct Interpreter<O: PineOutput> {
     /// state, mirroring TradingView (e.g. `o[1]` inside a function returns the
     /// previous bar's value of that call site's local `o`). A `call_id` of 0
     /// (a call with no stable identity) is not persisted.
-    function_local_state: HashMap<u32, HashMap<String, Variable<O>>>,
+    function_local_state: HashMap<u32, Vec<(Slot, Variable<O>)>>,
     /// `var`/`varip` declarations whose initializer already ran, keyed by
-    /// (call-site id, name). Pine `var` initializes only the FIRST time
+    /// (call-site id, variable). Pine `var` initializes only the FIRST time
     /// execution reaches the declaration (once ever, not per bar/iteration).
     /// The call-site id (0 at top level) scopes it per call site, so the same
     /// function-local `var` at two call sites initializes independently.
@@ -508,13 +565,25 @@ pub struct Interpreter<O: PineOutput> {
     ///
     /// The value is the bar it initialized on, so a reassignment can tell that
     /// there is no previous bar to read back yet.
-    var_decls_initialized: HashMap<(u32, String), u64>,
+    var_decls_initialized: HashMap<(u32, Slot), u64>,
+    /// The `varip` subset of `var_decls_initialized`: the variables a
+    /// [`rollback`](Self::rollback) leaves as they are.
+    varip_decls: HashSet<(u32, Slot)>,
     /// Lexical id of the call site currently executing (0 at top level). Scopes
     /// `var` init-once tracking to the active call site.
     current_call_id: u32,
     /// Counts bars executed. Stateful builtins compare against it to advance
     /// their state at most once per bar, however often their call site runs.
     bar_seq: u64,
+    /// Counts executions, including each re-run of a bar after a
+    /// [`rollback`](Self::rollback). Together with `bar_seq` it tells a
+    /// stateful builtin whether it is re-entered within one run or re-run on a
+    /// later tick of the same bar.
+    update_seq: u64,
+    /// The state the bar being re-run started from; see [`rollback`](Self::rollback).
+    checkpoint: Option<rollback::Checkpoint<O>>,
+    /// Set by a rollback: the next `execute` re-runs the current bar.
+    replaying: bool,
     /// The simulated broker a `strategy` script trades against. `None` for an
     /// `indicator`. The `strategy.*` order builtins reach it through `ctx`.
     pub broker: Option<Box<dyn pine_broker::Broker>>,
@@ -526,56 +595,18 @@ pub struct Interpreter<O: PineOutput> {
     /// The current bar's opening time (UNIX ms), the raw datum every date name
     /// (`time`, `year`, …) derives its bare value from. Set by the host each bar.
     pub current_time: Option<i64>,
+    /// The next chart bar's opening time, when the host knows it: the end of
+    /// the current bar's intrabars. Set by the host each bar.
+    pub next_bar_time: Option<i64>,
     pub per_bar_advances: Vec<PerBarAdvance<O>>,
     /// Host-supplied `input.*` overrides, keyed by the input's title.
     pub inputs: HashMap<String, pine_core::InputValue>,
-}
-
-/// Names a statement block ASSIGNS (declares or writes) directly — i.e. the true
-/// locals of a function body. Reads (e.g. `open`) are ignored, and nested function
-/// declarations are a separate scope so their bodies are not descended into. Used to
-/// decide which variables a call site's persistent state should carry across bars.
-fn collect_assigned_names(body: &[Stmt], out: &mut std::collections::HashSet<String>) {
-    for s in body {
-        match s {
-            Stmt::VarDecl { name, .. } => {
-                out.insert(name.clone());
-            }
-            Stmt::Assignment {
-                target: Expr::Variable { name: n, .. },
-                ..
-            } => {
-                out.insert(n.clone());
-            }
-            Stmt::TupleAssignment { names, .. } => {
-                for n in names {
-                    out.insert(n.clone());
-                }
-            }
-            Stmt::If {
-                then_branch,
-                else_if_branches,
-                else_branch,
-                ..
-            } => {
-                collect_assigned_names(then_branch, out);
-                for (_, b) in else_if_branches {
-                    collect_assigned_names(b, out);
-                }
-                if let Some(b) = else_branch {
-                    collect_assigned_names(b, out);
-                }
-            }
-            Stmt::For { var_name, body, .. } => {
-                out.insert(var_name.clone());
-                collect_assigned_names(body, out);
-            }
-            Stmt::While { body, .. } | Stmt::ForIn { body, .. } => {
-                collect_assigned_names(body, out)
-            }
-            _ => {}
-        }
-    }
+    /// The library whose code is running; `None` for the main script.
+    current_file: Option<Rc<str>>,
+    /// The user function and method calls in progress, outermost first.
+    call_stack: Vec<ActiveCall>,
+    /// Breakpoints, stepping and the handler, once a debugger is attached.
+    debug: Option<Box<debug::DebugState<O>>>,
 }
 
 /// The builtin namespace whose functions back a value's method syntax, e.g.
@@ -598,29 +629,51 @@ fn is_na_operand<O: PineOutput>(v: &Value<O>) -> bool {
 
 impl<O: PineOutput> Interpreter<O> {
     pub fn new() -> Self {
+        Self::with_names(Rc::default())
+    }
+
+    fn with_names(names: Rc<RefCell<Names>>) -> Self {
         Self {
-            variables: HashMap::new(),
+            names,
+            variables: Slots::default(),
             user_types: HashMap::new(),
+            enums: HashMap::new(),
             methods: HashMap::new(),
             library_loader: None,
+            libraries: HashMap::new(),
             exports: HashMap::new(),
             output: O::default(),
-            user_series_history: HashMap::new(),
+            user_series_history: Slots::default(),
             expr_history: HashMap::new(),
             function_local_state: HashMap::new(),
             var_decls_initialized: HashMap::new(),
+            varip_decls: HashSet::new(),
             current_call_id: 0,
             bar_seq: 0,
+            update_seq: 0,
+            checkpoint: None,
+            replaying: false,
             broker: None,
             broker_factory: Some(Box::new(pine_broker::DefaultBrokerFactory)),
             request_provider: None,
             chart_period: None,
             current_time: None,
+            next_bar_time: None,
             per_bar_advances: Vec::new(),
             inputs: HashMap::new(),
+            current_file: None,
+            call_stack: Vec::new(),
+            debug: None,
         }
     }
 
+    /// A fresh interpreter, with no variables, that resolves names to the same
+    /// slots as this one — so a function value taken from this interpreter
+    /// (say, in a [`snapshot`](Self::snapshot)) can be called from it.
+    pub fn child(&self) -> Self {
+        Self::with_names(Rc::clone(&self.names))
+    }
+
     /// A host-supplied `input.*` override for `title`, if any. An empty title
     /// (an untitled input) is never overridable.
     pub fn input(&self, title: &str) -> Option<&pine_core::InputValue> {
@@ -637,6 +690,12 @@ impl<O: PineOutput> Interpreter<O> {
         self.bar_seq
     }
 
+    /// How many times the program has been executed. Differs from
+    /// [`bar_seq`](Self::bar_seq) once a bar has been rolled back and re-run.
+    pub fn update_seq(&self) -> u64 {
+        self.update_seq
+    }
+
     /// Set the library loader
     pub fn set_library_loader(&mut self, library_loader: Box<dyn LibraryLoader>) {
         self.library_loader = Some(library_loader);
@@ -646,9 +705,10 @@ impl<O: PineOutput> Interpreter<O> {
     /// (`request.security`) can start from the same namespaces and builtins
     /// without re-registering them.
     pub fn snapshot(&self) -> HashMap<String, Value<O>> {
+        let names = self.names.borrow();
         self.variables
             .iter()
-            .map(|(name, var)| (name.clone(), var.value.clone()))
+            .map(|(slot, var)| (names.name(slot).to_string(), var.value.clone()))
             .collect()
     }
 
@@ -657,19 +717,41 @@ impl<O: PineOutput> Interpreter<O> {
         &self.exports
     }
 
-    /// Execute a program with a single bar
+    /// Compile `program` for [`run`](Self::run). Calls to the builtins
+    /// registered by now are bound to them here rather than looked up on
+    /// every bar, so compile after registering them.
+    pub fn compile(&self, program: &Program) -> Compiled<O> {
+        compile::Compiler::new(self, program).program(program)
+    }
+
+    /// Compile and run a program on a single bar. A host running many bars
+    /// should [`compile`](Self::compile) once and [`run`](Self::run) each.
     pub fn execute(&mut self, program: &Program) -> Result<O, RuntimeError> {
+        let compiled = self.compile(program);
+        self.run(&compiled)
+    }
+
+    /// Run a compiled program on a single bar
+    pub fn run(&mut self, program: &Compiled<O>) -> Result<O, RuntimeError> {
         // Clear output from previous iteration
         self.output.clear();
-        // A new bar: stateful builtins may advance their state again.
-        self.bar_seq += 1;
+        // A new bar: stateful builtins may advance their state again. A re-run
+        // after a rollback stays on the bar it rolled back to.
+        if !std::mem::take(&mut self.replaying) {
+            self.bar_seq += 1;
+        }
+        self.update_seq += 1;
 
         for advance in self.per_bar_advances.clone() {
             advance(self);
         }
+        if self.debug.is_some() {
+            self.debug_bar(&program.globals);
+        }
 
         for stmt in &program.statements {
-            self.execute_stmt(stmt)?;
+            self.execute_outside_loop(stmt)
+                .map_err(|error| self.traced_to_bar(error))?;
         }
 
         // Return a clone of the output
@@ -678,7 +760,15 @@ impl<O: PineOutput> Interpreter<O> {
 
     /// Get a variable value
     pub fn get_variable(&self, name: &str) -> Option<&Value<O>> {
-        self.variables.get(name).map(|var| &var.value)
+        let slot = self.names.borrow().get(name)?;
+        self.variables.get(slot).map(|var| &var.value)
+    }
+
+    /// What `name` held on the bars before this one, oldest first and at
+    /// most [`MAX_LOOKBACK`] of them; `None` if it has no recorded history.
+    pub fn series_history(&self, name: &str) -> Option<&VecDeque<Value<O>>> {
+        let slot = self.names.borrow().get(name)?;
+        self.user_series_history.get(slot)
     }
 
     /// Whether `name` is a declared user-defined type.
@@ -686,18 +776,34 @@ impl<O: PineOutput> Interpreter<O> {
         self.user_types.contains_key(name)
     }
 
+    /// The members of the enum `name`, each its name and title, in
+    /// declaration order; `None` for an enum this script did not declare.
+    pub fn enum_members(&self, name: &str) -> Option<&[(String, String)]> {
+        self.enums.get(name).map(Vec::as_slice)
+    }
+
     /// The `member` field of a builtin namespace object (e.g. `array`'s `push`).
     fn namespace_member(&self, namespace: &str, member: &str) -> Option<Value<O>> {
-        match self.variables.get(namespace).map(|var| &var.value) {
+        match self.get_variable(namespace) {
             Some(Value::Object { fields, .. }) => fields.borrow().get(member).cloned(),
             _ => None,
         }
     }
 
+    /// The name a slot stands for, for an error message.
+    fn name_of(&self, slot: Slot) -> String {
+        self.names.borrow().name(slot).to_string()
+    }
+
+    fn slot(&self, name: &str) -> Slot {
+        self.names.borrow_mut().slot(name)
+    }
+
     /// Set a variable value (useful for loading objects and test setup)
     pub fn set_variable(&mut self, name: &str, value: Value<O>) {
+        let slot = self.slot(name);
         self.variables.insert(
-            name.to_string(),
+            slot,
             Variable {
                 value,
                 is_const: false,
@@ -713,14 +819,15 @@ impl<O: PineOutput> Interpreter<O> {
     /// as any user variable: history accumulates as bars execute, so `close[1]`
     /// is na until a second bar has run.
     pub fn advance_series(&mut self, name: &str, value: Value<O>) {
-        if let Some(existing) = self.variables.get(name) {
+        let slot = self.slot(name);
+        if let Some(existing) = self.variables.get(slot) {
             // Record the number the series held, not the series wrapper, so a
             // `name[1]` lookback reads as a plain value.
             let previous = match &existing.value {
                 Value::Series(series) => (*series.current).clone(),
                 other => other.clone(),
             };
-            push_history(&mut self.user_series_history, name, previous);
+            push_history(&mut self.user_series_history, slot, previous);
         }
         self.set_variable(name, value);
     }
@@ -729,19 +836,16 @@ impl<O: PineOutput> Interpreter<O> {
     /// leaving the object's other fields untouched. A no-op if `object` is not
     /// a registered namespace object.
     pub fn set_object_field(&mut self, object: &str, field: &str, value: Value<O>) {
-        if let Some(Variable {
-            value: Value::Object { fields, .. },
-            ..
-        }) = self.variables.get(object)
-        {
+        if let Some(Value::Object { fields, .. }) = self.get_variable(object) {
             fields.borrow_mut().insert(field.to_string(), value);
         }
     }
 
     /// Set a const variable (cannot be reassigned)
     pub fn set_const_variable(&mut self, name: &str, value: Value<O>) {
+        let slot = self.slot(name);
         self.variables.insert(
-            name.to_string(),
+            slot,
             Variable {
                 value,
                 is_const: true,
@@ -758,35 +862,45 @@ impl<O: PineOutput> Interpreter<O> {
         }
     }
 
-    /// Helper to evaluate arguments and validate positional-before-named rule
-    /// Evaluate a call's arguments. A parameter marked lazy in `signature`
-    /// receives its argument unevaluated, as a captured [`Value::Expr`].
+    /// Evaluate a call's arguments. An argument bound to a parameter marked
+    /// lazy — in `signature`, or already when the call was compiled — is
+    /// passed unevaluated, as a captured [`Value::Expr`].
     fn evaluate_arguments(
         &mut self,
-        args: &[Argument],
+        args: &[Arg<O>],
         signature: Option<&BuiltinSignature>,
     ) -> Result<Vec<EvaluatedArg<O>>, RuntimeError> {
-        let mut evaluated_args = Vec::new();
+        let mut evaluated_args = Vec::with_capacity(args.len());
         let mut seen_named = false;
         let mut positional_index = 0;
 
         for arg in args {
-            match arg {
-                Argument::Positional(expr) => {
-                    if seen_named {
-                        return Err(RuntimeError::TypeError(
-                            "Positional arguments cannot follow named arguments".to_string(),
-                        ));
-                    }
-                    let lazy = signature.is_some_and(|s| s.positional_is_lazy(positional_index));
-                    let value = self.eval_or_capture(expr, lazy)?;
+            if arg.name.is_none() && seen_named {
+                return Err(RuntimeError::TypeError(
+                    "Positional arguments cannot follow named arguments".to_string(),
+                ));
+            }
+            let captured = match &arg.capture {
+                Capture::Never => None,
+                Capture::Always(expr) => Some(expr),
+                Capture::IfLazy(expr) => signature
+                    .is_some_and(|s| match &arg.name {
+                        Some(name) => s.named_is_lazy(name),
+                        None => s.positional_is_lazy(positional_index),
+                    })
+                    .then_some(expr),
+            };
+            let value = match captured {
+                Some(expr) => Value::Expr(Rc::clone(expr)),
+                None => self.eval_expr(&arg.value)?,
+            };
+            match &arg.name {
+                None => {
                     evaluated_args.push(EvaluatedArg::Positional(value));
                     positional_index += 1;
                 }
-                Argument::Named { name, value: expr } => {
+                Some(name) => {
                     seen_named = true;
-                    let lazy = signature.is_some_and(|s| s.named_is_lazy(name));
-                    let value = self.eval_or_capture(expr, lazy)?;
                     evaluated_args.push(EvaluatedArg::Named {
                         name: name.clone(),
                         value,
@@ -798,29 +912,56 @@ impl<O: PineOutput> Interpreter<O> {
         Ok(evaluated_args)
     }
 
-    /// Evaluate `expr`, or capture it unevaluated as a [`Value::Expr`] when the
-    /// parameter it binds to is lazy.
-    fn eval_or_capture(&mut self, expr: &Expr, lazy: bool) -> Result<Value<O>, RuntimeError> {
-        if lazy {
-            Ok(Value::Expr(Rc::new(expr.clone())))
-        } else {
-            self.eval_expr(expr)
+    fn execute_stmt(&mut self, stmt: &Stmt<O>) -> Result<LoopControl, RuntimeError> {
+        if self.debug.is_some() {
+            self.debug_statement(stmt.loc)?;
         }
+        self.execute_stmt_raw(stmt)
+            .map_err(|error| self.traced_and_stopped(error, stmt.loc))
     }
 
-    fn execute_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value<O>>, RuntimeError> {
-        match stmt {
-            Stmt::VarDecl {
-                name,
-                type_qualifier,
-                type_annotation: _,
+    /// Trace an error leaving a statement, stopping a debugged script where
+    /// it struck while the statement's variables are still in scope.
+    fn traced_and_stopped(&mut self, error: RuntimeError, loc: Option<Loc>) -> RuntimeError {
+        let error = self.traced(error, loc);
+        if self.debug.is_some() {
+            self.debug_error(&error);
+        }
+        error
+    }
+
+    /// Execute a statement no loop encloses, where a `break`/`continue` has
+    /// nothing to leave.
+    fn execute_outside_loop(&mut self, stmt: &Stmt<O>) -> Result<(), RuntimeError> {
+        match self.execute_stmt(stmt)? {
+            LoopControl::None => Ok(()),
+            LoopControl::Break(loc) => Err(self.traced(RuntimeError::BreakOutsideLoop, loc)),
+            LoopControl::Continue(loc) => Err(self.traced(RuntimeError::ContinueOutsideLoop, loc)),
+        }
+    }
+
+    /// Execute a block, stopping at a `break`/`continue` for the enclosing
+    /// loop to act on.
+    fn execute_block(&mut self, body: &[Stmt<O>]) -> Result<LoopControl, RuntimeError> {
+        for stmt in body {
+            let control = self.execute_stmt(stmt)?;
+            if control != LoopControl::None {
+                return Ok(control);
+            }
+        }
+        Ok(LoopControl::None)
+    }
+
+    fn execute_stmt_raw(&mut self, stmt: &Stmt<O>) -> Result<LoopControl, RuntimeError> {
+        match &stmt.kind {
+            StmtKind::VarDecl {
+                slot,
+                is_const,
+                kind,
                 initializer,
-                // varip's intrabar-update behavior is not yet implemented; it
-                // persists across bars exactly like var (is_persistent()).
-                var_kind,
-                ..
             } => {
-                let is_var_persistent = var_kind.is_persistent();
+                let slot = *slot;
+                let is_var_persistent = kind.is_persistent();
                 // Pine `var`/`varip` semantics: the initializer runs only the
                 // FIRST time execution reaches this declaration (once ever).
                 // Scoped by the current call site (0 at top level) so the same
@@ -828,9 +969,12 @@ impl<O: PineOutput> Interpreter<O> {
                 // independently. Tracked separately from `variables` so a `var`
                 // declaration can shadow a pre-existing host-injected builtin.
                 if is_var_persistent {
-                    let init_key = (self.current_call_id, name.clone());
+                    let init_key = (self.current_call_id, slot);
                     if self.var_decls_initialized.contains_key(&init_key) {
-                        return Ok(None);
+                        return Ok(LoopControl::None);
+                    }
+                    if *kind == VarKind::Varip {
+                        self.varip_decls.insert(init_key);
                     }
                     self.var_decls_initialized.insert(init_key, self.bar_seq);
                 }
@@ -838,28 +982,27 @@ impl<O: PineOutput> Interpreter<O> {
                 // Push the previous value to history so `name[1]` lookbacks work, exactly as
                 // the Assignment handler does for `:=` reassignments.
                 if !is_var_persistent {
-                    if let Some(existing) = self.variables.get(name) {
-                        push_history(&mut self.user_series_history, name, existing.value.clone());
+                    if let Some(existing) = self.variables.get(slot) {
+                        push_history(&mut self.user_series_history, slot, existing.value.clone());
                     }
                 }
-                let value = if let Some(init_expr) = initializer {
-                    self.eval_expr(init_expr)?
-                } else {
-                    Value::Na
+                let value = match initializer {
+                    Some(init_expr) => self.eval_expr(init_expr)?,
+                    None => Value::Na,
                 };
-                let is_const = matches!(type_qualifier, Some(pine_ast::TypeQualifier::Const));
                 self.variables.insert(
-                    name.clone(),
+                    slot,
                     Variable {
                         value,
-                        is_const,
+                        is_const: *is_const,
                         is_var_persistent,
                     },
                 );
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::Assignment { target, value } => {
+            StmtKind::Assign { slot, value } => {
+                let slot = *slot;
                 // Pine `var`-persistent variables: push their current (previous-bar) value to
                 // history BEFORE evaluating the RHS so that [1] lookback in the expression
                 // sees the correct previous-bar value.  Non-var variables push after eval
@@ -868,95 +1011,92 @@ impl<O: PineOutput> Interpreter<O> {
                 // Nothing is pushed on the bar the `var` initialized: there is no
                 // previous bar yet, and inventing one would make `acc[1]` read the
                 // initializer instead of na.
-                if let Expr::Variable { name, .. } = target {
-                    if let Some(var) = self.variables.get(name) {
-                        let born_this_bar = self
-                            .var_decls_initialized
-                            .get(&(self.current_call_id, name.clone()))
-                            == Some(&self.bar_seq);
-                        if var.is_var_persistent && !born_this_bar {
-                            push_history(&mut self.user_series_history, name, var.value.clone());
-                        }
+                if let Some(var) = self.variables.get(slot) {
+                    let born_this_bar = self
+                        .var_decls_initialized
+                        .get(&(self.current_call_id, slot))
+                        == Some(&self.bar_seq);
+                    if var.is_var_persistent && !born_this_bar {
+                        push_history(&mut self.user_series_history, slot, var.value.clone());
                     }
                 }
 
                 let val = self.eval_expr(value)?;
-                match target {
-                    Expr::Variable { name, .. } => {
-                        // Preserve the existing variable's flags (const, persistent).
-                        let (is_const, is_var_persistent) =
-                            if let Some(var) = self.variables.get(name) {
-                                if var.is_const {
-                                    return Err(RuntimeError::ConstReassignment(name.clone()));
-                                }
-                                if !var.is_var_persistent {
-                                    // Non-var: push current value to history after eval (Pine [n] lookback).
-                                    push_history(
-                                        &mut self.user_series_history,
-                                        name,
-                                        var.value.clone(),
-                                    );
-                                }
-                                // var-persistent: already pushed before eval above.
-                                (false, var.is_var_persistent)
-                            } else {
-                                (false, false)
-                            };
+                // Preserve the existing variable's flags (const, persistent).
+                match self.variables.get_mut(slot) {
+                    Some(var) => {
+                        if var.is_const {
+                            return Err(RuntimeError::ConstReassignment(self.name_of(slot)));
+                        }
+                        let previous = std::mem::replace(&mut var.value, val);
+                        if !var.is_var_persistent {
+                            // Non-var: push the replaced value to history after eval (Pine [n] lookback).
+                            push_history(&mut self.user_series_history, slot, previous);
+                        }
+                        // var-persistent: already pushed before eval above.
+                    }
+                    None => self.variables.insert(
+                        slot,
+                        Variable {
+                            value: val,
+                            is_const: false,
+                            is_var_persistent: false,
+                        },
+                    ),
+                }
+                Ok(LoopControl::None)
+            }
 
-                        self.variables.insert(
-                            name.clone(),
-                            Variable {
-                                value: val,
-                                is_const,
-                                is_var_persistent,
-                            },
-                        );
-                        Ok(None)
+            StmtKind::AssignMember {
+                object,
+                member,
+                root,
+                value,
+            } => {
+                let val = self.eval_expr(value)?;
+                // Check if we're trying to modify a member of a const variable
+                if let Some(root) = root {
+                    if self.variables.get(*root).is_some_and(|var| var.is_const) {
+                        return Err(RuntimeError::ConstReassignment(format!(
+                            "{}.{}",
+                            self.name_of(*root),
+                            member
+                        )));
                     }
-                    Expr::MemberAccess { object, member, .. } => {
-                        // Check if we're trying to modify a member of a const variable
-                        if let Expr::Variable { name: var_name, .. } = object.as_ref() {
-                            if let Some(var) = self.variables.get(var_name) {
-                                if var.is_const {
-                                    return Err(RuntimeError::ConstReassignment(format!(
-                                        "{}.{}",
-                                        var_name, member
-                                    )));
-                                }
-                            }
-                        }
+                }
 
-                        // Get the object
-                        let obj_value = self.eval_expr(object)?;
+                // Get the object
+                let obj_value = self.eval_expr(object)?;
 
-                        if let Value::Object { fields, .. } = obj_value {
-                            let mut obj = fields.borrow_mut();
-                            obj.insert(member.clone(), val);
-                            Ok(None)
-                        } else {
-                            Err(RuntimeError::TypeError(
-                                "Cannot assign to member of non-object value".to_string(),
-                            ))
-                        }
-                    }
-                    _ => Err(RuntimeError::TypeError(
-                        "Invalid assignment target".to_string(),
-                    )),
+                if let Value::Object { fields, .. } = obj_value {
+                    fields.borrow_mut().insert(member.clone(), val);
+                    Ok(LoopControl::None)
+                } else {
+                    Err(RuntimeError::TypeError(
+                        "Cannot assign to member of non-object value".to_string(),
+                    ))
                 }
             }
 
-            Stmt::TupleAssignment { names, value, .. } => {
+            StmtKind::AssignInvalid(value) => {
+                self.eval_expr(value)?;
+                Err(RuntimeError::TypeError(
+                    "Invalid assignment target".to_string(),
+                ))
+            }
+
+            StmtKind::Tuple { slots, value } => {
                 let val = self.eval_expr(value)?;
                 if let Value::Array(arr_ref) = val {
                     let arr = arr_ref.borrow();
-                    for (i, name) in names.iter().enumerate() {
+                    for (i, slot) in slots.iter().enumerate() {
                         // Push current value to history before overwriting (supports [n] lookback).
-                        if let Some(var) = self.variables.get(name) {
-                            push_history(&mut self.user_series_history, name, var.value.clone());
+                        if let Some(var) = self.variables.get(*slot) {
+                            push_history(&mut self.user_series_history, *slot, var.value.clone());
                         }
                         let element_val = arr.get(i).cloned().unwrap_or(Value::Na);
                         self.variables.insert(
-                            name.clone(),
+                            *slot,
                             Variable {
                                 value: element_val,
                                 is_const: false,
@@ -964,7 +1104,7 @@ impl<O: PineOutput> Interpreter<O> {
                             },
                         );
                     }
-                    Ok(None)
+                    Ok(LoopControl::None)
                 } else {
                     Err(RuntimeError::TypeError(
                         "Expected array for tuple destructuring".to_string(),
@@ -972,55 +1112,33 @@ impl<O: PineOutput> Interpreter<O> {
                 }
             }
 
-            Stmt::Expression(expr) => {
+            StmtKind::Expression(expr) => {
                 self.eval_expr(expr)?;
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::If {
-                condition,
-                then_branch,
-                else_if_branches,
-                else_branch,
+            StmtKind::If {
+                branches,
+                otherwise,
             } => {
-                let cond_value = self.eval_expr(condition)?;
-                if cond_value.truthy_for_condition()? {
-                    for stmt in then_branch {
-                        self.execute_stmt(stmt)?;
-                    }
-                } else {
-                    // Try each else if branch in order
-                    let mut executed = false;
-                    for (else_if_cond, else_if_body) in else_if_branches {
-                        let else_if_value = self.eval_expr(else_if_cond)?;
-                        if else_if_value.truthy_for_condition()? {
-                            for stmt in else_if_body {
-                                self.execute_stmt(stmt)?;
-                            }
-                            executed = true;
-                            break;
-                        }
-                    }
-
-                    // If no else if matched, try else branch
-                    if !executed {
-                        if let Some(else_stmts) = else_branch {
-                            for stmt in else_stmts {
-                                self.execute_stmt(stmt)?;
-                            }
-                        }
+                // The first branch whose condition holds runs, else the `else`.
+                for (condition, body) in branches {
+                    if self.eval_expr(condition)?.truthy_for_condition()? {
+                        return self.execute_block(body);
                     }
                 }
-                Ok(None)
+                match otherwise {
+                    Some(body) => self.execute_block(body),
+                    None => Ok(LoopControl::None),
+                }
             }
 
-            Stmt::For {
-                var_name,
+            StmtKind::For {
+                slot,
                 from,
                 to,
                 step,
                 body,
-                ..
             } => {
                 let from_val = self.eval_expr(from)?.as_number()?;
                 let to_val = self.eval_expr(to)?.as_number()?;
@@ -1042,7 +1160,7 @@ impl<O: PineOutput> Interpreter<O> {
 
                 while if down { i >= end } else { i <= end } {
                     self.variables.insert(
-                        var_name.clone(),
+                        *slot,
                         Variable {
                             value: Value::Int(i),
                             is_const: false,
@@ -1050,8 +1168,7 @@ impl<O: PineOutput> Interpreter<O> {
                         },
                     );
 
-                    let control = self.execute_loop_body(body)?;
-                    if control == LoopControl::Break {
+                    if let LoopControl::Break(_) = self.execute_block(body)? {
                         break;
                     }
 
@@ -1062,27 +1179,26 @@ impl<O: PineOutput> Interpreter<O> {
                     }
                 }
 
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::ForIn {
-                index_var,
-                item_var,
+            StmtKind::ForIn {
+                index,
+                item,
                 collection,
                 body,
-                ..
             } => {
                 let collection_value = self.eval_expr(collection)?;
                 let arr = collection_value.as_array()?;
                 let arr_borrowed = arr.borrow();
 
-                for (index, item) in arr_borrowed.iter().enumerate() {
+                for (i, element) in arr_borrowed.iter().enumerate() {
                     // Set index variable if tuple form
-                    if let Some(idx_var) = index_var {
+                    if let Some(index) = index {
                         self.variables.insert(
-                            idx_var.clone(),
+                            *index,
                             Variable {
-                                value: Value::Int(index as i64),
+                                value: Value::Int(i as i64),
                                 is_const: false,
                                 is_var_persistent: false,
                             },
@@ -1091,55 +1207,45 @@ impl<O: PineOutput> Interpreter<O> {
 
                     // Set item variable
                     self.variables.insert(
-                        item_var.clone(),
+                        *item,
                         Variable {
-                            value: item.clone(),
+                            value: element.clone(),
                             is_const: false,
                             is_var_persistent: false,
                         },
                     );
 
-                    let control = self.execute_loop_body(body)?;
-                    if control == LoopControl::Break {
+                    if let LoopControl::Break(_) = self.execute_block(body)? {
                         break;
                     }
                 }
 
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::While { condition, body } => {
+            StmtKind::While { condition, body } => {
                 loop {
                     let cond_value = self.eval_expr(condition)?;
                     if !cond_value.truthy_for_condition()? {
                         break;
                     }
 
-                    let control = self.execute_loop_body(body)?;
-                    if control == LoopControl::Break {
+                    if let LoopControl::Break(_) = self.execute_block(body)? {
                         break;
                     }
                 }
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::Break { .. } => Err(RuntimeError::BreakOutsideLoop),
-            Stmt::Continue { .. } => Err(RuntimeError::ContinueOutsideLoop),
+            StmtKind::Break => Ok(LoopControl::Break(stmt.loc)),
+            StmtKind::Continue => Ok(LoopControl::Continue(stmt.loc)),
 
-            Stmt::TypeDecl {
-                name,
-                fields,
-                export,
-                ..
-            } => {
+            StmtKind::TypeDecl { slot, ty, export } => {
                 // Create a Type value and store it as a variable
-                let type_value = Value::Type {
-                    name: name.clone(),
-                    fields: fields.clone(),
-                };
-                self.user_types.insert(name.clone(), type_value.clone());
+                let type_value = Value::Type(Rc::clone(ty));
+                self.user_types.insert(ty.name.clone(), type_value.clone());
                 self.variables.insert(
-                    name.clone(),
+                    *slot,
                     Variable {
                         value: type_value.clone(),
                         is_const: false,
@@ -1149,29 +1255,29 @@ impl<O: PineOutput> Interpreter<O> {
 
                 // If exported, also store in exports
                 if *export {
-                    self.exports.insert(name.clone(), type_value);
+                    self.exports.insert(ty.name.clone(), type_value);
                 }
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::EnumDecl {
+            StmtKind::EnumDecl {
+                slot,
                 name,
-                fields,
+                members,
                 export,
-                ..
             } => {
                 // Create an Object that contains all enum members as fields
-                let mut enum_fields = HashMap::new();
-
-                for field in fields {
-                    let title = field.title.clone().unwrap_or_else(|| field.name.clone());
-                    let enum_value = Value::Enum {
-                        enum_name: name.clone(),
-                        field_name: field.name.clone(),
-                        title,
-                    };
-                    enum_fields.insert(field.name.clone(), enum_value);
-                }
+                let enum_fields = members
+                    .iter()
+                    .map(|(field_name, title)| {
+                        let enum_value = Value::Enum {
+                            enum_name: name.clone(),
+                            field_name: field_name.clone(),
+                            title: title.clone(),
+                        };
+                        (field_name.clone(), enum_value)
+                    })
+                    .collect();
 
                 let enum_object = Value::Object {
                     type_name: name.clone(),
@@ -1179,8 +1285,11 @@ impl<O: PineOutput> Interpreter<O> {
                     call: None,
                     value: None,
                 };
+                self.enums
+                    .entry(name.clone())
+                    .or_insert_with(|| members.clone());
                 self.variables.insert(
-                    name.clone(),
+                    *slot,
                     Variable {
                         value: enum_object.clone(),
                         is_const: false,
@@ -1192,136 +1301,73 @@ impl<O: PineOutput> Interpreter<O> {
                 if *export {
                     self.exports.insert(name.clone(), enum_object);
                 }
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::Export { item } => {
-                // Mark the item for export
-                match item {
-                    pine_ast::ExportItem::Type(type_name) => {
-                        // Export the type - it should already be in variables
-                        if let Some(var) = self.variables.get(type_name) {
-                            self.exports.insert(type_name.clone(), var.value.clone());
-                        }
-                    }
-                    pine_ast::ExportItem::Function(func_name) => {
-                        // Export the function - it should already be in variables
-                        if let Some(var) = self.variables.get(func_name) {
-                            self.exports.insert(func_name.clone(), var.value.clone());
-                        }
-                    }
+            StmtKind::Error => Err(RuntimeError::UserError(
+                "Cannot execute a statement that failed to parse".to_string(),
+            )),
+
+            StmtKind::Export { slot, name } => {
+                // Mark the item for export - it should already be in variables
+                if let Some(var) = self.variables.get(*slot) {
+                    self.exports.insert(name.clone(), var.value.clone());
                 }
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::Import { path, alias, .. } => {
-                let source = match &self.library_loader {
-                    Some(loader) => loader.load_library(path),
+            StmtKind::Import { path, slot, alias } => {
+                let namespace = match self.libraries.get(path) {
+                    Some(namespace) => namespace.clone(),
                     None => {
-                        return Err(RuntimeError::LibraryError(
-                            "Cannot import library: no library loader configured".to_string(),
-                        ))
-                    }
-                }
-                .map_err(|e| {
-                    RuntimeError::LibraryError(format!("Failed to load library '{}': {}", path, e))
-                })?;
-
-                let library_program = pine_parser::Parser::parse_source(&source).map_err(|e| {
-                    RuntimeError::LibraryError(format!("Failed to parse library '{}': {}", path, e))
-                })?;
-
-                // Seed the library with the same built-in namespaces/globals
-                // (e.g. `library`, `math`) so its declaration and body resolve.
-                let mut library_interp = Interpreter::new();
-                for (name, value) in self.snapshot() {
-                    library_interp.set_variable(&name, value);
-                }
-                library_interp.execute(&library_program)?;
-                let library_exports = library_interp.exports();
-
-                for (method_name, method_defs) in &library_interp.methods {
-                    for method_def in method_defs {
-                        self.methods
-                            .entry(method_name.clone())
-                            .or_default()
-                            .push(method_def.clone());
+                        let namespace = self.import(path, alias)?;
+                        self.libraries.insert(path.clone(), namespace.clone());
+                        namespace
                     }
-                }
-
-                let namespace: Value<O> = Value::Object {
-                    type_name: alias.clone(),
-                    fields: Rc::new(RefCell::new(library_exports.clone())),
-                    call: None,
-                    value: None,
                 };
                 self.variables.insert(
-                    alias.clone(),
+                    *slot,
                     Variable {
                         value: namespace,
                         is_const: false,
                         is_var_persistent: false,
                     },
                 );
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::MethodDecl {
+            StmtKind::MethodDecl {
                 name,
-                params,
-                body,
-                export,
-                ..
+                type_name,
+                method,
             } => {
-                // Extract the type name from the first parameter's type annotation
-                let type_name = if let Some(first_param) = params.first() {
-                    first_param.type_annotation.clone().ok_or_else(|| {
-                        RuntimeError::TypeError(
-                            "Method's first parameter must have a type annotation".to_string(),
-                        )
-                    })?
-                } else {
-                    return Err(RuntimeError::TypeError(
-                        "Method must have at least one parameter (this)".to_string(),
-                    ));
-                };
-
-                // Store the method definition
-                let method_def = MethodDef {
-                    type_name,
-                    params: params.clone(),
-                    body: body.clone(),
-                };
-
-                self.methods
-                    .entry(name.clone())
-                    .or_default()
-                    .push(method_def);
-
-                // If exported, store the method in exports
-                // Methods are exported as part of their type, so we may need to handle this differently
-                // For now, just mark it as exported (this might need more work)
-                if *export {
-                    // TODO: Handle method exports properly
+                let type_name = type_name
+                    .clone()
+                    .map_err(|reason| RuntimeError::TypeError(reason.to_string()))?;
+
+                // Store the method definition, once: the declaration runs
+                // again on every bar.
+                let defs = self.methods.entry(name.clone()).or_default();
+                if !defs.iter().any(|def| Rc::ptr_eq(&def.method, method)) {
+                    defs.push(MethodDef {
+                        type_name,
+                        method: Rc::clone(method),
+                    });
                 }
 
-                Ok(None)
+                Ok(LoopControl::None)
             }
 
-            Stmt::FunctionDecl {
+            StmtKind::FunctionDecl {
+                slot,
                 name,
-                params,
-                body,
+                function,
                 export,
-                ..
             } => {
                 // Create a function value
-                let func_value = Value::Function {
-                    params: params.clone(),
-                    body: body.clone(),
-                };
+                let func_value = Value::Function(Rc::clone(function));
                 self.variables.insert(
-                    name.clone(),
+                    *slot,
                     Variable {
                         value: func_value.clone(),
                         is_const: false,
@@ -1334,66 +1380,60 @@ impl<O: PineOutput> Interpreter<O> {
                     self.exports.insert(name.clone(), func_value);
                 }
 
-                Ok(None)
+                Ok(LoopControl::None)
             }
         }
     }
 
-    /// Execute loop body, handling break/continue
-    fn execute_loop_body(&mut self, body: &[Stmt]) -> Result<LoopControl, RuntimeError> {
-        for stmt in body {
-            match stmt {
-                Stmt::Break { .. } => return Ok(LoopControl::Break),
-                Stmt::Continue { .. } => return Ok(LoopControl::Continue),
-                Stmt::If {
-                    condition,
-                    then_branch,
-                    else_if_branches,
-                    else_branch,
-                } => {
-                    let cond_value = self.eval_expr(condition)?;
-                    let branch = if cond_value.truthy_for_condition()? {
-                        then_branch
-                    } else {
-                        // Try each else if branch
-                        let mut matched_branch = None;
-                        for (else_if_cond, else_if_body) in else_if_branches {
-                            let else_if_value = self.eval_expr(else_if_cond)?;
-                            if else_if_value.truthy_for_condition()? {
-                                matched_branch = Some(else_if_body);
-                                break;
-                            }
-                        }
-
-                        if let Some(branch) = matched_branch {
-                            branch
-                        } else if let Some(else_stmts) = else_branch {
-                            else_stmts
-                        } else {
-                            continue;
-                        }
-                    };
-
-                    let control = self.execute_loop_body(branch)?;
-                    if control != LoopControl::None {
-                        return Ok(control);
-                    }
-                }
-                Stmt::For { .. } | Stmt::ForIn { .. } | Stmt::While { .. } => {
-                    // Nested loops handle their own break/continue
-                    self.execute_stmt(stmt)?;
-                }
-                _ => {
-                    self.execute_stmt(stmt)?;
-                }
+    /// Load, run and register the library at `path`, returning the namespace
+    /// its exports are reached through as `alias.name`.
+    fn import(&mut self, path: &str, alias: &str) -> Result<Value<O>, RuntimeError> {
+        let source = match &self.library_loader {
+            Some(loader) => loader.load_library(path),
+            None => {
+                return Err(RuntimeError::LibraryError(
+                    "Cannot import library: no library loader configured".to_string(),
+                ))
             }
         }
-        Ok(LoopControl::None)
+        .map_err(|e| {
+            RuntimeError::LibraryError(format!("Failed to load library '{}': {}", path, e))
+        })?;
+
+        let library_program = pine_parser::Parser::parse_source(&source).map_err(|e| {
+            RuntimeError::LibraryError(format!("Failed to parse library '{}': {}", path, e))
+        })?;
+
+        // Seed the library with the same built-in namespaces/globals
+        // (e.g. `library`, `math`) so its declaration and body resolve.
+        let mut library_interp = self.child();
+        library_interp.current_file = Some(path.into());
+        library_interp.variables = self.variables.clone();
+        library_interp.execute(&library_program)?;
+
+        for (method_name, method_defs) in library_interp.methods {
+            self.methods
+                .entry(method_name)
+                .or_default()
+                .extend(method_defs);
+        }
+
+        Ok(Value::Object {
+            type_name: alias.to_string(),
+            fields: Rc::new(RefCell::new(library_interp.exports)),
+            call: None,
+            value: None,
+        })
     }
 
     /// Bare use unwraps a value-carrying object to its value; `.member` and
     /// `(...)` positions use [`eval_expr_raw`] to keep the object.
-    fn eval_expr(&mut self, expr: &Expr) -> Result<Value<O>, RuntimeError> {
+    fn eval_expr(&mut self, expr: &Expr<O>) -> Result<Value<O>, RuntimeError> {
+        self.eval_unwrapped(expr)
+            .map_err(|error| self.traced(error, expr.loc))
+    }
+
+    fn eval_unwrapped(&mut self, expr: &Expr<O>) -> Result<Value<O>, RuntimeError> {
         let value = self.eval_expr_raw(expr)?;
         if let Value::Object {
             value: Some(compute),
@@ -1406,19 +1446,91 @@ impl<O: PineOutput> Interpreter<O> {
         Ok(value)
     }
 
-    fn eval_expr_raw(&mut self, expr: &Expr) -> Result<Value<O>, RuntimeError> {
-        match expr {
-            Expr::Literal(lit) => Ok(self.eval_literal(lit)),
+    /// Trace `error` to `loc` unless it already is: the innermost located node
+    /// an error passes through is where it struck. The trace snapshots the
+    /// calls in progress, which unwind as the error propagates.
+    fn traced(&self, error: RuntimeError, loc: Option<Loc>) -> RuntimeError {
+        match (error, loc) {
+            (error @ RuntimeError::Traced { .. }, _) | (error, None) => error,
+            (error, Some(loc)) => RuntimeError::Traced {
+                error: Box::new(error),
+                trace: Box::new(Trace {
+                    loc: Some(loc),
+                    file: self.current_file.as_deref().map(str::to_string),
+                    bar_index: None,
+                    time: None,
+                    stack: self
+                        .call_stack
+                        .iter()
+                        .rev()
+                        .map(ActiveCall::frame)
+                        .collect(),
+                }),
+            },
+        }
+    }
+
+    /// Stamp the bar being run on an error leaving it, tracing it first if no
+    /// located node caught it.
+    fn traced_to_bar(&self, error: RuntimeError) -> RuntimeError {
+        let (error, mut trace) = match error {
+            RuntimeError::Traced { error, trace } => (error, trace),
+            error => (
+                Box::new(error),
+                Box::new(Trace {
+                    file: self.current_file.as_deref().map(str::to_string),
+                    ..Trace::default()
+                }),
+            ),
+        };
+        if trace.bar_index.is_none() {
+            trace.bar_index = self.bar_index();
+            trace.time = self.current_time;
+        }
+        RuntimeError::Traced { error, trace }
+    }
+
+    /// The `bar_index` of the bar being run, if the host set one.
+    fn bar_index(&self) -> Option<i64> {
+        match self.get_variable("bar_index") {
+            Some(Value::Int(index)) => Some(*index),
+            Some(Value::Number(index)) => Some(*index as i64),
+            _ => None,
+        }
+    }
 
-            Expr::Variable { name, .. } => self
+    /// Run a user function or method `call` as a frame on the call stack,
+    /// with the file `callee` was declared in as the running file.
+    fn call_frame(
+        &mut self,
+        call: &Call<O>,
+        callee: &Function<O>,
+        run: impl FnOnce(&mut Self) -> Result<Value<O>, RuntimeError>,
+    ) -> Result<Value<O>, RuntimeError> {
+        self.call_stack.push(ActiveCall {
+            function: Rc::clone(&call.name),
+            call_site: call.site,
+            file: self.current_file.clone(),
+            locals: Rc::clone(&callee.writes),
+        });
+        let caller_file = std::mem::replace(&mut self.current_file, callee.file.clone());
+        let result = run(self);
+        self.current_file = caller_file;
+        self.call_stack.pop();
+        result
+    }
+
+    fn eval_expr_raw(&mut self, expr: &Expr<O>) -> Result<Value<O>, RuntimeError> {
+        match &expr.kind {
+            ExprKind::Literal(value) => Ok(value.clone()),
+
+            ExprKind::Variable(slot) => self
                 .variables
-                .get(name)
+                .get(*slot)
                 .map(|var| var.value.clone())
-                .ok_or_else(|| RuntimeError::UndefinedVariable(name.clone())),
+                .ok_or_else(|| RuntimeError::UndefinedVariable(self.name_of(*slot))),
 
-            Expr::Binary {
-                left, op, right, ..
-            } => {
+            ExprKind::Binary { left, op, right } => {
                 let left_val = self.eval_expr(left)?;
                 // Pine `and`/`or` are lazy: when the left operand alone decides
                 // the result (false-and / true-or), the right operand is NOT
@@ -1437,57 +1549,39 @@ impl<O: PineOutput> Interpreter<O> {
                 self.eval_binary_op(&left_val, op, &right_val)
             }
 
-            Expr::Unary { op, expr } => {
+            ExprKind::Unary { op, expr } => {
                 let val = self.eval_expr(expr)?;
                 self.eval_unary_op(op, &val)
             }
 
-            Expr::Ternary {
-                condition,
-                then_expr,
-                else_expr,
-            } => {
-                let cond_val = self.eval_expr(condition)?;
-                if cond_val.truthy_for_condition()? {
-                    self.eval_expr(then_expr)
-                } else {
-                    self.eval_expr(else_expr)
-                }
-            }
-
-            Expr::IfExpr {
-                condition,
-                then_expr,
-                else_if_branches,
-                else_expr,
+            ExprKind::Conditional {
+                branches,
+                otherwise,
             } => {
-                let cond_val = self.eval_expr(condition)?;
-                if cond_val.truthy_for_condition()? {
-                    self.eval_expr(then_expr)
-                } else {
-                    // Try each else if branch
-                    for (else_if_cond, else_if_expr) in else_if_branches {
-                        let else_if_val = self.eval_expr(else_if_cond)?;
-                        if else_if_val.truthy_for_condition()? {
-                            return self.eval_expr(else_if_expr);
-                        }
-                    }
-                    // No else if matched, evaluate else branch or return na
-                    if let Some(expr) = else_expr {
-                        self.eval_expr(expr)
-                    } else {
-                        Ok(Value::Na)
+                for (condition, then_expr) in branches {
+                    if self.eval_expr(condition)?.truthy_for_condition()? {
+                        return self.eval_expr(then_expr);
                     }
                 }
+                // No branch matched: the else value, or na without one
+                match otherwise {
+                    Some(expr) => self.eval_expr(expr),
+                    None => Ok(Value::Na),
+                }
             }
 
-            Expr::Array(elements) => {
+            ExprKind::Array(elements) => {
                 let values: Result<Vec<_>, _> =
                     elements.iter().map(|e| self.eval_expr(e)).collect();
                 Ok(Value::Array(Rc::new(RefCell::new(values?))))
             }
 
-            Expr::Index { expr, index, id } => {
+            ExprKind::Index {
+                expr,
+                index,
+                id,
+                variable,
+            } => {
                 let index_num = self.eval_expr(index)?.as_number()?;
                 if index_num < 0.0 {
                     return Err(RuntimeError::TypeError(format!(
@@ -1501,21 +1595,19 @@ impl<O: PineOutput> Interpreter<O> {
                 // variable with insufficient depth yields na (warm-up). Variables
                 // WITHOUT tracked history (e.g. builtin Series like `close` fed by
                 // the host) fall through to the shared path below.
-                if index_val > 0 {
-                    if let Expr::Variable { name: var_name, .. } = expr.as_ref() {
-                        if let Some(h) = self.user_series_history.get(var_name) {
-                            return Ok(if h.len() >= index_val {
-                                h[h.len() - index_val].clone()
-                            } else {
-                                Value::Na
-                            });
-                        }
-                        // A plain non-series value with no history (a user var
-                        // assigned only this bar) indexes as na, not an error.
-                        if let Some(var) = self.variables.get(var_name) {
-                            if !matches!(var.value, Value::Series(_) | Value::Array(_)) {
-                                return Ok(Value::Na);
-                            }
+                if let (true, Some(slot)) = (index_val > 0, variable) {
+                    if let Some(h) = self.user_series_history.get(*slot) {
+                        return Ok(if h.len() >= index_val {
+                            h[h.len() - index_val].clone()
+                        } else {
+                            Value::Na
+                        });
+                    }
+                    // A plain non-series value with no history (a user var
+                    // assigned only this bar) indexes as na, not an error.
+                    if let Some(var) = self.variables.get(*slot) {
+                        if !matches!(var.value, Value::Series(_) | Value::Array(_)) {
+                            return Ok(Value::Na);
                         }
                     }
                 }
@@ -1553,7 +1645,7 @@ impl<O: PineOutput> Interpreter<O> {
                 // keyed by the node's id makes `(expr)[n]` match `v = expr; v[n]`,
                 // and a warm-up `na` yields `na` instead of erroring.
                 let current = match val {
-                    Value::Series(series) => (*series.current).clone(),
+                    Value::Series(series) => *series.current,
                     other => other,
                 };
                 if index_val == 0 {
@@ -1566,10 +1658,9 @@ impl<O: PineOutput> Interpreter<O> {
                     // MAX_LOOKBACK, exactly like user_series_history, so memory
                     // stays flat over a long run.
                     if let Some(previous) = site.current.take() {
-                        site.history.push(previous);
+                        site.history.push_back(previous);
                         if site.history.len() > MAX_LOOKBACK {
-                            let drop = site.history.len() - MAX_LOOKBACK;
-                            site.history.drain(..drop);
+                            site.history.pop_front();
                         }
                     }
                     site.bar = seq;
@@ -1582,23 +1673,21 @@ impl<O: PineOutput> Interpreter<O> {
                 })
             }
 
-            Expr::Switch { value, cases } => {
+            ExprKind::Switch { value, cases } => {
                 let switch_val = self.eval_expr(value)?;
 
-                for (pattern, result) in cases {
+                for case in cases {
                     // Check if pattern matches
-                    let pattern_val = self.eval_expr(pattern)?;
+                    let pattern_val = self.eval_expr(&case.pattern)?;
 
                     // Special case: default pattern (true literal)
-                    if pattern_val == Value::Bool(true)
-                        && matches!(pattern, Expr::Literal(Literal::Bool(true)))
-                    {
-                        return self.eval_expr(result);
+                    if case.default && pattern_val == Value::Bool(true) {
+                        return self.eval_expr(&case.result);
                     }
 
                     // Check equality
                     if self.values_equal(&switch_val, &pattern_val)? {
-                        return self.eval_expr(result);
+                        return self.eval_expr(&case.result);
                     }
                 }
 
@@ -1606,178 +1695,198 @@ impl<O: PineOutput> Interpreter<O> {
                 Ok(Value::Na)
             }
 
-            Expr::Call {
-                callee,
-                type_args,
-                args,
-                id,
-                ..
+            ExprKind::Call(call) => self.eval_call(call),
+
+            ExprKind::Member {
+                object,
+                member,
+                user_type,
             } => {
-                // Check if this is a method call (object.method())
-                if let Expr::MemberAccess { object, member, .. } = callee.as_ref() {
-                    // Try to find a method with this name
-                    if let Some(method_defs) = self.methods.get(member).cloned() {
-                        // Evaluate the object (this will be the first parameter)
-                        let obj_value = self.eval_expr_raw(object)?;
-
-                        // Find the method that matches the object's type
-                        let obj_type = self.get_object_type_name(&obj_value)?;
-
-                        if let Some(method_def) =
-                            method_defs.iter().find(|m| m.type_name == obj_type)
-                        {
-                            // Evaluate the other arguments
-                            let mut evaluated_args: Vec<EvaluatedArg<O>> =
-                                vec![EvaluatedArg::Positional(obj_value)];
-                            evaluated_args.extend(self.evaluate_arguments(args, None)?);
-
-                            // Call the method (treating it like a function),
-                            // threading the call site id so method-local state
-                            // persists per call site.
-                            return self.call_method(
-                                &method_def.params,
-                                &method_def.body,
-                                evaluated_args,
-                                *id,
-                            );
-                        }
-                    }
-                }
+                let obj_value = match self.user_type(user_type.as_deref()) {
+                    Some(ty) => ty,
+                    None => self.eval_expr_raw(object)?,
+                };
+                self.member_of(obj_value, member)
+            }
 
-                // Builtin method syntax: a collection receiver `x.m(args)` is
-                // sugar for `namespace.m(x, args)` — the same builtins in
-                // function form, with the receiver passed first. (Skip `Call`
-                // receivers so a side-effecting `f().m()` is not evaluated twice.)
-                if let Expr::MemberAccess { object, member, .. } = callee.as_ref() {
-                    if !matches!(object.as_ref(), Expr::Call { .. }) {
-                        let receiver = self.eval_expr_raw(object)?;
-                        if let Some(namespace) = builtin_namespace(&receiver) {
-                            if let Some(Value::BuiltinFunction(builtin_fn)) =
-                                self.namespace_member(namespace, member)
-                            {
-                                let mut evaluated_args = vec![EvaluatedArg::Positional(receiver)];
-                                evaluated_args.extend(self.evaluate_arguments(args, None)?);
-                                let call_args =
-                                    FunctionCallArgs::new(type_args.clone(), evaluated_args)
-                                        .with_call_id(*id);
-                                return (builtin_fn.call)(self, call_args);
-                            }
-                        }
-                    }
+            ExprKind::Function(function) => Ok(Value::Function(Rc::clone(function))),
+        }
+    }
+
+    /// The type `Type.new`/`Type.copy` reaches: `Type.new` / `Type.copy`
+    /// resolve via the type namespace, so a type may share its name with a
+    /// shadowing function/variable.
+    fn user_type(&self, name: Option<&str>) -> Option<Value<O>> {
+        self.user_types.get(name?).cloned()
+    }
+
+    /// `member` read from an already evaluated object.
+    fn member_of(&self, obj_value: Value<O>, member: &str) -> Result<Value<O>, RuntimeError> {
+        match obj_value {
+            Value::Object { fields, .. } => {
+                let obj = fields.borrow();
+                obj.get(member).cloned().ok_or_else(|| {
+                    RuntimeError::TypeError(format!("Object has no member '{}'", member))
+                })
+            }
+            Value::Type(ty) => {
+                // Types have 'new' and 'copy' methods
+                if member == "new" {
+                    // Return a constructor function
+                    Ok(Value::BuiltinFunction(Builtin::untyped(
+                        Self::create_constructor(ty),
+                    )))
+                } else if member == "copy" {
+                    // Return a copy function
+                    Ok(Value::BuiltinFunction(Builtin::untyped(
+                        Self::create_copy_function(),
+                    )))
+                } else {
+                    Err(RuntimeError::TypeError(format!(
+                        "Type '{}' has no member '{}' (only 'new' and 'copy' are supported)",
+                        ty.name, member
+                    )))
                 }
+            }
+            _ => Err(RuntimeError::TypeError(format!(
+                "Cannot access member '{}' on non-object value",
+                member
+            ))),
+        }
+    }
 
-                // Not a method call, proceed with regular function call.
-                // Resolve the callee first so a builtin's lazy parameters can
-                // capture their arguments unevaluated.
+    fn eval_call(&mut self, call: &Call<O>) -> Result<Value<O>, RuntimeError> {
+        let (object, member, user_type) = match &call.callee {
+            // Bound when the program was compiled.
+            Callee::Builtin(builtin) => {
+                let evaluated_args = self.evaluate_arguments(&call.args, None)?;
+                return self.call_builtin(builtin, call, evaluated_args);
+            }
+            Callee::Value(callee) => {
                 let callee_value = self.eval_expr_raw(callee)?;
-                let signature = match &callee_value {
-                    Value::BuiltinFunction(builtin) => Some(builtin.signature),
-                    _ => None,
-                };
-                let evaluated_args = self.evaluate_arguments(args, signature)?;
-
-                // Call the function based on its type
-                match callee_value {
-                    Value::Function { params, body } => {
-                        // Thread the call site's lexical id so function-local
-                        // state persists per call site, not per function name.
-                        self.call_user_function(&params, &body, args, evaluated_args, *id)
-                    }
-                    Value::BuiltinFunction(builtin_fn) => {
-                        // Pass type_args from the parsed call expression, and the
-                        // call node's lexical id for per-call-site builtin state.
-                        let call_args = FunctionCallArgs::new(type_args.clone(), evaluated_args)
-                            .with_call_id(*id);
-                        (builtin_fn.call)(self, call_args)
-                    }
-                    // A callable namespace object, like `input(...)` alongside
-                    // `input.int(...)`. Objects without a `call` are not callable.
-                    Value::Object {
-                        call: Some(builtin),
-                        ..
-                    } => {
-                        let call_args = FunctionCallArgs::new(type_args.clone(), evaluated_args)
-                            .with_call_id(*id);
-                        (builtin.call)(self, call_args)
-                    }
-                    // Pine's `na` is a keyword that doubles as a function: na(x) → is x na?
-                    Value::Na => {
-                        let is_na = matches!(
-                            evaluated_args.first(),
-                            Some(EvaluatedArg::Positional(Value::Na)) | None
-                        );
-                        Ok(Value::Bool(is_na))
-                    }
-                    _ => Err(RuntimeError::TypeError(
-                        "Attempted to call a non-function value".to_string(),
-                    )),
-                }
+                return self.call_value(call, callee_value);
             }
+            Callee::Member {
+                object,
+                member,
+                user_type,
+            } => (object, member, user_type),
+        };
+
+        // A method call (object.method()): the object is the first argument.
+        // Its value is kept so the paths below do not evaluate it again.
+        let mut receiver = None;
+        if self.methods.contains_key(member) {
+            let obj_value = self.eval_expr_raw(object)?;
+
+            // Find the method that matches the object's type
+            let obj_type = self.get_object_type_name(&obj_value)?;
+            let method = self.methods[member]
+                .iter()
+                .find(|m| m.type_name == obj_type)
+                .map(|m| Rc::clone(&m.method));
+
+            if let Some(method) = method {
+                // Evaluate the other arguments
+                let mut evaluated_args: Vec<EvaluatedArg<O>> =
+                    vec![EvaluatedArg::Positional(obj_value)];
+                evaluated_args.extend(self.evaluate_arguments(&call.args, None)?);
+
+                // Call the method (treating it like a function), threading
+                // the call site id so method-local state persists per call
+                // site.
+                return self.call_frame(call, &method, |interp| {
+                    interp.call_method(&method, evaluated_args, call.id)
+                });
+            }
+            receiver = Some(obj_value);
+        }
 
-            Expr::MemberAccess { object, member, .. } => {
-                // `Type.new` / `Type.copy` resolve via the type namespace, so a
-                // type may share its name with a shadowing function/variable.
-                let obj_value = match object.as_ref() {
-                    Expr::Variable { name, .. }
-                        if (member == "new" || member == "copy")
-                            && self.user_types.contains_key(name) =>
-                    {
-                        self.user_types[name].clone()
-                    }
-                    _ => self.eval_expr_raw(object)?,
-                };
-                match obj_value {
-                    Value::Object { fields, .. } => {
-                        let obj = fields.borrow();
-                        obj.get(member).cloned().ok_or_else(|| {
-                            RuntimeError::TypeError(format!("Object has no member '{}'", member))
-                        })
-                    }
-                    Value::Type { name, fields } => {
-                        // Types have 'new' and 'copy' methods
-                        if member == "new" {
-                            // Return a constructor function
-                            Ok(Value::BuiltinFunction(Builtin::untyped(
-                                Self::create_constructor(name, fields),
-                            )))
-                        } else if member == "copy" {
-                            // Return a copy function
-                            Ok(Value::BuiltinFunction(Builtin::untyped(
-                                Self::create_copy_function(),
-                            )))
-                        } else {
-                            Err(RuntimeError::TypeError(format!(
-                                "Type '{}' has no member '{}' (only 'new' and 'copy' are supported)",
-                                name, member
-                            )))
-                        }
-                    }
-                    _ => Err(RuntimeError::TypeError(format!(
-                        "Cannot access member '{}' on non-object value",
-                        member
-                    ))),
+        // Builtin method syntax: a collection receiver `x.m(args)` is sugar
+        // for `namespace.m(x, args)` — the same builtins in function form,
+        // with the receiver passed first.
+        if !matches!(object.kind, ExprKind::Call(_)) || receiver.is_some() {
+            let obj_value = match receiver.take() {
+                Some(value) => value,
+                None => self.eval_expr_raw(object)?,
+            };
+            if let Some(namespace) = builtin_namespace(&obj_value) {
+                if let Some(Value::BuiltinFunction(builtin_fn)) =
+                    self.namespace_member(namespace, member)
+                {
+                    let mut evaluated_args = vec![EvaluatedArg::Positional(obj_value)];
+                    evaluated_args.extend(self.evaluate_arguments(&call.args, None)?);
+                    return self.call_builtin(&builtin_fn, call, evaluated_args);
                 }
             }
+            receiver = Some(obj_value);
+        }
+
+        // Not a method call: call the member itself.
+        let obj_value = match (self.user_type(user_type.as_deref()), receiver) {
+            (Some(ty), _) => ty,
+            (None, Some(value)) => value,
+            (None, None) => self.eval_expr_raw(object)?,
+        };
+        let callee_value = self.member_of(obj_value, member)?;
+        self.call_value(call, callee_value)
+    }
 
-            Expr::Function { params, body } => {
-                // params is already Vec<FunctionParam> from the AST
-                Ok(Value::Function {
-                    params: params.clone(),
-                    body: body.clone(),
+    /// Call an evaluated callee. It is resolved before the arguments so a
+    /// builtin's lazy parameters can capture their arguments unevaluated.
+    fn call_value(
+        &mut self,
+        call: &Call<O>,
+        callee_value: Value<O>,
+    ) -> Result<Value<O>, RuntimeError> {
+        let signature = match &callee_value {
+            Value::BuiltinFunction(builtin) => Some(builtin.signature),
+            _ => None,
+        };
+        let evaluated_args = self.evaluate_arguments(&call.args, signature)?;
+
+        // Call the function based on its type
+        match callee_value {
+            Value::Function(function) => {
+                // Thread the call site's lexical id so function-local
+                // state persists per call site, not per function name.
+                self.call_frame(call, &function, |interp| {
+                    interp.call_user_function(&function, &call.args, evaluated_args, call.id)
                 })
             }
+            Value::BuiltinFunction(builtin_fn) => {
+                self.call_builtin(&builtin_fn, call, evaluated_args)
+            }
+            // A callable namespace object, like `input(...)` alongside
+            // `input.int(...)`. Objects without a `call` are not callable.
+            Value::Object {
+                call: Some(builtin),
+                ..
+            } => self.call_builtin(&builtin, call, evaluated_args),
+            // Pine's `na` is a keyword that doubles as a function: na(x) → is x na?
+            Value::Na => {
+                let is_na = matches!(
+                    evaluated_args.first(),
+                    Some(EvaluatedArg::Positional(Value::Na)) | None
+                );
+                Ok(Value::Bool(is_na))
+            }
+            _ => Err(RuntimeError::TypeError(
+                "Attempted to call a non-function value".to_string(),
+            )),
         }
     }
 
-    fn eval_literal(&self, lit: &Literal) -> Value<O> {
-        match lit {
-            Literal::Int(n) => Value::Int(*n),
-            Literal::Number(n) => Value::Number(*n),
-            Literal::String(s) => Value::String(s.clone()),
-            Literal::Bool(b) => Value::Bool(*b),
-            Literal::Na => Value::Na,
-            Literal::HexColor(hex) => Value::String(hex.clone()),
-        }
+    /// Pass the call's type arguments, and the call node's lexical id for
+    /// per-call-site builtin state.
+    fn call_builtin(
+        &mut self,
+        builtin: &Builtin<O>,
+        call: &Call<O>,
+        args: Vec<EvaluatedArg<O>>,
+    ) -> Result<Value<O>, RuntimeError> {
+        let call_args = FunctionCallArgs::new(call.type_args.clone(), args).with_call_id(call.id);
+        (builtin.call)(self, call_args)
     }
 
     fn eval_binary_op(
@@ -1937,43 +2046,28 @@ impl<O: PineOutput> Interpreter<O> {
     }
 
     /// Check if an expression evaluates to a const value
-    fn is_const_expr(&self, expr: &Expr) -> bool {
-        match expr {
-            // Literals are always const
-            Expr::Literal(_) => true,
-            // Variable is const if it's stored as const
-            Expr::Variable { name, .. } => self
-                .variables
-                .get(name)
-                .map(|var| var.is_const)
-                .unwrap_or(false),
-            // Member access is const if the base object is const
-            Expr::MemberAccess { object, .. } => self.is_const_expr(object),
-            // All other expressions are not const
-            _ => false,
+    /// Whether an argument is a const value, for a `const` parameter
+    fn is_const_arg(&self, arg: &Arg<O>) -> bool {
+        match arg.constness {
+            Constness::Always => true,
+            // A variable is const if it's stored as const
+            Constness::Slot(slot) => self.variables.get(slot).is_some_and(|var| var.is_const),
+            Constness::Never => false,
         }
     }
 
     fn call_user_function(
         &mut self,
-        params: &[pine_ast::FunctionParam],
-        body: &[Stmt],
-        arg_exprs: &[Argument],
+        function: &Function<O>,
+        arg_exprs: &[Arg<O>],
         args: Vec<EvaluatedArg<O>>,
         call_id: u32,
     ) -> Result<Value<O>, RuntimeError> {
         // Extract positional arguments (user functions don't support named args yet)
-        let mut positional_values = Vec::new();
-        let mut positional_exprs = Vec::new();
-
-        for (i, arg) in args.iter().enumerate() {
+        let mut positional_values = Vec::with_capacity(args.len());
+        for arg in args {
             match arg {
-                EvaluatedArg::Positional(value) => {
-                    positional_values.push(value.clone());
-                    if let Some(Argument::Positional(expr)) = arg_exprs.get(i) {
-                        positional_exprs.push(expr);
-                    }
-                }
+                EvaluatedArg::Positional(value) => positional_values.push(value),
                 EvaluatedArg::Named { .. } => {
                     return Err(RuntimeError::TypeError(
                         "User-defined functions do not support named arguments yet".to_string(),
@@ -1984,7 +2078,8 @@ impl<O: PineOutput> Interpreter<O> {
 
         // Parameters with a default may be omitted, so the count must land
         // between the required parameters and the full list.
-        let required = params.iter().filter(|p| p.default_value.is_none()).count();
+        let params = &function.params;
+        let required = params.iter().filter(|p| p.default.is_none()).count();
         if positional_values.len() < required || positional_values.len() > params.len() {
             return Err(RuntimeError::TypeError(format!(
                 "Expected {} arguments, got {}",
@@ -1994,27 +2089,24 @@ impl<O: PineOutput> Interpreter<O> {
         }
 
         // Validate const parameters receive const arguments
-        for (i, param) in params.iter().enumerate() {
-            if matches!(param.type_qualifier, Some(pine_ast::TypeQualifier::Const)) {
-                if let Some(arg_expr) = positional_exprs.get(i) {
-                    if !self.is_const_expr(arg_expr) {
-                        return Err(RuntimeError::TypeError(format!(
-                            "Parameter '{}' requires a const argument, but received a non-const value",
-                            param.name
-                        )));
-                    }
-                }
+        for (param, arg) in params.iter().zip(arg_exprs) {
+            if param.is_const && !self.is_const_arg(arg) {
+                return Err(RuntimeError::TypeError(format!(
+                    "Parameter '{}' requires a const argument, but received a non-const value",
+                    param.name
+                )));
             }
         }
 
         // Bind each parameter to its argument, falling back to the declared
         // default (evaluated in the caller's scope) when one was omitted.
-        let mut param_bindings: Vec<(String, Variable<O>)> = Vec::with_capacity(params.len());
-        for (i, param) in params.iter().enumerate() {
-            let value = match positional_values.get(i) {
-                Some(value) => value.clone(),
+        let mut param_bindings = Vec::with_capacity(params.len());
+        let mut positional_values = positional_values.into_iter();
+        for param in params {
+            let value = match positional_values.next() {
+                Some(value) => value,
                 None => {
-                    let default = param.default_value.as_ref().ok_or_else(|| {
+                    let default = param.default.as_ref().ok_or_else(|| {
                         RuntimeError::TypeError(format!(
                             "Missing argument for parameter '{}'",
                             param.name
@@ -2023,18 +2115,17 @@ impl<O: PineOutput> Interpreter<O> {
                     self.eval_expr(default)?
                 }
             };
-            let is_const = matches!(param.type_qualifier, Some(pine_ast::TypeQualifier::Const));
             param_bindings.push((
-                param.name.clone(),
+                param.slot,
                 Variable {
                     value,
-                    is_const,
+                    is_const: param.is_const,
                     is_var_persistent: false,
                 },
             ));
         }
 
-        self.run_call_site_body(call_id, param_bindings, body)
+        self.run_call_site_body(call_id, function, param_bindings)
     }
 
     /// Run a user function or method body as a stateful call site: restore this
@@ -2047,70 +2138,81 @@ impl<O: PineOutput> Interpreter<O> {
     fn run_call_site_body(
         &mut self,
         call_id: u32,
-        param_bindings: Vec<(String, Variable<O>)>,
-        body: &[Stmt],
+        function: &Function<O>,
+        param_bindings: Vec<(Slot, Variable<O>)>,
     ) -> Result<Value<O>, RuntimeError> {
-        let param_names: std::collections::HashSet<String> =
-            param_bindings.iter().map(|(n, _)| n.clone()).collect();
-
-        // Save the outer scope.
-        let saved_vars = self.variables.clone();
+        // Save what the body may overwrite in the outer scope.
+        let saved: Vec<(Slot, Option<Variable<O>>)> = function
+            .writes
+            .iter()
+            .map(|&slot| (slot, self.variables.get(slot).cloned()))
+            .collect();
 
         // Restore this call site's locals (all locals persist across calls, not
         // just `var`s, so series indexing like `o[1]` works inside the body).
-        // Parameters are excluded — they are freshly bound below.
+        // Parameters are not among them — they are freshly bound below.
         if call_id != 0 {
             if let Some(local_state) = self.function_local_state.get(&call_id) {
-                for (var_name, var) in local_state {
-                    if !param_names.contains(var_name) {
-                        self.variables.insert(var_name.clone(), var.clone());
-                    }
+                for (slot, var) in local_state {
+                    self.variables.insert(*slot, var.clone());
                 }
             }
         }
 
         // Bind parameters (freshly each call).
-        for (name, var) in param_bindings {
-            self.variables.insert(name, var);
+        for (slot, var) in param_bindings {
+            self.variables.insert(slot, var);
         }
 
         // Execute the body under this call site's id, so `var` init-once tracking
         // is scoped to the call site. Restored afterwards to support
-        // nested/recursive calls. (Like the scope restore below, an error just
-        // propagates and aborts the script.)
-        let prev_call_id = self.current_call_id;
-        self.current_call_id = call_id;
-        let mut result: Value<O> = Value::Na;
-        for stmt in body {
-            if let Some(return_value) = self.execute_stmt(stmt)? {
-                result = return_value;
-            } else if let Stmt::Expression(expr) = stmt {
-                // Last expression is the return value
-                result = self.eval_expr(expr)?;
+        // nested/recursive calls.
+        let prev_call_id = std::mem::replace(&mut self.current_call_id, call_id);
+        let result = self.execute_body(&function.body);
+        self.current_call_id = prev_call_id;
+
+        // Persist only the names the body actually ASSIGNS — its true locals
+        // (both `var` and plain, so their series history advances). The scope
+        // also holds read-only builtins/globals inherited from the outer scope
+        // (`open`/`high`/`low`/`close`/…); saving one of those would restore it
+        // stale on the next call, freezing any indicator that reads it inside
+        // the function (e.g. a recursive Heikin-Ashi open).
+        if call_id != 0 && result.is_ok() {
+            let local_state = self.function_local_state.entry(call_id).or_default();
+            local_state.clear();
+            for &slot in &function.persisted {
+                if let Some(var) = self.variables.take(slot) {
+                    local_state.push((slot, var));
+                }
             }
         }
-        self.current_call_id = prev_call_id;
 
         // Restore the outer scope. This call site's locals live only in
         // function_local_state (keyed by call_id) and are NOT leaked into the
         // outer/global scope, so two call sites keep independent state.
-        let call_vars = std::mem::replace(&mut self.variables, saved_vars);
-        if call_id != 0 {
-            // Persist only the names the body actually ASSIGNS — its true locals
-            // (both `var` and plain, so their series history advances). The scope
-            // also holds read-only builtins/globals inherited from the outer scope
-            // (`open`/`high`/`low`/`close`/…); saving one of those would restore it
-            // stale on the next call, freezing any indicator that reads it inside
-            // the function (e.g. a recursive Heikin-Ashi open).
-            let mut assigned: std::collections::HashSet<String> = std::collections::HashSet::new();
-            collect_assigned_names(body, &mut assigned);
-            let local_state: HashMap<String, Variable<O>> = call_vars
-                .into_iter()
-                .filter(|(k, _)| !param_names.contains(k) && assigned.contains(k))
-                .collect();
-            self.function_local_state.insert(call_id, local_state);
+        for (slot, var) in saved {
+            self.variables.replace(slot, var);
         }
 
+        result
+    }
+
+    /// Execute a function body; the value of its last expression statement is
+    /// the call's result.
+    fn execute_body(&mut self, body: &[Stmt<O>]) -> Result<Value<O>, RuntimeError> {
+        let mut result = Value::Na;
+        for stmt in body {
+            if let StmtKind::Expression(expr) = &stmt.kind {
+                if self.debug.is_some() {
+                    self.debug_statement(stmt.loc)?;
+                }
+                result = self
+                    .eval_expr(expr)
+                    .map_err(|error| self.traced_and_stopped(error, stmt.loc))?;
+            } else {
+                self.execute_outside_loop(stmt)?;
+            }
+        }
         Ok(result)
     }
 
@@ -2124,11 +2226,10 @@ impl<O: PineOutput> Interpreter<O> {
         }
     }
 
-    /// Call a method (similar to call_user_function but handles MethodParam with defaults)
+    /// Call a method (similar to call_user_function but binds named arguments and defaults)
     fn call_method(
         &mut self,
-        params: &[MethodParam],
-        body: &[Stmt],
+        method: &Function<O>,
         args: Vec<EvaluatedArg<O>>,
         call_id: u32,
     ) -> Result<Value<O>, RuntimeError> {
@@ -2136,9 +2237,9 @@ impl<O: PineOutput> Interpreter<O> {
         // the body as a stateful call site. Defaults are evaluated in the caller
         // scope, before entering the method's scope.
         let mut positional_idx = 0;
-        let mut param_bindings: Vec<(String, Variable<O>)> = Vec::with_capacity(params.len());
+        let mut param_bindings = Vec::with_capacity(method.params.len());
 
-        for param in params {
+        for param in &method.params {
             let param_value = if positional_idx < args.len() {
                 match &args[positional_idx] {
                     EvaluatedArg::Positional(value) => {
@@ -2149,21 +2250,21 @@ impl<O: PineOutput> Interpreter<O> {
                         if name == &param.name {
                             positional_idx += 1;
                             value.clone()
-                        } else if let Some(default_expr) = &param.default_value {
+                        } else if let Some(default_expr) = &param.default {
                             self.eval_expr(default_expr)?
                         } else {
                             Value::Na
                         }
                     }
                 }
-            } else if let Some(default_expr) = &param.default_value {
+            } else if let Some(default_expr) = &param.default {
                 self.eval_expr(default_expr)?
             } else {
                 Value::Na
             };
 
             param_bindings.push((
-                param.name.clone(),
+                param.slot,
                 Variable {
                     value: param_value,
                     is_const: false,
@@ -2172,13 +2273,14 @@ impl<O: PineOutput> Interpreter<O> {
             ));
         }
 
-        self.run_call_site_body(call_id, param_bindings, body)
+        self.run_call_site_body(call_id, method, param_bindings)
     }
 
     /// Create a constructor function for a user-defined type
-    fn create_constructor(type_name: String, fields: Vec<TypeField>) -> BuiltinFn<O> {
+    fn create_constructor(ty: Rc<UserType<O>>) -> BuiltinFn<O> {
         Rc::new(
             move |interp: &mut Interpreter<O>, call_args: FunctionCallArgs<O>| {
+                let fields = &ty.fields;
                 let mut instance_fields = HashMap::new();
 
                 // Match arguments to fields
@@ -2195,7 +2297,7 @@ impl<O: PineOutput> Interpreter<O> {
                             } else {
                                 return Err(RuntimeError::TypeError(format!(
                                     "Too many arguments for type '{}' (expected {} fields)",
-                                    type_name,
+                                    ty.name,
                                     fields.len()
                                 )));
                             }
@@ -2207,7 +2309,7 @@ impl<O: PineOutput> Interpreter<O> {
                             } else {
                                 return Err(RuntimeError::TypeError(format!(
                                     "Type '{}' has no field '{}'",
-                                    type_name, name
+                                    ty.name, name
                                 )));
                             }
                         }
@@ -2215,9 +2317,9 @@ impl<O: PineOutput> Interpreter<O> {
                 }
 
                 // Fill in defaults for missing fields
-                for field in &fields {
+                for field in fields {
                     if !instance_fields.contains_key(&field.name) {
-                        if let Some(default_expr) = &field.default_value {
+                        if let Some(default_expr) = &field.default {
                             let default_val = interp.eval_expr(default_expr)?;
                             instance_fields.insert(field.name.clone(), default_val);
                         } else {
@@ -2228,7 +2330,7 @@ impl<O: PineOutput> Interpreter<O> {
                 }
 
                 Ok(Value::Object {
-                    type_name: type_name.clone(),
+                    type_name: ty.name.clone(),
                     fields: Rc::new(RefCell::new(instance_fields)),
                     call: None,
                     value: None,
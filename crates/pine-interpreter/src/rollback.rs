@@ -0,0 +1,265 @@
+//! Intrabar rollback: re-running the bar that is still forming.
+//!
+//! On a realtime bar the script runs once per tick, and every run but the one
+//! that closes the bar is provisional. Pine resets all state to what it was when
+//! the bar opened before each tick, so `x := x + 1` counts bars, not ticks —
+//! except for `varip` variables, which keep what the previous tick wrote.
+//!
+//! The host takes a [`checkpoint`](Interpreter::checkpoint) before the bar's
+//! first tick, [`rollback`](Interpreter::rollback)s before every later one and
+//! [`commit`](Interpreter::commit)s once the bar closes.
+
+use super::{Interpreter, SeriesSite, Value, Variable};
+use crate::slots::{Slot, Slots};
+use pine_core::PineOutput;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+/// What a script had written when the current bar opened.
+pub(crate) struct Checkpoint<O: PineOutput> {
+    variables: Slots<Variable<O>>,
+    user_series_history: Slots<VecDeque<Value<O>>>,
+    expr_history: HashMap<u32, SeriesSite<O>>,
+    function_local_state: HashMap<u32, Vec<(Slot, Variable<O>)>>,
+    var_decls_initialized: HashMap<(u32, Slot), u64>,
+    output: O,
+    /// Whether a `strategy` had built its broker yet. One built by a tick is
+    /// discarded with the tick.
+    had_broker: bool,
+}
+
+/// Copies values so that the copy shares no arrays, maps, matrices or objects
+/// with the original, while two references to the same collection still point
+/// at one collection afterwards.
+#[derive(Default)]
+struct Detach<O: PineOutput> {
+    seen: HashMap<usize, Value<O>>,
+}
+
+impl<O: PineOutput> Detach<O> {
+    fn value(&mut self, value: &Value<O>) -> Value<O> {
+        match value {
+            Value::Array(items) => {
+                let key = Rc::as_ptr(items) as usize;
+                if let Some(copy) = self.seen.get(&key) {
+                    return copy.clone();
+                }
+                let items = items.borrow().iter().map(|v| self.value(v)).collect();
+                let copy = Value::Array(Rc::new(RefCell::new(items)));
+                self.seen.insert(key, copy.clone());
+                copy
+            }
+            Value::Matrix { element_type, data } => {
+                let key = Rc::as_ptr(data) as usize;
+                if let Some(copy) = self.seen.get(&key) {
+                    return copy.clone();
+                }
+                let rows = data
+                    .borrow()
+                    .iter()
+                    .map(|row| row.iter().map(|v| self.value(v)).collect())
+                    .collect();
+                let copy = Value::Matrix {
+                    element_type: element_type.clone(),
+                    data: Rc::new(RefCell::new(rows)),
+                };
+                self.seen.insert(key, copy.clone());
+                copy
+            }
+            Value::Map {
+                key_type,
+                value_type,
+                data,
+            } => {
+                let key = Rc::as_ptr(data) as usize;
+                if let Some(copy) = self.seen.get(&key) {
+                    return copy.clone();
+                }
+                let entries = data
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| (self.value(k), self.value(v)))
+                    .collect();
+                let copy = Value::Map {
+                    key_type: key_type.clone(),
+                    value_type: value_type.clone(),
+                    data: Rc::new(RefCell::new(entries)),
+                };
+                self.seen.insert(key, copy.clone());
+                copy
+            }
+            // A user-defined type instance. Builtin namespaces carry a callable
+            // or a lazy value and are shared as they are.
+            Value::Object {
+                type_name,
+                fields,
+                call: None,
+                value: None,
+            } => {
+                let key = Rc::as_ptr(fields) as usize;
+                if let Some(copy) = self.seen.get(&key) {
+                    return copy.clone();
+                }
+                let copied = fields
+                    .borrow()
+                    .iter()
+                    .map(|(name, v)| (name.clone(), self.value(v)))
+                    .collect();
+                let copy = Value::Object {
+                    type_name: type_name.clone(),
+                    fields: Rc::new(RefCell::new(copied)),
+                    call: None,
+                    value: None,
+                };
+                self.seen.insert(key, copy.clone());
+                copy
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// Consts are the builtin namespaces and the script's compile-time
+    /// constants; neither changes from one tick to the next.
+    fn variable(&mut self, var: &Variable<O>) -> Variable<O> {
+        Variable {
+            value: if var.is_const {
+                var.value.clone()
+            } else {
+                self.value(&var.value)
+            },
+            is_const: var.is_const,
+            is_var_persistent: var.is_var_persistent,
+        }
+    }
+
+    fn scope(&mut self, scope: &Slots<Variable<O>>) -> Slots<Variable<O>> {
+        scope.map(|var| self.variable(var))
+    }
+
+    fn locals(&mut self, locals: &[(Slot, Variable<O>)]) -> Vec<(Slot, Variable<O>)> {
+        locals
+            .iter()
+            .map(|(slot, var)| (*slot, self.variable(var)))
+            .collect()
+    }
+
+    fn history(&mut self, history: &Slots<VecDeque<Value<O>>>) -> Slots<VecDeque<Value<O>>> {
+        history.map(|values| values.iter().map(|v| self.value(v)).collect())
+    }
+
+    fn sites(&mut self, sites: &HashMap<u32, SeriesSite<O>>) -> HashMap<u32, SeriesSite<O>> {
+        sites
+            .iter()
+            .map(|(id, site)| {
+                let copy = SeriesSite {
+                    history: site.history.iter().map(|v| self.value(v)).collect(),
+                    current: site.current.as_ref().map(|v| self.value(v)),
+                    bar: site.bar,
+                };
+                (*id, copy)
+            })
+            .collect()
+    }
+
+    /// A copy of `checkpoint` that can be handed out while the original stays
+    /// intact for the next rollback.
+    fn checkpoint(&mut self, checkpoint: &Checkpoint<O>) -> Checkpoint<O> {
+        Checkpoint {
+            variables: self.scope(&checkpoint.variables),
+            user_series_history: self.history(&checkpoint.user_series_history),
+            expr_history: self.sites(&checkpoint.expr_history),
+            function_local_state: checkpoint
+                .function_local_state
+                .iter()
+                .map(|(id, locals)| (*id, self.locals(locals)))
+                .collect(),
+            var_decls_initialized: checkpoint.var_decls_initialized.clone(),
+            output: checkpoint.output.clone(),
+            had_broker: checkpoint.had_broker,
+        }
+    }
+}
+
+impl<O: PineOutput> Interpreter<O> {
+    /// Remember the script's state as it is now, at the open of a bar that
+    /// will be run more than once. Stateful builtins keep their own copy of
+    /// the state they entered the bar with until [`commit`](Self::commit).
+    ///
+    /// The checkpoint is a deep copy of every variable, all series history and
+    /// the output so far, and each [`rollback`](Self::rollback) copies it
+    /// again, so both cost time in proportion to the whole script state.
+    pub fn checkpoint(&mut self) {
+        let live = Checkpoint {
+            variables: self.variables.clone(),
+            user_series_history: self.user_series_history.clone(),
+            expr_history: self.expr_history.clone(),
+            function_local_state: self.function_local_state.clone(),
+            var_decls_initialized: self.var_decls_initialized.clone(),
+            output: self.output.clone(),
+            had_broker: self.broker.is_some(),
+        };
+        self.checkpoint = Some(Detach::default().checkpoint(&live));
+    }
+
+    /// Whether a [`checkpoint`](Self::checkpoint) is live, i.e. the current
+    /// bar may still be rolled back.
+    pub fn is_checkpointed(&self) -> bool {
+        self.checkpoint.is_some()
+    }
+
+    /// Return to the last checkpoint so the next [`execute`](Self::execute)
+    /// re-runs the same bar instead of starting a new one. `varip` variables
+    /// keep their current values. A no-op without a checkpoint.
+    pub fn rollback(&mut self) {
+        let Some(checkpoint) = self.checkpoint.as_ref() else {
+            return;
+        };
+        let mut restored = Detach::default().checkpoint(checkpoint);
+
+        // `varip` survives the rollback, along with the record that its
+        // initializer already ran.
+        for key in &self.varip_decls {
+            let (call_id, slot) = *key;
+            let live = if call_id == 0 {
+                self.variables.get(slot)
+            } else {
+                self.function_local_state
+                    .get(&call_id)
+                    .and_then(|locals| locals.iter().find(|(s, _)| *s == slot))
+                    .map(|(_, var)| var)
+            };
+            let Some(live) = live.cloned() else {
+                continue;
+            };
+            if call_id == 0 {
+                restored.variables.insert(slot, live);
+            } else {
+                let locals = restored.function_local_state.entry(call_id).or_default();
+                match locals.iter_mut().find(|(s, _)| *s == slot) {
+                    Some((_, var)) => *var = live,
+                    None => locals.push((slot, live)),
+                }
+            }
+            if let Some(bar) = self.var_decls_initialized.get(key) {
+                restored.var_decls_initialized.insert(*key, *bar);
+            }
+        }
+
+        self.variables = restored.variables;
+        self.user_series_history = restored.user_series_history;
+        self.expr_history = restored.expr_history;
+        self.function_local_state = restored.function_local_state;
+        self.var_decls_initialized = restored.var_decls_initialized;
+        self.output = restored.output;
+        if !restored.had_broker {
+            self.broker = None;
+        }
+        self.replaying = true;
+    }
+
+    /// The bar closed: its last run stands, and the checkpoint is dropped.
+    pub fn commit(&mut self) {
+        self.checkpoint = None;
+    }
+}
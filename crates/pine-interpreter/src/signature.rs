@@ -7,7 +7,7 @@
 //! check safe: it can only turn a guaranteed runtime error into a compile-time
 //! one, never reject a call that would have worked.
 
-use pine_ast::Literal;
+use pine_ast::{Literal, TypeQualifier};
 
 /// The kind of value a parameter accepts, taken from the builtin's field type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -63,6 +63,9 @@ pub struct Param {
     /// True when the argument is passed unevaluated (as a captured `Expr`),
     /// for intrinsics like `request.security` that run it in another context.
     pub lazy: bool,
+    /// The strongest qualifier an argument may carry: `Series` for most, but
+    /// `Simple` for a length that sizes a buffer once, or `Const` for a title.
+    pub qualifier: TypeQualifier,
 }
 
 /// The parameters a builtin accepts, in positional order.
@@ -92,6 +95,11 @@ impl BuiltinSignature {
         self.params.iter().find(|param| param.name == name)
     }
 
+    /// The index a positional argument for parameter `name` takes.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.params.iter().position(|param| param.name == name)
+    }
+
     /// Whether the positional argument at `index` is passed unevaluated.
     pub fn positional_is_lazy(&self, index: usize) -> bool {
         self.positional(index).is_some_and(|param| param.lazy)
@@ -0,0 +1,105 @@
+//! Variable slots: every name a program binds or reads is numbered once, when
+//! the program is compiled, so the evaluator indexes a vector instead of
+//! hashing a string on each access.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A variable's index into [`Slots`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Slot(u32);
+
+/// The name ↔ slot table. An interpreter shares it with the library and
+/// `request.security` interpreters it spawns, so a function value compiled by
+/// one reads the same variables when another calls it.
+#[derive(Debug, Default)]
+pub(crate) struct Names {
+    slots: HashMap<Rc<str>, Slot>,
+    names: Vec<Rc<str>>,
+}
+
+impl Names {
+    /// The slot for `name`, numbering it if it is new.
+    pub(crate) fn slot(&mut self, name: &str) -> Slot {
+        if let Some(slot) = self.slots.get(name) {
+            return *slot;
+        }
+        let slot = Slot(self.names.len() as u32);
+        let name: Rc<str> = name.into();
+        self.names.push(Rc::clone(&name));
+        self.slots.insert(name, slot);
+        slot
+    }
+
+    /// The slot for `name`, if any program or host call has used it.
+    pub(crate) fn get(&self, name: &str) -> Option<Slot> {
+        self.slots.get(name).copied()
+    }
+
+    pub(crate) fn name(&self, slot: Slot) -> &Rc<str> {
+        &self.names[slot.0 as usize]
+    }
+}
+
+/// One value per slot; `None` where the name is unbound.
+#[derive(Clone)]
+pub(crate) struct Slots<T>(Vec<Option<T>>);
+
+impl<T> Default for Slots<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<T> Slots<T> {
+    pub(crate) fn get(&self, slot: Slot) -> Option<&T> {
+        self.0.get(slot.0 as usize).and_then(Option::as_ref)
+    }
+
+    pub(crate) fn get_mut(&mut self, slot: Slot) -> Option<&mut T> {
+        self.0.get_mut(slot.0 as usize).and_then(Option::as_mut)
+    }
+
+    /// The entry for `slot`, bound with `default()` if it is not yet.
+    pub(crate) fn get_or_insert_with(&mut self, slot: Slot, default: impl FnOnce() -> T) -> &mut T {
+        self.entry(slot).get_or_insert_with(default)
+    }
+
+    pub(crate) fn insert(&mut self, slot: Slot, value: T) {
+        *self.entry(slot) = Some(value);
+    }
+
+    /// Bind or unbind `slot`, returning what it held.
+    pub(crate) fn replace(&mut self, slot: Slot, value: Option<T>) -> Option<T> {
+        std::mem::replace(self.entry(slot), value)
+    }
+
+    pub(crate) fn take(&mut self, slot: Slot) -> Option<T> {
+        self.0.get_mut(slot.0 as usize).and_then(Option::take)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (Slot, &T)> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| Some((Slot(index as u32), value.as_ref()?)))
+    }
+
+    /// Apply `f` to every bound entry, keeping the slots as they are.
+    pub(crate) fn map<U>(&self, mut f: impl FnMut(&T) -> U) -> Slots<U> {
+        Slots(
+            self.0
+                .iter()
+                .map(|value| value.as_ref().map(&mut f))
+                .collect(),
+        )
+    }
+
+    fn entry(&mut self, slot: Slot) -> &mut Option<T> {
+        let index = slot.0 as usize;
+        if index >= self.0.len() {
+            self.0.resize_with(index + 1, || None);
+        }
+        &mut self.0[index]
+    }
+}
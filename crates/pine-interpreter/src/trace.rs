@@ -0,0 +1,126 @@
+//! Where a runtime error struck: the innermost located expression or statement
+//! it passed through, the bar being run and the user calls in progress.
+
+use std::rc::Rc;
+
+use pine_ast::{Expr, Loc, Stmt};
+
+use crate::slots::Slot;
+
+/// A user function or method call in progress when an error struck.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// The callee as written at the call site: `f`, `lib.f`, `p.area`.
+    pub function: String,
+    /// Where the call was made.
+    pub call_site: Loc,
+    /// The library the call was made from; `None` for the main script.
+    pub file: Option<String>,
+}
+
+/// A user call in progress, as the interpreter tracks it while it runs.
+pub(crate) struct ActiveCall {
+    pub(crate) function: Rc<str>,
+    pub(crate) call_site: Loc,
+    /// The file the call was made from; `None` for the main script.
+    pub(crate) file: Option<Rc<str>>,
+    /// The callee's variables, which a debugger shows as its locals.
+    pub(crate) locals: Rc<[Slot]>,
+}
+
+impl ActiveCall {
+    pub(crate) fn frame(&self) -> Frame {
+        Frame {
+            function: self.function.to_string(),
+            call_site: self.call_site,
+            file: self.file.as_deref().map(str::to_string),
+        }
+    }
+}
+
+/// The context a [`RuntimeError::Traced`](crate::RuntimeError::Traced) carries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trace {
+    /// The failing expression or statement, when one with a position was
+    /// reached before the error left the bar.
+    pub loc: Option<Loc>,
+    /// The library `loc` is in; `None` for the main script.
+    pub file: Option<String>,
+    pub bar_index: Option<i64>,
+    /// The bar's opening time (UNIX ms).
+    pub time: Option<i64>,
+    /// The user calls in progress, innermost first.
+    pub stack: Vec<Frame>,
+}
+
+impl Trace {
+    /// The innermost position in the main script: where the error struck, or
+    /// else the call that led into the library it struck in.
+    pub fn script_location(&self) -> Option<(u32, u32)> {
+        if self.file.is_none() {
+            return self.loc.and_then(|loc| loc.position());
+        }
+        self.stack
+            .iter()
+            .find(|frame| frame.file.is_none())
+            .and_then(|frame| frame.call_site.position())
+    }
+}
+
+/// The position recorded on an expression, or on the sub-expression it leads
+/// with when it records none itself.
+pub(crate) fn expr_loc(expr: &Expr) -> Option<Loc> {
+    let loc = match expr {
+        Expr::Variable { loc, .. } | Expr::Binary { loc, .. } => *loc,
+        Expr::Call { callee, loc, .. } => return call_loc(callee, *loc),
+        Expr::MemberAccess { member_loc, .. } => *member_loc,
+        Expr::Index { expr, .. } | Expr::Unary { expr, .. } => return expr_loc(expr),
+        Expr::Ternary { condition, .. } => return expr_loc(condition),
+        _ => return None,
+    };
+    loc.position().map(|_| loc)
+}
+
+/// The position recorded on a statement, or on the expression it leads with.
+pub(crate) fn stmt_loc(stmt: &Stmt) -> Option<Loc> {
+    let loc = match stmt {
+        Stmt::VarDecl { loc, .. }
+        | Stmt::TupleAssignment { loc, .. }
+        | Stmt::For { loc, .. }
+        | Stmt::ForIn { loc, .. }
+        | Stmt::TypeDecl { loc, .. }
+        | Stmt::MethodDecl { loc, .. }
+        | Stmt::EnumDecl { loc, .. }
+        | Stmt::FunctionDecl { loc, .. }
+        | Stmt::Import { loc, .. }
+        | Stmt::Break { loc }
+        | Stmt::Continue { loc }
+        | Stmt::Error { loc } => *loc,
+        Stmt::Assignment { target, value } => return expr_loc(target).or_else(|| expr_loc(value)),
+        Stmt::Expression(expr) => return expr_loc(expr),
+        Stmt::If { condition, .. } | Stmt::While { condition, .. } => return expr_loc(condition),
+        Stmt::Export { .. } => return None,
+    };
+    loc.position().map(|_| loc)
+}
+
+/// Where a call starts, `lib` in `lib.f(x)`, rather than at its `(`.
+pub(crate) fn call_loc(callee: &Expr, loc: Loc) -> Option<Loc> {
+    let start = match callee {
+        Expr::MemberAccess { object, .. } => expr_loc(object).or_else(|| expr_loc(callee)),
+        _ => expr_loc(callee),
+    };
+    start.or(loc.position().map(|_| loc))
+}
+
+/// A callee as written: `f`, `lib.f`, or the bare member of a longer chain.
+pub(crate) fn callee_name(callee: &Expr) -> String {
+    match callee {
+        Expr::Variable { name, .. } => name.clone(),
+        Expr::MemberAccess { object, member, .. } => match object.as_ref() {
+            Expr::Variable { name, .. } => format!("{name}.{member}"),
+            _ => member.clone(),
+        },
+        _ => "<function>".to_string(),
+    }
+}
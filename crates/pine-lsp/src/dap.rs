@@ -0,0 +1,993 @@
+//! A Debug Adapter Protocol server, so an editor can run a script under the
+//! interpreter's debugger: set breakpoints, step through bars and user
+//! functions, and inspect variables and their history.
+//!
+//! Requests arrive on one thread; the script runs on another, since a `Script`
+//! cannot leave the thread that built it. The runner answers everything about
+//! the script: before the run, while it is stopped, and once it has ended. A
+//! request that arrives while the script runs (new breakpoints, a pause, a
+//! disconnect) raises the script's pause flag, so it stops long enough to read
+//! it.
+
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use pine_lang::builtins::DefaultPineOutput;
+use pine_lang::core::LogLevel;
+use pine_lang::data::StaticProvider;
+use pine_lang::interpreter::{
+    Breakpoint, DebugHandler, Interpreter, Local, Resume, RuntimeError, Stop, StopReason, Value,
+};
+use pine_lang::{DirLoader, Error, RunResult, Script, ScriptBuilder};
+use serde_json::{json, Value as Json};
+
+type Output = DefaultPineOutput;
+
+/// How many of the latest statements the "Recent statements" scope lists.
+const TRACE_CAPACITY: usize = 200;
+
+/// How many past bars of a variable's history it shows.
+const HISTORY_SHOWN: usize = 100;
+
+/// Serve the debug adapter over stdio until the client disconnects.
+pub fn run_dap() {
+    serve(io::stdin().lock(), io::stdout());
+}
+
+/// Serve the debug adapter, reading requests from `input` and writing
+/// responses and events to `output`, until the client disconnects or
+/// `input` ends.
+fn serve(mut input: impl BufRead, output: impl Write + Send + 'static) {
+    let client = Client::new(output);
+    let control = Arc::new(Control::default());
+    let (requests, queued) = mpsc::channel();
+    let mut queued = Some(queued);
+    let mut runner = None;
+
+    while let Some(request) = read_message(&mut input) {
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                client.respond(&request, capabilities());
+                client.event("initialized", json!({}));
+            }
+            "launch" => match queued.take() {
+                Some(queued) => {
+                    let (client, control) = (client.clone(), Arc::clone(&control));
+                    runner = Some(thread::spawn(move || {
+                        run_session(request, queued, client, control)
+                    }));
+                }
+                None => client.fail(&request, "A script is already running"),
+            },
+            "threads" => client.respond(
+                &request,
+                json!({ "threads": [{ "id": 1, "name": "script" }] }),
+            ),
+            "pause" => {
+                control.pause_requested.store(true, Ordering::SeqCst);
+                control.interrupt();
+                client.respond(&request, json!({}));
+            }
+            command => {
+                let disconnect = command == "disconnect";
+                if disconnect && runner.is_none() {
+                    client.respond(&request, json!({}));
+                    break;
+                }
+                if matches!(command, "setBreakpoints" | "disconnect" | "terminate") {
+                    control.interrupt();
+                }
+                // Until `launch`, requests wait in the queue for the runner.
+                if let Err(mpsc::SendError(request)) = requests.send(request) {
+                    client.fail(&request, "The script is not running");
+                }
+                if disconnect {
+                    break;
+                }
+            }
+        }
+    }
+    drop(requests);
+    if let Some(runner) = runner {
+        let _ = runner.join();
+    }
+}
+
+/// What the request thread and the runner share.
+#[derive(Default)]
+struct Control {
+    /// Whether the script is running, rather than stopped or not started.
+    running: AtomicBool,
+    /// Whether the client asked for a pause, rather than the request thread
+    /// stopping the script to hand it requests.
+    pause_requested: AtomicBool,
+    /// The script's pause flag, once it is built.
+    pause: Mutex<Option<Arc<AtomicBool>>>,
+}
+
+impl Control {
+    /// Stop the running script at its next statement, so it reads the
+    /// requests queued for it.
+    fn interrupt(&self) {
+        if !self.running.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Some(pause) = self.pause.lock().expect("pause lock").as_ref() {
+            pause.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// The client end: every response and event goes out through it, numbered
+/// in the order sent.
+#[derive(Clone)]
+struct Client(Arc<Mutex<Channel>>);
+
+struct Channel {
+    writer: Box<dyn Write + Send>,
+    seq: i64,
+}
+
+impl Client {
+    fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Channel {
+            writer: Box::new(writer),
+            seq: 0,
+        })))
+    }
+
+    fn send(&self, mut message: Json) {
+        let mut channel = self.0.lock().expect("client lock");
+        channel.seq += 1;
+        message["seq"] = json!(channel.seq);
+        let body = message.to_string();
+        // The client going away ends the session through its input instead.
+        let _ = write!(
+            channel.writer,
+            "Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let _ = channel.writer.flush();
+    }
+
+    fn respond(&self, request: &Json, body: Json) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn fail(&self, request: &Json, message: impl Into<String>) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message.into(),
+        }));
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn output(&self, category: &str, text: String) {
+        self.event(
+            "output",
+            json!({ "category": category, "output": text + "\n" }),
+        );
+    }
+}
+
+/// The next framed message, or `None` once the input ends.
+fn read_message(input: &mut impl BufRead) -> Option<Json> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    // A message that is not JSON has no command, and is ignored.
+    Some(serde_json::from_slice(&body).unwrap_or(Json::Null))
+}
+
+fn capabilities() -> Json {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsConditionalBreakpoints": true,
+        "supportsEvaluateForHovers": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+/// Build the script `launch` names, run it under the debugger, and answer
+/// requests until the client disconnects.
+fn run_session(launch: Json, requests: Receiver<Json>, client: Client, control: Arc<Control>) {
+    // Shared with the debugger, which the script drops when its run ends.
+    let requests = Rc::new(requests);
+    let args = &launch["arguments"];
+    match build(args) {
+        Ok(mut script) => {
+            client.respond(&launch, json!({}));
+            let mut debugger = Debugger {
+                program: args["program"]
+                    .as_str()
+                    .map(PathBuf::from)
+                    .unwrap_or_default(),
+                lines: Vec::new(),
+                stop_on_bar: args["stopOnBar"].as_i64(),
+                requests: Rc::clone(&requests),
+                client: client.clone(),
+                control: Arc::clone(&control),
+                nodes: Vec::new(),
+            };
+            *control.pause.lock().expect("pause lock") = Some(script.pause_handle());
+            script.trace_statements(TRACE_CAPACITY);
+            if args["stopOnEntry"].as_bool() == Some(true) {
+                script.stop_on_entry();
+            }
+            script.set_breakpoints(debugger.breakpoints());
+            if debugger.configure(&mut script) {
+                script.set_debug_handler(debugger);
+                control.running.store(true, Ordering::SeqCst);
+                let run = script.run();
+                control.running.store(false, Ordering::SeqCst);
+                report(&client, run);
+            }
+        }
+        Err(message) => {
+            client.fail(&launch, message);
+            client.event("terminated", json!({}));
+        }
+    }
+    finish(&requests, &client);
+}
+
+/// Answer requests after the run until the client disconnects.
+fn finish(requests: &Receiver<Json>, client: &Client) {
+    while let Ok(request) = requests.recv() {
+        match request["command"].as_str().unwrap_or_default() {
+            "disconnect" => {
+                client.respond(&request, json!({}));
+                return;
+            }
+            "terminate" | "configurationDone" => client.respond(&request, json!({})),
+            "setBreakpoints" => client.respond(&request, json!({ "breakpoints": [] })),
+            _ => client.fail(&request, "The script is not running"),
+        }
+    }
+}
+
+/// Build the script from `launch`'s arguments, the way `pinecone run` does
+/// from its command line.
+fn build(args: &Json) -> Result<Script<Output>, String> {
+    let program = args["program"]
+        .as_str()
+        .ok_or("launch needs a `program`, the script to run")?;
+    let data = args["data"]
+        .as_str()
+        .ok_or("launch needs `data`, a CSV of bars to run over")?;
+    let source = std::fs::read_to_string(program).map_err(|e| format!("{program}: {e}"))?;
+    let provider = StaticProvider::from_csv(Path::new(data)).map_err(|e| format!("{data}: {e}"))?;
+    let timeframe = args["timeframe"]
+        .as_str()
+        .unwrap_or("1D")
+        .parse()
+        .map_err(|e| format!("timeframe: {e}"))?;
+    let inputs = match args.get("inputs") {
+        Some(inputs) if !inputs.is_null() => {
+            pine_lang::inputs_from_json(&inputs.to_string()).map_err(|e| format!("inputs: {e}"))?
+        }
+        _ => Default::default(),
+    };
+    let roots = match args["lib"].as_array() {
+        Some(dirs) if !dirs.is_empty() => dirs
+            .iter()
+            .filter_map(Json::as_str)
+            .map(PathBuf::from)
+            .collect(),
+        _ => vec![Path::new(program)
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf()],
+    };
+
+    let mut builder = ScriptBuilder::<Output>::with_code(&source)
+        .with_data(provider.data().clone())
+        .with_timeframe(timeframe)
+        .with_inputs(inputs)
+        .with_library_loader(Box::new(DirLoader::new(roots)))
+        .with_request_provider(Box::new(provider));
+    if let Some(bars) = args["bars"].as_u64() {
+        builder = builder.with_bar_count(bars as usize);
+    }
+    builder.compile().map_err(|e| format!("{program}: {e}"))
+}
+
+/// Tell the client how the run ended: its logs, any error, and its end.
+fn report(client: &Client, run: Result<pine_lang::Run<Output>, Error>) {
+    let failed = match run {
+        Ok(run) => {
+            for log in RunResult::collect(&run.outputs).logs {
+                let category = match log.level {
+                    LogLevel::Error => "stderr",
+                    LogLevel::Info | LogLevel::Warning => "stdout",
+                };
+                client.output(category, log.message);
+            }
+            false
+        }
+        Err(Error::Runtime(error)) if matches!(error.untraced(), RuntimeError::Terminated) => false,
+        Err(error) => {
+            client.output("stderr", error.to_string());
+            true
+        }
+    };
+    client.event("exited", json!({ "exitCode": i32::from(failed) }));
+    client.event("terminated", json!({}));
+}
+
+/// A variable reference's children: a scope's variables, or a value's
+/// elements and past values.
+enum Node {
+    Locals,
+    Script,
+    Bar,
+    Trace,
+    Value {
+        value: Value<Output>,
+        /// What it held on earlier bars, the latest first.
+        history: Vec<Value<Output>>,
+    },
+}
+
+/// The runner's side of a session: the breakpoints the client set, and the
+/// requests it sends while the script is stopped.
+struct Debugger {
+    program: PathBuf,
+    /// Line breakpoints in the program, as the client set them.
+    lines: Vec<Breakpoint>,
+    /// The bar `stopOnBar` asked to stop at the start of.
+    stop_on_bar: Option<i64>,
+    requests: Rc<Receiver<Json>>,
+    client: Client,
+    control: Arc<Control>,
+    /// The variable references handed out since the script stopped; a
+    /// reference is its index plus one.
+    nodes: Vec<Node>,
+}
+
+impl Debugger {
+    /// Answer requests until `configurationDone`; false if the client ended
+    /// the session first.
+    fn configure(&mut self, script: &mut Script<Output>) -> bool {
+        while let Ok(request) = self.requests.recv() {
+            match request["command"].as_str().unwrap_or_default() {
+                "setBreakpoints" => {
+                    let body = self.set_breakpoints(&request, |breakpoints| {
+                        script.set_breakpoints(breakpoints)
+                    });
+                    self.client.respond(&request, body);
+                }
+                "configurationDone" => {
+                    self.client.respond(&request, json!({}));
+                    return true;
+                }
+                "disconnect" => {
+                    self.client.respond(&request, json!({}));
+                    return false;
+                }
+                "terminate" => {
+                    self.client.respond(&request, json!({}));
+                    self.client.event("terminated", json!({}));
+                    return false;
+                }
+                _ => self.client.fail(&request, "The script has not started"),
+            }
+        }
+        false
+    }
+
+    /// The breakpoints to hand the interpreter: the client's lines, then the
+    /// `stopOnBar` bar.
+    fn breakpoints(&self) -> Vec<Breakpoint> {
+        let mut breakpoints = self.lines.clone();
+        breakpoints.extend(self.stop_on_bar.map(Breakpoint::bar));
+        breakpoints
+    }
+
+    /// Take the client's breakpoints for a source, and describe how each was
+    /// set.
+    fn set_breakpoints(
+        &mut self,
+        request: &Json,
+        set: impl FnOnce(Vec<Breakpoint>) -> Vec<Result<(), RuntimeError>>,
+    ) -> Json {
+        let args = &request["arguments"];
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let path = args["source"]["path"].as_str().map(Path::new);
+        if !path.is_some_and(|path| same_file(path, &self.program)) {
+            let unverified: Vec<_> = requested
+                .iter()
+                .map(|breakpoint| {
+                    json!({
+                        "verified": false,
+                        "line": breakpoint["line"],
+                        "message": "Breakpoints can only be set in the launched script",
+                    })
+                })
+                .collect();
+            return json!({ "breakpoints": unverified });
+        }
+
+        self.lines = requested
+            .iter()
+            .map(|breakpoint| Breakpoint {
+                line: breakpoint["line"].as_u64().map(|line| line as u32),
+                condition: breakpoint["condition"]
+                    .as_str()
+                    .filter(|condition| !condition.trim().is_empty())
+                    .map(str::to_string),
+                ..Breakpoint::default()
+            })
+            .collect();
+        let results = set(self.breakpoints());
+        let set: Vec<_> = self
+            .lines
+            .iter()
+            .zip(results)
+            .enumerate()
+            .map(|(index, (breakpoint, result))| {
+                let mut body = json!({
+                    "id": index + 1,
+                    "verified": result.is_ok(),
+                    "line": breakpoint.line,
+                });
+                if let Err(error) = result {
+                    body["message"] = json!(error.to_string());
+                }
+                body
+            })
+            .collect();
+        json!({ "breakpoints": set })
+    }
+
+    /// Answer one request while the script is stopped; `Some` once the
+    /// client says how to go on.
+    fn handle(&mut self, interp: &mut Interpreter<Output>, request: &Json) -> Option<Resume> {
+        let args = &request["arguments"];
+        let resume = match request["command"].as_str().unwrap_or_default() {
+            "stackTrace" => {
+                let body = self.stack_trace(interp);
+                self.client.respond(request, body);
+                return None;
+            }
+            "scopes" => {
+                let body = self.scopes(interp, args["frameId"].as_u64().unwrap_or(0) as usize);
+                self.client.respond(request, body);
+                return None;
+            }
+            "variables" => {
+                let reference = args["variablesReference"].as_u64().unwrap_or(0) as usize;
+                let variables = self.variables(interp, reference);
+                self.client
+                    .respond(request, json!({ "variables": variables }));
+                return None;
+            }
+            "evaluate" => {
+                let expression = args["expression"].as_str().unwrap_or_default();
+                match interp.evaluate(expression) {
+                    Ok(value) => {
+                        let reference = self.reference(&value, Vec::new());
+                        self.client.respond(
+                            request,
+                            json!({ "result": display(&value), "variablesReference": reference }),
+                        );
+                    }
+                    Err(error) => self.client.fail(request, error.to_string()),
+                }
+                return None;
+            }
+            "setBreakpoints" => {
+                let body = self
+                    .set_breakpoints(request, |breakpoints| interp.set_breakpoints(breakpoints));
+                self.client.respond(request, body);
+                return None;
+            }
+            "configurationDone" => {
+                self.client.respond(request, json!({}));
+                return None;
+            }
+            "continue" => Resume::Continue,
+            "next" => Resume::StepOver,
+            "stepIn" => Resume::StepIn,
+            "stepOut" => Resume::StepOut,
+            "disconnect" | "terminate" => Resume::Terminate,
+            command => {
+                self.client
+                    .fail(request, format!("Unsupported request '{command}'"));
+                return None;
+            }
+        };
+        let body = match resume {
+            Resume::Continue => json!({ "allThreadsContinued": true }),
+            _ => json!({}),
+        };
+        self.client.respond(request, body);
+        Some(resume)
+    }
+
+    fn stack_trace(&self, interp: &Interpreter<Output>) -> Json {
+        let frames: Vec<_> = interp
+            .stack_frames()
+            .into_iter()
+            .enumerate()
+            .map(|(id, frame)| {
+                let (line, column) = frame.loc.map_or((0, 0), |loc| (loc.line, loc.column));
+                let source = match &frame.file {
+                    Some(file) => json!({ "name": file }),
+                    None => json!({
+                        "name": self.program.file_name().map(|name| name.to_string_lossy()),
+                        "path": self.program,
+                    }),
+                };
+                let name = frame.function.unwrap_or_else(|| "(script)".to_string());
+                json!({ "id": id, "name": name, "line": line, "column": column, "source": source })
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    /// The scopes of frame `frame`, innermost first. Only the innermost
+    /// function's locals can be shown; an outer frame shows the script's.
+    fn scopes(&mut self, interp: &Interpreter<Output>, frame: usize) -> Json {
+        let in_call = interp.stack_frames().len() > 1;
+        let mut scopes = Vec::new();
+        if frame == 0 {
+            scopes.push(("Locals", Node::Locals));
+        }
+        if in_call {
+            scopes.push(("Script", Node::Script));
+        }
+        scopes.push(("Bar", Node::Bar));
+        scopes.push(("Recent statements", Node::Trace));
+        let scopes: Vec<_> = scopes
+            .into_iter()
+            .map(|(name, node)| {
+                self.nodes.push(node);
+                json!({
+                    "name": name,
+                    "variablesReference": self.nodes.len(),
+                    "expensive": name == "Recent statements",
+                })
+            })
+            .collect();
+        json!({ "scopes": scopes })
+    }
+
+    fn variables(&mut self, interp: &mut Interpreter<Output>, reference: usize) -> Vec<Json> {
+        let Some(node) = reference
+            .checked_sub(1)
+            .and_then(|index| self.nodes.get(index))
+        else {
+            return Vec::new();
+        };
+        match node {
+            Node::Locals => self.locals(interp.locals()),
+            Node::Script => self.locals(interp.script_variables()),
+            Node::Bar => interp
+                .bar_variables()
+                .into_iter()
+                .map(|(name, value)| self.variable(name.to_string(), &value, None, Vec::new()))
+                .collect(),
+            Node::Trace => interp
+                .execution_trace()
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .enumerate()
+                .map(|(index, executed)| {
+                    let file = executed
+                        .file
+                        .as_deref()
+                        .map(|file| format!("{file}:"))
+                        .unwrap_or_default();
+                    let bar = executed
+                        .bar_index
+                        .map(|bar| format!("bar {bar}, "))
+                        .unwrap_or_default();
+                    json!({
+                        "name": format!("{index}"),
+                        "value": format!(
+                            "{bar}{file}{}:{}{}",
+                            executed.loc.line,
+                            executed.loc.column,
+                            " ·".repeat(executed.depth),
+                        ),
+                        "variablesReference": 0,
+                    })
+                })
+                .collect(),
+            Node::Value { value, history } => {
+                let (value, history) = (value.clone(), history.clone());
+                let mut children: Vec<_> = elements(&value)
+                    .into_iter()
+                    .map(|(name, value)| self.variable(name, &value, None, Vec::new()))
+                    .collect();
+                children.extend(history.iter().enumerate().map(|(back, value)| {
+                    self.variable(format!("[{}]", back + 1), value, None, Vec::new())
+                }));
+                children
+            }
+        }
+    }
+
+    fn locals(&mut self, locals: Vec<Local<'_, Output>>) -> Vec<Json> {
+        locals
+            .into_iter()
+            .map(|local| {
+                let history = local
+                    .history
+                    .map(|history| history.iter().rev().take(HISTORY_SHOWN).cloned().collect())
+                    .unwrap_or_default();
+                let qualifier = match local.kind {
+                    _ if local.is_const => Some("const"),
+                    pine_lang::ast::VarKind::Var => Some("var"),
+                    pine_lang::ast::VarKind::Varip => Some("varip"),
+                    _ => None,
+                };
+                self.variable(local.name, local.value, qualifier, history)
+            })
+            .collect()
+    }
+
+    fn variable(
+        &mut self,
+        name: String,
+        value: &Value<Output>,
+        qualifier: Option<&str>,
+        history: Vec<Value<Output>>,
+    ) -> Json {
+        let kind = match qualifier {
+            Some(qualifier) => format!("{qualifier} {}", type_name(value)),
+            None => type_name(value),
+        };
+        json!({
+            "name": name,
+            "value": display(value),
+            "type": kind,
+            "variablesReference": self.reference(value, history),
+        })
+    }
+
+    /// A reference to `value`'s children, or 0 if it has none.
+    fn reference(&mut self, value: &Value<Output>, history: Vec<Value<Output>>) -> usize {
+        if history.is_empty() && elements(value).is_empty() {
+            return 0;
+        }
+        self.nodes.push(Node::Value {
+            value: value.clone(),
+            history,
+        });
+        self.nodes.len()
+    }
+
+    /// Tell the client where the script stopped, then answer its requests
+    /// until it says how to go on.
+    fn serve_stop(&mut self, interp: &mut Interpreter<Output>, stop: &Stop) -> Resume {
+        if stop.reason == StopReason::Pause
+            && !self.control.pause_requested.swap(false, Ordering::SeqCst)
+        {
+            // Stopped only to read what arrived while the script ran.
+            while let Ok(request) = self.requests.try_recv() {
+                if let Some(resume) = self.handle(interp, &request) {
+                    return resume;
+                }
+            }
+            return Resume::Continue;
+        }
+
+        self.nodes.clear();
+        let (reason, description) = match &stop.reason {
+            StopReason::Entry => ("entry", None),
+            StopReason::Breakpoint(_) => ("breakpoint", None),
+            StopReason::Step => ("step", None),
+            StopReason::Pause => ("pause", None),
+            StopReason::Error(message) => ("exception", Some(message.clone())),
+        };
+        let mut body = json!({
+            "reason": reason,
+            "threadId": 1,
+            "allThreadsStopped": true,
+        });
+        if let Some(bar) = stop.bar_index {
+            body["description"] = json!(format!("Stopped on bar {bar}"));
+        }
+        if let Some(message) = description {
+            body["description"] = json!(message);
+            body["text"] = json!(message);
+        }
+        if let StopReason::Breakpoint(index) = stop.reason {
+            if index < self.lines.len() {
+                body["hitBreakpointIds"] = json!([index + 1]);
+            }
+        }
+        self.client.event("stopped", body);
+
+        while let Ok(request) = self.requests.recv() {
+            if let Some(resume) = self.handle(interp, &request) {
+                return resume;
+            }
+        }
+        // The client went away.
+        Resume::Terminate
+    }
+}
+
+impl DebugHandler<Output> for Debugger {
+    fn stopped(&mut self, interp: &mut Interpreter<Output>, stop: &Stop) -> Resume {
+        self.control.running.store(false, Ordering::SeqCst);
+        let resume = self.serve_stop(interp, stop);
+        self.control.running.store(true, Ordering::SeqCst);
+        resume
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// A value as the variables view shows it.
+fn display(value: &Value<Output>) -> String {
+    match value {
+        Value::Na => "na".to_string(),
+        Value::Number(n) if n.is_nan() => "na".to_string(),
+        Value::Int(n) => n.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::String(s) => format!("{s:?}"),
+        Value::Color(c) => format!("rgba({}, {}, {}, {})", c.r, c.g, c.b, c.t),
+        Value::Series(series) => display(&series.current),
+        Value::Array(items) => format!("array({})", items.borrow().len()),
+        Value::Matrix { data, .. } => {
+            let rows = data.borrow();
+            format!(
+                "matrix({}x{})",
+                rows.len(),
+                rows.first().map_or(0, Vec::len)
+            )
+        }
+        Value::Map { data, .. } => format!("map({})", data.borrow().len()),
+        Value::Object { type_name, .. } => type_name.clone(),
+        Value::Enum {
+            enum_name,
+            field_name,
+            ..
+        } => format!("{enum_name}.{field_name}"),
+        Value::Function(_) | Value::BuiltinFunction(_) => "function".to_string(),
+        Value::Expr(_) => "expression".to_string(),
+        Value::Type(ty) => format!("type {}", ty.name()),
+    }
+}
+
+fn type_name(value: &Value<Output>) -> String {
+    match value {
+        Value::Na => "na",
+        Value::Int(_) => "int",
+        Value::Number(_) => "float",
+        Value::Bool(_) => "bool",
+        Value::String(_) => "string",
+        Value::Color(_) => "color",
+        Value::Series(series) => return type_name(&series.current),
+        Value::Array(_) => "array",
+        Value::Matrix { element_type, .. } => return format!("matrix<{element_type}>"),
+        Value::Map {
+            key_type,
+            value_type,
+            ..
+        } => return format!("map<{key_type}, {value_type}>"),
+        Value::Object { type_name, .. } => type_name,
+        Value::Enum { enum_name, .. } => enum_name,
+        Value::Function(_) | Value::BuiltinFunction(_) => "function",
+        Value::Expr(_) => "expression",
+        Value::Type(_) => "type",
+    }
+    .to_string()
+}
+
+/// The parts of a compound value: an array's or a matrix's rows, a map's
+/// entries, an object's fields.
+fn elements(value: &Value<Output>) -> Vec<(String, Value<Output>)> {
+    match value {
+        Value::Series(series) => elements(&series.current),
+        Value::Array(items) => items
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (format!("[{index}]"), item.clone()))
+            .collect(),
+        Value::Matrix { data, .. } => data
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(index, row)| {
+                let row = Value::Array(Rc::new(RefCell::new(row.clone())));
+                (format!("[{index}]"), row)
+            })
+            .collect(),
+        Value::Map { data, .. } => data
+            .borrow()
+            .iter()
+            .map(|(key, value)| (display(key), value.clone()))
+            .collect(),
+        Value::Object { fields, .. } => {
+            let mut fields: Vec<_> = fields
+                .borrow()
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            fields
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A writer the test reads back once the session is over.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(requests: &[Json]) -> Vec<u8> {
+        let mut framed = Vec::new();
+        for (seq, request) in requests.iter().enumerate() {
+            let mut request = request.clone();
+            request["seq"] = json!(seq + 1);
+            request["type"] = json!("request");
+            let body = request.to_string();
+            write!(framed, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        }
+        framed
+    }
+
+    #[test]
+    fn a_session_stops_at_a_conditional_breakpoint_and_steps_into_a_call() {
+        let dir = std::env::temp_dir().join(format!("pine-dap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("script.pine");
+        std::fs::write(
+            &program,
+            "//@version=6\nindicator(\"x\")\nf(x) =>\n    x * 2\nvar total = 0.0\ntotal += f(close)\nplot(total)\n",
+        )
+        .unwrap();
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests/data/bars.csv");
+        let source = json!({ "path": program });
+
+        // Queued up front: the runner reads each once the script is where it
+        // can answer.
+        let input = frame(&[
+            json!({ "command": "initialize", "arguments": {} }),
+            json!({ "command": "launch", "arguments": { "program": program, "data": data } }),
+            json!({ "command": "setBreakpoints", "arguments": {
+                "source": source,
+                "breakpoints": [{ "line": 6, "condition": "bar_index == 3" }],
+            } }),
+            json!({ "command": "configurationDone" }),
+            json!({ "command": "stepIn" }),
+            json!({ "command": "stackTrace" }),
+            json!({ "command": "evaluate", "arguments": { "expression": "x + 1" } }),
+            json!({ "command": "stepOut" }),
+            json!({ "command": "scopes", "arguments": { "frameId": 0 } }),
+            json!({ "command": "variables", "arguments": { "variablesReference": 1 } }),
+            json!({ "command": "variables", "arguments": { "variablesReference": 4 } }),
+            json!({ "command": "continue" }),
+            json!({ "command": "disconnect" }),
+        ]);
+        let output = Captured::default();
+        serve(io::Cursor::new(input), output.clone());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let written = output.0.lock().unwrap().clone();
+        let mut reader = io::BufReader::new(written.as_slice());
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut reader) {
+            messages.push(message);
+        }
+        let response = |command: &str, nth: usize| {
+            messages
+                .iter()
+                .filter(|m| m["type"] == "response" && m["command"] == command)
+                .nth(nth)
+                .unwrap_or_else(|| panic!("no {command} response"))
+                .clone()
+        };
+        let stops: Vec<_> = messages
+            .iter()
+            .filter(|m| m["event"] == "stopped")
+            .map(|m| {
+                (
+                    m["body"]["reason"].clone(),
+                    m["body"]["description"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            stops,
+            [
+                (json!("breakpoint"), json!("Stopped on bar 3")),
+                (json!("step"), json!("Stopped on bar 3")),
+                (json!("step"), json!("Stopped on bar 3")),
+            ]
+        );
+
+        let frames = &response("stackTrace", 0)["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "f");
+        assert_eq!(frames[0]["line"], 4);
+        assert_eq!(frames[1]["name"], "(script)");
+        assert_eq!(frames[1]["line"], 6);
+        assert_eq!(response("evaluate", 0)["body"]["result"], "106");
+
+        // Back in the script on `plot`: bars 0 to 3 added 2 * (102 + … + 105),
+        // and `[1]` is what bar 2 left.
+        let locals = &response("variables", 0)["body"]["variables"];
+        assert_eq!(locals[0]["name"], "total");
+        assert_eq!(locals[0]["value"], "828");
+        assert_eq!(locals[0]["type"], "var float");
+        let history = &response("variables", 1)["body"]["variables"];
+        assert_eq!(
+            history[0],
+            json!({ "name": "[1]", "value": "618", "type": "float", "variablesReference": 0 })
+        );
+        assert_eq!(history.as_array().unwrap().len(), 3);
+
+        let end: Vec<_> = messages
+            .iter()
+            .rev()
+            .take(3)
+            .map(|m| {
+                m["event"]
+                    .as_str()
+                    .or(m["command"].as_str())
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(end, ["disconnect", "terminated", "exited"]);
+    }
+}
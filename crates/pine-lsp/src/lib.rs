@@ -1,3 +1,7 @@
+mod dap;
+
+pub use dap::run_dap;
+
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Mutex;
@@ -60,13 +64,15 @@ impl Backend {
                     .collect(),
                 Some(analysis.symbols),
             ),
-            // A lex/parse/version error stops analysis; publish it as a single diagnostic.
+            // Syntax errors come back as diagnostics over a partial program; a
+            // lex or version error stops analysis, so publish it on its own.
             Err(err) => (vec![error_diagnostic(&err, &text)], None),
         };
         {
             let mut documents = self.documents.lock().unwrap();
-            // A transient parse error yields no table (e.g. right after typing a
-            // `.`); keep the last good one so completion and hover still answer.
+            // A transient lex error yields no table (e.g. right after typing an
+            // unterminated string); keep the last good one so completion and
+            // hover still answer.
             let symbols =
                 analyzed.or_else(|| documents.get_mut(&uri).and_then(|d| d.symbols.take()));
             documents.insert(uri.clone(), Document { text, symbols });
@@ -770,10 +776,13 @@ mod tests {
     #[test]
     fn parse_error_points_at_its_line() {
         let source = "//@version=6\nindicator(\"x\")\nlog.info(str.tostring. (up))\n";
-        let Err(err) = pine_lang::analyze(source, None) else {
-            panic!("expected a parse error");
-        };
-        let diag = error_diagnostic(&err, source);
+        let analysis = pine_lang::analyze(source, None).unwrap();
+        let diag = analysis
+            .diagnostics
+            .iter()
+            .find(|d| d.rule == "syntax-error")
+            .map(|d| to_lsp(d, source))
+            .expect("syntax error diagnostic");
         assert_eq!(diag.severity, Some(DiagnosticSeverity::ERROR));
         // Points at the offending `up` token, not the top of the file.
         assert_eq!(diag.range.start, Position::new(2, 24));
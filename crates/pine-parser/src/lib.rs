@@ -108,6 +108,11 @@ pub struct Parser {
     comments: Vec<Comment>,
     current: usize,
     next_call_id: u32,
+    /// Whether a statement's syntax error is recorded and skipped, rather
+    /// than ending the parse.
+    recover: bool,
+    /// The syntax errors skipped so far while recovering.
+    errors: Vec<ParserError>,
 }
 
 impl Parser {
@@ -130,6 +135,8 @@ impl Parser {
             comments,
             current: 0,
             next_call_id: 1,
+            recover: false,
+            errors: Vec::new(),
         }
     }
 
@@ -144,6 +151,17 @@ impl Parser {
         Self::new(tokens).parse_program()
     }
 
+    /// Parse the whole program, recovering from each syntax error at the next
+    /// statement. Returns the partial program, with a [`Stmt::Error`] for each
+    /// statement that failed, and every error in source order. No error
+    /// means the program is exactly what [`Parser::parse_program`] returns.
+    pub fn parse_program_recovering(mut self) -> (Program, Vec<ParserError>) {
+        self.recover = true;
+        let statements = self.parse().unwrap_or_default();
+        let program = Program::new(statements).with_comments(self.comments);
+        (program, self.errors)
+    }
+
     fn next_call_id(&mut self) -> u32 {
         let id = self.next_call_id;
         self.next_call_id += 1;
@@ -186,7 +204,11 @@ impl Parser {
         F: FnOnce(&mut Self) -> Result<T, ParserError>,
     {
         let saved_pos = self.current;
-        match f(self) {
+        // A speculative parse must fail outright to backtrack, not recover.
+        let recover = std::mem::replace(&mut self.recover, false);
+        let result = f(self);
+        self.recover = recover;
+        match result {
             Ok(val) => Some(val),
             Err(_) => {
                 self.current = saved_pos;
@@ -487,12 +509,103 @@ impl Parser {
                 break;
             }
 
-            statements.push(self.declaration()?);
+            statements.push(self.next_statement()?);
         }
 
         Ok(statements)
     }
 
+    /// Parse one statement. While recovering, a syntax error is recorded and
+    /// the statement replaced by a [`Stmt::Error`], and parsing resumes after
+    /// it.
+    fn next_statement(&mut self) -> Result<Stmt, ParserError> {
+        let start = self.current;
+        let result = self.declaration();
+        if !self.recover {
+            return result;
+        }
+        let result = match result {
+            // A statement that stops short of its line is a broken one when
+            // what follows it fails: `a = )` backtracks to a bare `a`.
+            Ok(stmt) if !self.at_statement_end() => match self.check_rest_of_line() {
+                Some(err) => Err(err),
+                None => Ok(stmt),
+            },
+            result => result,
+        };
+        result.or_else(|err| {
+            let loc = err.loc;
+            self.errors.push(err);
+            // From the statement's start, so the skip sees every block it
+            // opened.
+            self.current = start;
+            self.synchronize();
+            Ok(Stmt::Error { loc })
+        })
+    }
+
+    /// Whether the statement just parsed ran to the end of its line.
+    fn at_statement_end(&self) -> bool {
+        let next = self.peek();
+        matches!(
+            next.typ,
+            TokenType::Newline | TokenType::Dedent | TokenType::Else | TokenType::Eof
+        ) || self.current == 0
+            || self.tokens[self.current - 1].line != next.line
+    }
+
+    /// The error the rest of the line fails with, if it does, parsing it
+    /// without recording anything or moving past it.
+    fn check_rest_of_line(&mut self) -> Option<ParserError> {
+        let saved_pos = self.current;
+        let recover = std::mem::replace(&mut self.recover, false);
+        let result = self.declaration();
+        self.recover = recover;
+        self.current = saved_pos;
+        result.err()
+    }
+
+    /// Skip the rest of a broken statement: up to and past the newline that
+    /// ends it, along with any block indented under it. A dedent closing the
+    /// enclosing block is left for that block to consume.
+    fn synchronize(&mut self) {
+        let mut depth = 0usize;
+        while !self.is_at_end() {
+            match self.peek().typ {
+                TokenType::Indent => depth += 1,
+                TokenType::Dedent if depth == 0 => return,
+                TokenType::Dedent => depth -= 1,
+                TokenType::Newline if depth == 0 => {
+                    self.advance();
+                    // The broken statement's own body, if it has one.
+                    if self.check(&TokenType::Indent) {
+                        self.skip_block();
+                    }
+                    return;
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Skip an indented block, from its indent through the matching dedent.
+    fn skip_block(&mut self) {
+        let mut depth = 0usize;
+        while !self.is_at_end() {
+            match self.advance().typ {
+                TokenType::Indent => depth += 1,
+                TokenType::Dedent => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
     // Declarations (var declarations, assignments, etc.)
     /// The position of the current token, for attaching to a declaration node.
     fn cur_loc(&self) -> Loc {
@@ -1338,7 +1451,7 @@ impl Parser {
                 && !self.check(&TokenType::Else)
                 && !self.is_at_end()
             {
-                stmts.push(self.declaration()?);
+                stmts.push(self.next_statement()?);
             }
             return Ok(stmts);
         }
@@ -1373,7 +1486,7 @@ impl Parser {
             }
 
             // Parse a statement
-            stmts.push(self.declaration()?);
+            stmts.push(self.next_statement()?);
         }
 
         Ok(stmts)
@@ -2313,4 +2426,53 @@ mod tests {
 
         Ok(())
     }
+
+    fn parse_recovering(input: &str) -> (Program, Vec<ParserError>) {
+        let tokens = Lexer::new(input).tokenize().unwrap();
+        Parser::new(tokens).parse_program_recovering()
+    }
+
+    #[test]
+    fn recovery_reports_every_syntax_error() {
+        let (program, errors) =
+            parse_recovering("a = )\nb = 2\nif b > 1\n    c = (1 +)\n    d = 3\ne = b\n");
+        let lines: Vec<u32> = errors.iter().map(|e| e.loc.line).collect();
+        assert_eq!(lines, vec![1, 4]);
+
+        // The statements around each error survive, each broken one standing
+        // in as a single error node at its own nesting level.
+        let names = |stmts: &[Stmt]| -> Vec<String> {
+            stmts
+                .iter()
+                .map(|stmt| match stmt {
+                    Stmt::VarDecl { name, .. } => name.clone(),
+                    Stmt::If { .. } => "if".to_string(),
+                    Stmt::Error { .. } => "error".to_string(),
+                    _ => "other".to_string(),
+                })
+                .collect()
+        };
+        let top = &program.statements;
+        assert_eq!(names(top), ["error", "b", "if", "e"]);
+        let Stmt::If { then_branch, .. } = &top[2] else {
+            unreachable!()
+        };
+        assert_eq!(names(then_branch), ["error", "d"]);
+    }
+
+    #[test]
+    fn recovery_skips_the_body_of_a_broken_header() {
+        let (program, errors) = parse_recovering("if > 1\n    x := 1\ny = 2\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(program.statements.len(), 2);
+        assert!(matches!(program.statements[1], Stmt::VarDecl { ref name, .. } if name == "y"));
+    }
+
+    #[test]
+    fn recovery_without_errors_matches_a_plain_parse() {
+        let source = "x = close\nif x > 1\n    x := 2\n";
+        let (program, errors) = parse_recovering(source);
+        assert!(errors.is_empty());
+        assert_eq!(program, Parser::parse_source(source).unwrap());
+    }
 }
@@ -1,5 +1,5 @@
 //! The semantic analyzer: a scope-aware walk that emits Tier 1 (name
-//! resolution) and Tier 4 (structural) errors.
+//! resolution), Tier 2 (type and qualifier) and Tier 4 (structural) errors.
 //!
 //! This intentionally does **not** use the shared [`pine_ast::Visitor`]. That
 //! traversal is for observational passes; sema needs to push/pop a scope at
@@ -9,13 +9,17 @@
 
 use std::collections::{HashMap, HashSet};
 
-use pine_ast::{Argument, ExportItem, Expr, FunctionParam, Literal, Loc, Program, Stmt, UnOp};
+use pine_ast::{
+    Argument, BinOp, ExportItem, Expr, FunctionParam, Literal, Loc, MethodParam, Program, Stmt,
+    TypeQualifier, UnOp, VarKind,
+};
 use pine_core::{LibraryLoader, PineOutput};
 use pine_interpreter::{BuiltinSignature, Value};
 use pine_parser::Parser;
 
 use crate::scope::{is_global_only, Namespace, SymbolKind};
 use crate::symbols::{FileId, ScopeId, ScopeKind, Symbol, SymbolId, SymbolTable};
+use crate::types::{Base, Type};
 use pine_diagnostics::Diagnostic;
 
 pub struct Analyzer<'a, O: PineOutput> {
@@ -41,6 +45,29 @@ pub struct Analyzer<'a, O: PineOutput> {
     scope_ids: Vec<ScopeId>,
     /// Resolves `import` paths to source; absent means no cross-file resolution.
     loader: Option<&'a dyn LibraryLoader>,
+    /// The inferred type of each variable, as of the current point in the walk.
+    types: HashMap<SymbolId, Type>,
+    /// Each user function's declared parameters and inferred result.
+    signatures: HashMap<SymbolId, FunctionType>,
+    /// Set for an expression statement, whose value is thrown away — a
+    /// `switch` run for its effects need not agree on one result type.
+    discarded: bool,
+}
+
+/// What a user function's declaration says about its parameters, and the
+/// type its body was found to produce.
+struct FunctionType {
+    params: Vec<DeclaredParam>,
+    result: Type,
+}
+
+/// One declared parameter: `simple int length` has both halves, a bare
+/// `length` neither.
+#[derive(Clone)]
+struct DeclaredParam {
+    name: String,
+    qualifier: Option<TypeQualifier>,
+    base: Option<Base>,
 }
 
 /// Per-file state saved and restored around analyzing a library.
@@ -55,6 +82,40 @@ struct FileState {
     call_edges: Vec<CallEdge>,
 }
 
+/// A parameter as a body binds it, from a function's or a method's
+/// declaration alike.
+struct ParamDecl<'p> {
+    name: &'p str,
+    default: Option<&'p Expr>,
+    loc: Loc,
+    qualifier: Option<TypeQualifier>,
+    annotation: Option<&'p String>,
+}
+
+impl<'p> From<&'p FunctionParam> for ParamDecl<'p> {
+    fn from(param: &'p FunctionParam) -> Self {
+        Self {
+            name: &param.name,
+            default: param.default_value.as_ref(),
+            loc: param.loc,
+            qualifier: param.type_qualifier,
+            annotation: param.type_annotation.as_ref(),
+        }
+    }
+}
+
+impl<'p> From<&'p MethodParam> for ParamDecl<'p> {
+    fn from(param: &'p MethodParam) -> Self {
+        Self {
+            name: &param.name,
+            default: param.default_value.as_ref(),
+            loc: param.loc,
+            qualifier: param.type_qualifier,
+            annotation: param.type_annotation.as_ref(),
+        }
+    }
+}
+
 /// One call-graph edge: `(caller, callee, call-site location)`.
 type CallEdge = (String, String, Loc);
 
@@ -81,6 +142,9 @@ const BUILTIN_TYPES: &[&str] = &[
     "volume_row",
 ];
 
+/// Per-bar builtins that hold an `int`.
+const INT_VARIABLES: &[&str] = &["bar_index", "last_bar_index", "last_bar_time", "timenow"];
+
 /// The called name as written, for diagnostics: `plot` or `ta.sma`.
 fn callee_name(callee: &Expr) -> String {
     match callee {
@@ -161,6 +225,16 @@ fn expr_loc(expr: &Expr) -> Loc {
     }
 }
 
+/// The first tracked location among `exprs`, for a node (a conditional) that
+/// has none of its own.
+fn first_loc<'e>(exprs: impl IntoIterator<Item = &'e Expr>) -> Loc {
+    exprs
+        .into_iter()
+        .map(expr_loc)
+        .find(|loc| loc.position().is_some())
+        .unwrap_or_default()
+}
+
 /// How to name a literal's type in a diagnostic.
 fn describe_literal(literal: &Literal) -> &'static str {
     match literal {
@@ -172,6 +246,22 @@ fn describe_literal(literal: &Literal) -> &'static str {
     }
 }
 
+/// An argument as written, for quoting in a diagnostic: a name, a member path
+/// or a literal. `None` for anything longer.
+fn source_text(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Variable { name, .. } => Some(name.clone()),
+        Expr::MemberAccess { object, member, .. } => {
+            Some(format!("{}.{member}", source_text(object)?))
+        }
+        Expr::Literal(Literal::Int(n)) => Some(n.to_string()),
+        Expr::Literal(Literal::Number(n)) => Some(n.to_string()),
+        Expr::Literal(Literal::String(s)) => Some(format!("\"{s}\"")),
+        Expr::Literal(Literal::Bool(b)) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
 impl<'a, O: PineOutput> Analyzer<'a, O> {
     pub fn new(
         builtins: &'a HashMap<String, Value<O>>,
@@ -190,6 +280,9 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
             symbols: SymbolTable::new(),
             scope_ids: vec![SymbolTable::GLOBAL],
             loader,
+            types: HashMap::new(),
+            signatures: HashMap::new(),
+            discarded: false,
         }
     }
 
@@ -464,6 +557,321 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
         }
     }
 
+    /// Check each argument's inferred type against the builtin parameter it
+    /// binds to: a base the runtime would reject, or a qualifier stronger than
+    /// the parameter takes (a `series` length for `ta.ema`).
+    fn check_builtin_arg_types(
+        &mut self,
+        name: &str,
+        signature: &BuiltinSignature,
+        args: &[Argument],
+        types: &[Type],
+        loc: Loc,
+    ) {
+        let mut index = 0;
+        for (arg, ty) in args.iter().zip(types) {
+            let (param, value) = match arg {
+                Argument::Positional(value) => {
+                    index += 1;
+                    (signature.positional(index - 1), value)
+                }
+                Argument::Named { name: label, value } => (signature.named(label), value),
+            };
+            // An unmatched argument was reported by `check_builtin_args`.
+            let Some(param) = param else {
+                continue;
+            };
+            // So was a literal's type.
+            let inferred = ty
+                .base
+                .as_ref()
+                .filter(|_| !matches!(value, Expr::Literal(_)));
+            if let Some(found) = inferred.filter(|base| base.rejected_by(param.ty)) {
+                let expected = param.ty.describe();
+                let found = found.describe();
+                self.emit(
+                    "argument-type",
+                    loc,
+                    format!(
+                        "`{name}` expects {expected} for `{}`, found {found}",
+                        param.name
+                    ),
+                );
+            } else if ty.qualifier.is_some_and(|q| q > param.qualifier) {
+                let expected = Type {
+                    qualifier: Some(param.qualifier),
+                    base: Base::of_param(param.ty),
+                };
+                self.qualifier_mismatch(name, &param.name, value, ty, expected, loc);
+            }
+        }
+    }
+
+    /// Check a user function's arguments against the types and qualifiers
+    /// its parameters declare.
+    fn check_user_args(
+        &mut self,
+        name: &str,
+        function: SymbolId,
+        args: &[Argument],
+        types: &[Type],
+        loc: Loc,
+    ) {
+        let Some(params) = self.signatures.get(&function).map(|f| f.params.clone()) else {
+            return;
+        };
+        let mut index = 0;
+        for (arg, ty) in args.iter().zip(types) {
+            let (param, value) = match arg {
+                Argument::Positional(value) => {
+                    index += 1;
+                    (params.get(index - 1), value)
+                }
+                Argument::Named { name: label, value } => {
+                    (params.iter().find(|param| param.name == *label), value)
+                }
+            };
+            let Some(param) = param else {
+                continue;
+            };
+            if let (Some(declared), Some(found)) = (&param.base, &ty.base) {
+                if !declared.accepts(found) {
+                    let (expected, found) = (declared.describe(), found.describe());
+                    self.emit(
+                        "argument-type",
+                        loc,
+                        format!(
+                            "`{name}` expects {expected} for `{}`, found {found}",
+                            param.name
+                        ),
+                    );
+                    continue;
+                }
+            }
+            if let (Some(declared), Some(found)) = (param.qualifier, ty.qualifier) {
+                if found > declared {
+                    let expected = Type {
+                        qualifier: Some(declared),
+                        base: param.base.clone(),
+                    };
+                    self.qualifier_mismatch(name, &param.name, value, ty, expected, loc);
+                }
+            }
+        }
+    }
+
+    /// The user type a `Type.new(...)` call constructs.
+    fn constructed_type(&self, callee: &Expr) -> Option<SymbolId> {
+        let Expr::MemberAccess { object, member, .. } = callee else {
+            return None;
+        };
+        let owner = self.expr_type(object).filter(|_| member == "new")?;
+        (self.symbols.symbol(owner).kind == SymbolKind::Type).then_some(owner)
+    }
+
+    /// Check `Type.new(...)` arguments against the fields they initialize.
+    fn check_constructor_args(
+        &mut self,
+        owner: SymbolId,
+        args: &[Argument],
+        types: &[Type],
+        loc: Loc,
+    ) {
+        let type_name = self.symbols.symbol(owner).name.clone();
+        let fields: Vec<(String, Option<String>)> = self
+            .symbols
+            .members_of(owner)
+            .map(|field| (field.name.clone(), field.type_annotation.clone()))
+            .collect();
+        let mut index = 0;
+        for (arg, ty) in args.iter().zip(types) {
+            let field = match arg {
+                Argument::Positional(_) => {
+                    index += 1;
+                    fields.get(index - 1)
+                }
+                Argument::Named { name, .. } => fields.iter().find(|(field, _)| field == name),
+            };
+            let (Some((field, Some(annotation))), Some(found)) = (field, &ty.base) else {
+                continue;
+            };
+            let declared = self.parse_base(annotation);
+            if !declared.accepts(found) {
+                self.emit(
+                    "type-mismatch",
+                    loc,
+                    format!(
+                        "Cannot assign a value of '{ty}' type to the '{declared}' field \
+                         '{type_name}.{field}'"
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Report an argument more variable than its parameter allows, in Pine's
+    /// words. A base the argument's type leaves unknown is named from the
+    /// parameter's.
+    fn qualifier_mismatch(
+        &mut self,
+        callee: &str,
+        param: &str,
+        arg: &Expr,
+        found: &Type,
+        expected: Type,
+        loc: Loc,
+    ) {
+        let base = found.base.clone().or(expected.base);
+        let found = Type {
+            qualifier: found.qualifier,
+            base: base.clone(),
+        };
+        let expected = Type {
+            qualifier: expected.qualifier,
+            base,
+        };
+        let quoted = source_text(arg)
+            .map(|text| format!("='{text}'"))
+            .unwrap_or_default();
+        self.emit(
+            "qualifier-mismatch",
+            loc,
+            format!(
+                "Cannot call '{callee}' with argument '{param}'{quoted}. An argument of \
+                 '{found}' type was used but a '{expected}' is expected."
+            ),
+        );
+    }
+
+    /// The builtin a callee names (`plot`, `ta.ema`), unless a user
+    /// declaration shadows it.
+    fn builtin_name(&self, callee: &Expr) -> Option<String> {
+        let root = match callee {
+            Expr::Variable { name, .. } => name,
+            Expr::MemberAccess { object, .. } => match object.as_ref() {
+                Expr::Variable { name, .. } => name,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        (self.resolve(root).is_none() && self.is_builtin(root)).then(|| callee_name(callee))
+    }
+
+    /// The base an annotation names, telling this file's user types apart.
+    fn parse_base(&self, annotation: &str) -> Base {
+        Base::parse(annotation, |name| self.user_types.contains(name))
+    }
+
+    fn declared_param(&self, param: &ParamDecl) -> DeclaredParam {
+        DeclaredParam {
+            name: param.name.to_string(),
+            qualifier: param.qualifier,
+            base: param
+                .annotation
+                .map(|annotation| self.parse_base(annotation)),
+        }
+    }
+
+    /// Check a declaration's initializer against its annotation and qualifier,
+    /// returning the type the variable takes: as declared, else as inferred.
+    fn declared_type(
+        &mut self,
+        name: &str,
+        qualifier: Option<TypeQualifier>,
+        annotation: Option<&str>,
+        value: Type,
+        loc: Loc,
+    ) -> Type {
+        let declared = Type {
+            qualifier,
+            base: annotation.map(|annotation| self.parse_base(annotation)),
+        };
+        let message = || {
+            format!("Cannot assign a value of '{value}' type to the '{declared}' variable '{name}'")
+        };
+        let fits = match (&declared.base, &value.base) {
+            (Some(expected), Some(found)) => expected.accepts(found),
+            _ => true,
+        };
+        if !fits {
+            let message = message();
+            self.emit("type-mismatch", loc, message);
+        } else if qualifier
+            .zip(value.qualifier)
+            .is_some_and(|(expected, found)| found > expected)
+        {
+            let message = message();
+            self.emit("qualifier-mismatch", loc, message);
+        }
+        Type {
+            qualifier: qualifier.or(value.qualifier),
+            base: declared
+                .base
+                .or(value.base)
+                .filter(|base| *base != Base::Na),
+        }
+    }
+
+    /// Check a `:=` against the type its target was declared or inferred
+    /// with. A reassigned variable is a series once a series is stored in it;
+    /// otherwise its qualifier is no longer known.
+    fn check_reassignment(&mut self, target: &Expr, value: Type) {
+        let (declared, target, loc) = match target {
+            Expr::Variable { name, loc } => {
+                let Some(id) = self.symbols.resolve_id(self.current_scope(), name) else {
+                    return;
+                };
+                if self.symbols.symbol(id).kind != SymbolKind::Var {
+                    return;
+                }
+                let base = self.types.get(&id).and_then(|ty| ty.base.clone());
+                let qualifier = (value.qualifier == Some(TypeQualifier::Series))
+                    .then_some(TypeQualifier::Series);
+                self.types.insert(
+                    id,
+                    Type {
+                        qualifier,
+                        base: base.clone(),
+                    },
+                );
+                (base, format!("variable '{name}'"), *loc)
+            }
+            Expr::MemberAccess {
+                object,
+                member,
+                member_loc,
+            } => {
+                let Some(id) = self.resolve_member(object, member) else {
+                    return;
+                };
+                let field = self.symbols.symbol(id);
+                let owner = field
+                    .container
+                    .map(|owner| &self.symbols.symbol(owner).name);
+                let label = match owner {
+                    Some(owner) => format!("field '{owner}.{member}'"),
+                    None => format!("field '{member}'"),
+                };
+                let base = field.type_annotation.as_deref();
+                (
+                    base.map(|annotation| self.parse_base(annotation)),
+                    label,
+                    *member_loc,
+                )
+            }
+            _ => return,
+        };
+        if let (Some(declared), Some(found)) = (declared, &value.base) {
+            if !declared.accepts(found) {
+                self.emit(
+                    "type-mismatch",
+                    loc,
+                    format!("Cannot assign a value of '{value}' type to the '{declared}' {target}"),
+                );
+            }
+        }
+    }
+
     /// Analyze one file in its own scope, type set, and call graph.
     fn run_file(&mut self, program: &Program) {
         // Types may be referenced before their declaration, so collect them first.
@@ -678,18 +1086,24 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
             self.check_type_annotation(param.type_annotation.as_ref(), param.loc);
         }
         self.fn_stack.push(name.to_string());
-        self.function_body(
-            params.iter().map(|p| {
-                (
-                    p.name.as_str(),
-                    p.default_value.as_ref(),
-                    p.loc,
-                    p.type_annotation.as_ref(),
-                )
-            }),
-            body,
-        );
+        let result = self.function_body(params, body);
         self.fn_stack.pop();
+        let params = params
+            .iter()
+            .map(|p| self.declared_param(&p.into()))
+            .collect();
+        // The result's qualifier follows each call's arguments; only its
+        // base is fixed by the body.
+        self.signatures.insert(
+            id,
+            FunctionType {
+                params,
+                result: Type {
+                    qualifier: None,
+                    base: result.base,
+                },
+            },
+        );
         id
     }
 
@@ -727,18 +1141,21 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
         self.loop_depth -= 1;
     }
 
-    /// Visit a function body in a fresh scope with `params` bound.
-    fn function_body<'p>(
+    /// Visit a function body in a fresh scope with `params` bound, returning
+    /// the type of its last expression: the function's result.
+    fn function_body<'p, P: Into<ParamDecl<'p>>>(
         &mut self,
-        params: impl Iterator<Item = (&'p str, Option<&'p Expr>, Loc, Option<&'p String>)>,
+        params: impl IntoIterator<Item = P>,
         body: &[Stmt],
-    ) {
+    ) -> Type {
         self.enter_scope(ScopeKind::Function);
         let saved_loop_depth = self.loop_depth;
         self.loop_depth = 0;
         let scope = self.current_scope();
-        for (name, default, loc, type_annotation) in params {
-            if let Some(default) = default {
+        for param in params {
+            let param = param.into();
+            let (name, loc) = (param.name, param.loc);
+            if let Some(default) = param.default {
                 self.check_expr(default);
             }
             self.check_shadow(name, loc);
@@ -749,26 +1166,43 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                     format!("parameter `{name}` is declared more than once"),
                 );
             }
-            self.record(
+            let id = self.record(
                 Symbol::new(name, SymbolKind::Var, loc.position(), scope)
-                    .with_type(type_annotation.cloned()),
+                    .with_type(param.annotation.cloned()),
+            );
+            let declared = self.declared_param(&param);
+            self.types.insert(
+                id,
+                Type {
+                    qualifier: declared.qualifier,
+                    base: declared.base,
+                },
             );
         }
-        for stmt in body {
-            self.check_stmt(stmt);
+        let mut result = Type::unknown();
+        for (index, stmt) in body.iter().enumerate() {
+            match stmt {
+                // The last expression is returned, not discarded.
+                Stmt::Expression(expr) if index + 1 == body.len() => {
+                    result = self.check_expr(expr);
+                }
+                _ => self.check_stmt(stmt),
+            }
         }
         self.loop_depth = saved_loop_depth;
         self.exit_scope();
+        result
     }
 
     fn check_stmt(&mut self, stmt: &Stmt) {
         match stmt {
             Stmt::VarDecl {
                 name,
+                type_qualifier,
                 initializer,
                 type_annotation,
+                var_kind,
                 loc,
-                ..
             } => {
                 self.check_type_annotation(type_annotation.as_ref(), *loc);
                 self.check_shadow(name, *loc);
@@ -778,9 +1212,10 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                 } else {
                     // Check the initializer *before* declaring the name, so a
                     // self-reference (`x = x`) resolves against the outer scope.
-                    if let Some(init) = initializer {
-                        self.check_expr(init);
-                    }
+                    let value = initializer
+                        .as_ref()
+                        .map(|init| self.check_expr(init))
+                        .unwrap_or_default();
                     let scope = self.current_scope();
                     if self
                         .symbols
@@ -796,16 +1231,30 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                     }
                     let type_ref =
                         self.infer_var_type(type_annotation.as_ref(), initializer.as_ref());
-                    self.record(
+                    let id = self.record(
                         Symbol::new(name, SymbolKind::Var, loc.position(), scope)
                             .with_type(type_annotation.clone())
                             .with_type_ref(type_ref),
                     );
+                    let mut ty = self.declared_type(
+                        name,
+                        *type_qualifier,
+                        type_annotation.as_deref(),
+                        value,
+                        *loc,
+                    );
+                    // `var` state carries over from bar to bar; how variable
+                    // it ends up is not tracked.
+                    if *var_kind != VarKind::Plain {
+                        ty.qualifier = None;
+                    }
+                    self.types.insert(id, ty);
                 }
             }
             Stmt::Assignment { target, value } => {
-                self.check_expr(value);
+                let value = self.check_expr(value);
                 self.check_assign_target(target);
+                self.check_reassignment(target, value);
             }
             Stmt::TupleAssignment {
                 names, value, loc, ..
@@ -832,17 +1281,20 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                     self.record(Symbol::new(name, SymbolKind::Var, loc.position(), scope));
                 }
             }
-            Stmt::Expression(expr) => self.check_expr(expr),
+            Stmt::Expression(expr) => {
+                self.discarded = true;
+                self.check_expr(expr);
+            }
             Stmt::If {
                 condition,
                 then_branch,
                 else_if_branches,
                 else_branch,
             } => {
-                self.check_expr(condition);
+                self.check_condition(condition);
                 self.block(then_branch);
                 for (cond, body) in else_if_branches {
-                    self.check_expr(cond);
+                    self.check_condition(cond);
                     self.block(body);
                 }
                 if let Some(body) = else_branch {
@@ -857,20 +1309,33 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                 body,
                 loc,
             } => {
-                self.check_expr(from);
-                self.check_expr(to);
+                let from = self.check_expr(from);
+                let to = self.check_expr(to);
                 if let Some(step) = step {
                     self.check_expr(step);
                 }
                 self.enter_scope(ScopeKind::Block);
                 self.check_shadow(var_name, *loc);
                 let scope = self.current_scope();
-                self.record(Symbol::new(
+                let id = self.record(Symbol::new(
                     var_name,
                     SymbolKind::Var,
                     loc.position(),
                     scope,
                 ));
+                // The counter counts in its bounds' type; a series bound
+                // makes it a series, so its qualifier is left unknown.
+                let base = match (from.base, to.base) {
+                    (Some(from), Some(to)) => Base::unify(&from, &to).ok(),
+                    _ => None,
+                };
+                self.types.insert(
+                    id,
+                    Type {
+                        qualifier: None,
+                        base,
+                    },
+                );
                 self.loop_body(body);
                 self.exit_scope();
             }
@@ -899,13 +1364,15 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                 self.exit_scope();
             }
             Stmt::While { condition, body } => {
-                self.check_expr(condition);
+                self.check_condition(condition);
                 self.enter_scope(ScopeKind::Block);
                 self.loop_body(body);
                 self.exit_scope();
             }
             Stmt::Break { loc } => self.check_loop_keyword("break", *loc),
             Stmt::Continue { loc } => self.check_loop_keyword("continue", *loc),
+            // Already reported as a syntax error.
+            Stmt::Error { .. } => {}
             Stmt::FunctionDecl {
                 name,
                 params,
@@ -938,17 +1405,7 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                 for param in params {
                     self.check_type_annotation(param.type_annotation.as_ref(), param.loc);
                 }
-                self.function_body(
-                    params.iter().map(|p| {
-                        (
-                            p.name.as_str(),
-                            p.default_value.as_ref(),
-                            p.loc,
-                            p.type_annotation.as_ref(),
-                        )
-                    }),
-                    body,
-                );
+                self.function_body(params, body);
             }
             Stmt::TypeDecl {
                 name,
@@ -1037,11 +1494,15 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                 ),
             },
             // `obj.field := …` or `arr[i] := …`: validate the object/index.
-            other => self.check_expr(other),
+            other => {
+                self.check_expr(other);
+            }
         }
     }
 
-    fn check_expr(&mut self, expr: &Expr) {
+    /// Check an expression, returning what is known of its type.
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        let discarded = std::mem::take(&mut self.discarded);
         match expr {
             Expr::Variable { name, loc } => {
                 if self.resolve(name).is_none() && !self.is_builtin(name) {
@@ -1050,13 +1511,16 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                         *loc,
                         format!("undeclared variable `{name}`"),
                     );
+                    Type::unknown()
                 } else {
                     self.record_use(name, *loc);
+                    self.variable_type(name)
                 }
             }
             Expr::Call {
                 callee, args, loc, ..
             } => {
+                let mut function = None;
                 if let Expr::Variable {
                     name: fname,
                     loc: fname_loc,
@@ -1093,6 +1557,7 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                             if let Some(&(required, total)) = self.functions.get(fname) {
                                 self.check_call_arity(fname, args.len(), required, total, *loc);
                             }
+                            function = self.symbols.resolve_id(self.current_scope(), fname);
                         }
                         // A value, type or enum is not callable.
                         Some(kind @ (SymbolKind::Var | SymbolKind::Type | SymbolKind::Enum)) => {
@@ -1117,24 +1582,74 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                 } else {
                     self.check_expr(callee);
                 }
-                if let Some(signature) = self.builtin_signature(callee) {
+                let signature = self.builtin_signature(callee);
+                if let Some(signature) = signature {
                     let name = callee_name(callee);
                     self.check_builtin_args(&name, signature, args, *loc);
                 }
-                for arg in args {
-                    match arg {
+                let types: Vec<Type> = args
+                    .iter()
+                    .map(|arg| match arg {
                         Argument::Positional(e) => self.check_expr(e),
                         Argument::Named { value, .. } => self.check_expr(value),
+                    })
+                    .collect();
+                if let Some(signature) = signature {
+                    let name = callee_name(callee);
+                    self.check_builtin_arg_types(&name, signature, args, &types, *loc);
+                }
+                if let Some(id) = function {
+                    if let Expr::Variable { name, .. } = callee.as_ref() {
+                        self.check_user_args(name, id, args, &types, *loc);
                     }
+                    return self
+                        .signatures
+                        .get(&id)
+                        .map_or_else(Type::unknown, |signature| signature.result.clone());
                 }
+                if let Some(owner) = self.constructed_type(callee) {
+                    self.check_constructor_args(owner, args, &types, *loc);
+                    let name = self.symbols.symbol(owner).name.clone();
+                    return Type::of_base(Base::Udt(name));
+                }
+                match self.builtin_name(callee) {
+                    Some(name) => builtin_result(&name, &types),
+                    None => Type::unknown(),
+                }
+            }
+            Expr::Binary {
+                left,
+                op,
+                right,
+                loc,
+            } => {
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                self.binary_type(op, left, right, *loc)
             }
-            Expr::Binary { left, right, .. } => {
-                self.check_expr(left);
-                self.check_expr(right);
+            Expr::Unary { op, expr: operand } => {
+                let ty = self.check_expr(operand);
+                let (symbol, rejected): (_, &[Base]) = match op {
+                    UnOp::Neg => ("-", &[Base::String, Base::Bool, Base::Color]),
+                    UnOp::Not => ("not", &[Base::String, Base::Color]),
+                };
+                if ty.base.as_ref().is_some_and(|base| rejected.contains(base)) {
+                    self.emit(
+                        "operator-type",
+                        expr_loc(operand),
+                        format!("Cannot apply operator '{symbol}' to a value of '{ty}' type"),
+                    );
+                }
+                match op {
+                    UnOp::Neg => ty,
+                    UnOp::Not => Type {
+                        base: Some(Base::Bool),
+                        ..ty
+                    },
+                }
             }
-            Expr::Unary { expr, .. } => self.check_expr(expr),
             Expr::Index { expr, index, .. } => {
-                self.check_expr(expr);
+                let ty = self.check_expr(expr);
                 self.check_expr(index);
                 // `series[n]` reads `n` bars back; a constant negative offset
                 // would be a forbidden look into the future, rejected by Pine.
@@ -1146,6 +1661,11 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                          current or earlier bars, never a future one",
                     );
                 }
+                // A past bar's value differs from bar to bar.
+                Type {
+                    qualifier: Some(TypeQualifier::Series),
+                    ..ty
+                }
             }
             // When the object's type is known, record the member's occurrence.
             Expr::MemberAccess {
@@ -1166,15 +1686,17 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                         );
                     }
                 }
+                self.member_type(object, member)
             }
             Expr::Ternary {
                 condition,
                 then_expr,
                 else_expr,
             } => {
-                self.check_expr(condition);
-                self.check_expr(then_expr);
-                self.check_expr(else_expr);
+                let loc = first_loc([condition, then_expr, else_expr].map(Box::as_ref));
+                let condition = self.check_condition(condition);
+                let branches = [self.check_expr(then_expr), self.check_expr(else_expr)];
+                self.branches_type(&[condition], &branches, loc, true)
             }
             Expr::IfExpr {
                 condition,
@@ -1182,43 +1704,298 @@ impl<'a, O: PineOutput> Analyzer<'a, O> {
                 else_if_branches,
                 else_expr,
             } => {
-                self.check_expr(condition);
-                self.check_expr(then_expr);
+                let loc = first_loc([condition.as_ref(), then_expr]);
+                let mut conditions = vec![self.check_condition(condition)];
+                let mut branches = vec![self.check_expr(then_expr)];
                 for (cond, e) in else_if_branches {
-                    self.check_expr(cond);
-                    self.check_expr(e);
-                }
-                if let Some(e) = else_expr {
-                    self.check_expr(e);
+                    conditions.push(self.check_condition(cond));
+                    branches.push(self.check_expr(e));
                 }
+                // Without an `else`, no branch matching yields `na`.
+                branches.push(match else_expr {
+                    Some(e) => self.check_expr(e),
+                    None => Type::literal(&Literal::Na),
+                });
+                self.branches_type(&conditions, &branches, loc, !discarded)
             }
             Expr::Switch { value, cases } => {
-                self.check_expr(value);
+                let mut conditions = vec![self.check_expr(value)];
+                let mut branches = Vec::new();
                 for (pattern, result) in cases {
-                    self.check_expr(pattern);
-                    self.check_expr(result);
+                    conditions.push(self.check_expr(pattern));
+                    branches.push(self.check_expr(result));
                 }
+                let loc = first_loc(
+                    std::iter::once(value.as_ref())
+                        .chain(cases.iter().flat_map(|(pattern, result)| [pattern, result])),
+                );
+                self.branches_type(&conditions, &branches, loc, !discarded)
             }
             Expr::Array(elements) => {
                 for e in elements {
                     self.check_expr(e);
                 }
+                Type::unknown()
             }
             // A lambda: its own scope with parameters bound.
             Expr::Function { params, body } => {
-                self.function_body(
-                    params.iter().map(|p| {
-                        (
-                            p.name.as_str(),
-                            p.default_value.as_ref(),
-                            p.loc,
-                            p.type_annotation.as_ref(),
-                        )
-                    }),
-                    body,
-                );
+                self.function_body(params, body);
+                Type::unknown()
+            }
+            Expr::Literal(literal) => Type::literal(literal),
+        }
+    }
+
+    /// Check a condition: Pine takes a bool (or a number, cast), never a
+    /// string or a color.
+    fn check_condition(&mut self, condition: &Expr) -> Type {
+        let ty = self.check_expr(condition);
+        if matches!(ty.base, Some(Base::String | Base::Color)) {
+            self.emit(
+                "type-mismatch",
+                expr_loc(condition),
+                format!("Cannot use a value of '{ty}' type as a condition; a 'bool' is expected"),
+            );
+        }
+        ty
+    }
+
+    /// The type of `left op right`, reporting operands the operator cannot
+    /// take: arithmetic on a string, bool or color, or `and`/`or` on a
+    /// string or color.
+    fn binary_type(&mut self, op: &BinOp, left: Type, right: Type, loc: Loc) -> Type {
+        let qualifier = Type::join([left.qualifier, right.qualifier]);
+        let bases = (left.base.as_ref(), right.base.as_ref());
+        let is = |base: Option<&Base>, of: &[Base]| base.is_some_and(|base| of.contains(base));
+        let (invalid, base) = match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
+                let strings = bases == (Some(&Base::String), Some(&Base::String));
+                let invalid = if *op == BinOp::Add {
+                    // `+` also joins two strings, but no string meets a number.
+                    !strings
+                        && (is(bases.0, &[Base::Bool, Base::Color])
+                            || is(bases.1, &[Base::Bool, Base::Color])
+                            || (is(bases.0, &[Base::String]) && bases.1.is_some_and(numeric))
+                            || (is(bases.1, &[Base::String]) && bases.0.is_some_and(numeric)))
+                } else {
+                    let rejected = [Base::String, Base::Bool, Base::Color];
+                    is(bases.0, &rejected) || is(bases.1, &rejected)
+                };
+                let base = match bases {
+                    _ if strings => Some(Base::String),
+                    (Some(Base::Int), Some(Base::Int)) if *op != BinOp::Div => Some(Base::Int),
+                    (Some(a), Some(b)) if numeric(a) && numeric(b) => {
+                        let float = *a == Base::Float || *b == Base::Float;
+                        Some(if float { Base::Float } else { Base::Number })
+                    }
+                    _ => None,
+                };
+                (invalid, base)
+            }
+            BinOp::And | BinOp::Or => {
+                let rejected = [Base::String, Base::Color];
+                (
+                    is(bases.0, &rejected) || is(bases.1, &rejected),
+                    Some(Base::Bool),
+                )
+            }
+            BinOp::Eq
+            | BinOp::NotEq
+            | BinOp::Less
+            | BinOp::Greater
+            | BinOp::LessEq
+            | BinOp::GreaterEq => (false, Some(Base::Bool)),
+        };
+        if invalid {
+            self.emit(
+                "operator-type",
+                loc,
+                format!(
+                    "Cannot apply operator '{}' to values of '{left}' and '{right}' types",
+                    operator(op)
+                ),
+            );
+        }
+        Type { qualifier, base }
+    }
+
+    /// The type of a conditional: the branches' common type, as strong as
+    /// the strongest condition or branch. Branches that share no type are
+    /// reported when the value is used.
+    fn branches_type(
+        &mut self,
+        conditions: &[Type],
+        branches: &[Type],
+        loc: Loc,
+        used: bool,
+    ) -> Type {
+        let qualifier = Type::join(conditions.iter().chain(branches).map(|t| t.qualifier));
+        let mut base = Base::Na;
+        let mut first: Option<&Type> = None;
+        for branch in branches {
+            let Some(next) = &branch.base else {
+                continue;
+            };
+            match Base::unify(&base, next) {
+                Ok(unified) => base = unified,
+                Err(()) => {
+                    if let (true, Some(first)) = (used, first) {
+                        self.emit(
+                            "type-mismatch",
+                            loc,
+                            format!(
+                                "The branches of a conditional must share a type, \
+                                 found '{first}' and '{branch}'"
+                            ),
+                        );
+                    }
+                    return Type {
+                        qualifier,
+                        base: None,
+                    };
+                }
             }
-            Expr::Literal(_) => {}
+            if first.is_none() && *next != Base::Na {
+                first = Some(branch);
+            }
+        }
+        let known = branches.iter().all(|branch| branch.base.is_some());
+        Type {
+            qualifier,
+            base: Some(base).filter(|base| known && *base != Base::Na),
+        }
+    }
+
+    /// The type a name reads as: a variable's as inferred so far, or a
+    /// builtin's. The per-bar builtins (`close`, `bar_index`) are series.
+    fn variable_type(&self, name: &str) -> Type {
+        if let Some(id) = self.symbols.resolve_id(self.current_scope(), name) {
+            return self.types.get(&id).cloned().unwrap_or_default();
+        }
+        // Counters and timestamps; the runtime stores every number alike.
+        if INT_VARIABLES.contains(&name) {
+            return Type::new(TypeQualifier::Series, Base::Int);
+        }
+        match self.builtins.get(name) {
+            // `time` and friends read as a series, and are callable too.
+            Some(Value::Object { value: Some(_), .. }) => Type::qualified(TypeQualifier::Series),
+            Some(Value::Object { .. } | Value::BuiltinFunction(_)) | None => Type::unknown(),
+            Some(value) => Type {
+                qualifier: Some(TypeQualifier::Series),
+                base: Base::of_value(value),
+            },
+        }
+    }
+
+    /// The type `object.member` reads as: an enum case, a UDT field's
+    /// declared type, a library export, or a builtin namespace's value.
+    fn member_type(&self, object: &Expr, member: &str) -> Type {
+        if let Some(id) = self.resolve_member(object, member) {
+            let symbol = self.symbols.symbol(id);
+            return match symbol.container.map(|owner| self.symbols.symbol(owner)) {
+                Some(owner) if owner.kind == SymbolKind::Enum => {
+                    Type::new(TypeQualifier::Const, Base::Udt(owner.name.clone()))
+                }
+                Some(_) => Type {
+                    qualifier: None,
+                    base: symbol
+                        .type_annotation
+                        .as_deref()
+                        .map(|annotation| self.parse_base(annotation)),
+                },
+                None => self.types.get(&id).cloned().unwrap_or_default(),
+            };
+        }
+        let Expr::Variable {
+            name: namespace, ..
+        } = object
+        else {
+            return Type::unknown();
+        };
+        if self.resolve(namespace).is_some() {
+            return Type::unknown();
+        }
+        let Some(Value::Object { fields, .. }) = self.builtins.get(namespace) else {
+            return Type::unknown();
+        };
+        let fields = fields.borrow();
+        let Some(value) = fields.get(member) else {
+            return Type::unknown();
+        };
+        let qualifier = match (namespace.as_str(), value) {
+            (_, Value::Series(_) | Value::Object { value: Some(_), .. }) => TypeQualifier::Series,
+            (_, Value::Object { .. } | Value::BuiltinFunction(_)) => return Type::unknown(),
+            // A named option like `strategy.long` is fixed; the state is not.
+            (_, Value::String(_)) => TypeQualifier::Const,
+            ("barstate" | "session" | "strategy", _) => TypeQualifier::Series,
+            ("syminfo" | "timeframe" | "chart", _) => TypeQualifier::Simple,
+            _ => TypeQualifier::Const,
+        };
+        Type {
+            qualifier: Some(qualifier),
+            base: Base::of_value(value),
         }
     }
 }
+
+/// What a builtin call yields. Only the families whose result is fixed are
+/// named: `ta`/`request`/`strategy` are series, and each `input.*` is an input
+/// of its widget's type. Any other builtin is as strong as its arguments,
+/// which is only certain when one of them is a series.
+fn builtin_result(name: &str, args: &[Type]) -> Type {
+    let input = |base| Type::new(TypeQualifier::Input, base);
+    match name {
+        "input.int" | "input.time" => input(Base::Int),
+        "input.float" | "input.price" => input(Base::Float),
+        "input.bool" => input(Base::Bool),
+        "input.color" => input(Base::Color),
+        "input.string" | "input.session" | "input.symbol" | "input.timeframe"
+        | "input.text_area" => input(Base::String),
+        "input.source" => Type::new(TypeQualifier::Series, Base::Float),
+        "input.enum" => Type::qualified(TypeQualifier::Input),
+        // The untyped `input(defval)` takes its default's type; a source
+        // default makes it a series.
+        "input" => match args.first() {
+            Some(arg) if arg.qualifier == Some(TypeQualifier::Series) => arg.clone(),
+            Some(arg) if arg.qualifier.is_some() => Type {
+                qualifier: Some(TypeQualifier::Input),
+                base: arg.base.clone().filter(|base| *base != Base::Na),
+            },
+            _ => Type::unknown(),
+        },
+        "time" | "time_close" => Type::qualified(TypeQualifier::Series),
+        _ if ["ta.", "request.", "strategy."]
+            .iter()
+            .any(|family| name.starts_with(family)) =>
+        {
+            Type::qualified(TypeQualifier::Series)
+        }
+        _ if Type::join(args.iter().map(|arg| arg.qualifier)) == Some(TypeQualifier::Series) => {
+            Type::qualified(TypeQualifier::Series)
+        }
+        _ => Type::unknown(),
+    }
+}
+
+fn numeric(base: &Base) -> bool {
+    matches!(base, Base::Int | Base::Float | Base::Number)
+}
+
+/// An operator as written.
+fn operator(op: &BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Mod => "%",
+        BinOp::Eq => "==",
+        BinOp::NotEq => "!=",
+        BinOp::Less => "<",
+        BinOp::Greater => ">",
+        BinOp::LessEq => "<=",
+        BinOp::GreaterEq => ">=",
+        BinOp::And => "and",
+        BinOp::Or => "or",
+    }
+}
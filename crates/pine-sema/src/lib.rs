@@ -28,6 +28,7 @@
 mod analyzer;
 mod scope;
 mod symbols;
+mod types;
 
 pub use analyzer::Analyzer;
 pub use pine_core::LibraryLoader;
@@ -0,0 +1,259 @@
+//! Pine's static types: a base type (`int`, `string`, a UDT) and the qualifier
+//! saying when its value is fixed (`const` < `input` < `simple` < `series`).
+//!
+//! Inference is deliberately partial. Either half of a [`Type`] may be unknown,
+//! and an unknown half never takes part in a diagnostic, so a check can only
+//! fire on facts the analyzer actually established.
+
+use std::fmt;
+
+use pine_ast::{Literal, TypeQualifier};
+use pine_core::PineOutput;
+use pine_interpreter::{ParamType, Value};
+
+/// The base type of a value, without its qualifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Base {
+    Int,
+    Float,
+    /// An `int` or a `float`, not known which: the runtime stores both as one
+    /// number, so a builtin's value does not say.
+    Number,
+    Bool,
+    String,
+    Color,
+    /// The bare `na` literal, which fits any type.
+    Na,
+    /// A user-declared type or enum.
+    Udt(String),
+    /// Any other annotation: `line`, `float[]`, `map<string, int>`.
+    Named(String),
+}
+
+impl Base {
+    /// The base an annotation names; `udt` tells a user type from a builtin one.
+    pub fn parse(annotation: &str, udt: impl Fn(&str) -> bool) -> Base {
+        match annotation {
+            "int" => Base::Int,
+            "float" => Base::Float,
+            "bool" => Base::Bool,
+            "string" => Base::String,
+            "color" => Base::Color,
+            name if udt(name) => Base::Udt(name.to_string()),
+            other => Base::Named(other.to_string()),
+        }
+    }
+
+    pub fn of_literal(literal: &Literal) -> Base {
+        match literal {
+            Literal::Int(_) => Base::Int,
+            Literal::Number(_) => Base::Float,
+            Literal::String(_) => Base::String,
+            Literal::Bool(_) => Base::Bool,
+            Literal::HexColor(_) => Base::Color,
+            Literal::Na => Base::Na,
+        }
+    }
+
+    /// The base of a builtin's registered value; a price series is a float.
+    pub fn of_value<O: PineOutput>(value: &Value<O>) -> Option<Base> {
+        match value {
+            Value::Int(_) => Some(Base::Int),
+            Value::Number(_) => Some(Base::Number),
+            Value::Series(_) => Some(Base::Float),
+            Value::String(_) => Some(Base::String),
+            Value::Bool(_) => Some(Base::Bool),
+            Value::Color(_) => Some(Base::Color),
+            _ => None,
+        }
+    }
+
+    /// The base a builtin parameter names, where it names one exactly.
+    pub fn of_param(ty: ParamType) -> Option<Base> {
+        match ty {
+            ParamType::String => Some(Base::String),
+            ParamType::Bool => Some(Base::Bool),
+            ParamType::Color => Some(Base::Color),
+            // `int` or `float`, the parameter does not say.
+            ParamType::Number | ParamType::Any => None,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Base::Int | Base::Float | Base::Number)
+    }
+
+    /// Whether a value of type `value` may be stored where `self` is declared:
+    /// an `int` widens to a `float`, and `na` fits anything.
+    pub fn accepts(&self, value: &Base) -> bool {
+        match (self, value) {
+            (_, Base::Na) => true,
+            (Base::Float | Base::Number, value) if value.is_numeric() => true,
+            (Base::Int, Base::Int | Base::Number) => true,
+            (Base::Udt(a), Base::Udt(b)) => a == b,
+            // How a builtin or library type relates to another is not known.
+            (Base::Named(_) | Base::Udt(_), Base::Named(_) | Base::Udt(_)) => true,
+            (a, b) => a == b,
+        }
+    }
+
+    /// The type two branches of a conditional share, or `Err` when no value
+    /// could be either: `int` and `float` meet at `float`, `na` yields to the
+    /// other side.
+    pub fn unify(a: &Base, b: &Base) -> Result<Base, ()> {
+        match (a, b) {
+            (Base::Na, other) | (other, Base::Na) => Ok(other.clone()),
+            (Base::Float, other) | (other, Base::Float) if other.is_numeric() => Ok(Base::Float),
+            (Base::Number, other) | (other, Base::Number) if other.is_numeric() => Ok(Base::Number),
+            _ if a.accepts(b) && b.accepts(a) => Ok(a.clone()),
+            _ => Err(()),
+        }
+    }
+
+    /// Whether a builtin parameter of type `ty` would reject this value at
+    /// runtime, mirroring [`ParamType::accepts`] for literals.
+    pub fn rejected_by(&self, ty: ParamType) -> bool {
+        match ty {
+            ParamType::Any | ParamType::String => false,
+            ParamType::Number | ParamType::Bool => matches!(self, Base::String | Base::Color),
+            ParamType::Color => self.is_numeric() || *self == Base::Bool,
+        }
+    }
+
+    /// How to name the type in a diagnostic's prose: "a string".
+    pub fn describe(&self) -> String {
+        match self {
+            Base::Int => "an int".to_string(),
+            Base::Number => "a number".to_string(),
+            Base::Na => "na".to_string(),
+            other => format!("a {other}"),
+        }
+    }
+}
+
+impl fmt::Display for Base {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Base::Int => f.write_str("int"),
+            Base::Float | Base::Number => f.write_str("float"),
+            Base::Bool => f.write_str("bool"),
+            Base::String => f.write_str("string"),
+            Base::Color => f.write_str("color"),
+            Base::Na => f.write_str("na"),
+            Base::Udt(name) | Base::Named(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Pine's keyword for a qualifier.
+pub fn qualifier_name(qualifier: TypeQualifier) -> &'static str {
+    match qualifier {
+        TypeQualifier::Const => "const",
+        TypeQualifier::Input => "input",
+        TypeQualifier::Simple => "simple",
+        TypeQualifier::Series => "series",
+    }
+}
+
+/// A value's inferred type; `None` in either half means not known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Type {
+    pub qualifier: Option<TypeQualifier>,
+    pub base: Option<Base>,
+}
+
+impl Type {
+    pub fn new(qualifier: TypeQualifier, base: Base) -> Self {
+        Self {
+            qualifier: Some(qualifier),
+            base: Some(base),
+        }
+    }
+
+    /// Neither half known.
+    pub fn unknown() -> Self {
+        Self::default()
+    }
+
+    /// A known qualifier over an unknown base.
+    pub fn qualified(qualifier: TypeQualifier) -> Self {
+        Self {
+            qualifier: Some(qualifier),
+            base: None,
+        }
+    }
+
+    /// A known base under an unknown qualifier.
+    pub fn of_base(base: Base) -> Self {
+        Self {
+            qualifier: None,
+            base: Some(base),
+        }
+    }
+
+    pub fn literal(literal: &Literal) -> Self {
+        Self::new(TypeQualifier::Const, Base::of_literal(literal))
+    }
+
+    /// The qualifier of a value computed from all of `qualifiers`: the
+    /// strongest one. A `series` input decides it even when another is
+    /// unknown; otherwise any unknown leaves the result unknown.
+    pub fn join(
+        qualifiers: impl IntoIterator<Item = Option<TypeQualifier>>,
+    ) -> Option<TypeQualifier> {
+        let mut joined = Some(TypeQualifier::Const);
+        for qualifier in qualifiers {
+            match qualifier {
+                Some(TypeQualifier::Series) => return Some(TypeQualifier::Series),
+                Some(q) => joined = joined.map(|j| j.max(q)),
+                None => joined = None,
+            }
+        }
+        joined
+    }
+}
+
+/// The type as Pine names it (`series float`), leaving out an unknown half.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.qualifier, &self.base) {
+            (Some(qualifier), Some(base)) => write!(f, "{} {base}", qualifier_name(qualifier)),
+            (Some(qualifier), None) => f.write_str(qualifier_name(qualifier)),
+            (None, Some(base)) => write!(f, "{base}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ints_widen_to_floats_but_not_back() {
+        assert!(Base::Float.accepts(&Base::Int));
+        assert!(!Base::Int.accepts(&Base::Float));
+        assert!(Base::Int.accepts(&Base::Number));
+        assert!(Base::String.accepts(&Base::Na));
+        assert!(!Base::String.accepts(&Base::Int));
+        assert!(!Base::Udt("A".into()).accepts(&Base::Udt("B".into())));
+        assert!(Base::Udt("A".into()).accepts(&Base::Named("line".into())));
+    }
+
+    #[test]
+    fn branches_unify_to_their_widest_type() {
+        assert_eq!(Base::unify(&Base::Int, &Base::Float), Ok(Base::Float));
+        assert_eq!(Base::unify(&Base::Na, &Base::Color), Ok(Base::Color));
+        assert_eq!(Base::unify(&Base::Int, &Base::Number), Ok(Base::Number));
+        assert!(Base::unify(&Base::String, &Base::Int).is_err());
+    }
+
+    #[test]
+    fn series_dominates_a_join_and_unknown_blocks_the_rest() {
+        use TypeQualifier::*;
+        assert_eq!(Type::join([Some(Const), Some(Input)]), Some(Input));
+        assert_eq!(Type::join([None, Some(Series)]), Some(Series));
+        assert_eq!(Type::join([Some(Simple), None]), None);
+        assert_eq!(Type::join([]), Some(Const));
+    }
+}
@@ -0,0 +1,250 @@
+//! Turning what a bar raised into [`AlertEvent`]s.
+//!
+//! The `alert` and `alertcondition` builtins only record what fired on the
+//! bar, and the broker only reports its fills. The host knows the rest — the
+//! bar, the symbol, the position — so it stamps each one into an event,
+//! applies `alert()`'s frequency, expands the message's placeholders and hands
+//! the events to the bar's output and the [`AlertSink`].
+
+use pine_broker::Fill;
+use pine_core::{
+    expand_placeholders, AlertCondition, AlertConditionOutput, AlertEvent, AlertSink, AlertSource,
+    Bar, Frequency, SymInfo,
+};
+
+/// The message of a fill whose order set no `alert_message`, as TradingView
+/// words it.
+const DEFAULT_FILL_MESSAGE: &str = "Order {{strategy.order.action}} @ {{strategy.order.contracts}} filled on {{ticker}}. New strategy position is {{strategy.position_size}}";
+
+pub(crate) struct Alerts {
+    pub(crate) sink: Option<Box<dyn AlertSink>>,
+    syminfo: SymInfo,
+    /// The chart timeframe, for `{{interval}}`.
+    interval: String,
+    /// The bar a `freq_once_per_bar` alert last fired on. Kept by the host, so
+    /// a realtime tick rolling the script back cannot fire it again.
+    once_per_bar: Option<u64>,
+}
+
+impl Alerts {
+    pub(crate) fn new(syminfo: SymInfo, interval: String) -> Self {
+        Self {
+            sink: None,
+            syminfo,
+            interval,
+            once_per_bar: None,
+        }
+    }
+
+    /// Events for the broker's `fills` on `bar`.
+    pub(crate) fn fills(&self, bar: &Bar, fills: Vec<Fill>) -> Vec<AlertEvent> {
+        fills
+            .into_iter()
+            .map(|fill| {
+                let template = if fill.alert_message.is_empty() {
+                    DEFAULT_FILL_MESSAGE
+                } else {
+                    &fill.alert_message
+                };
+                let message = expand_placeholders(template, |name| {
+                    let order = match name {
+                        "strategy.order.action" => {
+                            Some(if fill.qty > 0.0 { "buy" } else { "sell" }.to_string())
+                        }
+                        "strategy.order.contracts" => Some(fill.qty.abs().to_string()),
+                        "strategy.order.price" => Some(fill.price.to_string()),
+                        "strategy.order.id" => Some(fill.id.clone()),
+                        "strategy.order.comment" => Some(fill.comment.clone()),
+                        "strategy.order.alert_message" => Some(fill.alert_message.clone()),
+                        // The price as of the fill: one at the open must not
+                        // read a close that has not printed yet.
+                        "close" => Some(fill.price.to_string()),
+                        _ => None,
+                    };
+                    order.or_else(|| self.placeholder(name, bar, fill.position_size))
+                });
+                AlertEvent {
+                    time: bar.time,
+                    bar_index: bar.index,
+                    source: AlertSource::OrderFill,
+                    frequency: None,
+                    title: fill.id,
+                    message,
+                }
+            })
+            .collect()
+    }
+
+    /// Events for the `alert` and `alertcondition` calls that fired on `bar`.
+    /// A `freq_once_per_bar` alert fires only for the first such call on a bar,
+    /// and a `freq_once_per_bar_close` one only once the bar has closed.
+    pub(crate) fn script(
+        &mut self,
+        bar: &Bar,
+        fired: &[AlertCondition],
+        position_size: f64,
+    ) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for alert in fired {
+            let source = match alert.frequency {
+                None => AlertSource::AlertCondition,
+                Some(Frequency::All) => AlertSource::Alert,
+                Some(Frequency::OncePerBar) => {
+                    if self.once_per_bar == Some(bar.index) {
+                        continue;
+                    }
+                    self.once_per_bar = Some(bar.index);
+                    AlertSource::Alert
+                }
+                Some(Frequency::OncePerBarClose) => {
+                    if !bar.is_confirmed {
+                        continue;
+                    }
+                    AlertSource::Alert
+                }
+            };
+            events.push(AlertEvent {
+                time: bar.time,
+                bar_index: bar.index,
+                source,
+                frequency: alert.frequency,
+                title: alert.title.clone(),
+                message: expand_placeholders(&alert.message, |name| {
+                    self.placeholder(name, bar, position_size)
+                }),
+            });
+        }
+        events
+    }
+
+    /// Record `events` on the bar's output and pass them to the sink.
+    pub(crate) fn deliver<O: AlertConditionOutput>(
+        &mut self,
+        output: &mut O,
+        events: Vec<AlertEvent>,
+    ) {
+        for event in events {
+            if let Some(sink) = self.sink.as_mut() {
+                sink.send(&event);
+            }
+            output.add_alert_event(event);
+        }
+    }
+
+    /// The value of a placeholder every alert can use.
+    fn placeholder(&self, name: &str, bar: &Bar, position_size: f64) -> Option<String> {
+        let value = match name {
+            "open" => bar.open.to_string(),
+            "high" => bar.high.to_string(),
+            "low" => bar.low.to_string(),
+            "close" => bar.close.to_string(),
+            "volume" => bar.volume.to_string(),
+            "time" => chrono::DateTime::from_timestamp_millis(bar.time)?
+                .format("%Y-%m-%dT%H:%M:%SZ")
+                .to_string(),
+            "ticker" => self.syminfo.ticker.clone(),
+            "exchange" => self.syminfo.prefix.clone(),
+            "interval" => self.interval.clone(),
+            "syminfo.currency" => self.syminfo.currency.clone(),
+            "syminfo.basecurrency" => self.syminfo.basecurrency.clone(),
+            "strategy.position_size" => position_size.to_string(),
+            "strategy.market_position" => match position_size {
+                size if size > 0.0 => "long",
+                size if size < 0.0 => "short",
+                _ => "flat",
+            }
+            .to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(index: u64, is_confirmed: bool) -> Bar {
+        Bar {
+            index,
+            time: 1_700_000_000_000,
+            close: 101.5,
+            is_confirmed,
+            ..Bar::default()
+        }
+    }
+
+    fn alerts() -> Alerts {
+        let syminfo = SymInfo {
+            ticker: "AAPL".to_string(),
+            ..SymInfo::default()
+        };
+        Alerts::new(syminfo, "5".to_string())
+    }
+
+    fn alert(message: &str, frequency: Frequency) -> AlertCondition {
+        AlertCondition {
+            title: String::new(),
+            message: message.to_string(),
+            frequency: Some(frequency),
+        }
+    }
+
+    #[test]
+    fn once_per_bar_fires_for_the_first_call_only() {
+        let mut a = alerts();
+        let fired = [
+            alert("a", Frequency::OncePerBar),
+            alert("b", Frequency::OncePerBar),
+            alert("c", Frequency::All),
+        ];
+        let messages = |events: Vec<AlertEvent>| -> Vec<String> {
+            events.into_iter().map(|e| e.message).collect()
+        };
+        assert_eq!(messages(a.script(&bar(0, false), &fired, 0.0)), ["a", "c"]);
+        // A later tick of the same bar.
+        assert_eq!(messages(a.script(&bar(0, true), &fired, 0.0)), ["c"]);
+        assert_eq!(messages(a.script(&bar(1, true), &fired, 0.0)), ["a", "c"]);
+    }
+
+    #[test]
+    fn once_per_bar_close_waits_for_the_close() {
+        let mut a = alerts();
+        let fired = [alert("x", Frequency::OncePerBarClose)];
+        assert!(a.script(&bar(0, false), &fired, 0.0).is_empty());
+        assert_eq!(a.script(&bar(0, true), &fired, 0.0).len(), 1);
+    }
+
+    #[test]
+    fn a_fill_without_a_message_gets_the_default() {
+        let fill = Fill {
+            id: "L".to_string(),
+            qty: -2.0,
+            price: 100.0,
+            position_size: 0.0,
+            comment: String::new(),
+            alert_message: String::new(),
+        };
+        let events = alerts().fills(&bar(3, true), vec![fill]);
+        assert_eq!(
+            events[0].message,
+            "Order sell @ 2 filled on AAPL. New strategy position is 0"
+        );
+        assert_eq!(events[0].source, AlertSource::OrderFill);
+        assert_eq!(events[0].bar_index, 3);
+    }
+
+    #[test]
+    fn a_fill_reads_the_close_as_its_own_price() {
+        let fill = Fill {
+            id: "L".to_string(),
+            qty: 1.0,
+            price: 100.0,
+            position_size: 1.0,
+            comment: String::new(),
+            alert_message: "at {{close}}".to_string(),
+        };
+        let events = alerts().fills(&bar(3, true), vec![fill]);
+        assert_eq!(events[0].message, "at 100");
+    }
+}
@@ -1,6 +1,6 @@
 //! The outcome of replaying a `strategy`: its equity curve and trade log.
 
-use pine_broker::Trade;
+use pine_broker::{Trade, MARGIN_CALL};
 use pine_core::Timeframe;
 
 /// Milliseconds in a 365-day year, for annualising a per-bar figure.
@@ -8,11 +8,18 @@ const MS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0 * 1000.0;
 
 /// What a `strategy` produced over a run: the equity curve, the trade log, and
 /// the summary values Pine exposes as `strategy.*`. Field names follow Pine's.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct Backtest {
     pub initial_capital: f64,
+    /// The account currency every amount is reported in (`strategy`'s
+    /// `currency`, else the symbol's), converted at the provider's rates.
+    pub currency: String,
     /// Account value at each bar's close.
     pub equity: Vec<f64>,
+    /// The benchmark's price at each bar `equity` holds: the traded symbol's
+    /// close, unless [`ScriptBuilder::with_benchmark`](crate::ScriptBuilder::with_benchmark)
+    /// named another. `NaN` before another symbol's first bar.
+    pub benchmark: Vec<f64>,
     /// Every trade, closed ones (in the order they closed) before still-open
     /// ones. `exit_price` is `None` while open; `profit(price)` values it.
     pub trades: Vec<Trade>,
@@ -28,6 +35,8 @@ pub struct Backtest {
     pub even_trades: usize,
     /// Signed: positive long, negative short.
     pub position_size: f64,
+    /// The first bar's close, where buying and holding would have started.
+    pub first_price: f64,
     /// The last bar's close, at which open trades are valued.
     pub mark_price: f64,
     /// The bar the run halted on if a rest-of-run risk rule fired
@@ -75,13 +84,16 @@ impl Backtest {
         let returns = bar_returns(&self.equity);
         let (mean, deviation) = mean_and_deviation(&returns);
         let annualise = bars_per_year.sqrt();
+        let total_return = ratio(final_equity - self.initial_capital, self.initial_capital);
+        let benchmark_return = held_return(&self.benchmark);
+        let versus = Versus::of(&self.equity, &self.benchmark);
 
         Metrics {
             bars: self.equity.len(),
             initial_capital: self.initial_capital,
             final_equity,
             net_profit: self.net_profit,
-            total_return: ratio(final_equity - self.initial_capital, self.initial_capital),
+            total_return,
             annual_return,
             max_drawdown,
             sharpe: ratio(mean * annualise, deviation),
@@ -94,14 +106,173 @@ impl Backtest {
             profit_factor: ratio(self.gross_profit, self.gross_loss),
             avg_trade: ratio(self.net_profit, trades as f64),
             exposure: exposure(&self.trades, self.equity.len()),
+            benchmark_return,
+            excess_return: total_return - benchmark_return,
+            alpha: versus.alpha * bars_per_year,
+            beta: versus.beta,
+            correlation: versus.correlation,
+            information_ratio: versus.information_ratio * annualise,
         }
     }
+
+    /// TradingView's "Performance Summary": trade statistics for every closed
+    /// trade and for the long and short ones apart, beside the account-wide
+    /// figures.
+    pub fn report(&self) -> Report {
+        let closed: Vec<&Trade> = self.closed_trades().collect();
+        let mut margin_call_bars: Vec<u64> = self
+            .trades
+            .iter()
+            .filter(|t| t.exit_id.as_deref() == Some(MARGIN_CALL))
+            .filter_map(|t| t.exit_bar)
+            .collect();
+        margin_call_bars.dedup();
+        Report {
+            all: TradeStats::of(&closed),
+            long: TradeStats::of(
+                &closed
+                    .iter()
+                    .copied()
+                    .filter(|t| t.size > 0.0)
+                    .collect::<Vec<_>>(),
+            ),
+            short: TradeStats::of(
+                &closed
+                    .iter()
+                    .copied()
+                    .filter(|t| t.size < 0.0)
+                    .collect::<Vec<_>>(),
+            ),
+            open_profit: self.open_profit,
+            max_runup: self.max_runup,
+            max_drawdown: self.max_drawdown,
+            buy_and_hold_return: held_return(&[self.first_price, self.mark_price]),
+            commission_paid: self.trades.iter().map(|t| t.commission).sum(),
+            margin_calls: margin_call_bars.len(),
+        }
+    }
+}
+
+/// A strategy's performance summary, laid out as TradingView's, from
+/// [`Backtest::report`]. Amounts are in the account currency; losses,
+/// drawdowns and run-ups are positive magnitudes.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Report {
+    /// Every closed trade.
+    pub all: TradeStats,
+    /// The closed long trades.
+    pub long: TradeStats,
+    /// The closed short trades.
+    pub short: TradeStats,
+    /// Profit of the trades still open, at the last close.
+    pub open_profit: f64,
+    /// Largest rise in equity from a trough, intrabar.
+    pub max_runup: f64,
+    /// Largest fall in equity from a peak, intrabar.
+    pub max_drawdown: f64,
+    /// What holding the symbol from the first bar's close to the last would
+    /// have returned, as a fraction. [`Metrics::benchmark_return`] over the
+    /// same bars, unless the benchmark is another symbol.
+    pub buy_and_hold_return: f64,
+    /// Commission on every trade, open ones included.
+    pub commission_paid: f64,
+    /// Forced liquidations: bars on which a margin call closed trades.
+    pub margin_calls: usize,
+}
+
+/// Statistics over a set of closed trades: one column of a [`Report`].
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct TradeStats {
+    pub trades: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub even: usize,
+    pub net_profit: f64,
+    pub gross_profit: f64,
+    pub gross_loss: f64,
+    /// Gross profit over gross loss.
+    pub profit_factor: f64,
+    /// Fraction of trades that won ("percent profitable").
+    pub win_rate: f64,
+    pub avg_trade: f64,
+    pub avg_win: f64,
+    pub avg_loss: f64,
+    /// Average win over average loss.
+    pub win_loss_ratio: f64,
+    pub largest_win: f64,
+    pub largest_loss: f64,
+    /// Bars from entry to exit, on average.
+    pub avg_bars: f64,
+    pub avg_bars_win: f64,
+    pub avg_bars_loss: f64,
+    /// Longest run of winners, and of losers, in the order trades closed. A
+    /// breakeven trade ends both.
+    pub max_consecutive_wins: usize,
+    pub max_consecutive_losses: usize,
+    pub commission: f64,
+    /// The largest run-up and drawdown any one trade showed while open.
+    pub largest_runup: f64,
+    pub largest_drawdown: f64,
+}
+
+impl TradeStats {
+    /// The statistics of `trades`, in the order they closed.
+    fn of(trades: &[&Trade]) -> Self {
+        let mut stats = TradeStats {
+            trades: trades.len(),
+            ..TradeStats::default()
+        };
+        let (mut bars, mut win_bars, mut loss_bars) = (0u64, 0u64, 0u64);
+        let (mut wins_in_a_row, mut losses_in_a_row) = (0, 0);
+        for trade in trades {
+            // A closed trade's profit ignores the mark price.
+            let profit = trade.profit(0.0);
+            let held = trade.exit_bar.unwrap_or(trade.entry_bar) - trade.entry_bar;
+            bars += held;
+            stats.net_profit += profit;
+            stats.commission += trade.commission;
+            stats.largest_runup = stats.largest_runup.max(trade.max_runup);
+            stats.largest_drawdown = stats.largest_drawdown.max(trade.max_drawdown);
+            if profit > 0.0 {
+                stats.wins += 1;
+                stats.gross_profit += profit;
+                stats.largest_win = stats.largest_win.max(profit);
+                win_bars += held;
+                wins_in_a_row += 1;
+                losses_in_a_row = 0;
+            } else if profit < 0.0 {
+                stats.losses += 1;
+                stats.gross_loss -= profit;
+                stats.largest_loss = stats.largest_loss.max(-profit);
+                loss_bars += held;
+                losses_in_a_row += 1;
+                wins_in_a_row = 0;
+            } else {
+                stats.even += 1;
+                wins_in_a_row = 0;
+                losses_in_a_row = 0;
+            }
+            stats.max_consecutive_wins = stats.max_consecutive_wins.max(wins_in_a_row);
+            stats.max_consecutive_losses = stats.max_consecutive_losses.max(losses_in_a_row);
+        }
+        let (count, wins, losses) = (stats.trades as f64, stats.wins as f64, stats.losses as f64);
+        stats.profit_factor = ratio(stats.gross_profit, stats.gross_loss);
+        stats.win_rate = ratio(wins, count);
+        stats.avg_trade = ratio(stats.net_profit, count);
+        stats.avg_win = ratio(stats.gross_profit, wins);
+        stats.avg_loss = ratio(stats.gross_loss, losses);
+        stats.win_loss_ratio = ratio(stats.avg_win, stats.avg_loss);
+        stats.avg_bars = ratio(bars as f64, count);
+        stats.avg_bars_win = ratio(win_bars as f64, wins);
+        stats.avg_bars_loss = ratio(loss_bars as f64, losses);
+        stats
+    }
 }
 
 /// Standard summary metrics of a run, from [`Backtest::generate_metrics`]. Every
 /// figure is reported with the context that makes it comparable — returns beside
 /// drawdown, wins beside profit factor.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Metrics {
     /// Bars the strategy ran over.
     pub bars: usize,
@@ -131,6 +302,128 @@ pub struct Metrics {
     pub avg_trade: f64,
     /// Fraction of bars holding a position; can exceed 1.0 with pyramiding.
     pub exposure: f64,
+    /// Buying and holding the [`Backtest::benchmark`] over the same bars, as
+    /// a fraction.
+    pub benchmark_return: f64,
+    /// Total return less the benchmark's.
+    pub excess_return: f64,
+    /// Annualised return the benchmark's moves don't explain (Jensen's alpha,
+    /// with no risk-free rate).
+    pub alpha: f64,
+    /// How far equity moves with the benchmark, bar to bar: 1.0 in step with
+    /// it, 0.0 unrelated.
+    pub beta: f64,
+    /// Correlation of the bar returns with the benchmark's, -1.0 to 1.0.
+    pub correlation: f64,
+    /// Annualised mean return over the benchmark's, per unit of tracking
+    /// error.
+    pub information_ratio: f64,
+}
+
+/// A [`Metrics`] field to rank runs by, named as the field is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    NetProfit,
+    FinalEquity,
+    TotalReturn,
+    AnnualReturn,
+    MaxDrawdown,
+    Sharpe,
+    Sortino,
+    Calmar,
+    Trades,
+    WinRate,
+    ProfitFactor,
+    AvgTrade,
+    Exposure,
+    BenchmarkReturn,
+    ExcessReturn,
+    Alpha,
+    Beta,
+    Correlation,
+    InformationRatio,
+}
+
+impl Metric {
+    const NAMES: [(&'static str, Metric); 19] = [
+        ("net_profit", Metric::NetProfit),
+        ("final_equity", Metric::FinalEquity),
+        ("total_return", Metric::TotalReturn),
+        ("annual_return", Metric::AnnualReturn),
+        ("max_drawdown", Metric::MaxDrawdown),
+        ("sharpe", Metric::Sharpe),
+        ("sortino", Metric::Sortino),
+        ("calmar", Metric::Calmar),
+        ("trades", Metric::Trades),
+        ("win_rate", Metric::WinRate),
+        ("profit_factor", Metric::ProfitFactor),
+        ("avg_trade", Metric::AvgTrade),
+        ("exposure", Metric::Exposure),
+        ("benchmark_return", Metric::BenchmarkReturn),
+        ("excess_return", Metric::ExcessReturn),
+        ("alpha", Metric::Alpha),
+        ("beta", Metric::Beta),
+        ("correlation", Metric::Correlation),
+        ("information_ratio", Metric::InformationRatio),
+    ];
+
+    /// Every metric, in [`Metrics`] field order.
+    pub fn all() -> impl Iterator<Item = Metric> {
+        Self::NAMES.iter().map(|(_, metric)| *metric)
+    }
+
+    /// The field's value in `metrics`.
+    pub fn of(self, metrics: &Metrics) -> f64 {
+        match self {
+            Metric::NetProfit => metrics.net_profit,
+            Metric::FinalEquity => metrics.final_equity,
+            Metric::TotalReturn => metrics.total_return,
+            Metric::AnnualReturn => metrics.annual_return,
+            Metric::MaxDrawdown => metrics.max_drawdown,
+            Metric::Sharpe => metrics.sharpe,
+            Metric::Sortino => metrics.sortino,
+            Metric::Calmar => metrics.calmar,
+            Metric::Trades => metrics.trades as f64,
+            Metric::WinRate => metrics.win_rate,
+            Metric::ProfitFactor => metrics.profit_factor,
+            Metric::AvgTrade => metrics.avg_trade,
+            Metric::Exposure => metrics.exposure,
+            Metric::BenchmarkReturn => metrics.benchmark_return,
+            Metric::ExcessReturn => metrics.excess_return,
+            Metric::Alpha => metrics.alpha,
+            Metric::Beta => metrics.beta,
+            Metric::Correlation => metrics.correlation,
+            Metric::InformationRatio => metrics.information_ratio,
+        }
+    }
+
+    /// Whether a larger value is the better run. Only drawdown is a cost.
+    pub fn higher_is_better(self) -> bool {
+        self != Metric::MaxDrawdown
+    }
+
+    /// The field's name, as [`FromStr`](std::str::FromStr) accepts it.
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, metric)| *metric == self)
+            .map_or("", |(name, _)| name)
+    }
+}
+
+impl std::str::FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, metric)| *metric)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::NAMES.iter().map(|(name, _)| *name).collect();
+                format!("unknown metric `{s}`; expected one of {}", names.join(", "))
+            })
+    }
 }
 
 /// `numerator / denominator`, or 0 when the denominator can't divide. A summary
@@ -190,6 +483,58 @@ fn bar_returns(equity: &[f64]) -> Vec<f64> {
         .collect()
 }
 
+/// The return of buying at the first known price in `prices` and selling at
+/// the last.
+fn held_return(prices: &[f64]) -> f64 {
+    let mut known = prices.iter().filter(|p| p.is_finite() && **p > 0.0);
+    match (known.next(), known.next_back()) {
+        (Some(first), Some(last)) => last / first - 1.0,
+        _ => 0.0,
+    }
+}
+
+/// Per-bar figures of an equity curve against a benchmark's prices, over the
+/// bars where both have a return.
+#[derive(Default)]
+struct Versus {
+    alpha: f64,
+    beta: f64,
+    correlation: f64,
+    information_ratio: f64,
+}
+
+impl Versus {
+    fn of(equity: &[f64], benchmark: &[f64]) -> Self {
+        let known = |pair: &[f64]| pair[0] > 0.0 && pair[1].is_finite();
+        let (returns, benchmark): (Vec<f64>, Vec<f64>) = equity
+            .windows(2)
+            .zip(benchmark.windows(2))
+            .filter(|(e, b)| known(e) && known(b))
+            .map(|(e, b)| (e[1] / e[0] - 1.0, b[1] / b[0] - 1.0))
+            .unzip();
+        if returns.len() < 2 {
+            return Versus::default();
+        }
+        let (mean, deviation) = mean_and_deviation(&returns);
+        let (benchmark_mean, benchmark_deviation) = mean_and_deviation(&benchmark);
+        let covariance = returns
+            .iter()
+            .zip(&benchmark)
+            .map(|(r, b)| (r - mean) * (b - benchmark_mean))
+            .sum::<f64>()
+            / returns.len() as f64;
+        let beta = ratio(covariance, benchmark_deviation.powi(2));
+        let active: Vec<f64> = returns.iter().zip(&benchmark).map(|(r, b)| r - b).collect();
+        let (active_mean, tracking_error) = mean_and_deviation(&active);
+        Versus {
+            alpha: mean - beta * benchmark_mean,
+            beta,
+            correlation: ratio(covariance, deviation * benchmark_deviation),
+            information_ratio: ratio(active_mean, tracking_error),
+        }
+    }
+}
+
 /// Mean and (population) standard deviation of `returns`.
 fn mean_and_deviation(returns: &[f64]) -> (f64, f64) {
     if returns.is_empty() {
@@ -255,6 +600,86 @@ mod tests {
         assert_eq!(m.trades, 4);
     }
 
+    fn trade(size: f64, entry: f64, exit: f64, bars: (u64, u64)) -> Trade {
+        Trade {
+            entry_id: "T".to_string(),
+            size,
+            entry_price: entry,
+            entry_bar: bars.0,
+            exit_price: Some(exit),
+            exit_bar: Some(bars.1),
+            exit_id: Some("X".to_string()),
+            commission: 1.0,
+            fx_rate: 1.0,
+            max_runup: 0.0,
+            max_drawdown: 0.0,
+        }
+    }
+
+    #[test]
+    fn report_splits_longs_from_shorts() {
+        let mut margin_call = trade(-1.0, 100.0, 120.0, (6, 8));
+        margin_call.exit_id = Some(MARGIN_CALL.to_string());
+        let b = Backtest {
+            trades: vec![
+                trade(1.0, 100.0, 111.0, (0, 2)),  // +10
+                trade(1.0, 100.0, 106.0, (2, 3)),  // +5
+                trade(-1.0, 100.0, 103.0, (3, 6)), // -4
+                margin_call,                       // -21
+            ],
+            first_price: 100.0,
+            mark_price: 125.0,
+            // Another symbol's: buy-and-hold stays the traded one's.
+            benchmark: vec![50.0, 40.0],
+            ..Default::default()
+        };
+        let r = b.report();
+
+        assert_eq!(r.all.trades, 4);
+        assert_eq!(r.all.net_profit, -10.0);
+        assert_eq!(r.all.max_consecutive_wins, 2);
+        assert_eq!(r.all.max_consecutive_losses, 2);
+        assert_eq!(r.all.largest_loss, 21.0);
+        assert_eq!(r.all.avg_bars, 2.0); // (2 + 1 + 3 + 2) / 4
+        assert_eq!(r.long.wins, 2);
+        assert_eq!(r.long.avg_win, 7.5);
+        assert_eq!(r.long.avg_bars_win, 1.5);
+        assert_eq!(r.short.losses, 2);
+        assert_eq!(r.short.gross_loss, 25.0);
+        assert_eq!(r.commission_paid, 4.0);
+        assert_eq!(r.margin_calls, 1);
+        assert_eq!(r.buy_and_hold_return, 0.25);
+    }
+
+    #[test]
+    fn measures_equity_against_the_benchmark() {
+        // Equity moves twice as far as the benchmark, every bar.
+        let m = Backtest {
+            initial_capital: 1000.0,
+            equity: vec![1000.0, 1200.0, 960.0, 1152.0],
+            benchmark: vec![100.0, 110.0, 99.0, 108.9],
+            ..Default::default()
+        }
+        .generate_metrics();
+
+        assert!((m.benchmark_return - 0.089).abs() < 1e-12);
+        assert!((m.excess_return - (0.152 - 0.089)).abs() < 1e-12);
+        assert!((m.beta - 2.0).abs() < 1e-9);
+        assert!((m.correlation - 1.0).abs() < 1e-9);
+        // Doubling the benchmark's moves explains every return.
+        assert!(m.alpha.abs() < 1e-9);
+        assert!(m.information_ratio > 0.0);
+
+        // No benchmark, nothing to compare with.
+        let alone = Backtest {
+            initial_capital: 1000.0,
+            equity: vec![1000.0, 1100.0, 1050.0],
+            ..Default::default()
+        }
+        .generate_metrics();
+        assert_eq!((alone.beta, alone.benchmark_return), (0.0, 0.0));
+    }
+
     #[test]
     fn annualises_from_the_timeframe() {
         // 365 daily bars (the default timeframe) doubling equity = one year, so
@@ -0,0 +1,1502 @@
+//! Drawing a run as a static chart, to hold a script's output up against
+//! TradingView's.
+//!
+//! A [`Chart`] lays the bars out as candles and draws what the script produced
+//! over them: plots, markers (`plotshape`, `plotchar`, `plotarrow`),
+//! `plotcandle`/`plotbar`, `bgcolor`/`barcolor`, fills, and the drawings still
+//! live after the last bar — labels, lines, boxes, linefills, polylines and
+//! tables. An `overlay` script draws over the candles; any other gets a pane
+//! of its own beneath them, which its `force_overlay` plots leave for the
+//! candles' pane.
+//!
+//! The output is plain SVG, or the same SVG in an HTML page. Coordinates are
+//! rounded to a tenth of a pixel, so a run renders the same bytes every time
+//! and can be compared against a saved copy.
+
+use crate::Run;
+use pine_core::{
+    Bar, BoxOutput, Color, Data, DrawingOutput, FillKind, FillOutput, GlobalOutput, Label,
+    LabelOutput, LineObject, LineOutput, MetadataOutput, PineBox, PlotOutput, Table, TableOutput,
+};
+
+/// TradingView's default drawing blue.
+const BLUE: Color = Color {
+    r: 41,
+    g: 98,
+    b: 255,
+    t: 0,
+};
+const UP: Color = Color {
+    r: 38,
+    g: 166,
+    b: 154,
+    t: 0,
+};
+const DOWN: Color = Color {
+    r: 239,
+    g: 83,
+    b: 80,
+    t: 0,
+};
+const WHITE: Color = Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    t: 0,
+};
+const BLACK: Color = Color {
+    r: 19,
+    g: 23,
+    b: 34,
+    t: 0,
+};
+
+const LEFT: f64 = 10.0;
+/// Room for the price axis.
+const RIGHT: f64 = 70.0;
+/// Room for the title.
+const TOP: f64 = 24.0;
+const BOTTOM: f64 = 10.0;
+const GAP: f64 = 8.0;
+/// The script's own pane, when it is not an overlay, as a share of the height.
+const PANE_SHARE: f64 = 0.3;
+
+/// A run drawn over the bars it ran on.
+///
+/// ```no_run
+/// use pine_lang::{Chart, ScriptBuilder};
+/// use pine_lang::core::DefaultPineOutput;
+///
+/// let data = pine_lang::data::synthetic(200);
+/// let run = ScriptBuilder::<DefaultPineOutput>::with_code("//@version=5\nindicator(\"sma\", overlay = true)\nplot(ta.sma(close, 20))")
+///     .with_data(data.clone())
+///     .compile()?
+///     .run()?;
+/// std::fs::write("sma.html", Chart::new(&data, &run).html()).unwrap();
+/// # Ok::<(), pine_lang::Error>(())
+/// ```
+pub struct Chart<'a, O> {
+    bars: &'a [Bar],
+    outputs: &'a [O],
+    ticker: &'a str,
+    width: f64,
+    height: f64,
+}
+
+impl<'a, O> Chart<'a, O>
+where
+    O: PlotOutput
+        + LabelOutput
+        + LineOutput
+        + BoxOutput
+        + TableOutput
+        + DrawingOutput
+        + FillOutput
+        + GlobalOutput
+        + MetadataOutput,
+{
+    /// Chart `run` over `data`. A run over only the last bars of `data` (see
+    /// [`ScriptBuilder::with_bar_count`](crate::ScriptBuilder::with_bar_count))
+    /// is matched up with those.
+    pub fn new(data: &'a Data, run: &'a Run<O>) -> Self {
+        let count = run.outputs.len().min(data.bars.len());
+        Self {
+            bars: &data.bars[data.bars.len() - count..],
+            outputs: &run.outputs[..count],
+            ticker: &data.syminfo.ticker,
+            width: 1200.0,
+            height: 600.0,
+        }
+    }
+
+    /// The image's size in pixels; 1200×600 unless set.
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.width = f64::from(width);
+        self.height = f64::from(height);
+        self
+    }
+
+    /// The chart as a standalone SVG document.
+    pub fn svg(&self) -> String {
+        let mut frame = self.frame();
+        self.draw_background(&mut frame);
+        self.draw_candles(&mut frame);
+        self.draw_fills(&mut frame);
+        self.draw_plots(&mut frame);
+        self.draw_markers(&mut frame);
+        self.draw_drawings(&mut frame);
+
+        let (width, height) = (self.width, self.height);
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif" font-size="11">"#
+        );
+        svg.push('\n');
+        svg.push_str(r##"<rect width="100%" height="100%" fill="#ffffff"/>"##);
+        svg.push_str("\n<defs>\n");
+        for (n, pane) in frame.panes.iter().enumerate() {
+            svg.push_str(&format!(
+                r#"<clipPath id="pane{n}"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath>"#,
+                px(LEFT),
+                px(pane.top),
+                px(frame.right - LEFT),
+                px(pane.height)
+            ));
+            svg.push('\n');
+        }
+        svg.push_str("</defs>\n");
+        for (n, pane) in frame.panes.iter().enumerate() {
+            svg.push_str(&frame.axis(pane));
+            svg.push_str(&format!("<g clip-path=\"url(#pane{n})\">\n"));
+            svg.push_str(&frame.layers[n]);
+            svg.push_str("</g>\n");
+        }
+        svg.push_str(&text(
+            LEFT,
+            TOP - 8.0,
+            "start",
+            &BLACK,
+            13.0,
+            &self.heading(),
+        ));
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The chart as an HTML page holding the SVG.
+    pub fn html(&self) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body style=\"margin:0\">\n{}</body>\n</html>\n",
+            escape(&self.heading()),
+            self.svg()
+        )
+    }
+
+    /// The ticker and the script's title, as the chart is headed.
+    fn heading(&self) -> String {
+        let title = self
+            .outputs
+            .last()
+            .and_then(|output| output.indicator())
+            .map(|indicator| indicator.title.as_str())
+            .unwrap_or_default();
+        match (self.ticker, title) {
+            ("", title) => title.to_string(),
+            (ticker, "") => ticker.to_string(),
+            (ticker, title) => format!("{ticker} · {title}"),
+        }
+    }
+
+    /// Whether the script draws over the candles rather than in a pane of its
+    /// own. A script that declares no `indicator` — a strategy — does.
+    fn overlay(&self) -> bool {
+        self.outputs
+            .last()
+            .and_then(|output| output.indicator())
+            .is_none_or(|indicator| indicator.overlay)
+    }
+
+    /// Lay out the panes, each scaled to what it will show.
+    fn frame(&self) -> Frame {
+        let right = self.width - RIGHT;
+        let step = (right - LEFT) / self.bars.len().max(1) as f64;
+        let overlay = self.overlay();
+        let height = self.height - TOP - BOTTOM;
+        let own = !overlay && height > 0.0;
+        let main_height = if own {
+            height * (1.0 - PANE_SHARE) - GAP / 2.0
+        } else {
+            height
+        };
+
+        // Values each pane has to fit: the candles and whatever the script put
+        // on them; the script's own pane, what it plotted and its hlines.
+        let mut main: Vec<f64> = self.bars.iter().flat_map(|b| [b.high, b.low]).collect();
+        let mut script = Vec::new();
+        for output in self.outputs {
+            for plot in output.plots() {
+                if plot.display != "none" {
+                    pick(&mut main, &mut script, overlay || plot.force_overlay).push(plot.series);
+                }
+            }
+            for candle in output.plotcandles() {
+                pick(&mut main, &mut script, overlay || candle.force_overlay)
+                    .extend([candle.high, candle.low]);
+            }
+            for bar in output.plotbars() {
+                pick(&mut main, &mut script, overlay || bar.force_overlay)
+                    .extend([bar.high, bar.low]);
+            }
+            for shape in output.plotshapes() {
+                if shape.location == "absolute" {
+                    pick(&mut main, &mut script, overlay || shape.force_overlay).push(shape.series);
+                }
+            }
+        }
+        if own {
+            if let Some(last) = self.outputs.last() {
+                script.extend(
+                    last.lines()
+                        .into_iter()
+                        .filter(|(_, line)| is_level(line))
+                        .map(|(_, line)| line.y1),
+                );
+            }
+        }
+
+        let mut panes = vec![Pane::fit(TOP, main_height, main)];
+        if own {
+            panes.push(Pane::fit(
+                TOP + main_height + GAP,
+                height - main_height - GAP,
+                script,
+            ));
+        }
+        let layers = vec![String::new(); panes.len()];
+        Frame {
+            step,
+            right,
+            panes,
+            layers,
+        }
+    }
+
+    /// The pane a plot goes in: the script's, unless it is forced onto the
+    /// candles.
+    fn pane(&self, frame: &Frame, force_overlay: bool) -> usize {
+        if force_overlay {
+            0
+        } else {
+            frame.panes.len() - 1
+        }
+    }
+
+    /// The bar a drawing's x names, as a position on the chart.
+    fn position(&self, x: f64, xloc: &str) -> f64 {
+        let Some(first) = self.bars.first() else {
+            return x;
+        };
+        if xloc.ends_with("bar_time") {
+            let time = x as i64;
+            self.bars.partition_point(|bar| bar.time < time) as f64
+        } else {
+            x - first.index as f64
+        }
+    }
+
+    fn draw_background(&self, frame: &mut Frame) {
+        let pane = self.pane(frame, false);
+        let (top, height) = (frame.panes[pane].top, frame.panes[pane].height);
+        for (i, output) in self.outputs.iter().enumerate() {
+            if let Some(color) = &output.global_context().bgcolor {
+                let x = frame.x(i as f64) - frame.step / 2.0;
+                frame.draw(
+                    pane,
+                    format!(
+                        r#"<rect x="{}" y="{}" width="{}" height="{}"{}/>"#,
+                        px(x),
+                        px(top),
+                        px(frame.step),
+                        px(height),
+                        paint("fill", color)
+                    ),
+                );
+            }
+        }
+    }
+
+    fn draw_candles(&self, frame: &mut Frame) {
+        for (i, bar) in self.bars.iter().enumerate() {
+            let body = self.outputs[i]
+                .global_context()
+                .barcolor
+                .clone()
+                .unwrap_or(if bar.close >= bar.open { UP } else { DOWN });
+            let element = frame.candle(
+                0,
+                i as f64,
+                [bar.open, bar.high, bar.low, bar.close],
+                &body,
+                &body,
+            );
+            frame.draw(0, element);
+        }
+        for (i, output) in self.outputs.iter().enumerate() {
+            for candle in output.plotcandles() {
+                let ohlc = [candle.open, candle.high, candle.low, candle.close];
+                if ohlc.iter().all(|v| v.is_finite()) {
+                    let pane = self.pane(frame, candle.force_overlay);
+                    let body = candle
+                        .color
+                        .clone()
+                        .unwrap_or(if candle.close >= candle.open {
+                            UP
+                        } else {
+                            DOWN
+                        });
+                    let wick = candle.wickcolor.clone().unwrap_or(body.clone());
+                    let element = frame.candle(pane, i as f64, ohlc, &body, &wick);
+                    frame.draw(pane, element);
+                }
+            }
+            for bar in output.plotbars() {
+                let ohlc = [bar.open, bar.high, bar.low, bar.close];
+                if ohlc.iter().all(|v| v.is_finite()) {
+                    let pane = self.pane(frame, bar.force_overlay);
+                    let color =
+                        bar.color
+                            .clone()
+                            .unwrap_or(if bar.close >= bar.open { UP } else { DOWN });
+                    let element = frame.ohlc_bar(pane, i as f64, ohlc, &color);
+                    frame.draw(pane, element);
+                }
+            }
+        }
+    }
+
+    /// `fill`s between plots, bar by bar, and between hlines, across the
+    /// chart.
+    fn draw_fills(&self, frame: &mut Frame) {
+        let default = Color { t: 90, ..BLUE };
+        for (i, output) in self.outputs.iter().enumerate().skip(1) {
+            let previous = &self.outputs[i - 1];
+            for fill in output.fills() {
+                if fill.kind != FillKind::Plots {
+                    continue;
+                }
+                let (Some(id1), Some(id2)) = (fill.id1, fill.id2) else {
+                    continue;
+                };
+                let value = |output: &O, id: usize| {
+                    output
+                        .plots()
+                        .get(id)
+                        .map(|plot| plot.series)
+                        .filter(|v| v.is_finite())
+                };
+                let (Some(a0), Some(a1), Some(b0), Some(b1)) = (
+                    value(previous, id1),
+                    value(output, id1),
+                    value(previous, id2),
+                    value(output, id2),
+                ) else {
+                    continue;
+                };
+                let force = output.plots()[id1].force_overlay;
+                let pane = self.pane(frame, force);
+                let p = &frame.panes[pane];
+                let (x0, x1) = (frame.x(i as f64 - 1.0), frame.x(i as f64));
+                let points = format!(
+                    "{},{} {},{} {},{} {},{}",
+                    px(x0),
+                    px(p.y(a0)),
+                    px(x1),
+                    px(p.y(a1)),
+                    px(x1),
+                    px(p.y(b1)),
+                    px(x0),
+                    px(p.y(b0))
+                );
+                let color = fill.color.as_ref().unwrap_or(&default);
+                frame.draw(
+                    pane,
+                    format!(r#"<polygon points="{points}"{}/>"#, paint("fill", color)),
+                );
+            }
+        }
+
+        let Some(last) = self.outputs.last() else {
+            return;
+        };
+        let pane = self.pane(frame, false);
+        for fill in last.fills() {
+            if fill.kind != FillKind::Hlines {
+                continue;
+            }
+            let level = |id: Option<usize>| id.and_then(|id| last.get_line(id)).map(|l| l.y1);
+            let (Some(a), Some(b)) = (level(fill.id1), level(fill.id2)) else {
+                continue;
+            };
+            let p = &frame.panes[pane];
+            let (top, bottom) = (p.y(a.max(b)), p.y(a.min(b)));
+            let color = fill.color.as_ref().unwrap_or(&default);
+            let element = format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}"{}/>"#,
+                px(LEFT),
+                px(top),
+                px(frame.right - LEFT),
+                px(bottom - top),
+                paint("fill", color)
+            );
+            frame.draw(pane, element);
+        }
+    }
+
+    fn draw_plots(&self, frame: &mut Frame) {
+        let slots = self
+            .outputs
+            .iter()
+            .map(|output| output.plots().len())
+            .max()
+            .unwrap_or(0);
+        for slot in 0..slots {
+            let series: Vec<_> = self
+                .outputs
+                .iter()
+                .map(|output| output.plots().get(slot))
+                .collect();
+            let Some(first) = series.iter().flatten().next() else {
+                continue;
+            };
+            if first.display == "none" {
+                continue;
+            }
+            let pane = self.pane(frame, first.force_overlay);
+            let p = frame.panes[pane].clone();
+            let style = first.style.trim_start_matches("style_");
+            let dash = dasharray(&first.linestyle);
+            // Each plotted bar: where it is, and how it is drawn.
+            let points: Vec<Option<(f64, f64, Color, f64)>> = series
+                .iter()
+                .enumerate()
+                .map(|(i, plot)| {
+                    let plot = plot.filter(|plot| plot.series.is_finite())?;
+                    Some((
+                        frame.x(i as f64 + plot.offset),
+                        p.y(plot.series),
+                        plot.color.clone().unwrap_or(BLUE),
+                        plot.linewidth.max(1.0),
+                    ))
+                })
+                .collect();
+            let base = p.y(first.histbase);
+
+            match style {
+                "histogram" | "columns" => {
+                    for (x, y, color, width) in points.iter().flatten() {
+                        let (top, height) = (y.min(base), (y - base).abs().max(1.0));
+                        let width = if style == "columns" {
+                            (frame.step * 0.7).max(1.0)
+                        } else {
+                            *width
+                        };
+                        frame.draw(
+                            pane,
+                            format!(
+                                r#"<rect x="{}" y="{}" width="{}" height="{}"{}/>"#,
+                                px(x - width / 2.0),
+                                px(top),
+                                px(width),
+                                px(height),
+                                paint("fill", color)
+                            ),
+                        );
+                    }
+                }
+                "circles" | "cross" => {
+                    for (x, y, color, width) in points.iter().flatten() {
+                        let r = width + 1.5;
+                        let element = if style == "circles" {
+                            format!(
+                                r#"<circle cx="{}" cy="{}" r="{}"{}/>"#,
+                                px(*x),
+                                px(*y),
+                                px(r),
+                                paint("fill", color)
+                            )
+                        } else {
+                            format!(
+                                r#"<path d="M{} {}H{}M{} {}V{}"{} stroke-width="1"/>"#,
+                                px(x - r),
+                                px(*y),
+                                px(x + r),
+                                px(*x),
+                                px(y - r),
+                                px(y + r),
+                                paint("stroke", color)
+                            )
+                        };
+                        frame.draw(pane, element);
+                    }
+                }
+                _ => {
+                    let breaks = style.ends_with("br");
+                    let steps = style.starts_with("stepline");
+                    let area = style.starts_with("area");
+                    for run in runs(&points, breaks) {
+                        if area {
+                            for pair in run.windows(2) {
+                                let ((x0, y0, _, _), (x1, y1, color, _)) = (&pair[0], &pair[1]);
+                                let shade = Color {
+                                    t: color.t.max(80),
+                                    ..color.clone()
+                                };
+                                frame.draw(
+                                    pane,
+                                    format!(
+                                        r#"<polygon points="{},{} {},{} {},{} {},{}"{}/>"#,
+                                        px(*x0),
+                                        px(*y0),
+                                        px(*x1),
+                                        px(*y1),
+                                        px(*x1),
+                                        px(base),
+                                        px(*x0),
+                                        px(base),
+                                        paint("fill", &shade)
+                                    ),
+                                );
+                            }
+                        }
+                        for (color, width, line) in strokes(&run, steps) {
+                            frame.draw(
+                                pane,
+                                format!(
+                                    r#"<polyline points="{line}" fill="none"{} stroke-width="{}"{dash}/>"#,
+                                    paint("stroke", &color),
+                                    px(width)
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// `plotshape`, `plotchar` and `plotarrow` marks.
+    fn draw_markers(&self, frame: &mut Frame) {
+        let largest_arrow = self
+            .outputs
+            .iter()
+            .flat_map(|output| output.plotarrows())
+            .map(|arrow| arrow.series.abs())
+            .filter(|v| v.is_finite())
+            .fold(0.0, f64::max);
+
+        for (i, output) in self.outputs.iter().enumerate() {
+            let bar = &self.bars[i];
+            for shape in output.plotshapes() {
+                if !shows(shape.series) || shape.display == "none" {
+                    continue;
+                }
+                let pane = self.pane(frame, shape.force_overlay);
+                let size = marker_size(&shape.size);
+                let x = frame.x(i as f64 + shape.offset);
+                let y = frame.place(pane, &shape.location, bar, shape.series, size);
+                let color = shape.color.clone().unwrap_or(BLUE);
+                let style = shape.style.trim_start_matches("shape_");
+                frame.draw(pane, marker(style, x, y, size, &color));
+                if !shape.text.is_empty() {
+                    let below = shape.location == "belowbar" || shape.location == "bottom";
+                    let ty = if below {
+                        y + size + 12.0
+                    } else {
+                        y - size - 4.0
+                    };
+                    let textcolor = shape.textcolor.clone().unwrap_or(color);
+                    frame.draw(pane, text(x, ty, "middle", &textcolor, 11.0, &shape.text));
+                }
+            }
+            for mark in output.plotchars() {
+                if !shows(mark.series) || mark.display == "none" {
+                    continue;
+                }
+                let pane = self.pane(frame, mark.force_overlay);
+                let size = marker_size(&mark.size) * 1.6;
+                let x = frame.x(i as f64 + mark.offset);
+                let y = frame.place(pane, &mark.location, bar, mark.series, size / 2.0);
+                let color = mark.color.clone().unwrap_or(BLUE);
+                let glyph = if mark.char.is_empty() {
+                    "★"
+                } else {
+                    &mark.char
+                };
+                frame.draw(pane, text(x, y + size / 3.0, "middle", &color, size, glyph));
+                if !mark.text.is_empty() {
+                    let textcolor = mark.textcolor.clone().unwrap_or(color);
+                    frame.draw(
+                        pane,
+                        text(x, y - size, "middle", &textcolor, 11.0, &mark.text),
+                    );
+                }
+            }
+            for arrow in output.plotarrows() {
+                if !shows(arrow.series) || arrow.display == "none" || largest_arrow == 0.0 {
+                    continue;
+                }
+                let pane = self.pane(frame, arrow.force_overlay);
+                let p = &frame.panes[pane];
+                let length = arrow.minheight
+                    + (arrow.maxheight - arrow.minheight) * arrow.series.abs() / largest_arrow;
+                let x = frame.x(i as f64 + arrow.offset);
+                let head = (frame.step * 0.4).clamp(2.0, 6.0);
+                let (tip, tail, color) = if arrow.series > 0.0 {
+                    let tip = if pane == 0 {
+                        p.y(bar.low) + 4.0
+                    } else {
+                        p.bottom() - length
+                    };
+                    (tip, tip + length, arrow.colorup.clone().unwrap_or(UP))
+                } else {
+                    let tip = if pane == 0 {
+                        p.y(bar.high) - 4.0
+                    } else {
+                        p.top + length
+                    };
+                    (tip, tip - length, arrow.colordown.clone().unwrap_or(DOWN))
+                };
+                let back = if arrow.series > 0.0 { head } else { -head };
+                let element = format!(
+                    r#"<path d="M{x} {tail}V{tip}M{} {}L{x} {tip}L{} {}"{} stroke-width="1.5" fill="none"/>"#,
+                    px(x - head),
+                    px(tip + back),
+                    px(x + head),
+                    px(tip + back),
+                    paint("stroke", &color),
+                    x = px(x),
+                    tail = px(tail),
+                    tip = px(tip),
+                );
+                frame.draw(pane, element);
+            }
+        }
+    }
+
+    /// The drawings the last bar left: fills under the shapes, shapes under
+    /// labels, and tables on top.
+    fn draw_drawings(&self, frame: &mut Frame) {
+        let Some(last) = self.outputs.last() else {
+            return;
+        };
+        let pane = self.pane(frame, false);
+
+        for (_, linefill) in last.linefills() {
+            let (Some(a), Some(b)) = (last.get_line(linefill.line1), last.get_line(linefill.line2))
+            else {
+                continue;
+            };
+            let [a0, a1] = self.line_ends(frame, pane, a);
+            let [b0, b1] = self.line_ends(frame, pane, b);
+            let color = linefill.color.clone().unwrap_or(Color { t: 80, ..BLUE });
+            frame.draw(
+                pane,
+                format!(
+                    r#"<polygon points="{},{} {},{} {},{} {},{}"{}/>"#,
+                    px(a0.0),
+                    px(a0.1),
+                    px(a1.0),
+                    px(a1.1),
+                    px(b1.0),
+                    px(b1.1),
+                    px(b0.0),
+                    px(b0.1),
+                    paint("fill", &color)
+                ),
+            );
+        }
+        for (_, pine_box) in last.boxes() {
+            let element = self.pine_box(frame, pane, pine_box);
+            frame.draw(pane, element);
+        }
+        // `hline` draws its line anew every bar; each level is drawn once.
+        let mut levels: Vec<&LineObject> = Vec::new();
+        for (_, line) in last.lines() {
+            if is_level(line) {
+                if levels.contains(&line) {
+                    continue;
+                }
+                levels.push(line);
+            }
+            let [(x1, y1), (x2, y2)] = self.line_ends(frame, pane, line);
+            let color = line.color.clone().unwrap_or(BLUE);
+            frame.draw(
+                pane,
+                format!(
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}"{} stroke-width="{}"{}/>"#,
+                    px(x1),
+                    px(y1),
+                    px(x2),
+                    px(y2),
+                    paint("stroke", &color),
+                    px(line.width.max(1.0)),
+                    dasharray(&line.style)
+                ),
+            );
+        }
+        for (_, polyline) in last.polylines() {
+            let p = &frame.panes[pane];
+            let points: Vec<String> = polyline
+                .points
+                .iter()
+                .map(|&(x, y)| {
+                    format!(
+                        "{},{}",
+                        px(frame.x(self.position(x, &polyline.xloc))),
+                        px(p.y(y))
+                    )
+                })
+                .collect();
+            let tag = if polyline.closed {
+                "polygon"
+            } else {
+                "polyline"
+            };
+            let fill = match &polyline.fill_color {
+                Some(color) => paint("fill", color),
+                None => r#" fill="none""#.to_string(),
+            };
+            let stroke = polyline.line_color.clone().unwrap_or(BLUE);
+            frame.draw(
+                pane,
+                format!(
+                    r#"<{tag} points="{}"{fill}{} stroke-width="{}"{}/>"#,
+                    points.join(" "),
+                    paint("stroke", &stroke),
+                    px(polyline.line_width.max(1.0)),
+                    dasharray(&polyline.line_style)
+                ),
+            );
+        }
+        for (_, label) in last.labels() {
+            let element = self.label(frame, pane, label);
+            frame.draw(pane, element);
+        }
+        for (_, table) in last.tables() {
+            let element = self.table(frame, pane, table);
+            frame.draw(pane, element);
+        }
+    }
+
+    /// A line's two ends in pixels, stretched to the chart's edge on the
+    /// sides it extends to.
+    fn line_ends(&self, frame: &Frame, pane: usize, line: &LineObject) -> [(f64, f64); 2] {
+        let p = &frame.panes[pane];
+        let (y1, y2) = (p.y(line.y1), p.y(line.y2));
+        let extend = line.extend.trim_start_matches("extend.");
+        // An hline is drawn at x 0 and extends both ways: it spans the chart.
+        if is_level(line) {
+            return [(LEFT, y1), (frame.right, y2)];
+        }
+        let mut a = (frame.x(self.position(line.x1, &line.xloc)), y1);
+        let mut b = (frame.x(self.position(line.x2, &line.xloc)), y2);
+        let stretch = |from: (f64, f64), to: (f64, f64), edge: f64| {
+            let dx = to.0 - from.0;
+            if dx.abs() < f64::EPSILON {
+                let y = if to.1 >= from.1 { p.bottom() } else { p.top };
+                (to.0, y)
+            } else {
+                (edge, from.1 + (to.1 - from.1) * (edge - from.0) / dx)
+            }
+        };
+        if extend == "right" || extend == "both" {
+            let edge = if b.0 >= a.0 { frame.right } else { LEFT };
+            b = stretch(a, b, edge);
+        }
+        if extend == "left" || extend == "both" {
+            let edge = if a.0 <= b.0 { LEFT } else { frame.right };
+            a = stretch(b, a, edge);
+        }
+        [a, b]
+    }
+
+    fn pine_box(&self, frame: &Frame, pane: usize, pine_box: &PineBox) -> String {
+        let p = &frame.panes[pane];
+        let mut left = frame.x(self.position(pine_box.left, &pine_box.xloc));
+        let mut right = frame.x(self.position(pine_box.right, &pine_box.xloc));
+        if matches!(pine_box.extend.as_str(), "left" | "both") {
+            left = LEFT;
+        }
+        if matches!(pine_box.extend.as_str(), "right" | "both") {
+            right = frame.right;
+        }
+        let (top, bottom) = (p.y(pine_box.top), p.y(pine_box.bottom));
+        let (x, y) = (left.min(right), top.min(bottom));
+        let (width, height) = ((right - left).abs(), (bottom - top).abs());
+        let border = pine_box.border_color.clone().unwrap_or(BLUE);
+        let fill = pine_box.bgcolor.clone().unwrap_or(Color { t: 80, ..BLUE });
+        let mut element = format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}"{}{} stroke-width="{}"{}/>"#,
+            px(x),
+            px(y),
+            px(width),
+            px(height),
+            paint("fill", &fill),
+            paint("stroke", &border),
+            px(pine_box.border_width.max(0.0)),
+            dasharray(&pine_box.border_style)
+        );
+        if !pine_box.text.is_empty() {
+            let size = if pine_box.text_size > 0.0 {
+                pine_box.text_size
+            } else {
+                11.0
+            };
+            let (tx, anchor) = match pine_box.text_halign.as_str() {
+                h if h.contains("left") => (x + 4.0, "start"),
+                h if h.contains("right") => (x + width - 4.0, "end"),
+                _ => (x + width / 2.0, "middle"),
+            };
+            let ty = match pine_box.text_valign.as_str() {
+                v if v.contains("top") => y + size + 2.0,
+                v if v.contains("bottom") => y + height - 4.0,
+                _ => y + height / 2.0 + size / 3.0,
+            };
+            let color = pine_box.text_color.clone().unwrap_or(BLACK);
+            element.push_str(&text(tx, ty, anchor, &color, size, &pine_box.text));
+        }
+        element
+    }
+
+    fn label(&self, frame: &Frame, pane: usize, label: &Label) -> String {
+        let p = &frame.panes[pane];
+        let position = self.position(label.x, &label.xloc);
+        let bar = self
+            .bars
+            .get(position.max(0.0) as usize)
+            .or(self.bars.last());
+        let price = match (label.yloc.as_str(), bar) {
+            ("abovebar", Some(bar)) => bar.high,
+            ("belowbar", Some(bar)) => bar.low,
+            _ => label.y,
+        };
+        let (x, y) = (frame.x(position), p.y(price));
+        let style = label
+            .style
+            .trim_start_matches("label.")
+            .trim_start_matches("style_");
+        let font = font_size(&label.size);
+        let lines: Vec<&str> = label.text.split('\n').collect();
+        let chars = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let width = chars as f64 * font * 0.6 + 10.0;
+        let height = lines.len() as f64 * font * 1.2 + 6.0;
+        let color = label.color.clone().unwrap_or(BLUE);
+
+        let bubble = |left: f64, top: f64| {
+            let textcolor = label.textcolor.clone().unwrap_or(WHITE);
+            let mut element = format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}" rx="3"{}/>"#,
+                px(left),
+                px(top),
+                px(width),
+                px(height),
+                paint("fill", &color)
+            );
+            if chars > 0 {
+                element.push_str(&text(
+                    left + width / 2.0,
+                    top + 3.0 + font,
+                    "middle",
+                    &textcolor,
+                    font,
+                    &label.text,
+                ));
+            }
+            element
+        };
+        match style {
+            "label_down" | "" => bubble(x - width / 2.0, y - 5.0 - height),
+            "label_up" => bubble(x - width / 2.0, y + 5.0),
+            "label_left" => bubble(x + 5.0, y - height / 2.0),
+            "label_right" => bubble(x - 5.0 - width, y - height / 2.0),
+            s if s.starts_with("label") => bubble(x - width / 2.0, y - height / 2.0),
+            "none" | "text_outline" => {
+                let textcolor = label.textcolor.clone().unwrap_or(BLACK);
+                text(x, y + font / 3.0, "middle", &textcolor, font, &label.text)
+            }
+            shape => {
+                let size = marker_size(&label.size);
+                let textcolor = label.textcolor.clone().unwrap_or(color.clone());
+                let mut element = marker(shape, x, y, size, &color);
+                if chars > 0 {
+                    element.push_str(&text(
+                        x,
+                        y - size - 4.0,
+                        "middle",
+                        &textcolor,
+                        font,
+                        &label.text,
+                    ));
+                }
+                element
+            }
+        }
+    }
+
+    fn table(&self, frame: &Frame, pane: usize, table: &Table) -> String {
+        let p = &frame.panes[pane];
+        let font = |size: &str| font_size(if size.is_empty() { "normal" } else { size });
+        let mut widths = vec![0.0f64; table.columns];
+        let mut heights = vec![0.0f64; table.rows];
+        for (&(column, row), cell) in &table.cells {
+            if column < table.columns && row < table.rows {
+                let size = font(&cell.text_size);
+                let lines: Vec<&str> = cell.text.split('\n').collect();
+                let chars = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+                widths[column] = widths[column].max(chars as f64 * size * 0.6 + 8.0);
+                heights[row] = heights[row].max(lines.len() as f64 * size * 1.2 + 6.0);
+            }
+        }
+        let (width, height): (f64, f64) = (widths.iter().sum(), heights.iter().sum());
+        if width == 0.0 || height == 0.0 {
+            return String::new();
+        }
+        let position = table.position.trim_start_matches("position.");
+        let x = if position.ends_with("left") {
+            LEFT + 5.0
+        } else if position.ends_with("center") {
+            (LEFT + frame.right - width) / 2.0
+        } else {
+            frame.right - width - 5.0
+        };
+        let y = if position.starts_with("top") {
+            p.top + 5.0
+        } else if position.starts_with("middle") {
+            p.top + (p.height - height) / 2.0
+        } else {
+            p.bottom() - height - 5.0
+        };
+
+        let mut element = String::new();
+        if let Some(bgcolor) = &table.bgcolor {
+            element.push_str(&format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}"{}/>"#,
+                px(x),
+                px(y),
+                px(width),
+                px(height),
+                paint("fill", bgcolor)
+            ));
+        }
+        let mut cells: Vec<_> = table.cells.iter().collect();
+        cells.sort_by_key(|(&(column, row), _)| (row, column));
+        for (&(column, row), cell) in cells {
+            if column >= table.columns || row >= table.rows {
+                continue;
+            }
+            let cx = x + widths[..column].iter().sum::<f64>();
+            let cy = y + heights[..row].iter().sum::<f64>();
+            let (cw, ch) = (widths[column], heights[row]);
+            if let Some(bgcolor) = &cell.bgcolor {
+                element.push_str(&format!(
+                    r#"<rect x="{}" y="{}" width="{}" height="{}"{}/>"#,
+                    px(cx),
+                    px(cy),
+                    px(cw),
+                    px(ch),
+                    paint("fill", bgcolor)
+                ));
+            }
+            if !cell.text.is_empty() {
+                let size = font(&cell.text_size);
+                let (tx, anchor) = match cell.text_halign.as_str() {
+                    h if h.contains("left") => (cx + 4.0, "start"),
+                    h if h.contains("right") => (cx + cw - 4.0, "end"),
+                    _ => (cx + cw / 2.0, "middle"),
+                };
+                let color = cell.text_color.clone().unwrap_or(BLACK);
+                element.push_str(&text(tx, cy + 3.0 + size, anchor, &color, size, &cell.text));
+            }
+        }
+        element
+    }
+}
+
+/// The values of one pane, scaled to the pixels it covers.
+#[derive(Clone)]
+struct Pane {
+    top: f64,
+    height: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Pane {
+    /// A pane from `top`, `height` pixels tall, scaled to fit `values` with a
+    /// little room above and below.
+    fn fit(top: f64, height: f64, values: Vec<f64>) -> Pane {
+        let (mut min, mut max) = values
+            .into_iter()
+            .filter(|v| v.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                (lo.min(v), hi.max(v))
+            });
+        if min > max {
+            (min, max) = (0.0, 1.0);
+        } else if min == max {
+            (min, max) = (min - 1.0, max + 1.0);
+        }
+        let pad = (max - min) * 0.05;
+        Pane {
+            top,
+            height,
+            min: min - pad,
+            max: max + pad,
+        }
+    }
+
+    fn y(&self, price: f64) -> f64 {
+        self.top + (self.max - price) / (self.max - self.min) * self.height
+    }
+
+    fn bottom(&self) -> f64 {
+        self.top + self.height
+    }
+}
+
+/// The chart being drawn: where the bars fall, the panes, and what has been
+/// drawn into each.
+struct Frame {
+    /// Pixels per bar.
+    step: f64,
+    /// Where the bars end and the price axis begins.
+    right: f64,
+    panes: Vec<Pane>,
+    layers: Vec<String>,
+}
+
+impl Frame {
+    /// The middle of the bar at `position`, counted from the chart's first.
+    fn x(&self, position: f64) -> f64 {
+        LEFT + (position + 0.5) * self.step
+    }
+
+    fn draw(&mut self, pane: usize, element: String) {
+        let layer = &mut self.layers[pane];
+        layer.push_str(&element);
+        layer.push('\n');
+    }
+
+    /// Where a `location.*` puts a marker of `size` for `bar`: off its high or
+    /// low on the candles, or at the pane's edge elsewhere.
+    fn place(&self, pane: usize, location: &str, bar: &Bar, value: f64, size: f64) -> f64 {
+        let p = &self.panes[pane];
+        match location {
+            "absolute" => p.y(value),
+            "abovebar" if pane == 0 => p.y(bar.high) - size - 3.0,
+            "belowbar" if pane == 0 => p.y(bar.low) + size + 3.0,
+            "belowbar" | "bottom" => p.bottom() - size - 2.0,
+            _ => p.top + size + 2.0,
+        }
+    }
+
+    fn candle(
+        &self,
+        pane: usize,
+        position: f64,
+        ohlc: [f64; 4],
+        body: &Color,
+        wick: &Color,
+    ) -> String {
+        let p = &self.panes[pane];
+        let [open, high, low, close] = ohlc;
+        let x = self.x(position);
+        let width = (self.step * 0.7).max(1.0);
+        let top = p.y(open.max(close));
+        let height = (p.y(open.min(close)) - top).max(1.0);
+        format!(
+            r#"<line x1="{x}" y1="{}" x2="{x}" y2="{}"{}/><rect x="{}" y="{}" width="{}" height="{}"{}/>"#,
+            px(p.y(high)),
+            px(p.y(low)),
+            paint("stroke", wick),
+            px(x - width / 2.0),
+            px(top),
+            px(width),
+            px(height),
+            paint("fill", body),
+            x = px(x),
+        )
+    }
+
+    fn ohlc_bar(&self, pane: usize, position: f64, ohlc: [f64; 4], color: &Color) -> String {
+        let p = &self.panes[pane];
+        let [open, high, low, close] = ohlc;
+        let x = self.x(position);
+        let tick = (self.step * 0.35).max(1.0);
+        format!(
+            r#"<path d="M{x} {}V{}M{} {}H{x}M{x} {}H{}"{} fill="none"/>"#,
+            px(p.y(high)),
+            px(p.y(low)),
+            px(x - tick),
+            px(p.y(open)),
+            px(p.y(close)),
+            px(x + tick),
+            paint("stroke", color),
+            x = px(x),
+        )
+    }
+
+    /// Gridlines and price labels for `pane`, at five evenly spaced prices.
+    fn axis(&self, pane: &Pane) -> String {
+        let mut axis = String::new();
+        for n in 0..5 {
+            let price = pane.min + (pane.max - pane.min) * (n as f64 + 0.5) / 5.0;
+            let y = pane.y(price);
+            axis.push_str(&format!(
+                r##"<line x1="{}" y1="{y}" x2="{}" y2="{y}" stroke="#e0e3eb"/>"##,
+                px(LEFT),
+                px(self.right),
+                y = px(y)
+            ));
+            axis.push_str(&text(
+                self.right + 6.0,
+                y + 4.0,
+                "start",
+                &BLACK,
+                11.0,
+                &format!("{price:.2}"),
+            ));
+            axis.push('\n');
+        }
+        axis.push_str(&format!(
+            r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#b2b5be"/>"##,
+            px(LEFT),
+            px(pane.top),
+            px(self.right - LEFT),
+            px(pane.height)
+        ));
+        axis.push('\n');
+        axis
+    }
+}
+
+/// Stretches of plotted points a line is drawn through: everything plotted,
+/// or with `breaks`, each stretch between na values.
+fn runs<T: Clone>(points: &[Option<T>], breaks: bool) -> Vec<Vec<T>> {
+    let mut runs = vec![Vec::new()];
+    for point in points {
+        match point {
+            Some(point) => runs.last_mut().expect("a run").push(point.clone()),
+            None if breaks => runs.push(Vec::new()),
+            None => {}
+        }
+    }
+    runs.retain(|run| !run.is_empty());
+    runs
+}
+
+/// A run of points as polylines, one per stretch of a single color and
+/// width; each segment takes its later point's color.
+fn strokes(run: &[(f64, f64, Color, f64)], steps: bool) -> Vec<(Color, f64, String)> {
+    let mut strokes: Vec<(Color, f64, String)> = Vec::new();
+    for (n, (x, y, color, width)) in run.iter().enumerate() {
+        let previous = n.checked_sub(1).map(|m| (run[m].0, run[m].1));
+        let continues = strokes
+            .last()
+            .is_some_and(|(c, w, _)| c == color && w == width);
+        if !continues {
+            let start = previous.map_or(String::new(), |(x, y)| format!("{},{}", px(x), px(y)));
+            strokes.push((color.clone(), *width, start));
+        }
+        let line = &mut strokes.last_mut().expect("a stroke").2;
+        let mut point = |x: f64, y: f64| {
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&format!("{},{}", px(x), px(y)));
+        };
+        if let (true, Some((_, previous))) = (steps, previous) {
+            point(*x, previous);
+        }
+        point(*x, *y);
+    }
+    strokes
+}
+
+/// The values of the candles' pane when `main_pane`, else of the script's.
+fn pick<'v>(main: &'v mut Vec<f64>, script: &'v mut Vec<f64>, main_pane: bool) -> &'v mut Vec<f64> {
+    if main_pane {
+        main
+    } else {
+        script
+    }
+}
+
+/// A horizontal line spanning the chart, as `hline` draws.
+fn is_level(line: &LineObject) -> bool {
+    line.extend == "both" && line.x1 == line.x2 && line.y1 == line.y2
+}
+
+/// Whether a marker series calls for a mark on its bar: set, and not false.
+fn shows(series: f64) -> bool {
+    series.is_finite() && series != 0.0
+}
+
+/// A marker's half-size in pixels for a `size.*`.
+fn marker_size(size: &str) -> f64 {
+    match size.trim_start_matches("size.") {
+        "tiny" => 3.0,
+        "small" => 4.5,
+        "normal" => 6.0,
+        "large" => 9.0,
+        "huge" => 13.0,
+        _ => 4.5,
+    }
+}
+
+/// Text's font size in pixels for a `size.*`.
+fn font_size(size: &str) -> f64 {
+    match size.trim_start_matches("size.") {
+        "tiny" => 8.0,
+        "small" => 10.0,
+        "large" => 16.0,
+        "huge" => 22.0,
+        _ => 12.0,
+    }
+}
+
+/// A `shape.*` marker centred on `(x, y)`.
+fn marker(style: &str, x: f64, y: f64, size: f64, color: &Color) -> String {
+    let s = size;
+    let path = |d: String| format!(r#"<path d="{d}"{}/>"#, paint("fill", color));
+    let stroke = |d: String| {
+        format!(
+            r#"<path d="{d}"{} stroke-width="1.5" fill="none"/>"#,
+            paint("stroke", color)
+        )
+    };
+    match style {
+        "triangleup" | "labelup" => path(format!(
+            "M{} {}L{} {}L{} {}Z",
+            px(x),
+            px(y - s),
+            px(x + s),
+            px(y + s),
+            px(x - s),
+            px(y + s)
+        )),
+        "triangledown" | "labeldown" => path(format!(
+            "M{} {}L{} {}L{} {}Z",
+            px(x),
+            px(y + s),
+            px(x + s),
+            px(y - s),
+            px(x - s),
+            px(y - s)
+        )),
+        "arrowup" => stroke(format!(
+            "M{x} {}V{}M{} {}L{x} {}L{} {}",
+            px(y + s),
+            px(y - s),
+            px(x - s / 2.0),
+            px(y - s / 2.0),
+            px(y - s),
+            px(x + s / 2.0),
+            px(y - s / 2.0),
+            x = px(x)
+        )),
+        "arrowdown" => stroke(format!(
+            "M{x} {}V{}M{} {}L{x} {}L{} {}",
+            px(y - s),
+            px(y + s),
+            px(x - s / 2.0),
+            px(y + s / 2.0),
+            px(y + s),
+            px(x + s / 2.0),
+            px(y + s / 2.0),
+            x = px(x)
+        )),
+        "square" => format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}"{}/>"#,
+            px(x - s),
+            px(y - s),
+            px(2.0 * s),
+            px(2.0 * s),
+            paint("fill", color)
+        ),
+        "diamond" => path(format!(
+            "M{x} {}L{} {y}L{x} {}L{} {y}Z",
+            px(y - s),
+            px(x + s),
+            px(y + s),
+            px(x - s),
+            x = px(x),
+            y = px(y)
+        )),
+        "cross" => stroke(format!(
+            "M{} {y}H{}M{x} {}V{}",
+            px(x - s),
+            px(x + s),
+            px(y - s),
+            px(y + s),
+            x = px(x),
+            y = px(y)
+        )),
+        "flag" => path(format!(
+            "M{} {}H{}L{} {}H{}V{}Z",
+            px(x - s),
+            px(y - s),
+            px(x + s),
+            px(x + s / 2.0),
+            px(y - s / 2.0),
+            px(x - s),
+            px(y + s)
+        )),
+        "circle" => format!(
+            r#"<circle cx="{}" cy="{}" r="{}"{}/>"#,
+            px(x),
+            px(y),
+            px(s),
+            paint("fill", color)
+        ),
+        // `xcross`, Pine's default, and anything unrecognised.
+        _ => stroke(format!(
+            "M{} {}L{} {}M{} {}L{} {}",
+            px(x - s),
+            px(y - s),
+            px(x + s),
+            px(y + s),
+            px(x - s),
+            px(y + s),
+            px(x + s),
+            px(y - s)
+        )),
+    }
+}
+
+/// A `<text>`, its lines stacked from `y` down.
+fn text(x: f64, y: f64, anchor: &str, color: &Color, size: f64, content: &str) -> String {
+    let lines: Vec<&str> = content.split('\n').collect();
+    let body = if lines.len() == 1 {
+        escape(content)
+    } else {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(n, line)| {
+                let dy = if n == 0 { 0.0 } else { size * 1.2 };
+                format!(
+                    r#"<tspan x="{}" dy="{}">{}</tspan>"#,
+                    px(x),
+                    px(dy),
+                    escape(line)
+                )
+            })
+            .collect()
+    };
+    format!(
+        r#"<text x="{}" y="{}" text-anchor="{anchor}" font-size="{}"{}>{body}</text>"#,
+        px(x),
+        px(y),
+        px(size),
+        paint("fill", color)
+    )
+}
+
+/// `attr` set to `color`, with `attr-opacity` when it is see-through.
+fn paint(attr: &str, color: &Color) -> String {
+    let mut paint = format!(r#" {attr}="rgb({},{},{})""#, color.r, color.g, color.b);
+    if color.t > 0 {
+        let opacity = 1.0 - f64::from(color.t.min(100)) / 100.0;
+        paint.push_str(&format!(r#" {attr}-opacity="{opacity:.2}""#));
+    }
+    paint
+}
+
+/// The `stroke-dasharray` for a `line.style_*`/`plot.linestyle_*`, if any.
+fn dasharray(style: &str) -> &'static str {
+    if style.contains("dot") {
+        r#" stroke-dasharray="1 3""#
+    } else if style.contains("dash") {
+        r#" stroke-dasharray="6 4""#
+    } else {
+        ""
+    }
+}
+
+/// A coordinate, to a tenth of a pixel.
+fn px(value: f64) -> String {
+    let rounded = (value * 10.0).round() / 10.0;
+    // Avoid writing `-0`.
+    format!("{}", rounded + 0.0)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScriptBuilder;
+    use pine_core::DefaultPineOutput;
+
+    fn chart(source: &str, bars: usize) -> (Data, Run<DefaultPineOutput>) {
+        let data = crate::data::synthetic(bars);
+        let run = ScriptBuilder::<DefaultPineOutput>::with_code(source)
+            .with_data(data.clone())
+            .compile()
+            .expect("compile")
+            .run()
+            .expect("run");
+        (data, run)
+    }
+
+    /// The chart of a script drawing a bit of everything is compared against
+    /// `testdata/chart.svg`; run with `UPDATE_SNAPSHOTS=1` to rewrite it.
+    #[test]
+    fn chart_matches_snapshot() {
+        let source = r##"
+//@version=5
+indicator("Snapshot", overlay = true, max_labels_count = 3)
+fast = ta.sma(close, 5)
+slow = ta.sma(close, 15)
+p1 = plot(fast, "Fast", color = color.orange)
+p2 = plot(slow, "Slow", color = fast > slow ? color.green : color.red, linewidth = 2)
+fill(p1, p2, color = color.new(color.blue, 85))
+plotshape(ta.crossover(fast, slow), "Up", shape.triangleup, location.belowbar, color.green, size = size.small)
+plotchar(ta.crossunder(fast, slow), "Down", "▼", location.abovebar, color.red)
+bgcolor(bar_index % 10 == 0 ? color.new(color.gray, 90) : na)
+barcolor(close > open ? na : color.purple)
+if bar_index % 8 == 0
+    label.new(bar_index, high, "H & <" + str.tostring(bar_index), style = label.style_label_down)
+if barstate.islast
+    line.new(bar_index - 20, low, bar_index, high, extend = extend.right, color = color.teal, style = line.style_dashed)
+    box.new(bar_index - 10, high + 2, bar_index - 2, low - 2, bgcolor = color.new(color.yellow, 80), text = "box")
+    t = table.new(position.top_right, 2, 2, bgcolor = color.white)
+    table.cell(t, 0, 0, "Fast")
+    table.cell(t, 1, 0, str.tostring(fast, "#.##"))
+"##;
+        let (data, run) = chart(source, 40);
+        let svg = Chart::new(&data, &run).with_size(800, 400).svg();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/chart.svg");
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(std::path::Path::new(path).parent().unwrap()).unwrap();
+            std::fs::write(path, &svg).unwrap();
+        }
+        let expected = std::fs::read_to_string(path).expect("snapshot");
+        assert_eq!(svg, expected);
+    }
+
+    #[test]
+    fn a_non_overlay_script_gets_its_own_pane() {
+        let source = r#"
+//@version=5
+indicator("RSI")
+plot(ta.rsi(close, 14), "RSI")
+plot(close, "Close", force_overlay = true)
+hline(70)
+hline(30)
+"#;
+        let (data, run) = chart(source, 30);
+        let svg = Chart::new(&data, &run).svg();
+        assert_eq!(svg.matches("<clipPath").count(), 2);
+        // The RSI goes in the second pane, the close over the candles.
+        let second = &svg[svg.find(r#"url(#pane1)"#).unwrap()..];
+        let first = &svg[svg.find(r#"url(#pane0)"#).unwrap()..svg.find(r#"url(#pane1)"#).unwrap()];
+        assert_eq!(first.matches("<polyline").count(), 1);
+        assert_eq!(second.matches("<polyline").count(), 1);
+        // Both hlines span the pane.
+        assert_eq!(second.matches(r#"<line x1="10""#).count(), 2);
+
+        let (data, run) = chart(
+            "//@version=5\nindicator(\"o\", overlay = true)\nplot(close)",
+            30,
+        );
+        let html = Chart::new(&data, &run).html();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert_eq!(html.matches("<clipPath").count(), 1);
+    }
+}
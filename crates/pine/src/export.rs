@@ -0,0 +1,305 @@
+//! Writing what a run produced to disk, for analysis outside the engine.
+//!
+//! An [`Export`] bundles a [`RunResult`] with the run's [`Backtest`], if it
+//! had one, and lays them out three ways:
+//!
+//! - **JSON** ([`Export::write_json`]): one object, `{"result", "backtest",
+//!   "metrics"}`, each the serde form of its type. Plots are columns of
+//!   numbers (`null` where na), trades, alerts and drawings arrays of
+//!   objects; a drawing's `kind` names which it is.
+//! - **Columnar JSON** ([`Export::write_columnar`]): one object holding each
+//!   [`Table`] by name, and each table as an object of equal-length column
+//!   arrays — the shape dataframe libraries load directly.
+//! - **CSV** ([`Export::write_csv`]): one file per table, a header row of
+//!   column names, then one row per record. A missing value is an empty field.
+//!
+//! The tables and their columns, in order:
+//!
+//! | Table | Columns |
+//! |---|---|
+//! | `plots` | `bar`, then one per plot title, alphabetically |
+//! | `equity` | `bar`, `equity` |
+//! | `trades` | `trade`, `direction`, `entry_id`, `entry_bar`, `entry_price`, `exit_id`, `exit_bar`, `exit_price`, `size`, `profit`, `commission`, `max_runup`, `max_drawdown` |
+//! | `logs` | `level`, `message` |
+//! | `alerts` | `time`, `bar_index`, `source`, `frequency`, `title`, `message` |
+//!
+//! `equity` and `trades` are empty without a backtest. A trade still open has
+//! no exit, and its profit is valued at the last close.
+
+use crate::{Backtest, Metrics, RunResult};
+use serde_json::{json, Value as Json};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// One of the tables an [`Export`] lays a run out as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Plots,
+    Equity,
+    Trades,
+    Logs,
+    Alerts,
+}
+
+impl Table {
+    pub const ALL: [Table; 5] = [
+        Table::Plots,
+        Table::Equity,
+        Table::Trades,
+        Table::Logs,
+        Table::Alerts,
+    ];
+
+    /// The table's name: its key in columnar JSON, and its CSV file's stem.
+    pub fn name(self) -> &'static str {
+        match self {
+            Table::Plots => "plots",
+            Table::Equity => "equity",
+            Table::Trades => "trades",
+            Table::Logs => "logs",
+            Table::Alerts => "alerts",
+        }
+    }
+}
+
+/// A run's results, ready to be written out.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Export<'a> {
+    pub result: &'a RunResult,
+    pub backtest: Option<&'a Backtest>,
+    /// The backtest's summary, derived when the export is made.
+    pub metrics: Option<Metrics>,
+}
+
+impl<'a> Export<'a> {
+    pub fn new(result: &'a RunResult, backtest: Option<&'a Backtest>) -> Self {
+        Self {
+            result,
+            backtest,
+            metrics: backtest.map(Backtest::generate_metrics),
+        }
+    }
+
+    /// Write the export as one pretty-printed JSON object.
+    pub fn write_json(&self, out: impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(out, self)?;
+        Ok(())
+    }
+
+    /// Write every table as columnar JSON, keyed by table name.
+    pub fn write_columnar(&self, out: impl Write) -> io::Result<()> {
+        let tables: serde_json::Map<String, Json> = Table::ALL
+            .into_iter()
+            .map(|table| {
+                let columns: serde_json::Map<String, Json> = self
+                    .columns(table)
+                    .into_iter()
+                    .map(|(name, values)| (name, Json::Array(values)))
+                    .collect();
+                (table.name().to_string(), Json::Object(columns))
+            })
+            .collect();
+        serde_json::to_writer_pretty(out, &tables)?;
+        Ok(())
+    }
+
+    /// Write `table` as CSV.
+    pub fn write_table_csv(&self, table: Table, out: impl Write) -> io::Result<()> {
+        let columns = self.columns(table);
+        let mut csv = csv::Writer::from_writer(out);
+        csv.write_record(columns.iter().map(|(name, _)| name))?;
+        let rows = columns.first().map_or(0, |(_, values)| values.len());
+        for row in 0..rows {
+            csv.write_record(columns.iter().map(|(_, values)| field(&values[row])))?;
+        }
+        csv.flush()
+    }
+
+    /// Write every table into `dir` as `<name>.csv`, returning the files
+    /// written.
+    pub fn write_csv(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(dir)?;
+        Table::ALL
+            .into_iter()
+            .map(|table| {
+                let path = dir.join(format!("{}.csv", table.name()));
+                let file = std::fs::File::create(&path)?;
+                self.write_table_csv(table, io::BufWriter::new(file))?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// `table` as named columns of equal length.
+    fn columns(&self, table: Table) -> Vec<(String, Vec<Json>)> {
+        let named = |columns: Vec<(&str, Vec<Json>)>| {
+            columns
+                .into_iter()
+                .map(|(name, values)| (name.to_string(), values))
+                .collect()
+        };
+        match table {
+            Table::Plots => {
+                let mut columns = vec![("bar".to_string(), indices(self.result.bars))];
+                columns.extend(self.result.plots.iter().map(|(title, values)| {
+                    (title.clone(), values.iter().map(|v| json!(v)).collect())
+                }));
+                columns
+            }
+            Table::Equity => {
+                let equity = self.backtest.map_or(&[][..], |b| &b.equity);
+                named(vec![
+                    ("bar", indices(equity.len())),
+                    ("equity", equity.iter().map(|e| json!(e)).collect()),
+                ])
+            }
+            Table::Trades => {
+                let (trades, mark) = self
+                    .backtest
+                    .map_or((&[][..], 0.0), |b| (&b.trades[..], b.mark_price));
+                let column = |f: &dyn Fn(&pine_broker::Trade) -> Json| -> Vec<Json> {
+                    trades.iter().map(f).collect()
+                };
+                named(vec![
+                    ("trade", indices(trades.len())),
+                    (
+                        "direction",
+                        column(&|t| json!(if t.size > 0.0 { "long" } else { "short" })),
+                    ),
+                    ("entry_id", column(&|t| json!(t.entry_id))),
+                    ("entry_bar", column(&|t| json!(t.entry_bar))),
+                    ("entry_price", column(&|t| json!(t.entry_price))),
+                    ("exit_id", column(&|t| json!(t.exit_id))),
+                    ("exit_bar", column(&|t| json!(t.exit_bar))),
+                    ("exit_price", column(&|t| json!(t.exit_price))),
+                    ("size", column(&|t| json!(t.size))),
+                    ("profit", column(&|t| json!(t.profit(mark)))),
+                    ("commission", column(&|t| json!(t.commission))),
+                    ("max_runup", column(&|t| json!(t.max_runup))),
+                    ("max_drawdown", column(&|t| json!(t.max_drawdown))),
+                ])
+            }
+            Table::Logs => {
+                let logs = &self.result.logs;
+                named(vec![
+                    ("level", logs.iter().map(|l| json!(l.level)).collect()),
+                    ("message", logs.iter().map(|l| json!(l.message)).collect()),
+                ])
+            }
+            Table::Alerts => {
+                let events = &self.result.alert_events;
+                named(vec![
+                    ("time", events.iter().map(|e| json!(e.time)).collect()),
+                    (
+                        "bar_index",
+                        events.iter().map(|e| json!(e.bar_index)).collect(),
+                    ),
+                    ("source", events.iter().map(|e| json!(e.source)).collect()),
+                    (
+                        "frequency",
+                        events.iter().map(|e| json!(e.frequency)).collect(),
+                    ),
+                    ("title", events.iter().map(|e| json!(e.title)).collect()),
+                    ("message", events.iter().map(|e| json!(e.message)).collect()),
+                ])
+            }
+        }
+    }
+}
+
+/// `0..count` as a column.
+fn indices(count: usize) -> Vec<Json> {
+    (0..count).map(|i| json!(i)).collect()
+}
+
+/// A cell as CSV writes it: strings bare, a missing value empty.
+fn field(value: &Json) -> String {
+    match value {
+        Json::Null => String::new(),
+        Json::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScriptBuilder;
+    use pine_core::DefaultPineOutput;
+
+    fn run() -> (RunResult, Option<Backtest>) {
+        let source = r#"
+//@version=5
+strategy("t", initial_capital = 1000)
+plot(bar_index % 2 == 0 ? close : na, "Even")
+if bar_index == 1
+    strategy.entry("L", strategy.long)
+    log.info("entered, \"long\"")
+if bar_index == 3
+    strategy.close("L")
+"#;
+        let run = ScriptBuilder::<DefaultPineOutput>::with_code(source)
+            .with_data(crate::data::synthetic(5))
+            .compile()
+            .expect("compile")
+            .run()
+            .expect("run");
+        (RunResult::collect(&run.outputs), run.backtest)
+    }
+
+    #[test]
+    fn csv_tables_have_a_header_and_a_row_per_record() {
+        let (result, backtest) = run();
+        let export = Export::new(&result, backtest.as_ref());
+        let csv = |table| {
+            let mut out = Vec::new();
+            export.write_table_csv(table, &mut out).expect("write");
+            String::from_utf8(out).expect("utf-8")
+        };
+
+        let plots = csv(Table::Plots);
+        let lines: Vec<&str> = plots.lines().collect();
+        assert_eq!(lines[0], "bar,Even");
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[2], "1,");
+
+        let trades = csv(Table::Trades);
+        assert!(trades.starts_with("trade,direction,entry_id,entry_bar,"));
+        assert!(trades.lines().nth(1).unwrap().starts_with("0,long,L,2,"));
+
+        // Quotes in a message are escaped, not split on.
+        assert_eq!(
+            csv(Table::Logs),
+            "level,message\ninfo,\"entered, \"\"long\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn columnar_json_holds_equal_length_columns() {
+        let (result, backtest) = run();
+        let mut out = Vec::new();
+        Export::new(&result, backtest.as_ref())
+            .write_columnar(&mut out)
+            .expect("write");
+        let tables: Json = serde_json::from_slice(&out).expect("json");
+
+        assert_eq!(tables["equity"]["equity"].as_array().unwrap().len(), 5);
+        assert_eq!(tables["plots"]["Even"][1], Json::Null);
+        assert_eq!(tables["trades"]["exit_bar"][0], json!(4));
+    }
+
+    #[test]
+    fn json_nests_the_result_backtest_and_metrics() {
+        let (result, backtest) = run();
+        let mut out = Vec::new();
+        Export::new(&result, backtest.as_ref())
+            .write_json(&mut out)
+            .expect("write");
+        let export: Json = serde_json::from_slice(&out).expect("json");
+
+        assert_eq!(export["result"]["bars"], json!(5));
+        assert_eq!(export["backtest"]["trades"][0]["entry_id"], json!("L"));
+        assert_eq!(export["backtest"]["timeframe"], json!("1D"));
+        assert_eq!(export["metrics"]["trades"], json!(1));
+    }
+}
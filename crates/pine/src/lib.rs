@@ -13,25 +13,40 @@ pub use pine_lint as lint;
 pub use pine_parser as parser;
 pub use pine_sema as sema;
 
+mod alerts;
 mod backtest;
+mod chart;
+mod export;
+mod optimize;
 mod run;
+mod stream;
+mod walk_forward;
 
-pub use backtest::{Backtest, Metrics};
+pub use backtest::{Backtest, Metric, Metrics, Report, TradeStats};
+pub use chart::Chart;
+pub use export::{Export, Table};
+pub use optimize::{Optimizer, Search, Space, Trial};
 pub use pine_core::{DataProvider, DirLoader, FileResolver, LibraryLoader};
-pub use run::{Run, RunResult};
+pub use run::{Drawing, DrawingChange, DrawingEvent, Drawn, Run, RunResult};
+pub use stream::Stream;
+pub use walk_forward::{WalkForward, WalkForwardReport, WalkForwardWindow};
 
-use pine_ast::Program;
+use alerts::Alerts;
+use pine_ast::{Expr, Program, Stmt};
 use pine_core::{
-    AlertConditionOutput, BoxOutput, DrawingOutput, FillOutput, GlobalOutput, InputOutput,
-    LabelOutput, LineOutput, LogOutput, MetadataOutput, PineOutput, PlotOutput, TableOutput,
+    AlertConditionOutput, AlertEvent, AlertSink, BoxOutput, DrawingOutput, FillOutput,
+    GlobalOutput, InputOutput, LabelOutput, LineOutput, LogOutput, MetadataOutput, PineOutput,
+    PlotOutput, TableOutput,
 };
-use pine_core::{Bar, Data, PineVersion, Timeframe, VersionError};
+use pine_core::{Bar, Data, PineVersion, Timeframe, Timezone, VersionError};
 use pine_diagnostics::Diagnostic;
-use pine_interpreter::{Interpreter, RuntimeError, Value};
+use pine_interpreter::{Breakpoint, Compiled, DebugHandler, Interpreter, RuntimeError, Value};
 use pine_lexer::{Lexer, LexerError};
 use pine_parser::{Parser, ParserError};
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 /// Error type for Pine operations
 #[derive(Debug)]
@@ -48,15 +63,22 @@ pub enum Error {
     /// No bars to run over: neither data nor a provider was given, or the
     /// provider could not produce the requested feed.
     Data(pine_core::ProviderError),
+    /// A [`Stream`] was fed a bar (by its open time) older than one it has
+    /// already run, or history after its first realtime tick.
+    OutOfOrder(i64),
+    /// A backtest was asked of a script that declares no `strategy`.
+    NoBacktest,
 }
 
 impl Error {
     /// The 1-based `(line, column)` an editor should point at, when the error
-    /// carries a position. Version errors have none.
+    /// carries a position. Version errors have none; a runtime error in a
+    /// library points at the script's call into it.
     pub fn location(&self) -> Option<(u32, u32)> {
         match self {
             Error::Lexer(e) => Some(e.location()),
             Error::Parser(e) => Some(e.location()),
+            Error::Runtime(e) => e.trace().and_then(|trace| trace.script_location()),
             _ => None,
         }
     }
@@ -67,9 +89,14 @@ impl std::fmt::Display for Error {
         match self {
             Error::Lexer(e) => write!(f, "Lexer error: {}", e),
             Error::Parser(e) => write!(f, "Parser error: {}", e),
-            Error::Runtime(e) => write!(f, "Runtime error: {}", e),
+            Error::Runtime(e) => match e.trace() {
+                Some(trace) => write_traced(f, e.untraced(), trace),
+                None => write!(f, "Runtime error: {}", e),
+            },
             Error::Version(e) => write!(f, "Version error: {}", e),
             Error::Data(e) => write!(f, "Data error: {}", e),
+            Error::OutOfOrder(time) => write!(f, "Stream error: bar at {} is out of order", time),
+            Error::NoBacktest => write!(f, "the script declares no strategy to backtest"),
             // One diagnostic per line, so multiple errors are simply appended.
             Error::Sema(diags) => {
                 for (i, d) in diags.iter().enumerate() {
@@ -86,6 +113,40 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+/// A traced runtime error in the form of a diagnostic, followed by the calls
+/// that led to it, innermost first:
+///
+/// ```text
+/// error [runtime] 12:9: Index out of bounds: 7 (bar 41, 2024-01-02T00:00:00Z)
+///     in f, called at 20:1
+/// ```
+fn write_traced(
+    f: &mut std::fmt::Formatter<'_>,
+    error: &RuntimeError,
+    trace: &interpreter::Trace,
+) -> std::fmt::Result {
+    let time = trace
+        .time
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .map(|time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string());
+    let message = match (trace.bar_index, time) {
+        (Some(bar), Some(time)) => format!("{error} (bar {bar}, {time})"),
+        (Some(bar), None) => format!("{error} (bar {bar})"),
+        (None, _) => error.to_string(),
+    };
+    let position = trace.loc.and_then(|loc| loc.position());
+    let diagnostic = Diagnostic::error("runtime", position, message).in_file(trace.file.clone());
+    write!(f, "{diagnostic}")?;
+    for frame in &trace.stack {
+        write!(f, "\n    in {}, called at ", frame.function)?;
+        if let Some(file) = &frame.file {
+            write!(f, "{file}:")?;
+        }
+        write!(f, "{}:{}", frame.call_site.line, frame.call_site.column)?;
+    }
+    Ok(())
+}
+
 impl From<RuntimeError> for Error {
     fn from(e: RuntimeError) -> Self {
         Error::Runtime(e)
@@ -115,11 +176,13 @@ pub struct Analysis {
     pub symbols: sema::SymbolTable,
 }
 
-/// Parse, semantically analyze and lint `source`.
+/// Parse, semantically analyze and lint `source`. Every syntax error comes
+/// back as a `syntax-error` diagnostic, and the statements that did parse are
+/// still analyzed; only a lexer or version error fails outright.
 pub fn analyze(source: &str, loader: Option<&dyn LibraryLoader>) -> Result<Analysis, Error> {
     let version = PineVersion::detect(source)?.unwrap_or(PineVersion::LATEST);
     let tokens = Lexer::with_version(source, version).tokenize()?;
-    let program = Parser::new(tokens).parse_program()?;
+    let (program, syntax_errors) = Parser::new(tokens).parse_program_recovering();
 
     let (mut env, _): (HashMap<String, Value<DefaultPineOutput>>, _) =
         pine_builtins::register_namespace_objects(version, None, None);
@@ -128,7 +191,22 @@ pub fn analyze(source: &str, loader: Option<&dyn LibraryLoader>) -> Result<Analy
     }
 
     let (mut diagnostics, symbols) = pine_sema::analyze_with_symbols(&program, &env, loader);
+    if !syntax_errors.is_empty() {
+        // A name declared by a statement that failed to parse would read as
+        // undeclared everywhere it is used.
+        diagnostics.retain(|d| {
+            !matches!(
+                d.rule,
+                "undeclared-variable" | "unknown-function" | "unknown-type"
+            )
+        });
+    }
     diagnostics.extend(pine_lint::lint(&program));
+    diagnostics.extend(
+        syntax_errors
+            .into_iter()
+            .map(|e| Diagnostic::error("syntax-error", Some(e.location()), e.kind.to_string())),
+    );
     diagnostics.sort_by_key(|d| d.pos.unwrap_or((u32::MAX, u32::MAX)));
     Ok(Analysis {
         diagnostics,
@@ -159,23 +237,81 @@ pub fn inputs_from_json(
     serde_json::from_str(json)
 }
 
+/// A script lexed, parsed and semantically checked once, to be compiled into
+/// any number of [`Script`]s with [`ScriptBuilder::with_parsed`]. Cheap to
+/// clone and shareable across threads.
+#[derive(Clone, Debug)]
+pub struct ParsedScript {
+    program: Arc<Program>,
+    version: PineVersion,
+    /// Whether semantic analysis has already accepted the program.
+    checked: bool,
+}
+
+impl ParsedScript {
+    /// Parse and check `source`, resolving its imports through `loader`.
+    pub fn parse(source: &str, loader: Option<&dyn LibraryLoader>) -> Result<Self, Error> {
+        let mut parsed = Self::parse_unchecked(source)?;
+        let (mut builtins, _): (HashMap<String, Value<DefaultPineOutput>>, _) =
+            pine_builtins::register_namespace_objects(parsed.version, None, None);
+        for (name, value) in pine_builtins::per_bar_variables(&Bar::default(), None) {
+            builtins.insert(name, value);
+        }
+        reject_sema_errors(&parsed.program, &builtins, loader)?;
+        parsed.checked = true;
+        Ok(parsed)
+    }
+
+    fn parse_unchecked(source: &str) -> Result<Self, Error> {
+        let version = PineVersion::detect(source)?.unwrap_or(PineVersion::LATEST);
+        let tokens = Lexer::with_version(source, version).tokenize()?;
+        let statements = Parser::new(tokens).parse()?;
+        Ok(Self {
+            program: Arc::new(Program::new(statements)),
+            version,
+            checked: false,
+        })
+    }
+}
+
+/// Fail with every error-level diagnostic semantic analysis finds in `program`.
+fn reject_sema_errors<O: PineOutput>(
+    program: &Program,
+    builtins: &HashMap<String, Value<O>>,
+    loader: Option<&dyn LibraryLoader>,
+) -> Result<(), Error> {
+    let errors: Vec<_> = pine_sema::analyze(program, builtins, loader)
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == pine_diagnostics::Severity::Error)
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Sema(errors))
+    }
+}
+
 pub struct ScriptBuilder<O: PineOutput> {
     source: String,
+    parsed: Option<ParsedScript>,
     custom_variables: HashMap<String, Value<O>>,
     inputs: HashMap<String, pine_core::InputValue>,
     library_loader: Option<Box<dyn LibraryLoader>>,
     request_provider: Option<Box<dyn DataProvider>>,
     ticker: Option<String>,
     timeframe: Timeframe,
-    data: Option<Data>,
+    data: Option<Arc<Data>>,
     bar_count: Option<usize>,
+    benchmark: Option<String>,
     broker_factory: Option<Box<dyn pine_broker::BrokerFactory>>,
+    alert_sink: Option<Box<dyn AlertSink>>,
 }
 
 impl<O: PineOutput> ScriptBuilder<O> {
     pub fn with_code(source: &str) -> ScriptBuilder<O> {
         Self {
             source: source.to_string(),
+            parsed: None,
             custom_variables: HashMap::new(),
             inputs: HashMap::new(),
             library_loader: None,
@@ -184,13 +320,28 @@ impl<O: PineOutput> ScriptBuilder<O> {
             timeframe: Timeframe::default(),
             data: None,
             bar_count: None,
+            benchmark: None,
             broker_factory: None,
+            alert_sink: None,
+        }
+    }
+
+    /// Build from a script parsed and checked ahead of time, so compiling it
+    /// again — once per parameter set, say — skips lexing, parsing and
+    /// semantic analysis.
+    pub fn with_parsed(parsed: ParsedScript) -> ScriptBuilder<O> {
+        Self {
+            parsed: Some(parsed),
+            ..Self::with_code("")
         }
     }
 
     /// Host overrides for the script's `input.*` calls, keyed by input title.
-    /// Each `input.*` returns (and validates) the override for its title if one
-    /// is present, else its declared default. See [`inputs_from_json`].
+    /// Each `input.*` returns the override for its title if one is present,
+    /// else its declared default. An override of the wrong type, outside
+    /// `minval`/`maxval` or not among the `options` fails the run with
+    /// [`RuntimeError::InvalidInput`](interpreter::RuntimeError::InvalidInput).
+    /// See [`inputs_from_json`].
     pub fn with_inputs(mut self, inputs: HashMap<String, pine_core::InputValue>) -> Self {
         self.inputs = inputs;
         self
@@ -217,12 +368,23 @@ impl<O: PineOutput> ScriptBuilder<O> {
     }
 
     /// Swaps the broker a `strategy` trades against. Without one, the built-in
-    /// [`DefaultBrokerFactory`](pine_broker::DefaultBrokerFactory) is used.
+    /// [`DefaultBrokerFactory`](pine_broker::DefaultBrokerFactory) is used, or
+    /// with a request provider the
+    /// [`MagnifierFactory`](pine_broker::MagnifierFactory), so
+    /// `use_bar_magnifier` can fetch intrabars from it.
     pub fn with_broker(mut self, factory: Box<dyn pine_broker::BrokerFactory>) -> Self {
         self.broker_factory = Some(factory);
         self
     }
 
+    /// Receives every alert as it fires: `alert()`, `alertcondition()` and
+    /// strategy order fills. The same events are on each bar's output either
+    /// way.
+    pub fn with_alert_sink(mut self, sink: Box<dyn AlertSink>) -> Self {
+        self.alert_sink = Some(sink);
+        self
+    }
+
     pub fn with_ticker(mut self, ticker: String) -> Self {
         self.ticker = Some(ticker);
         self
@@ -242,6 +404,14 @@ impl<O: PineOutput> ScriptBuilder<O> {
         self
     }
 
+    /// Measure a strategy against buy-and-hold of `symbol`, fetched from the
+    /// request provider at the chart timeframe, rather than of the symbol it
+    /// trades. See [`Backtest::benchmark`].
+    pub fn with_benchmark(mut self, symbol: impl Into<String>) -> Self {
+        self.benchmark = Some(symbol.into());
+        self
+    }
+
     /// The market to run over: the bars, and the symbol and timeframe they
     /// belong to.
     ///
@@ -249,8 +419,11 @@ impl<O: PineOutput> ScriptBuilder<O> {
     /// too. An explicit [`ScriptBuilder::with_syminfo`] or
     /// [`ScriptBuilder::with_timeframe`] still wins, whichever order they are
     /// called in.
-    pub fn with_data(mut self, data: Data) -> Self {
-        self.data = Some(data);
+    ///
+    /// An `Arc<Data>` is shared rather than copied, so many runs over the same
+    /// bars hold them once.
+    pub fn with_data(mut self, data: impl Into<Arc<Data>>) -> Self {
+        self.data = Some(data.into());
         self
     }
 
@@ -279,41 +452,67 @@ impl<O: PineOutput> ScriptBuilder<O> {
                     .ok_or_else(|| Error::Data("no data or request provider set".into()))?;
 
                 let ticker = self.ticker.clone().unwrap_or_default();
-                provider
+                let data = provider
                     .request(&ticker, self.timeframe.clone())
-                    .map_err(Error::Data)?
+                    .map_err(Error::Data)?;
+                Arc::new(data)
             }
         };
 
-        let syminfo = data.syminfo;
+        let syminfo = data.syminfo.clone();
         let timeframe = self.timeframe;
+        // Every date and session builtin reads the symbol's timezone, so one
+        // that does not resolve fails the build rather than each of them.
+        if Timezone::parse(&syminfo.timezone).is_none() {
+            return Err(Error::Runtime(RuntimeError::InvalidTimezone(
+                syminfo.timezone,
+            )));
+        }
+
+        let benchmark = match &self.benchmark {
+            Some(symbol) => {
+                let provider = self.request_provider.as_ref().ok_or_else(|| {
+                    Error::Data(format!("no request provider to fetch benchmark {symbol:?}").into())
+                })?;
+                let data = provider
+                    .request(symbol, timeframe.clone())
+                    .map_err(Error::Data)?;
+                Some(data.bars)
+            }
+            None => None,
+        };
+        let ticker = self
+            .ticker
+            .clone()
+            .unwrap_or_else(|| syminfo.ticker.clone());
 
         // Keep only the last `bar_count` bars when the caller limited the run.
-        let mut bars = data.bars;
-        if let Some(n) = self.bar_count {
-            let len = bars.len();
-            bars = bars.split_off(len.saturating_sub(n.max(1)));
-        }
+        let first_bar = self
+            .bar_count
+            .map_or(0, |n| data.bars.len().saturating_sub(n.max(1)));
 
         // The chart's bar spacing, so `request.security_lower_tf` can reject a
         // request that is not actually lower than the chart timeframe.
-        let chart_period = bars
+        let chart_period = data.bars[first_bar..]
             .windows(2)
             .next()
             .map(|pair| pair[1].time - pair[0].time);
 
-        let source = self.source.as_str();
-        let version = PineVersion::detect(source)?.unwrap_or(PineVersion::LATEST);
-
-        let mut lexer = Lexer::with_version(source, version);
-        let tokens = lexer.tokenize()?;
-
-        let mut parser = Parser::new(tokens);
-        let statements = parser.parse()?;
-        let program = Program::new(statements);
+        let parsed = match self.parsed {
+            Some(parsed) => parsed,
+            None => ParsedScript::parse_unchecked(&self.source)?,
+        };
+        let ParsedScript {
+            program,
+            version,
+            checked,
+        } = parsed;
 
         // The interpreter's const environment: the registered namespaces plus any
         // host-supplied globals. Built once and handed over as-is.
+        let mut alerts = Alerts::new(syminfo.clone(), timeframe.period());
+        alerts.sink = self.alert_sink;
+
         let (mut consts, advances) = pine_builtins::register_namespace_objects(
             version,
             Some(syminfo),
@@ -323,19 +522,14 @@ impl<O: PineOutput> ScriptBuilder<O> {
             consts.insert(name, value);
         }
 
-        let mut builtins = consts.clone();
-        for (name, value) in pine_builtins::per_bar_variables(&Bar::default(), None) {
-            builtins.insert(name, value);
-        }
-
-        // Semantic pre-check: reject if sema produces errors.
-        let errors: Vec<_> =
-            pine_sema::analyze(&program, &builtins, self.library_loader.as_deref())
-                .into_iter()
-                .filter(|diagnostic| diagnostic.severity == pine_diagnostics::Severity::Error)
-                .collect();
-        if !errors.is_empty() {
-            return Err(Error::Sema(errors));
+        // Semantic pre-check: reject if sema produces errors. A `ParsedScript`
+        // was checked when it was parsed.
+        if !checked {
+            let mut builtins = consts.clone();
+            for (name, value) in pine_builtins::per_bar_variables(&Bar::default(), None) {
+                builtins.insert(name, value);
+            }
+            reject_sema_errors(&program, &builtins, self.library_loader.as_deref())?;
         }
 
         // Create interpreter and load builtin namespace objects
@@ -345,17 +539,30 @@ impl<O: PineOutput> ScriptBuilder<O> {
         interpreter.chart_period = chart_period;
         if let Some(broker_factory) = self.broker_factory {
             interpreter.broker_factory = Some(broker_factory);
+        } else if let Some(provider) = &interpreter.request_provider {
+            interpreter.broker_factory = Some(Box::new(pine_broker::MagnifierFactory::new(
+                Rc::clone(provider),
+                ticker,
+                timeframe.clone(),
+            )));
         }
         interpreter.set_const_variables(consts);
         interpreter.per_bar_advances = advances;
         interpreter.inputs = self.inputs;
+        let strategy = declares_strategy(&program);
+        let program = interpreter.compile(&program);
 
         Ok(Script {
             program,
             interpreter,
             timeframe,
-            bars,
+            strategy,
+            data,
+            first_bar,
             equity_curve: Vec::new(),
+            benchmark,
+            benchmark_curve: Vec::new(),
+            first_close: 0.0,
             last_close: 0.0,
             equity_peak: f64::NEG_INFINITY,
             equity_trough: f64::INFINITY,
@@ -366,10 +573,24 @@ impl<O: PineOutput> ScriptBuilder<O> {
             max_contracts_all: 0.0,
             max_contracts_long: 0.0,
             max_contracts_short: 0.0,
+            alerts,
         })
     }
 }
 
+/// Whether `program` declares itself a `strategy(...)`. Sema allows one
+/// declaration, as a top-level call.
+fn declares_strategy(program: &Program) -> bool {
+    program.statements.iter().any(|stmt| {
+        matches!(stmt, Stmt::Expression(Expr::Call { callee, .. })
+            if matches!(callee.as_ref(), Expr::Variable { name, .. } if name == "strategy"))
+    })
+}
+
+/// How many times `calc_on_order_fills` re-runs a script on one bar, so a
+/// strategy that trades on every run cannot loop forever.
+const MAX_RECALCS: usize = 10;
+
 /// A compiled PineScript program, and the bars it will run over.
 ///
 /// State accumulates across bars — series history, `var` locals, and every
@@ -377,15 +598,27 @@ impl<O: PineOutput> ScriptBuilder<O> {
 /// `Script` single-use: [`Script::run`] takes it by value so a second run
 /// cannot inherit the first one's state.
 pub struct Script<O: PineOutput> {
-    program: Program,
+    program: Compiled<O>,
     interpreter: Interpreter<O>,
     /// The chart timeframe, carried onto the `Backtest` so its metrics can
     /// annualise per-bar figures.
     timeframe: Timeframe,
-    /// Bars from the builder's source; empty when none was given.
-    bars: Vec<Bar>,
+    /// Whether the script declares a `strategy`, rather than an indicator or a
+    /// library.
+    strategy: bool,
+    /// The builder's data, shared with whoever else holds it.
+    data: Arc<Data>,
+    /// Where the run starts in `data`'s bars, when `bar_count` cut it short.
+    first_bar: usize,
     /// Account value at each bar's close, accumulated while a `strategy` runs.
     equity_curve: Vec<f64>,
+    /// Another symbol's bars to measure the strategy against, when not the
+    /// traded one.
+    benchmark: Option<Vec<Bar>>,
+    /// The benchmark's price at each bar `equity_curve` holds.
+    benchmark_curve: Vec<f64>,
+    /// The first marked bar's close, for the buy-and-hold comparison.
+    first_close: f64,
     /// The last bar's close, used to mark open trades at the run's end.
     last_close: f64,
     /// Running equity extremes for `strategy.max_drawdown`/`max_runup`.
@@ -401,54 +634,136 @@ pub struct Script<O: PineOutput> {
     max_contracts_all: f64,
     max_contracts_long: f64,
     max_contracts_short: f64,
+    alerts: Alerts,
 }
 
-impl<O: PineOutput> Script<O> {
-    /// Run one bar. Private: bars must be replayed in order from the first, so
-    /// [`Script::run`] is the only way in.
+impl<O: AlertConditionOutput> Script<O> {
+    /// Run one bar. Bars must be replayed in order from the first, through
+    /// [`Script::run`] or a [`Stream`].
     pub fn execute(&mut self, bar: &Bar, last_bar: Option<&Bar>) -> Result<O, Error> {
-        use interpreter::Value;
-
         self.interpreter.current_time = Some(bar.time);
-
-        for (name, value) in pine_builtins::per_bar_variables(bar, last_bar) {
-            if matches!(value, Value::Series(_)) {
-                self.interpreter.advance_series(&name, value);
-            } else {
-                self.interpreter.set_variable(&name, value);
-            }
-        }
+        self.interpreter.next_bar_time = bar.next_time;
 
         // Fill orders left pending by the previous bar before the body runs, so
         // it reads the position and equity they produced. A no-op unless the
         // script declared a `strategy`.
-        self.advance_broker(bar);
+        self.advance_broker(bar, None);
+        let mut opened = self.fill_alerts(bar);
+
+        // Under `calc_on_order_fills` the script also runs as of each fill, on
+        // the bar as it stood then. Those runs are rolled back like a realtime
+        // tick: the orders they placed stand, and only `varip` keeps what they
+        // wrote. Their orders fill on the rest of the bar, which may fill
+        // another order and call for another run.
+        for _ in 0..MAX_RECALCS {
+            let Some(price) = self
+                .interpreter
+                .broker
+                .as_mut()
+                .and_then(|broker| broker.take_recalc())
+            else {
+                break;
+            };
+            let as_of_fill = Bar {
+                high: bar.open.max(price),
+                low: bar.open.min(price),
+                close: price,
+                is_confirmed: false,
+                ..bar.clone()
+            };
+            self.interpreter.checkpoint();
+            self.run_body(&as_of_fill, last_bar)?;
+            self.interpreter.rollback();
+            self.interpreter.commit();
 
-        let output = self.interpreter.execute(&self.program)?;
+            self.advance_broker(bar, Some(price));
+            opened.extend(self.fill_alerts(bar));
+        }
 
-        // Read after the body so the bar a `strategy` is declared on is counted.
-        if let Some(broker) = self.interpreter.broker.as_ref() {
+        let mut output = self.run_body(bar, last_bar)?;
+
+        // Read after the body so the bar a `strategy` is declared on is counted,
+        // and after `process_orders_on_close` filled what the body placed.
+        let mut position_size = 0.0;
+        let benchmark_close = self.benchmark_close(bar);
+        if let Some(broker) = self.interpreter.broker.as_mut() {
+            broker.close(bar);
+            if self.equity_curve.is_empty() {
+                self.first_close = bar.close;
+            }
             self.equity_curve.push(broker.equity(bar.close));
+            self.benchmark_curve.push(benchmark_close);
             self.last_close = bar.close;
+            position_size = broker.position().size;
         }
 
+        // The bar's alerts in the order they fired: fills at the open, the
+        // script's own, then fills at the close.
+        let fired = self
+            .alerts
+            .script(bar, output.alertconditions(), position_size);
+        let closed = self.fill_alerts(bar);
+        let events = opened.into_iter().chain(fired).chain(closed).collect();
+        self.alerts.deliver(&mut output, events);
+
         Ok(output)
     }
 
+    /// Alert events for the broker's fills since the last call.
+    fn fill_alerts(&mut self, bar: &Bar) -> Vec<AlertEvent> {
+        let fills = self
+            .interpreter
+            .broker
+            .as_mut()
+            .map(|broker| broker.take_fills())
+            .unwrap_or_default();
+        self.alerts.fills(bar, fills)
+    }
+
+    /// Load `bar` into the per-bar builtins and run the script body on it.
+    fn run_body(&mut self, bar: &Bar, last_bar: Option<&Bar>) -> Result<O, Error> {
+        use interpreter::Value;
+
+        for (name, value) in pine_builtins::per_bar_variables(bar, last_bar) {
+            if matches!(value, Value::Series(_)) {
+                self.interpreter.advance_series(&name, value);
+            } else {
+                self.interpreter.set_variable(&name, value);
+            }
+        }
+        Ok(self.interpreter.run(&self.program)?)
+    }
+
     /// Advance the simulated broker one bar and refresh the read-only
     /// `strategy.*` values from it. The interpreter only holds the broker
     /// handle; the backtest accounting that maps it onto script variables lives
     /// here, in the host.
-    fn advance_broker(&mut self, bar: &Bar) {
+    ///
+    /// With `resume_at`, the broker fills only the rest of the bar after price
+    /// traded there (`calc_on_order_fills`), and the equity extremes are taken
+    /// over that rest alone: the part before it was marked already.
+    fn advance_broker(&mut self, bar: &Bar, resume_at: Option<f64>) {
         use interpreter::Value;
 
         let close = bar.close;
+        let fx_rate = pine_builtins::account_rate(&self.interpreter);
 
         // Read from the broker, then drop the borrow to update `self`'s state.
         let Some(broker) = self.interpreter.broker.as_mut() else {
             return;
         };
-        broker.advance(bar);
+        broker.set_fx_rate(fx_rate);
+        let marked = match resume_at {
+            Some(price) => {
+                broker.resume(bar, price);
+                pine_broker::rest_of_bar(bar, price)
+            }
+            None => {
+                broker.advance(bar);
+                bar.clone()
+            }
+        };
+        let bar = &marked;
 
         let position = broker.position();
         let equity = broker.equity(close);
@@ -483,7 +798,7 @@ impl<O: PineOutput> Script<O> {
         let (mut trade_pcts, mut win_pcts, mut loss_pcts) = (Vec::new(), Vec::new(), Vec::new());
         for trade in broker.closed_trades() {
             let profit = trade.profit(close);
-            let basis = trade.entry_price * trade.size.abs();
+            let basis = trade.cost();
             let ret = if basis != 0.0 {
                 profit / basis * 100.0
             } else {
@@ -500,6 +815,9 @@ impl<O: PineOutput> Script<O> {
             .open_trades()
             .last()
             .map_or(Value::Na, |t| Value::String(t.entry_id.clone()));
+        let margin_liquidation_price = broker
+            .margin_liquidation_price()
+            .map_or(Value::Na, Value::Number);
 
         // Drawdown/run-up measure the intrabar extreme against a peak/trough
         // that tracks close equity — an intrabar swing does not move the mark.
@@ -612,25 +930,83 @@ impl<O: PineOutput> Script<O> {
             ("avg_winning_trade_percent", mean(&win_pcts)),
             ("avg_losing_trade_percent", mean(&loss_pcts)),
             ("position_entry_name", position_entry_name),
+            ("margin_liquidation_price", margin_liquidation_price),
         ];
         for (name, value) in derived {
             self.interpreter.set_object_field("strategy", name, value);
         }
     }
 
+    /// Debug the script as it runs: `handler` is called wherever it stops,
+    /// and inspects it through the [`Interpreter`] it is handed.
+    pub fn set_debug_handler(&mut self, handler: impl DebugHandler<O> + 'static) {
+        self.interpreter.set_debug_handler(handler);
+    }
+
+    /// Replace the breakpoints; see [`Interpreter::set_breakpoints`].
+    pub fn set_breakpoints(
+        &mut self,
+        breakpoints: Vec<Breakpoint>,
+    ) -> Vec<Result<(), RuntimeError>> {
+        self.interpreter.set_breakpoints(breakpoints)
+    }
+
+    /// Stop at the script's first statement.
+    pub fn stop_on_entry(&mut self) {
+        self.interpreter.stop_on_entry();
+    }
+
+    /// A flag another thread can raise to stop the script at its next
+    /// statement.
+    pub fn pause_handle(&mut self) -> Arc<AtomicBool> {
+        self.interpreter.pause_handle()
+    }
+
+    /// Record the last `capacity` statements run; see
+    /// [`Interpreter::execution_trace`].
+    pub fn trace_statements(&mut self, capacity: usize) {
+        self.interpreter.trace_statements(capacity);
+    }
+
     /// Replay the script over every bar from its source, returning what each
     /// one produced.
     pub fn run(mut self) -> Result<Run<O>, Error> {
-        let bars = std::mem::take(&mut self.bars);
+        let data = std::mem::take(&mut self.data);
+        let bars = data.bars.get(self.first_bar..).unwrap_or_default();
         let last_bar = bars.last().cloned();
         let outputs = bars
             .iter()
-            .map(|bar| self.execute(bar, last_bar.as_ref()))
+            .enumerate()
+            .map(|(i, bar)| {
+                // Each bar ends where the next one opens.
+                let next_time = bars
+                    .get(i + 1)
+                    .map_or(bar.next_time, |next| Some(next.time));
+                self.execute(
+                    &Bar {
+                        next_time,
+                        ..bar.clone()
+                    },
+                    last_bar.as_ref(),
+                )
+            })
             .collect::<Result<Vec<O>, Error>>()?;
         let backtest = self.take_backtest();
         Ok(Run { outputs, backtest })
     }
 
+    /// The benchmark's price at `bar`'s close: the bar's own close, or the
+    /// benchmark symbol's last close at or before it — `NaN` before its first.
+    fn benchmark_close(&self, bar: &Bar) -> f64 {
+        match &self.benchmark {
+            Some(bars) => {
+                let at = bars.partition_point(|b| b.time <= bar.time);
+                at.checked_sub(1).map_or(f64::NAN, |i| bars[i].close)
+            }
+            None => bar.close,
+        }
+    }
+
     fn take_backtest(&mut self) -> Option<Backtest> {
         let broker = self.interpreter.broker.as_ref()?;
         let close = self.last_close;
@@ -659,10 +1035,15 @@ impl<O: PineOutput> Script<O> {
         }
 
         let equity = std::mem::take(&mut self.equity_curve);
+        let benchmark = std::mem::take(&mut self.benchmark_curve);
         let final_equity = equity.last().copied().unwrap_or(initial_capital);
+        let currency =
+            pine_builtins::object_string(&self.interpreter, "strategy", "account_currency")
+                .unwrap_or_default();
 
         Some(Backtest {
             initial_capital,
+            currency,
             net_profit: final_equity - initial_capital - open_profit,
             open_profit,
             gross_profit,
@@ -673,8 +1054,10 @@ impl<O: PineOutput> Script<O> {
             loss_trades,
             even_trades,
             position_size,
+            first_price: self.first_close,
             mark_price: close,
             equity,
+            benchmark,
             trades,
             halted: broker.halted_bar(),
             timeframe: self.timeframe.clone(),
@@ -692,8 +1075,8 @@ pub fn execute(source: &str, data: Data) -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use super::inputs_from_json;
-    use pine_core::InputValue;
+    use super::*;
+    use pine_core::{InputValue, ProviderError};
 
     #[test]
     fn decodes_input_overrides_from_json() {
@@ -704,4 +1087,259 @@ mod tests {
         assert_eq!(map["On"], InputValue::Bool(true));
         assert_eq!(map["Mode"], InputValue::Str("fast".to_string()));
     }
+
+    #[test]
+    fn runtime_errors_carry_their_position_bar_and_calls() {
+        let source = "//@version=6\nindicator(\"x\")\nget(arr, i) =>\n    array.get(arr, i)\na = array.new_float(3, 0)\nplot(get(a, bar_index + 5))\n";
+        let err = ScriptBuilder::<DefaultPineOutput>::with_code(source)
+            .with_data(pine_data::synthetic(3))
+            .compile()
+            .expect("compiles")
+            .run()
+            .err()
+            .expect("a runtime error");
+        assert_eq!(err.location(), Some((4, 5)));
+        let rendered = err.to_string();
+        assert!(
+            rendered.starts_with("error [runtime] 4:5: Index out of bounds: 5 (bar 0, "),
+            "{rendered}"
+        );
+        assert!(
+            rendered.ends_with("\n    in get, called at 6:6"),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn analysis_reports_every_syntax_error() {
+        let source = "//@version=6\nindicator(\"x\")\na = )\nb = close\nc = (1 +)\nplot(b + a)\n";
+        let analysis = analyze(source, None).expect("analysis");
+        let positions: Vec<_> = analysis
+            .diagnostics
+            .iter()
+            .map(|d| (d.rule, d.pos.map(|(line, _)| line)))
+            .collect();
+        // `a` failed to parse, so its use is not flagged as undeclared.
+        assert_eq!(
+            positions,
+            [("syntax-error", Some(3)), ("syntax-error", Some(5))]
+        );
+        assert!(analysis.symbols.symbols().iter().any(|s| s.name == "b"));
+    }
+
+    /// Serves `BENCH`: the chart's bars, flat at 50.
+    struct Flat(Data);
+
+    impl DataProvider for Flat {
+        fn request(&self, symbol: &str, _: Timeframe) -> Result<Data, ProviderError> {
+            if symbol != "BENCH" {
+                return Err(format!("no data for {symbol}").into());
+            }
+            let bars = self
+                .0
+                .bars
+                .iter()
+                .map(|bar| Bar {
+                    close: 50.0,
+                    ..bar.clone()
+                })
+                .collect();
+            Ok(Data::new(bars))
+        }
+    }
+
+    #[test]
+    fn backtests_are_measured_against_a_benchmark() {
+        let source = "//@version=5\nstrategy(\"hold\")\nif bar_index == 0\n    strategy.entry(\"L\", strategy.long)";
+        let data = pine_data::synthetic(50);
+        let backtest = |benchmark: Option<&str>| {
+            let mut builder = ScriptBuilder::<DefaultPineOutput>::with_code(source)
+                .with_data(data.clone())
+                .with_request_provider(Box::new(Flat(data.clone())));
+            if let Some(symbol) = benchmark {
+                builder = builder.with_benchmark(symbol);
+            }
+            builder
+                .compile()?
+                .run()
+                .map(|run| run.backtest.expect("backtest"))
+        };
+
+        // By default, buying and holding the symbol traded.
+        let own = backtest(None).expect("run");
+        let closes: Vec<f64> = data.bars.iter().map(|bar| bar.close).collect();
+        assert_eq!(own.benchmark, closes);
+        let metrics = own.generate_metrics();
+        assert_eq!(metrics.benchmark_return, closes[49] / closes[0] - 1.0);
+
+        let flat = backtest(Some("BENCH")).expect("run").generate_metrics();
+        assert_eq!(flat.benchmark_return, 0.0);
+        assert_eq!(flat.excess_return, flat.total_return);
+
+        assert!(matches!(backtest(Some("NOPE")), Err(Error::Data(_))));
+    }
+
+    /// Where a debugged script stopped, with its stack frames and locals.
+    type Seen = (interpreter::Stop, Vec<String>, Vec<String>);
+
+    /// Runs `source` over five bars, stopping at `breakpoints` and going on as
+    /// `resumes` says, one per stop; returns what each stop saw.
+    fn debug_stops(
+        source: &str,
+        breakpoints: Vec<Breakpoint>,
+        resumes: &[interpreter::Resume],
+    ) -> (Vec<Seen>, Result<(), Error>) {
+        use std::cell::RefCell;
+
+        let stops = Rc::new(RefCell::new(Vec::new()));
+        let mut resumes = Vec::from(resumes).into_iter();
+        let mut script = ScriptBuilder::<DefaultPineOutput>::with_code(source)
+            .with_data(pine_data::synthetic(5))
+            .compile()
+            .expect("compiles");
+        assert!(script
+            .set_breakpoints(breakpoints)
+            .iter()
+            .all(Result::is_ok));
+        let seen = Rc::clone(&stops);
+        script.set_debug_handler(
+            move |interp: &mut Interpreter<_>, stop: &interpreter::Stop| {
+                let frames = interp
+                    .stack_frames()
+                    .into_iter()
+                    .map(|frame| {
+                        let line = frame.loc.map_or(0, |loc| loc.line);
+                        format!("{}:{line}", frame.function.as_deref().unwrap_or("<script>"))
+                    })
+                    .collect();
+                let locals = interp
+                    .locals()
+                    .into_iter()
+                    .map(|local| format!("{}={}", local.name, shown(local.value)))
+                    .collect();
+                seen.borrow_mut().push((stop.clone(), frames, locals));
+                resumes.next().unwrap_or(interpreter::Resume::Continue)
+            },
+        );
+        let result = script.run().map(|_| ());
+        let stops = stops.take();
+        (stops, result)
+    }
+
+    fn shown(value: &Value<DefaultPineOutput>) -> String {
+        match value {
+            Value::Int(n) => n.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::Array(items) => format!("array({})", items.borrow().len()),
+            Value::Series(series) => shown(&series.current),
+            other => format!("{other:?}"),
+        }
+    }
+
+    const DEBUGGED: &str = "//@version=6\nindicator(\"x\")\nf(x) =>\n    y = x * 2\n    y + 1\nvar total = 0\ntotal += f(bar_index)\nplot(total)\n";
+
+    #[test]
+    fn debugger_steps_into_and_out_of_user_functions() {
+        use interpreter::{Resume, StopReason};
+
+        let (stops, result) = debug_stops(
+            DEBUGGED,
+            vec![Breakpoint::line(7).on_bar(2)],
+            &[Resume::StepIn, Resume::StepOver, Resume::StepOut],
+        );
+        result.expect("runs to the end");
+        let seen: Vec<_> = stops
+            .iter()
+            .map(|(stop, frames, locals)| {
+                (
+                    stop.reason.clone(),
+                    stop.loc.line,
+                    frames.join(" "),
+                    locals.join(" "),
+                )
+            })
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                (
+                    StopReason::Breakpoint(0),
+                    7,
+                    "<script>:7".into(),
+                    "total=4".into()
+                ),
+                // `y` still holds what this call site left it on the last bar.
+                (
+                    StopReason::Step,
+                    4,
+                    "f:4 <script>:7".into(),
+                    "x=2 y=2".into()
+                ),
+                (
+                    StopReason::Step,
+                    5,
+                    "f:5 <script>:7".into(),
+                    "x=2 y=4".into()
+                ),
+                (StopReason::Step, 8, "<script>:8".into(), "total=9".into()),
+            ]
+        );
+        assert!(stops.iter().all(|(stop, ..)| stop.bar_index == Some(2)));
+    }
+
+    #[test]
+    fn debugger_breakpoints_filter_by_bar_and_condition() {
+        let (stops, result) = debug_stops(
+            DEBUGGED,
+            vec![Breakpoint::bar(1), Breakpoint::line(7).when("total > 5")],
+            &[],
+        );
+        result.expect("runs to the end");
+        let bars: Vec<_> = stops
+            .iter()
+            .map(|(stop, ..)| (stop.reason.clone(), stop.bar_index))
+            .collect();
+        use interpreter::StopReason::Breakpoint as Hit;
+        assert_eq!(
+            bars,
+            [(Hit(0), Some(1)), (Hit(1), Some(3)), (Hit(1), Some(4))]
+        );
+
+        let mut script = ScriptBuilder::<DefaultPineOutput>::with_code(DEBUGGED)
+            .with_data(pine_data::synthetic(1))
+            .compile()
+            .expect("compiles");
+        let results = script.set_breakpoints(vec![Breakpoint::line(7).when("total >")]);
+        assert!(matches!(
+            results[0],
+            Err(RuntimeError::InvalidExpression(_))
+        ));
+    }
+
+    #[test]
+    fn debugger_stops_where_an_error_strikes_and_can_terminate() {
+        use interpreter::{Resume, StopReason};
+
+        let source = "//@version=6\nindicator(\"x\")\nget(arr, i) =>\n    array.get(arr, i)\na = array.new_float(3, 0)\nplot(get(a, bar_index + 5))\n";
+        let (stops, result) = debug_stops(source, Vec::new(), &[]);
+        assert!(matches!(result, Err(Error::Runtime(_))));
+        let [(stop, frames, locals)] = stops.as_slice() else {
+            panic!("one stop: {stops:?}");
+        };
+        assert_eq!(
+            stop.reason,
+            StopReason::Error("Index out of bounds: 5".into())
+        );
+        assert_eq!((stop.loc.line, stop.loc.column), (4, 5));
+        assert_eq!(frames.join(" "), "get:4 <script>:6");
+        assert_eq!(locals.join(" "), "arr=array(3) i=5");
+
+        let (stops, result) =
+            debug_stops(DEBUGGED, vec![Breakpoint::line(8)], &[Resume::Terminate]);
+        assert_eq!(stops.len(), 1);
+        let Err(Error::Runtime(error)) = result else {
+            panic!("terminated");
+        };
+        assert!(matches!(error.untraced(), RuntimeError::Terminated));
+    }
 }
@@ -0,0 +1,572 @@
+//! Parameter sweeps: one strategy backtested under many `input.*` settings.
+//!
+//! An [`Optimizer`] takes a space of values per input title, searches it —
+//! every combination, or a random sample of them — and ranks the runs by a
+//! [`Metric`]. The script is parsed once and the data fetched once; each run
+//! compiles a fresh [`Script`](crate::Script) from them, since a script's state
+//! is single-use. Runs are spread over threads, each building its own
+//! interpreter: an interpreter is not `Send`, but the parsed program and the
+//! bars are shared.
+
+use crate::{Backtest, Error, Metric, Metrics, ParsedScript, RunResult, ScriptBuilder};
+use pine_builtins::DefaultPineOutput;
+use pine_core::{Data, DataProvider, InputValue, LibraryLoader, Timeframe};
+use pine_interpreter::RuntimeError;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The values one input is tried at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Space {
+    /// `start`, `start + step`, … up to and including `end`.
+    Int { start: i64, end: i64, step: i64 },
+    /// As [`Space::Int`], for an `input.float`.
+    Float { start: f64, end: f64, step: f64 },
+    /// Each of these, for a bool, string or source input — or hand-picked
+    /// numbers.
+    Choices(Vec<InputValue>),
+}
+
+impl Space {
+    /// Every value in the space, in order. An empty range (or a non-positive
+    /// step) yields just its start.
+    pub fn values(&self) -> Vec<InputValue> {
+        match self {
+            Space::Int { start, end, step } => {
+                if *step <= 0 || end < start {
+                    return vec![InputValue::Int(*start)];
+                }
+                (*start..=*end)
+                    .step_by(*step as usize)
+                    .map(InputValue::Int)
+                    .collect()
+            }
+            Space::Float { start, end, step } => {
+                if *step <= 0.0 || step.is_nan() || end < start {
+                    return vec![InputValue::Float(*start)];
+                }
+                // Count the steps rather than accumulate them, so rounding
+                // cannot add or drop the last value.
+                let steps = ((end - start) / step + 1e-9).floor() as usize;
+                (0..=steps)
+                    .map(|i| InputValue::Float(start + step * i as f64))
+                    .collect()
+            }
+            Space::Choices(values) => values.clone(),
+        }
+    }
+}
+
+/// How an [`Optimizer`] picks the input sets it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Search {
+    /// Every combination of every space's values.
+    Grid,
+    /// `trials` distinct combinations drawn at random, reproducibly from
+    /// `seed` — every combination, when there are no more than that.
+    Random { trials: usize, seed: u64 },
+}
+
+/// One run of a sweep: the inputs it was given and how it did.
+#[derive(Debug)]
+pub struct Trial {
+    /// The overridden inputs, by title.
+    pub inputs: BTreeMap<String, InputValue>,
+    /// The run's metrics, or why it could not produce them.
+    pub result: Result<Metrics, Error>,
+}
+
+type Factory<T> = Arc<dyn Fn() -> T + Send + Sync>;
+
+/// Backtests a strategy over a space of input settings and ranks the runs.
+///
+/// ```no_run
+/// use pine_lang::{Metric, Optimizer, ParsedScript, Space};
+///
+/// let parsed = ParsedScript::parse(r#"
+/// //@version=5
+/// strategy("cross")
+/// fast = input.int(10, "Fast")
+/// slow = input.int(30, "Slow")
+/// if ta.crossover(ta.sma(close, fast), ta.sma(close, slow))
+///     strategy.entry("L", strategy.long)
+/// if ta.crossunder(ta.sma(close, fast), ta.sma(close, slow))
+///     strategy.close("L")
+/// "#, None)?;
+/// let trials = Optimizer::new(parsed, pine_lang::data::synthetic(500))
+///     .with_param("Fast", Space::Int { start: 5, end: 20, step: 5 })
+///     .with_param("Slow", Space::Int { start: 20, end: 60, step: 10 })
+///     .with_metric(Metric::Sharpe)
+///     .run()?;
+/// let best = &trials[0];
+/// # Ok::<(), pine_lang::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct Optimizer {
+    parsed: ParsedScript,
+    pub(crate) data: Arc<Data>,
+    timeframe: Timeframe,
+    params: Vec<(String, Space)>,
+    search: Search,
+    metric: Metric,
+    threads: usize,
+    library_loader: Option<Factory<Box<dyn LibraryLoader>>>,
+    request_provider: Option<Factory<Box<dyn DataProvider>>>,
+    benchmark: Option<String>,
+}
+
+impl Optimizer {
+    /// Sweep `parsed` over `data`. Without parameters there is a single run,
+    /// at the script's defaults. Every run shares the one copy of the bars.
+    pub fn new(parsed: ParsedScript, data: impl Into<Arc<Data>>) -> Self {
+        Self {
+            parsed,
+            data: data.into(),
+            timeframe: Timeframe::default(),
+            params: Vec::new(),
+            search: Search::Grid,
+            metric: Metric::NetProfit,
+            threads: std::thread::available_parallelism().map_or(1, usize::from),
+            library_loader: None,
+            request_provider: None,
+            benchmark: None,
+        }
+    }
+
+    /// The chart timeframe of `data`, as [`ScriptBuilder::with_timeframe`].
+    pub fn with_timeframe(mut self, timeframe: Timeframe) -> Self {
+        self.timeframe = timeframe;
+        self
+    }
+
+    /// Try the input titled `title` at each value of `space`. A title the
+    /// script declares no input for fails [`Optimizer::run`].
+    pub fn with_param(mut self, title: impl Into<String>, space: Space) -> Self {
+        self.params.push((title.into(), space));
+        self
+    }
+
+    /// How the input sets are picked. Defaults to [`Search::Grid`].
+    pub fn with_search(mut self, search: Search) -> Self {
+        self.search = search;
+        self
+    }
+
+    /// What the runs are ranked by. Defaults to [`Metric::NetProfit`].
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// How many runs go at once. Defaults to the machine's parallelism.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Resolves `import`s. Called once per run, since a loader is not shared
+    /// between threads.
+    pub fn with_library_loader(
+        mut self,
+        loader: impl Fn() -> Box<dyn LibraryLoader> + Send + Sync + 'static,
+    ) -> Self {
+        self.library_loader = Some(Arc::new(loader));
+        self
+    }
+
+    /// Supplies `request.*` data. Called once per run, as
+    /// [`Optimizer::with_library_loader`].
+    pub fn with_request_provider(
+        mut self,
+        provider: impl Fn() -> Box<dyn DataProvider> + Send + Sync + 'static,
+    ) -> Self {
+        self.request_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Measure each run against `symbol`, as [`ScriptBuilder::with_benchmark`];
+    /// it is fetched from the request provider.
+    pub fn with_benchmark(mut self, symbol: impl Into<String>) -> Self {
+        self.benchmark = Some(symbol.into());
+        self
+    }
+
+    /// The input sets the search will run, in order.
+    pub fn candidates(&self) -> Vec<BTreeMap<String, InputValue>> {
+        let spaces: Vec<(&str, Vec<InputValue>)> = self
+            .params
+            .iter()
+            .map(|(title, space)| (title.as_str(), space.values()))
+            .collect();
+        let pick = |indices: &[usize]| {
+            spaces
+                .iter()
+                .zip(indices)
+                .map(|((title, values), &i)| (title.to_string(), values[i].clone()))
+                .collect()
+        };
+        if spaces.iter().any(|(_, values)| values.is_empty()) {
+            return Vec::new();
+        }
+
+        // A random search asking for every combination or more runs the grid.
+        let combinations = spaces
+            .iter()
+            .try_fold(1usize, |n, (_, values)| n.checked_mul(values.len()));
+        let Search::Random { trials, seed } = self.search else {
+            return grid(&spaces, pick);
+        };
+        if combinations.is_some_and(|n| n <= trials) {
+            return grid(&spaces, pick);
+        }
+
+        // Fewer trials than combinations, so redrawing a repeat ends.
+        let mut rng = SplitMix64(seed);
+        let mut seen = HashSet::new();
+        let mut candidates = Vec::with_capacity(trials);
+        while candidates.len() < trials {
+            let indices: Vec<usize> = spaces
+                .iter()
+                .map(|(_, values)| rng.below(values.len()))
+                .collect();
+            if seen.insert(indices.clone()) {
+                candidates.push(pick(&indices));
+            }
+        }
+        candidates
+    }
+
+    /// Run every candidate, best first by the metric. Runs that failed come
+    /// last, in the order they were tried. Fails before any run when a
+    /// parameter names no input the script declares, since a typo would
+    /// otherwise sweep the same run over and over.
+    pub fn run(&self) -> Result<Vec<Trial>, Error> {
+        self.check_params()?;
+        let candidates = self.candidates();
+        let next = AtomicUsize::new(0);
+        let done = Mutex::new(Vec::with_capacity(candidates.len()));
+        std::thread::scope(|scope| {
+            for _ in 0..self.threads.min(candidates.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(inputs) = candidates.get(i) else {
+                        break;
+                    };
+                    let result = self.backtest(inputs);
+                    done.lock()
+                        .expect("a run panicked")
+                        .push((i, inputs.clone(), result));
+                });
+            }
+        });
+
+        let mut done = done.into_inner().expect("a run panicked");
+        done.sort_by_key(|(i, ..)| *i);
+        let mut trials: Vec<Trial> = done
+            .into_iter()
+            .map(|(_, inputs, result)| Trial { inputs, result })
+            .collect();
+        let metric = self.metric;
+        let score = |trial: &Trial| match &trial.result {
+            Ok(metrics) if !metric.of(metrics).is_nan() => {
+                let value = metric.of(metrics);
+                Some(if metric.higher_is_better() {
+                    value
+                } else {
+                    -value
+                })
+            }
+            _ => None,
+        };
+        // Stable, so ties keep the order they were tried in.
+        trials.sort_by(|a, b| match (score(a), score(b)) {
+            (Some(a), Some(b)) => b.total_cmp(&a),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+        Ok(trials)
+    }
+
+    /// Fail on the first parameter whose title no input of the script has.
+    /// The inputs are read off a run over the first bar alone; a script that
+    /// cannot get that far at its defaults is left for the trials to report.
+    fn check_params(&self) -> Result<(), Error> {
+        if self.params.is_empty() {
+            return Ok(());
+        }
+        let first = Arc::new(
+            Data::new(self.data.bars.iter().take(1).cloned().collect())
+                .with_syminfo(self.data.syminfo.clone()),
+        );
+        let Ok(run) = self
+            .builder(&first, &BTreeMap::new())
+            .compile()
+            .and_then(|script| script.run())
+        else {
+            return Ok(());
+        };
+        let declared = RunResult::collect(&run.outputs).inputs;
+        match self
+            .params
+            .iter()
+            .find(|(title, _)| !declared.iter().any(|input| &input.title == title))
+        {
+            Some((title, _)) => Err(Error::Runtime(RuntimeError::InvalidInput {
+                title: title.clone(),
+                reason: "the script declares no input with this title".to_string(),
+            })),
+            None => Ok(()),
+        }
+    }
+
+    /// Compile and run the script once over the sweep's data, with `inputs`.
+    fn backtest(&self, inputs: &BTreeMap<String, InputValue>) -> Result<Metrics, Error> {
+        Ok(self.replay(&self.data, inputs)?.generate_metrics())
+    }
+
+    /// Compile and run the script once over `data`, with `inputs`.
+    pub(crate) fn replay(
+        &self,
+        data: &Arc<Data>,
+        inputs: &BTreeMap<String, InputValue>,
+    ) -> Result<Backtest, Error> {
+        let run = self.builder(data, inputs).compile()?.run()?;
+        run.backtest.ok_or(Error::NoBacktest)
+    }
+
+    /// A script builder for one run over `data`, with `inputs`.
+    fn builder(
+        &self,
+        data: &Arc<Data>,
+        inputs: &BTreeMap<String, InputValue>,
+    ) -> ScriptBuilder<DefaultPineOutput> {
+        let inputs: HashMap<String, InputValue> = inputs
+            .iter()
+            .map(|(title, value)| (title.clone(), value.clone()))
+            .collect();
+        let mut builder = ScriptBuilder::<DefaultPineOutput>::with_parsed(self.parsed.clone())
+            .with_data(Arc::clone(data))
+            .with_timeframe(self.timeframe.clone())
+            .with_inputs(inputs);
+        if let Some(loader) = &self.library_loader {
+            builder = builder.with_library_loader(loader());
+        }
+        if let Some(provider) = &self.request_provider {
+            builder = builder.with_request_provider(provider());
+        }
+        if let Some(symbol) = &self.benchmark {
+            builder = builder.with_benchmark(symbol.clone());
+        }
+        builder
+    }
+}
+
+/// Every combination of `spaces`' values, as `pick` builds one from an index
+/// into each, in odometer order: the last input varies fastest.
+fn grid<T>(spaces: &[(&str, Vec<InputValue>)], pick: impl Fn(&[usize]) -> T) -> Vec<T> {
+    let mut indices = vec![0; spaces.len()];
+    let mut candidates = Vec::new();
+    loop {
+        candidates.push(pick(&indices));
+        let Some(digit) = (0..spaces.len())
+            .rev()
+            .find(|&d| indices[d] + 1 < spaces[d].1.len())
+        else {
+            return candidates;
+        };
+        indices[digit] += 1;
+        indices[digit + 1..].fill(0);
+    }
+}
+
+/// A small, seedable generator for [`Search::Random`], so a sweep can be
+/// repeated exactly.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CROSS: &str = r#"
+//@version=5
+strategy("cross", initial_capital = 10000)
+len = input.int(5, "Length")
+if ta.crossover(close, ta.sma(close, len))
+    strategy.entry("L", strategy.long)
+if ta.crossunder(close, ta.sma(close, len))
+    strategy.close("L")
+"#;
+
+    fn optimizer() -> Optimizer {
+        let parsed = ParsedScript::parse(CROSS, None).expect("parse");
+        Optimizer::new(parsed, pine_data::synthetic(200))
+    }
+
+    #[test]
+    fn spaces_include_their_end() {
+        let ints = Space::Int {
+            start: 10,
+            end: 20,
+            step: 5,
+        };
+        assert_eq!(
+            ints.values(),
+            [
+                InputValue::Int(10),
+                InputValue::Int(15),
+                InputValue::Int(20)
+            ]
+        );
+        let floats = Space::Float {
+            start: 0.1,
+            end: 0.3,
+            step: 0.1,
+        };
+        assert_eq!(floats.values().len(), 3);
+    }
+
+    #[test]
+    fn grid_covers_every_combination() {
+        let o = optimizer()
+            .with_param(
+                "A",
+                Space::Choices(vec![InputValue::Int(1), InputValue::Int(2)]),
+            )
+            .with_param(
+                "B",
+                Space::Choices(vec![InputValue::Bool(true), InputValue::Bool(false)]),
+            );
+        let candidates = o.candidates();
+        assert_eq!(candidates.len(), 4);
+        assert_eq!(candidates[1]["A"], InputValue::Int(1));
+        assert_eq!(candidates[1]["B"], InputValue::Bool(false));
+    }
+
+    #[test]
+    fn random_search_is_reproducible() {
+        let o = |seed| {
+            optimizer()
+                .with_param(
+                    "Length",
+                    Space::Int {
+                        start: 1,
+                        end: 100,
+                        step: 1,
+                    },
+                )
+                .with_search(Search::Random { trials: 5, seed })
+                .candidates()
+        };
+        assert_eq!(o(7), o(7));
+        assert_eq!(o(7).len(), 5);
+        let drawn = o(7);
+        assert!(drawn
+            .iter()
+            .enumerate()
+            .all(|(i, candidate)| !drawn[..i].contains(candidate)));
+    }
+
+    #[test]
+    fn random_search_over_a_small_space_runs_each_combination_once() {
+        let candidates = optimizer()
+            .with_param(
+                "Length",
+                Space::Int {
+                    start: 1,
+                    end: 3,
+                    step: 1,
+                },
+            )
+            .with_search(Search::Random {
+                trials: 10,
+                seed: 1,
+            })
+            .candidates();
+        assert_eq!(candidates.len(), 3);
+    }
+
+    #[test]
+    fn an_unknown_title_fails_the_sweep() {
+        let result = optimizer()
+            .with_param(
+                "Lenght",
+                Space::Int {
+                    start: 2,
+                    end: 4,
+                    step: 1,
+                },
+            )
+            .run();
+        assert!(matches!(
+            result,
+            Err(Error::Runtime(RuntimeError::InvalidInput { title, .. })) if title == "Lenght"
+        ));
+    }
+
+    #[test]
+    fn runs_are_ranked_by_the_metric() {
+        let trials = optimizer()
+            .with_param(
+                "Length",
+                Space::Int {
+                    start: 2,
+                    end: 10,
+                    step: 2,
+                },
+            )
+            .with_metric(Metric::NetProfit)
+            .with_threads(3)
+            .run()
+            .expect("sweep");
+        assert_eq!(trials.len(), 5);
+        let profits: Vec<f64> = trials
+            .iter()
+            .map(|t| t.result.as_ref().expect("run").net_profit)
+            .collect();
+        assert!(profits.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        // The same sweep on one thread ranks the same way.
+        let serial = optimizer()
+            .with_param(
+                "Length",
+                Space::Int {
+                    start: 2,
+                    end: 10,
+                    step: 2,
+                },
+            )
+            .with_threads(1)
+            .run()
+            .expect("sweep");
+        let order =
+            |trials: &[Trial]| -> Vec<_> { trials.iter().map(|t| t.inputs.clone()).collect() };
+        assert_eq!(order(&trials), order(&serial));
+    }
+
+    #[test]
+    fn an_indicator_has_nothing_to_rank() {
+        let parsed = ParsedScript::parse("//@version=5\nindicator(\"i\")\nplot(close)", None)
+            .expect("parse");
+        let trials = Optimizer::new(parsed, pine_data::synthetic(10))
+            .run()
+            .expect("sweep");
+        assert!(matches!(trials[0].result, Err(Error::NoBacktest)));
+    }
+}
@@ -2,8 +2,9 @@
 
 use crate::Backtest;
 use pine_core::{
-    AlertCondition, AlertConditionOutput, Indicator, Input, InputOutput, LogEntry, LogOutput,
-    MetadataOutput, PineOutput, Plot, PlotOutput,
+    AlertCondition, AlertConditionOutput, AlertEvent, BoxOutput, DrawingOutput, Indicator, Input,
+    InputOutput, Label, LabelOutput, LineObject, LineOutput, LinefillObject, LogEntry, LogOutput,
+    MetadataOutput, PineBox, PineOutput, Plot, PlotOutput, PolylineObject, Table, TableOutput,
 };
 use std::collections::BTreeMap;
 
@@ -16,32 +17,142 @@ pub struct Run<O: PineOutput> {
     pub backtest: Option<Backtest>,
 }
 
+impl<O: AlertConditionOutput> Run<O> {
+    /// Every alert that fired over the run, oldest first.
+    pub fn alert_events(&self) -> impl Iterator<Item = &AlertEvent> {
+        self.outputs.iter().flat_map(|output| output.alert_events())
+    }
+}
+
 /// A run's per-bar outputs turned into columns.
-///
-/// Drawings are missing because the output traits expose labels, lines and
-/// boxes only by id, so there is no way to enumerate what a bar created.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct RunResult {
     pub bars: usize,
     /// Plotted values by title, one slot per bar; `None` where the plot was na.
     pub plots: BTreeMap<String, Vec<Option<f64>>>,
     pub logs: Vec<LogEntry>,
+    /// The `alertcondition()`s the script declares — what a user can set an
+    /// alert on, not alerts that fired.
     pub alerts: Vec<AlertCondition>,
+    /// Every alert that fired, oldest first: `alert()` calls, declared
+    /// `alertcondition()`s whose condition held, and a strategy's order fills,
+    /// told apart by their [`source`](AlertEvent::source).
+    pub alert_events: Vec<AlertEvent>,
     pub indicator: Option<Indicator>,
     pub inputs: Vec<Input>,
+    /// The drawings still on the chart after the last bar, by kind, oldest
+    /// first — what deletes and garbage collection left.
+    pub drawings: Vec<Drawn>,
+    /// How the drawings got there: every creation, change and deletion, bar
+    /// by bar.
+    pub drawing_events: Vec<DrawingEvent>,
+}
+
+/// A label, line, box, table, linefill or polyline, as a bar left it.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Drawing {
+    Label(Label),
+    Line(LineObject),
+    Box(PineBox),
+    Table(Table),
+    Linefill(LinefillObject),
+    Polyline(PolylineObject),
+}
+
+/// A drawing and its id. Ids count up from 0 per kind, as the script's
+/// handles to them do.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Drawn {
+    pub id: usize,
+    pub drawing: Drawing,
+}
+
+/// What happened to a drawing on a bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawingChange {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// A drawing created, changed or deleted on a bar. Changes are seen by
+/// comparing one bar's drawings with the last's, so a drawing created and
+/// deleted on the same bar leaves no event.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DrawingEvent {
+    /// The bar, counted from the run's first.
+    pub bar: usize,
+    pub change: DrawingChange,
+    pub id: usize,
+    /// The drawing as the bar left it, or as it was before it was deleted.
+    pub drawing: Drawing,
+}
+
+impl Drawing {
+    /// The kind's name, as serialized under `kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Drawing::Label(_) => "label",
+            Drawing::Line(_) => "line",
+            Drawing::Box(_) => "box",
+            Drawing::Table(_) => "table",
+            Drawing::Linefill(_) => "linefill",
+            Drawing::Polyline(_) => "polyline",
+        }
+    }
+
+    /// Every drawing live in `output`, keyed by kind and id.
+    fn all_of<O>(output: &O) -> BTreeMap<(&'static str, usize), Drawing>
+    where
+        O: LabelOutput + LineOutput + BoxOutput + TableOutput + DrawingOutput,
+    {
+        fn keyed<'a, T: Clone + 'a>(
+            items: Vec<(usize, &'a T)>,
+            wrap: fn(T) -> Drawing,
+        ) -> impl Iterator<Item = ((&'static str, usize), Drawing)> + 'a {
+            items.into_iter().map(move |(id, item)| {
+                let drawing = wrap(item.clone());
+                ((drawing.kind(), id), drawing)
+            })
+        }
+        keyed(output.labels(), Drawing::Label)
+            .chain(keyed(output.lines(), Drawing::Line))
+            .chain(keyed(output.boxes(), Drawing::Box))
+            .chain(keyed(output.tables(), Drawing::Table))
+            .chain(keyed(output.linefills(), Drawing::Linefill))
+            .chain(keyed(output.polylines(), Drawing::Polyline))
+            .collect()
+    }
 }
 
 impl RunResult {
     /// Transpose the per-bar outputs [`crate::Script::run`] returns.
     pub fn collect<O>(outputs: &[O]) -> Self
     where
-        O: PlotOutput + LogOutput + AlertConditionOutput + MetadataOutput + InputOutput,
+        O: PlotOutput
+            + LogOutput
+            + AlertConditionOutput
+            + MetadataOutput
+            + InputOutput
+            + LabelOutput
+            + LineOutput
+            + BoxOutput
+            + TableOutput
+            + DrawingOutput,
     {
         let mut result = Self::default();
+        let mut drawings = BTreeMap::new();
 
         for output in outputs {
+            let bar = result.bars;
+            drawings = result.diff_drawings(bar, drawings, Drawing::all_of(output));
             result.push_bar(output.plots());
             result.logs.extend(output.get_logs().iter().cloned());
+            result
+                .alert_events
+                .extend(output.alert_events().iter().cloned());
         }
 
         // These describe the script, not a bar, so the last word wins.
@@ -50,10 +161,47 @@ impl RunResult {
             result.inputs = last.inputs().to_vec();
             result.indicator = last.indicator().cloned();
         }
+        result.drawings = drawings
+            .into_iter()
+            .map(|((_, id), drawing)| Drawn { id, drawing })
+            .collect();
 
         result
     }
 
+    /// Record how `bar`'s drawings differ from the previous bar's, returning
+    /// `bar`'s.
+    fn diff_drawings(
+        &mut self,
+        bar: usize,
+        mut previous: BTreeMap<(&'static str, usize), Drawing>,
+        current: BTreeMap<(&'static str, usize), Drawing>,
+    ) -> BTreeMap<(&'static str, usize), Drawing> {
+        for ((_, id), drawing) in &current {
+            let change = match previous.remove(&(drawing.kind(), *id)) {
+                None => DrawingChange::Created,
+                Some(before) if before != *drawing => DrawingChange::Updated,
+                Some(_) => continue,
+            };
+            self.drawing_events.push(DrawingEvent {
+                bar,
+                change,
+                id: *id,
+                drawing: drawing.clone(),
+            });
+        }
+        // What is left was live on the last bar but is gone from this one.
+        for ((_, id), drawing) in previous {
+            self.drawing_events.push(DrawingEvent {
+                bar,
+                change: DrawingChange::Deleted,
+                id,
+                drawing,
+            });
+        }
+        current
+    }
+
     /// Append one bar, padding every column so titles stay aligned whether a
     /// plot starts late or stops early.
     fn push_bar(&mut self, plots: &[Plot]) {
@@ -133,6 +281,100 @@ mod tests {
         assert!(run.plot("nope").is_none());
     }
 
+    #[test]
+    fn drawings_are_collected_with_their_history() {
+        use crate::core::DefaultPineOutput;
+        use crate::ScriptBuilder;
+
+        let source = r#"
+//@version=5
+indicator("t", overlay = true)
+var lbl = label.new(bar_index, close, "start")
+var line ln = na
+if bar_index == 1
+    ln := line.new(bar_index - 1, low, bar_index, high)
+if bar_index == 2
+    label.set_text(lbl, "moved")
+if bar_index == 3
+    line.delete(ln)
+    table.cell(table.new(position.top_right, 2, 2), 1, 0, "T")
+"#;
+        let run = ScriptBuilder::<DefaultPineOutput>::with_code(source)
+            .with_data(crate::data::synthetic(5))
+            .compile()
+            .expect("compile")
+            .run()
+            .expect("run");
+        let result = RunResult::collect(&run.outputs);
+
+        let events: Vec<_> = result
+            .drawing_events
+            .iter()
+            .map(|e| (e.bar, e.change, e.drawing.kind(), e.id))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (0, DrawingChange::Created, "label", 0),
+                (1, DrawingChange::Created, "line", 0),
+                (2, DrawingChange::Updated, "label", 0),
+                (3, DrawingChange::Created, "table", 0),
+                (3, DrawingChange::Deleted, "line", 0),
+            ]
+        );
+
+        let kinds: Vec<_> = result.drawings.iter().map(|d| d.drawing.kind()).collect();
+        assert_eq!(kinds, ["label", "table"]);
+        let Drawing::Label(label) = &result.drawings[0].drawing else {
+            panic!("expected a label");
+        };
+        assert_eq!(label.text, "moved");
+
+        // Table cells serialize as a list, since JSON keys cannot be pairs.
+        let json = serde_json::to_value(&result.drawings[1]).expect("json");
+        assert_eq!(json["drawing"]["kind"], "table");
+        assert_eq!(json["drawing"]["cells"][0]["column"], 1);
+        assert_eq!(json["drawing"]["cells"][0]["text"], "T");
+    }
+
+    #[test]
+    fn drawings_past_the_declared_limit_push_out_the_oldest() {
+        use crate::core::DefaultPineOutput;
+        use crate::ScriptBuilder;
+
+        let drawn = |declaration: &str| {
+            let source = format!(
+                "//@version=5\n{declaration}\nlabel.new(bar_index, close)\nline.new(bar_index, low, bar_index, high)\n"
+            );
+            let run = ScriptBuilder::<DefaultPineOutput>::with_code(&source)
+                .with_data(crate::data::synthetic(60))
+                .compile()
+                .expect("compile")
+                .run()
+                .expect("run");
+            let result = RunResult::collect(&run.outputs);
+            let ids = |kind| {
+                result
+                    .drawings
+                    .iter()
+                    .filter(|d| d.drawing.kind() == kind)
+                    .map(|d| d.id)
+                    .collect::<Vec<_>>()
+            };
+            (ids("label"), ids("line"))
+        };
+
+        let (labels, lines) = drawn(r#"indicator("t", max_labels_count = 3)"#);
+        assert_eq!(labels, [57, 58, 59]);
+        // Unset, a limit is 50.
+        assert_eq!(lines, (10..60).collect::<Vec<_>>());
+
+        // A strategy declares them the same way.
+        let (labels, lines) = drawn(r#"strategy("t", max_lines_count = 100)"#);
+        assert_eq!(labels.len(), 50);
+        assert_eq!(lines.len(), 60);
+    }
+
     #[test]
     fn with_broker_swaps_the_broker_factory() {
         use crate::broker::{Broker, BrokerConfig, BrokerFactory, DefaultBrokerFactory};
@@ -171,6 +413,45 @@ if bar_index == 1
         assert_eq!(calls.load(Ordering::SeqCst), 1);
     }
 
+    #[test]
+    fn inputs_carry_their_constraints() {
+        use crate::core::{DefaultPineOutput, InputValue};
+        use crate::ScriptBuilder;
+
+        let source = r#"
+//@version=5
+indicator("t")
+len = input.int(14, "Length", minval = 2, maxval = 50, step = 2, tooltip = "Bars", inline = "a")
+mode = input.string("ema", "Mode", options = ["ema", "sma"], confirm = true)
+plot(len)
+"#;
+        let run = ScriptBuilder::<DefaultPineOutput>::with_code(source)
+            .with_data(crate::data::synthetic(3))
+            .compile()
+            .expect("compile")
+            .run()
+            .expect("run");
+        let inputs = RunResult::collect(&run.outputs).inputs;
+
+        let length = &inputs[0];
+        assert_eq!(length.constraints.minval, Some(2.0));
+        assert_eq!(length.constraints.maxval, Some(50.0));
+        assert_eq!(length.constraints.step, Some(2.0));
+        assert_eq!(
+            (length.tooltip.as_str(), length.inline.as_str()),
+            ("Bars", "a")
+        );
+        let mode = &inputs[1];
+        assert_eq!(
+            mode.constraints.options,
+            [
+                InputValue::Str("ema".to_string()),
+                InputValue::Str("sma".to_string())
+            ]
+        );
+        assert!(mode.confirm);
+    }
+
     #[test]
     fn backtest_reports_the_halt_bar() {
         use crate::core::DefaultPineOutput;
@@ -208,4 +489,53 @@ if bar_index == 1
             .expect("run");
         assert_eq!(run.backtest.expect("strategy").halted, None);
     }
+
+    #[test]
+    fn alerts_fire_as_events_with_their_bar() {
+        use crate::core::{AlertEvent, AlertSink, AlertSource, DefaultPineOutput};
+        use crate::ScriptBuilder;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Collect(Rc<RefCell<Vec<AlertEvent>>>);
+        impl AlertSink for Collect {
+            fn send(&mut self, event: &AlertEvent) {
+                self.0.borrow_mut().push(event.clone());
+            }
+        }
+
+        // Synthetic bar i opens at 99 + i and closes at 100 + i.
+        let source = r#"
+//@version=5
+strategy("t")
+if bar_index == 1
+    strategy.entry("L", strategy.long, qty = 2, alert_message = "{{strategy.order.action}} {{strategy.order.contracts}} @ {{strategy.order.price}}")
+if bar_index == 3
+    strategy.close("L", disable_alert = true)
+alertcondition(bar_index == 2, "two", "close {{close}}")
+"#;
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let run = ScriptBuilder::<DefaultPineOutput>::with_code(source)
+            .with_data(crate::data::synthetic(5))
+            .with_alert_sink(Box::new(Collect(Rc::clone(&sent))))
+            .compile()
+            .expect("compile")
+            .run()
+            .expect("run");
+
+        let events: Vec<_> = run.alert_events().cloned().collect();
+        let fired: Vec<_> = events
+            .iter()
+            .map(|e| (e.bar_index, e.source, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            fired,
+            [
+                (2, AlertSource::OrderFill, "buy 2 @ 101"),
+                (2, AlertSource::AlertCondition, "close 102"),
+            ]
+        );
+        assert_eq!(events[0].time, 2 * 60_000);
+        assert_eq!(*sent.borrow(), events);
+    }
 }
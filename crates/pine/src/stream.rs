@@ -0,0 +1,334 @@
+//! Running a script against a live feed.
+//!
+//! [`Script::run`] replays a finished series, where every bar is confirmed
+//! history. A [`Stream`] instead follows the market as it moves: history first,
+//! then tick updates to the bar that is still forming. Each tick re-runs the
+//! script on that bar from the state it opened with, so only `varip` variables
+//! see the ticks before it, and the bar's last run stands once it closes.
+
+use crate::{Backtest, Error, Script};
+use pine_core::{AlertConditionOutput, Bar, Ohlcv, PineOutput};
+
+/// A [`Script`] kept alive to follow a live feed. Built by [`Script::stream`].
+///
+/// Bars go in oldest first: [`history`](Self::history) for the ones already
+/// closed, then [`update`](Self::update) for every tick of the realtime bar.
+/// A tick with a later open time closes the bar before it; so does
+/// [`close`](Self::close), for a feed that reports closes itself.
+///
+/// A `strategy` is only run when a realtime bar closes, as TradingView does
+/// without `calc_on_every_tick`: its ticks produce no output.
+///
+/// Every other script re-runs on each tick from a checkpoint of its state at
+/// the bar's open. Taking that checkpoint, and restoring it before each later
+/// tick, copies every variable, series history and the output accumulated so
+/// far, so a tick costs time in proportion to the script's whole state rather
+/// than to the one bar it re-runs.
+pub struct Stream<O: PineOutput> {
+    script: Script<O>,
+    /// The realtime bar, from its first tick until it closes.
+    forming: Option<Bar>,
+    /// The index the next bar will get.
+    next_index: u64,
+    /// The open time of the latest bar seen, so the feed cannot go backwards.
+    last_time: Option<i64>,
+    /// Set by the first tick; history is closed from then on.
+    realtime: bool,
+}
+
+impl<O: AlertConditionOutput> Script<O> {
+    /// Keep the script alive to follow a live feed, rather than replaying a
+    /// fixed series. The bars it was built with are run by
+    /// [`Stream::replay`].
+    pub fn stream(self) -> Stream<O> {
+        Stream {
+            script: self,
+            forming: None,
+            next_index: 0,
+            last_time: None,
+            realtime: false,
+        }
+    }
+}
+
+impl<O: AlertConditionOutput> Stream<O> {
+    /// Run the bars the script was built with as confirmed history, the last
+    /// of them marked `barstate.islastconfirmedhistory`.
+    pub fn replay(&mut self) -> Result<Vec<O>, Error> {
+        let data = std::mem::take(&mut self.script.data);
+        let bars = data.bars.get(self.script.first_bar..).unwrap_or_default();
+        let last = bars.len().saturating_sub(1);
+        bars.iter()
+            .enumerate()
+            .map(|(i, bar)| self.history_bar(ohlcv(bar), i == last))
+            .collect()
+    }
+
+    /// Run one closed bar. History must come before the first
+    /// [`update`](Self::update).
+    pub fn history(&mut self, row: Ohlcv) -> Result<O, Error> {
+        self.history_bar(row, false)
+    }
+
+    fn history_bar(&mut self, row: Ohlcv, last_history: bool) -> Result<O, Error> {
+        if self.realtime {
+            return Err(Error::OutOfOrder(row.time));
+        }
+        self.advance_time(row.time)?;
+        let bar = Bar {
+            is_new: true,
+            is_confirmed: true,
+            is_history: true,
+            is_last_confirmed_history: last_history,
+            ..self.new_bar(row)
+        };
+        self.next_index += 1;
+        self.script.execute(&bar, None)
+    }
+
+    /// Apply a tick: `tick` is the realtime bar as it stands now, opening at
+    /// `tick.time`. Returns what each run produced — normally one output, two
+    /// when the tick opened a new bar and so closed the previous one, none for
+    /// the intrabar ticks of a `strategy`.
+    pub fn update(&mut self, tick: Ohlcv) -> Result<Vec<O>, Error> {
+        let mut outputs = Vec::new();
+        match &self.forming {
+            Some(bar) if tick.time == bar.time => {
+                let bar = Bar {
+                    is_new: false,
+                    ..with_prices(bar, tick)
+                };
+                self.forming = Some(bar.clone());
+                if self.script.interpreter.is_checkpointed() {
+                    self.script.interpreter.rollback();
+                    outputs.push(self.script.execute(&bar, Some(&bar))?);
+                }
+                return Ok(outputs);
+            }
+            Some(bar) if tick.time < bar.time => return Err(Error::OutOfOrder(tick.time)),
+            Some(_) => outputs.extend(self.close()?),
+            None => self.advance_time(tick.time)?,
+        }
+
+        self.realtime = true;
+        self.last_time = Some(tick.time);
+        let bar = Bar {
+            is_last: true,
+            is_new: true,
+            is_realtime: true,
+            ..self.new_bar(tick)
+        };
+        self.next_index += 1;
+        self.forming = Some(bar.clone());
+        // A strategy waits for the close; anything else runs now, from a
+        // checkpoint the next tick can roll back to.
+        if !self.script.strategy {
+            self.script.interpreter.checkpoint();
+            outputs.push(self.script.execute(&bar, Some(&bar))?);
+        }
+        Ok(outputs)
+    }
+
+    /// Close the realtime bar at the prices of its last tick, and run it one
+    /// final time with `barstate.isconfirmed` set. `None` when no bar is
+    /// forming.
+    pub fn close(&mut self) -> Result<Option<O>, Error> {
+        let Some(bar) = self.forming.take() else {
+            return Ok(None);
+        };
+        let bar = Bar {
+            is_confirmed: true,
+            ..bar
+        };
+        let interpreter = &mut self.script.interpreter;
+        if interpreter.is_checkpointed() {
+            interpreter.rollback();
+        }
+        let output = self.script.execute(&bar, Some(&bar));
+        self.script.interpreter.commit();
+        output.map(Some)
+    }
+
+    /// The realtime bar as of its latest tick, if one is forming.
+    pub fn forming(&self) -> Option<&Bar> {
+        self.forming.as_ref()
+    }
+
+    /// Stop following the feed. For a `strategy`, the backtest of every bar
+    /// that closed.
+    pub fn finish(mut self) -> Option<Backtest> {
+        self.script.take_backtest()
+    }
+
+    fn advance_time(&mut self, time: i64) -> Result<(), Error> {
+        if self.last_time.is_some_and(|last| time <= last) {
+            return Err(Error::OutOfOrder(time));
+        }
+        self.last_time = Some(time);
+        Ok(())
+    }
+
+    /// A bar at the next index, with no barstate flags but `is_first`.
+    fn new_bar(&self, row: Ohlcv) -> Bar {
+        Bar {
+            index: self.next_index,
+            is_first: self.next_index == 0,
+            ..with_prices(&Bar::default(), row)
+        }
+    }
+}
+
+/// `bar` with its time and prices taken from `row`.
+fn with_prices(bar: &Bar, row: Ohlcv) -> Bar {
+    Bar {
+        open: row.open,
+        high: row.high,
+        low: row.low,
+        close: row.close,
+        volume: row.volume,
+        time: row.time,
+        ..bar.clone()
+    }
+}
+
+fn ohlcv(bar: &Bar) -> Ohlcv {
+    Ohlcv {
+        time: bar.time,
+        open: bar.open,
+        high: bar.high,
+        low: bar.low,
+        close: bar.close,
+        volume: bar.volume,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Data, DefaultPineOutput, LogOutput};
+    use crate::ScriptBuilder;
+
+    fn stream(source: &str) -> Stream<DefaultPineOutput> {
+        ScriptBuilder::<DefaultPineOutput>::with_code(source)
+            .with_data(Data::default())
+            .compile()
+            .unwrap()
+            .stream()
+    }
+
+    fn tick(time: i64, close: f64) -> Ohlcv {
+        Ohlcv {
+            time,
+            open: 100.0,
+            high: close.max(100.0),
+            low: close.min(100.0),
+            close,
+            volume: 1.0,
+        }
+    }
+
+    fn logs(outputs: &[DefaultPineOutput]) -> Vec<String> {
+        outputs
+            .iter()
+            .flat_map(|o| o.get_logs())
+            .map(|log| log.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn varip_keeps_ticks_while_var_rolls_back() {
+        let mut s = stream(
+            "//@version=6\nindicator(\"t\")\nvar int bars = 0\nvarip int ticks = 0\nbars += 1\nticks += 1\nlog.info(str.tostring(bars) + \"/\" + str.tostring(ticks))\n",
+        );
+        s.history(tick(0, 100.0)).unwrap();
+        let mut outputs = s.update(tick(60, 101.0)).unwrap();
+        outputs.extend(s.update(tick(60, 102.0)).unwrap());
+        outputs.extend(s.update(tick(60, 103.0)).unwrap());
+        outputs.extend(s.close().unwrap());
+
+        assert_eq!(logs(&outputs), ["2/2", "2/3", "2/4", "2/5"]);
+    }
+
+    #[test]
+    fn barstate_follows_the_forming_bar() {
+        let mut s = stream(
+            "//@version=6\nindicator(\"t\")\nlog.info(str.tostring(barstate.isnew) + \" \" + str.tostring(barstate.isconfirmed) + \" \" + str.tostring(barstate.isrealtime))\n",
+        );
+        let mut outputs = vec![s.history(tick(0, 100.0)).unwrap()];
+        outputs.extend(s.update(tick(60, 101.0)).unwrap());
+        outputs.extend(s.update(tick(60, 102.0)).unwrap());
+        outputs.extend(s.close().unwrap());
+
+        assert_eq!(
+            logs(&outputs),
+            [
+                "true true false",
+                "true false true",
+                "false false true",
+                "false true true",
+            ]
+        );
+    }
+
+    #[test]
+    fn stateful_builtins_advance_once_per_bar() {
+        let mut s = stream(
+            "//@version=6\nindicator(\"t\")\nlog.info(str.tostring(ta.cum(1)) + \" \" + str.tostring(close[1]))\n",
+        );
+        let mut outputs = vec![s.history(tick(0, 100.0)).unwrap()];
+        outputs.extend(s.update(tick(60, 101.0)).unwrap());
+        outputs.extend(s.update(tick(60, 102.0)).unwrap());
+        // A tick for the next bar closes this one first.
+        outputs.extend(s.update(tick(120, 103.0)).unwrap());
+
+        assert_eq!(
+            logs(&outputs),
+            ["1 NaN", "2 100", "2 100", "2 100", "3 102"]
+        );
+    }
+
+    #[test]
+    fn rejects_a_feed_that_goes_backwards() {
+        let mut s = stream("//@version=6\nindicator(\"t\")\n");
+        s.history(tick(60, 100.0)).unwrap();
+        assert!(matches!(
+            s.history(tick(0, 100.0)),
+            Err(Error::OutOfOrder(0))
+        ));
+
+        s.update(tick(120, 100.0)).unwrap();
+        assert!(matches!(
+            s.update(tick(60, 100.0)),
+            Err(Error::OutOfOrder(60))
+        ));
+        assert!(matches!(
+            s.history(tick(180, 100.0)),
+            Err(Error::OutOfOrder(180))
+        ));
+    }
+
+    #[test]
+    fn a_strategy_runs_only_when_the_bar_closes() {
+        let mut s = stream(
+            "//@version=6\nstrategy(\"t\")\nlog.info(str.tostring(close))\nif barstate.isconfirmed\n    strategy.entry(\"L\", strategy.long)\n",
+        );
+        s.history(tick(0, 100.0)).unwrap();
+        assert!(s.update(tick(60, 101.0)).unwrap().is_empty());
+        assert!(s.update(tick(60, 102.0)).unwrap().is_empty());
+        let closed = s.close().unwrap().unwrap();
+        assert_eq!(logs(&[closed]), ["102"]);
+
+        let backtest = s.finish().unwrap();
+        assert_eq!(backtest.equity.len(), 2);
+        assert_eq!(backtest.trades.len(), 1);
+    }
+
+    #[test]
+    fn a_strategy_with_no_history_still_waits_for_the_close() {
+        let mut s = stream("//@version=6\nstrategy(\"t\")\nlog.info(str.tostring(close))\n");
+        assert!(s.update(tick(0, 101.0)).unwrap().is_empty());
+        assert!(s.update(tick(0, 102.0)).unwrap().is_empty());
+        let closed = s.close().unwrap().unwrap();
+        assert_eq!(logs(&[closed]), ["102"]);
+    }
+}
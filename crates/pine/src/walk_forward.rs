@@ -0,0 +1,457 @@
+//! Walk-forward analysis: does an optimized parameter set survive data it was
+//! not chosen on?
+//!
+//! The bars are cut into windows, each an in-sample stretch followed by an
+//! out-of-sample one. An [`Optimizer`] picks the best inputs on the in-sample
+//! bars, and those inputs are replayed, untouched, on the out-of-sample bars —
+//! with the in-sample bars before them as a warm-up, so indicators are primed
+//! when the test starts. The out-of-sample windows tile the end of the series,
+//! so their runs stitch into one [`Backtest`] of how the strategy would have
+//! done had it been re-optimized at each window.
+
+use crate::{Backtest, Error, Metrics, Optimizer};
+use pine_broker::Trade;
+use pine_core::{Bar, Data, InputValue};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Re-optimizes a strategy over rolling windows and replays each winner on the
+/// bars that follow.
+///
+/// ```no_run
+/// use pine_lang::{Optimizer, ParsedScript, Space, WalkForward};
+///
+/// # let source = std::fs::read_to_string("cross.pine").unwrap();
+/// let parsed = ParsedScript::parse(&source, None)?;
+/// let optimizer = Optimizer::new(parsed, pine_lang::data::synthetic(2000))
+///     .with_param("Length", Space::Int { start: 5, end: 50, step: 5 });
+/// let report = WalkForward::new(optimizer, 500, 100).run()?;
+/// for window in &report.windows {
+///     println!("{:?}: {:?}", window.inputs, window.out_of_sample_metrics.net_profit);
+/// }
+/// println!("{}", report.backtest.generate_metrics().net_profit);
+/// # Ok::<(), pine_lang::Error>(())
+/// ```
+pub struct WalkForward {
+    optimizer: Optimizer,
+    in_sample: usize,
+    out_of_sample: usize,
+    anchored: bool,
+}
+
+/// One window of a walk-forward run.
+#[derive(Debug, Clone)]
+pub struct WalkForwardWindow {
+    /// The bars the inputs were chosen on, as indices into the full series.
+    pub in_sample: Range<usize>,
+    /// The bars they were then replayed on.
+    pub out_of_sample: Range<usize>,
+    /// The winning inputs, by title.
+    pub inputs: BTreeMap<String, InputValue>,
+    /// How the winner did on the bars it was chosen on.
+    pub in_sample_metrics: Metrics,
+    /// How it did on the bars after them.
+    pub out_of_sample_metrics: Metrics,
+}
+
+/// What [`WalkForward::run`] produced.
+#[derive(Debug, Clone)]
+pub struct WalkForwardReport {
+    /// Every window, oldest first.
+    pub windows: Vec<WalkForwardWindow>,
+    /// The out-of-sample runs stitched end to end: bar 0 is the first
+    /// out-of-sample bar, and trades are numbered from it.
+    pub backtest: Backtest,
+}
+
+impl WalkForward {
+    /// Walk `optimizer` over its data in windows of `in_sample` bars to
+    /// optimize on and `out_of_sample` bars to test on. Each window starts
+    /// `out_of_sample` bars after the last.
+    pub fn new(optimizer: Optimizer, in_sample: usize, out_of_sample: usize) -> Self {
+        Self {
+            optimizer,
+            in_sample,
+            out_of_sample,
+            anchored: false,
+        }
+    }
+
+    /// Grow the in-sample stretch from the first bar rather than sliding it,
+    /// so each window optimizes on everything before its test bars.
+    pub fn with_anchored(mut self, anchored: bool) -> Self {
+        self.anchored = anchored;
+        self
+    }
+
+    /// The `(in_sample, out_of_sample)` bar ranges of each window. The last
+    /// window's out-of-sample stretch is cut short by the end of the data;
+    /// none is made when not a single bar is left to test on.
+    pub fn windows(&self) -> Vec<(Range<usize>, Range<usize>)> {
+        let bars = self.optimizer.data.bars.len();
+        if self.in_sample == 0 || self.out_of_sample == 0 {
+            return Vec::new();
+        }
+        (self.in_sample..bars)
+            .step_by(self.out_of_sample)
+            .map(|split| {
+                let start = if self.anchored {
+                    0
+                } else {
+                    split - self.in_sample
+                };
+                let end = (split + self.out_of_sample).min(bars);
+                (start..split, split..end)
+            })
+            .collect()
+    }
+
+    /// Optimize and replay every window, then stitch the out-of-sample runs.
+    /// Fails with the first error a window could not get past: a sweep that
+    /// could not start, a replay that failed, or a sweep in which every run
+    /// did.
+    pub fn run(&self) -> Result<WalkForwardReport, Error> {
+        let mut windows = Vec::new();
+        let mut backtests = Vec::new();
+        for (in_sample, out_of_sample) in self.windows() {
+            let mut sweep = self.optimizer.clone();
+            sweep.data = Arc::new(slice(&self.optimizer.data, in_sample.clone()));
+            let mut trials = sweep.run()?.into_iter();
+            let Some(best) = trials.next() else {
+                // An empty space leaves nothing to choose from.
+                continue;
+            };
+            let in_sample_metrics = best.result?;
+
+            // Replay from the in-sample start so the script is warmed up, and
+            // keep what it did from the out-of-sample start on.
+            let replayed = Arc::new(slice(
+                &self.optimizer.data,
+                in_sample.start..out_of_sample.end,
+            ));
+            let warmup = out_of_sample.start - in_sample.start;
+            let backtest = after_warmup(
+                self.optimizer.replay(&replayed, &best.inputs)?,
+                warmup,
+                &replayed.bars,
+            );
+            windows.push(WalkForwardWindow {
+                in_sample,
+                out_of_sample,
+                inputs: best.inputs,
+                in_sample_metrics,
+                out_of_sample_metrics: backtest.generate_metrics(),
+            });
+            backtests.push(backtest);
+        }
+        Ok(WalkForwardReport {
+            windows,
+            backtest: stitch(backtests),
+        })
+    }
+}
+
+/// The bars in `range`, renumbered and re-flagged as a series of their own.
+fn slice(data: &Data, range: Range<usize>) -> Data {
+    let last = range.len().saturating_sub(1);
+    let bars = data.bars[range]
+        .iter()
+        .enumerate()
+        .map(|(index, bar)| Bar {
+            index: index as u64,
+            is_first: index == 0,
+            is_last: index == last,
+            is_last_confirmed_history: index == last,
+            ..bar.clone()
+        })
+        .collect();
+    Data::new(bars).with_syminfo(data.syminfo.clone())
+}
+
+/// What `run` did from bar `warmup` on, as if it had started there with its
+/// initial capital: trades entered before are dropped, and the equity curve
+/// is rebased to the capital at `warmup`, less what positions carried in from
+/// the warm-up made or lost after it. Bars are renumbered from `warmup`.
+fn after_warmup(run: Backtest, warmup: usize, bars: &[Bar]) -> Backtest {
+    if warmup == 0 || warmup > run.equity.len() {
+        return run;
+    }
+    let at = warmup as u64;
+    let (carried, trades): (Vec<_>, Vec<_>) =
+        run.trades.into_iter().partition(|t| t.entry_bar < at);
+    // A carried position's value at bar `i`, before commission: marked at
+    // the close while open, at its exit once closed.
+    let value = |trade: &Trade, i: usize| {
+        let price = match (trade.exit_bar, trade.exit_price) {
+            (Some(exit), Some(price)) if exit <= i as u64 => price,
+            _ => bars.get(i).map_or(run.mark_price, |bar| bar.close),
+        };
+        (price - trade.entry_price) * trade.size * trade.fx_rate
+    };
+    let carried: Vec<Trade> = carried
+        .into_iter()
+        .filter(|t| t.exit_bar.is_none_or(|exit| exit >= at))
+        .collect();
+    let start = run.equity[warmup - 1];
+    let equity = (warmup..run.equity.len())
+        .map(|i| {
+            let carried: f64 = carried
+                .iter()
+                .map(|t| value(t, i) - value(t, warmup - 1))
+                .sum();
+            run.initial_capital + run.equity[i] - start - carried
+        })
+        .collect();
+
+    let mut window = Backtest {
+        initial_capital: run.initial_capital,
+        currency: run.currency,
+        equity,
+        benchmark: run.benchmark.get(warmup..).unwrap_or_default().to_vec(),
+        first_price: bars.get(warmup).map_or(run.first_price, |bar| bar.close),
+        mark_price: run.mark_price,
+        halted: run.halted.map(|bar| bar.saturating_sub(at)),
+        timeframe: run.timeframe,
+        ..Backtest::default()
+    };
+    for mut trade in trades {
+        trade.entry_bar -= at;
+        trade.exit_bar = trade.exit_bar.map(|bar| bar - at);
+        if trade.is_open() {
+            window.open_profit += trade.profit(window.mark_price);
+            window.position_size += trade.size;
+        } else {
+            tally(&mut window, &trade);
+        }
+        window.trades.push(trade);
+    }
+    (window.max_drawdown, window.max_runup) = extremes(window.initial_capital, &window.equity);
+    window
+}
+
+/// Count the closed `trade` into `backtest`'s totals.
+fn tally(backtest: &mut Backtest, trade: &Trade) {
+    let profit = trade.profit(0.0); // closed, so the price is ignored
+    backtest.net_profit += profit;
+    if profit > 0.0 {
+        backtest.gross_profit += profit;
+        backtest.win_trades += 1;
+    } else if profit < 0.0 {
+        backtest.gross_loss -= profit;
+        backtest.loss_trades += 1;
+    } else {
+        backtest.even_trades += 1;
+    }
+}
+
+/// The largest fall from a peak and rise from a trough along `equity`, both
+/// seeded with the `initial` capital.
+fn extremes(initial: f64, equity: &[f64]) -> (f64, f64) {
+    let (mut peak, mut trough) = (initial, initial);
+    let (mut drawdown, mut runup) = (0.0f64, 0.0f64);
+    for &value in equity {
+        peak = peak.max(value);
+        trough = trough.min(value);
+        drawdown = drawdown.max(peak - value);
+        runup = runup.max(value - trough);
+    }
+    (drawdown, runup)
+}
+
+/// Lay `runs` end to end. Each run started from the same capital, so a run's
+/// equity continues from where the last left off by adding its gain; the
+/// totals are sums, and the drawdown and run-up those of the joined curve.
+/// Trades still open when their run ended are closed at its last bar, where
+/// the next window takes over.
+fn stitch(runs: Vec<Backtest>) -> Backtest {
+    let Some(first) = runs.first() else {
+        return Backtest::default();
+    };
+    let mut stitched = Backtest {
+        initial_capital: first.initial_capital,
+        currency: first.currency.clone(),
+        first_price: first.first_price,
+        timeframe: first.timeframe.clone(),
+        ..Backtest::default()
+    };
+    let mut gain = 0.0;
+    for run in runs {
+        let offset = stitched.equity.len() as u64;
+        let last = (offset + run.equity.len() as u64).saturating_sub(1);
+        stitched.equity.extend(
+            run.equity
+                .iter()
+                .map(|equity| stitched.initial_capital + gain + equity - run.initial_capital),
+        );
+        stitched.benchmark.extend(&run.benchmark);
+        gain += run.final_equity() - run.initial_capital;
+        // Closed trades first, in the order they closed, then the ones the
+        // window's end closed.
+        let (closed, open): (Vec<_>, Vec<_>) = run.trades.into_iter().partition(|t| !t.is_open());
+        for mut trade in closed {
+            trade.entry_bar += offset;
+            trade.exit_bar = trade.exit_bar.map(|bar| bar + offset);
+            stitched.trades.push(trade);
+        }
+        for mut trade in open {
+            trade.entry_bar += offset;
+            trade.exit_bar = Some(last);
+            trade.exit_price = Some(run.mark_price);
+            tally(&mut stitched, &trade);
+            stitched.trades.push(trade);
+        }
+        stitched.net_profit += run.net_profit;
+        stitched.gross_profit += run.gross_profit;
+        stitched.gross_loss += run.gross_loss;
+        stitched.win_trades += run.win_trades;
+        stitched.loss_trades += run.loss_trades;
+        stitched.even_trades += run.even_trades;
+        stitched.mark_price = run.mark_price;
+        stitched.halted = stitched.halted.or(run.halted.map(|bar| bar + offset));
+    }
+    (stitched.max_drawdown, stitched.max_runup) =
+        extremes(stitched.initial_capital, &stitched.equity);
+    stitched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParsedScript, Space};
+
+    const CROSS: &str = r#"
+//@version=5
+strategy("cross", initial_capital = 10000)
+len = input.int(5, "Length")
+if ta.crossover(close, ta.sma(close, len))
+    strategy.entry("L", strategy.long)
+if bar_index % 7 == 0
+    strategy.close("L")
+"#;
+
+    fn walk_forward(bars: usize) -> WalkForward {
+        let parsed = ParsedScript::parse(CROSS, None).expect("parse");
+        let optimizer = Optimizer::new(parsed, pine_data::synthetic(bars)).with_param(
+            "Length",
+            Space::Int {
+                start: 2,
+                end: 6,
+                step: 2,
+            },
+        );
+        WalkForward::new(optimizer, 50, 20)
+    }
+
+    #[test]
+    fn windows_roll_or_anchor() {
+        let rolling = walk_forward(110).windows();
+        assert_eq!(
+            rolling,
+            [(0..50, 50..70), (20..70, 70..90), (40..90, 90..110)]
+        );
+        let anchored = walk_forward(100).with_anchored(true).windows();
+        assert_eq!(
+            anchored,
+            [(0..50, 50..70), (0..70, 70..90), (0..90, 90..100)]
+        );
+        assert!(walk_forward(50).windows().is_empty());
+    }
+
+    #[test]
+    fn out_of_sample_runs_stitch_into_one_backtest() {
+        let report = walk_forward(110).run().expect("walk forward");
+        assert_eq!(report.windows.len(), 3);
+        let backtest = &report.backtest;
+        assert_eq!(backtest.equity.len(), 60);
+        // Each window's end closes what it left open.
+        assert!(backtest.trades.iter().all(|t| !t.is_open()));
+        assert_eq!(
+            backtest.win_trades + backtest.loss_trades + backtest.even_trades,
+            backtest.trades.len()
+        );
+        // The curve ends on the sum of every window's gain.
+        let gain: f64 = report
+            .windows
+            .iter()
+            .map(|w| w.out_of_sample_metrics.final_equity - w.out_of_sample_metrics.initial_capital)
+            .sum();
+        assert!((backtest.final_equity() - 10_000.0 - gain).abs() < 1e-6);
+        assert!(backtest
+            .trades
+            .iter()
+            .all(|t| t.exit_bar.unwrap_or(t.entry_bar) < 60));
+    }
+
+    #[test]
+    fn stitching_closes_open_trades_and_measures_the_joined_curve() {
+        let run = |equity: Vec<f64>, trades: Vec<Trade>| Backtest {
+            initial_capital: 100.0,
+            mark_price: 12.0,
+            equity,
+            trades,
+            ..Backtest::default()
+        };
+        let open = Trade {
+            entry_id: "L".to_string(),
+            size: 1.0,
+            entry_price: 10.0,
+            entry_bar: 1,
+            exit_price: None,
+            exit_bar: None,
+            exit_id: None,
+            commission: 0.0,
+            fx_rate: 1.0,
+            max_runup: 0.0,
+            max_drawdown: 0.0,
+        };
+        // Neither run falls by more than 6 on its own, but the second carries
+        // on the first's slide from its peak.
+        let stitched = stitch(vec![
+            run(vec![110.0, 105.0], vec![open]),
+            run(vec![97.0, 94.0], Vec::new()),
+        ]);
+        assert_eq!(stitched.equity, [110.0, 105.0, 102.0, 99.0]);
+        assert_eq!(stitched.max_drawdown, 11.0);
+        assert_eq!(stitched.max_runup, 10.0);
+        let trade = &stitched.trades[0];
+        assert_eq!((trade.exit_bar, trade.exit_price), (Some(1), Some(12.0)));
+        assert_eq!((stitched.net_profit, stitched.win_trades), (2.0, 1));
+    }
+
+    #[test]
+    fn a_position_carried_from_the_warmup_does_not_count() {
+        let bars: Vec<Bar> = [10.0, 12.0, 14.0, 13.0]
+            .into_iter()
+            .map(|close| Bar {
+                close,
+                ..Bar::default()
+            })
+            .collect();
+        let carried = Trade {
+            entry_id: "L".to_string(),
+            size: 1.0,
+            entry_price: 10.0,
+            entry_bar: 0,
+            exit_price: None,
+            exit_bar: None,
+            exit_id: None,
+            commission: 0.0,
+            fx_rate: 1.0,
+            max_runup: 0.0,
+            max_drawdown: 0.0,
+        };
+        let run = Backtest {
+            initial_capital: 100.0,
+            equity: vec![100.0, 102.0, 104.0, 103.0],
+            trades: vec![carried],
+            position_size: 1.0,
+            mark_price: 13.0,
+            ..Backtest::default()
+        };
+        let window = after_warmup(run, 2, &bars);
+        assert_eq!(window.equity, [100.0, 100.0]);
+        assert!(window.trades.is_empty());
+        assert_eq!((window.position_size, window.first_price), (0.0, 14.0));
+    }
+}
@@ -1,9 +1,16 @@
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pine_lang::core::{AlertSource, Data, DefaultPineOutput, InputValue, LogLevel, SymInfo};
+use pine_lang::data::StaticProvider;
 use pine_lang::diagnostics::{Diagnostic, Severity};
+use pine_lang::{
+    Backtest, Chart, DirLoader, Export, Metric, Optimizer, ParsedScript, Run, RunResult,
+    ScriptBuilder, Search, Space,
+};
 
 #[derive(Parser)]
 #[command(name = "pinecone", version, about = "Pine Script tools")]
@@ -36,12 +43,101 @@ enum Command {
         #[arg(required = true)]
         paths: Vec<PathBuf>,
     },
+    /// Run a script over a CSV of bars and print its plots, logs and alerts.
+    Run(RunArgs),
+    /// Backtest a strategy over a CSV of bars and print its summary and trades.
+    Backtest(RunArgs),
+    /// Backtest a strategy over a grid (or random sample) of input settings and
+    /// rank the runs.
+    Optimize {
+        /// The strategy script.
+        script: PathBuf,
+        /// Bars to run over: a `time,open,high,low,close,volume` CSV.
+        #[arg(long)]
+        data: PathBuf,
+        /// The bars' timeframe, e.g. `60` or `1D`.
+        #[arg(long, default_value = "1D")]
+        timeframe: String,
+        /// An input to sweep, by title: `Length=10..50:5` (a range, step 1 if
+        /// omitted) or `Mode=fast,slow` (choices). Repeatable.
+        #[arg(long = "param", value_parser = parse_param)]
+        params: Vec<(String, Space)>,
+        /// The metric to rank by (`net_profit`, `sharpe`, `max_drawdown`, …).
+        #[arg(long, default_value = "net_profit")]
+        metric: Metric,
+        /// Run this many random combinations instead of the whole grid.
+        #[arg(long)]
+        random: Option<usize>,
+        /// Seed for `--random`.
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Runs at once; defaults to the machine's parallelism.
+        #[arg(long)]
+        threads: Option<usize>,
+        /// How many of the best runs to print.
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
     /// Run the language server over stdio (for editor integration).
     Lsp {
         /// Accepted for editor compatibility; communication is always stdio.
         #[arg(long)]
         stdio: bool,
     },
+    /// Run the debug adapter over stdio, for an editor to run scripts under
+    /// the debugger.
+    Dap,
+}
+
+/// What `run` and `backtest` replay, over which bars, and how they report.
+#[derive(Args)]
+struct RunArgs {
+    /// The script.
+    script: PathBuf,
+    /// Bars to run over: a `time,open,high,low,close,volume` CSV.
+    #[arg(long)]
+    data: PathBuf,
+    /// The bars' timeframe, e.g. `60` or `1D`.
+    #[arg(long, default_value = "1D")]
+    timeframe: String,
+    /// The symbol the bars belong to, as `syminfo.ticker` reads it.
+    #[arg(long)]
+    ticker: Option<String>,
+    /// Input overrides, a JSON object keyed by input title: `{"Length": 20}`.
+    #[arg(long)]
+    inputs: Option<String>,
+    /// Run over only the last N bars.
+    #[arg(long)]
+    bars: Option<usize>,
+    /// A directory `import`s are resolved in; defaults to the script's own.
+    /// Repeatable.
+    #[arg(long)]
+    lib: Vec<PathBuf>,
+    /// How to write the results.
+    #[arg(long, value_enum, default_value_t = Output::Text)]
+    format: Output,
+    /// Where to write them: a file for `json`, `columnar`, `svg` and `html`
+    /// (stdout if omitted), the directory to write one file per table for
+    /// `csv`.
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+/// The `--format` of `run` and `backtest`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    /// A readable summary.
+    Text,
+    /// Everything, as one JSON object.
+    Json,
+    /// Each table as JSON columns.
+    Columnar,
+    /// Each table as a CSV file.
+    Csv,
+    /// The bars and what the script drew, as an SVG chart.
+    Svg,
+    /// The SVG chart in an HTML page.
+    Html,
 }
 
 /// What a command was pointed at.
@@ -106,10 +202,39 @@ fn main() -> ExitCode {
             report(file, &diagnostics);
             Ok(!diagnostics.iter().any(|d| d.severity == Severity::Error))
         }),
+        Command::Run(args) => run(args),
+        Command::Backtest(args) => backtest(args),
+        Command::Optimize {
+            script,
+            data,
+            timeframe,
+            params,
+            metric,
+            random,
+            seed,
+            threads,
+            top,
+        } => optimize(Sweep {
+            script,
+            data,
+            timeframe,
+            params,
+            metric,
+            search: match random {
+                Some(trials) => Search::Random { trials, seed },
+                None => Search::Grid,
+            },
+            threads,
+            top,
+        }),
         Command::Lsp { .. } => {
             pine_lsp::run();
             Ok(true)
         }
+        Command::Dap => {
+            pine_lsp::run_dap();
+            Ok(true)
+        }
     };
 
     match ok {
@@ -157,6 +282,359 @@ fn format(file: &Path, source: &str, stdout: bool, check: bool) -> eyre::Result<
     }
 }
 
+fn run(args: RunArgs) -> eyre::Result<bool> {
+    let (data, run) = replay(&args)?;
+    if write_chart(&data, &run, &args)? {
+        return Ok(true);
+    }
+    let result = RunResult::collect(&run.outputs);
+    if args.format != Output::Text {
+        write_export(&Export::new(&result, run.backtest.as_ref()), &args)?;
+        return Ok(true);
+    }
+
+    if !result.plots.is_empty() {
+        let titles: Vec<&String> = result.plots.keys().collect();
+        let widths: Vec<usize> = titles.iter().map(|t| t.len().max(12)).collect();
+        print!("{:>6}", "bar");
+        for (title, width) in titles.iter().zip(&widths) {
+            print!("  {title:>width$}");
+        }
+        println!();
+        for bar in 0..result.bars {
+            print!("{bar:>6}");
+            for (column, width) in result.plots.values().zip(&widths) {
+                match column[bar] {
+                    Some(value) => print!("  {value:>width$.4}"),
+                    None => print!("  {:>width$}", "na"),
+                }
+            }
+            println!();
+        }
+    }
+    for log in &result.logs {
+        let level = match log.level {
+            LogLevel::Info => "info",
+            LogLevel::Warning => "warning",
+            LogLevel::Error => "error",
+        };
+        println!("[{level}] {}", log.message);
+    }
+    for event in &result.alert_events {
+        let source = match event.source {
+            AlertSource::Alert => "alert",
+            AlertSource::AlertCondition => "alertcondition",
+            AlertSource::OrderFill => "order fill",
+        };
+        let title = if event.title.is_empty() {
+            String::new()
+        } else {
+            format!(" {}:", event.title)
+        };
+        println!(
+            "(alert) bar {} {source}{title} {}",
+            event.bar_index, event.message
+        );
+    }
+    Ok(true)
+}
+
+fn backtest(args: RunArgs) -> eyre::Result<bool> {
+    let (data, run) = replay(&args)?;
+    if run.backtest.is_some() && write_chart(&data, &run, &args)? {
+        return Ok(true);
+    }
+    let backtest = run.backtest.ok_or_else(|| {
+        eyre::eyre!(
+            "{}: {}",
+            args.script.display(),
+            pine_lang::Error::NoBacktest
+        )
+    })?;
+    if args.format != Output::Text {
+        let result = RunResult::collect(&run.outputs);
+        write_export(&Export::new(&result, Some(&backtest)), &args)?;
+        return Ok(true);
+    }
+
+    let metrics = backtest.generate_metrics();
+    println!("{:<17}  {:>14}", "bars", metrics.bars);
+    println!(
+        "{:<17}  {:>14.2}",
+        "initial_capital", metrics.initial_capital
+    );
+    for metric in Metric::all() {
+        let value = metric.of(&metrics);
+        if metric == Metric::Trades {
+            println!("{:<17}  {:>14}", metric.name(), value as usize);
+        } else {
+            println!("{:<17}  {value:>14.4}", metric.name());
+        }
+    }
+    if !backtest.trades.is_empty() {
+        println!();
+        print_trades(&backtest);
+    }
+    Ok(true)
+}
+
+/// Compile `args.script` and replay it over `args.data`, returning the bars
+/// with the run.
+fn replay(args: &RunArgs) -> eyre::Result<(Data, Run<DefaultPineOutput>)> {
+    let script = &args.script;
+    let source =
+        fs::read_to_string(script).map_err(|e| eyre::eyre!("{}: {e}", script.display()))?;
+    let mut provider = StaticProvider::from_csv(&args.data)
+        .map_err(|e| eyre::eyre!("{}: {e}", args.data.display()))?;
+    if let Some(ticker) = &args.ticker {
+        let syminfo = SymInfo {
+            ticker: ticker.clone(),
+            tickerid: ticker.clone(),
+            ..provider.data().syminfo.clone()
+        };
+        provider = provider.with_syminfo(syminfo);
+    }
+    let timeframe = args
+        .timeframe
+        .parse()
+        .map_err(|e| eyre::eyre!("--timeframe: {e}"))?;
+    let inputs = match &args.inputs {
+        Some(json) => {
+            pine_lang::inputs_from_json(json).map_err(|e| eyre::eyre!("--inputs: {e}"))?
+        }
+        None => Default::default(),
+    };
+    let roots = if args.lib.is_empty() {
+        vec![script.parent().unwrap_or(Path::new(".")).to_path_buf()]
+    } else {
+        args.lib.clone()
+    };
+
+    let data = provider.data().clone();
+    let mut builder = ScriptBuilder::<DefaultPineOutput>::with_code(&source)
+        .with_data(data.clone())
+        .with_timeframe(timeframe)
+        .with_inputs(inputs)
+        .with_library_loader(Box::new(DirLoader::new(roots)))
+        .with_request_provider(Box::new(provider));
+    if let Some(bars) = args.bars {
+        builder = builder.with_bar_count(bars);
+    }
+    let run = builder
+        .compile()
+        .and_then(|script| script.run())
+        .map_err(|e| eyre::eyre!("{}: {e}", script.display()))?;
+    Ok((data, run))
+}
+
+/// Write the run's chart, when `--format` asks for one; false when it asks for
+/// something else.
+fn write_chart(data: &Data, run: &Run<DefaultPineOutput>, args: &RunArgs) -> eyre::Result<bool> {
+    let chart = Chart::new(data, run);
+    let document = match args.format {
+        Output::Svg => chart.svg(),
+        Output::Html => chart.html(),
+        _ => return Ok(false),
+    };
+    write_to(args.out.as_deref(), &|out| {
+        out.write_all(document.as_bytes())
+    })?;
+    Ok(true)
+}
+
+/// Write `export` in the `--format` asked for, to `--out` or stdout.
+fn write_export(export: &Export, args: &RunArgs) -> eyre::Result<()> {
+    let out = args.out.as_deref();
+    match args.format {
+        Output::Text | Output::Svg | Output::Html => {
+            unreachable!("text is printed and charts drawn, not exported")
+        }
+        Output::Json => write_to(out, &|out| {
+            export.write_json(&mut *out)?;
+            writeln!(out)
+        }),
+        Output::Columnar => write_to(out, &|out| {
+            export.write_columnar(&mut *out)?;
+            writeln!(out)
+        }),
+        Output::Csv => {
+            let dir = out.ok_or_else(|| {
+                eyre::eyre!("--format csv writes a file per table; give --out a directory")
+            })?;
+            for path in export.write_csv(dir)? {
+                eprintln!("wrote {}", path.display());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Hand `write` the file at `out`, or stdout.
+fn write_to(
+    out: Option<&Path>,
+    write: &dyn Fn(&mut dyn Write) -> std::io::Result<()>,
+) -> eyre::Result<()> {
+    match out {
+        Some(path) => {
+            let file =
+                fs::File::create(path).map_err(|e| eyre::eyre!("{}: {e}", path.display()))?;
+            write(&mut std::io::BufWriter::new(file))?;
+        }
+        None => write(&mut std::io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+fn print_trades(backtest: &Backtest) {
+    println!(
+        "{:>4}  {:<5}  {:<10}  {:>6}  {:>12}  {:<10}  {:>6}  {:>12}  {:>10}  {:>12}",
+        "#", "side", "entry", "bar", "price", "exit", "bar", "price", "size", "profit"
+    );
+    for (n, trade) in backtest.trades.iter().enumerate() {
+        let side = if trade.size > 0.0 { "long" } else { "short" };
+        let (exit, exit_bar, exit_price) = match (&trade.exit_id, trade.exit_bar, trade.exit_price)
+        {
+            (Some(id), Some(bar), Some(price)) => {
+                (id.clone(), bar.to_string(), format!("{price:.4}"))
+            }
+            _ => ("open".to_string(), String::new(), String::new()),
+        };
+        println!(
+            "{:>4}  {side:<5}  {:<10}  {:>6}  {:>12.4}  {exit:<10}  {exit_bar:>6}  {exit_price:>12}  {:>10}  {:>12.2}",
+            n + 1,
+            trade.entry_id,
+            trade.entry_bar,
+            trade.entry_price,
+            trade.size.abs(),
+            trade.profit(backtest.mark_price),
+        );
+    }
+}
+
+/// What `pinecone optimize` was asked to do.
+struct Sweep {
+    script: PathBuf,
+    data: PathBuf,
+    timeframe: String,
+    params: Vec<(String, Space)>,
+    metric: Metric,
+    search: Search,
+    threads: Option<usize>,
+    top: usize,
+}
+
+fn optimize(sweep: Sweep) -> eyre::Result<bool> {
+    let source = fs::read_to_string(&sweep.script)
+        .map_err(|e| eyre::eyre!("{}: {e}", sweep.script.display()))?;
+    let root = sweep
+        .script
+        .parent()
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+    let parsed = ParsedScript::parse(&source, Some(&DirLoader::new(vec![root.clone()])))
+        .map_err(|e| eyre::eyre!("{}: {e}", sweep.script.display()))?;
+    let data = StaticProvider::from_csv(&sweep.data)
+        .map_err(|e| eyre::eyre!("{}: {e}", sweep.data.display()))?
+        .data()
+        .clone();
+    let timeframe = sweep
+        .timeframe
+        .parse()
+        .map_err(|e| eyre::eyre!("--timeframe: {e}"))?;
+
+    let served = data.clone();
+    let mut optimizer = Optimizer::new(parsed, data)
+        .with_timeframe(timeframe)
+        .with_search(sweep.search)
+        .with_metric(sweep.metric)
+        .with_library_loader(move || Box::new(DirLoader::new(vec![root.clone()])))
+        .with_request_provider(move || Box::new(StaticProvider::new(served.clone())));
+    for (title, space) in sweep.params {
+        optimizer = optimizer.with_param(title, space);
+    }
+    if let Some(threads) = sweep.threads {
+        optimizer = optimizer.with_threads(threads);
+    }
+
+    let trials = optimizer.run()?;
+    let metric = sweep.metric.name();
+    println!(
+        "{:>4}  {metric:>14}  {:>14}  {:>6}  {:>12}  inputs",
+        "rank", "net_profit", "trades", "max_drawdown"
+    );
+    for (rank, trial) in trials.iter().take(sweep.top).enumerate() {
+        let inputs = trial
+            .inputs
+            .iter()
+            .map(|(title, value)| format!("{title}={}", input_text(value)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match &trial.result {
+            Ok(metrics) => println!(
+                "{:>4}  {:>14.4}  {:>14.2}  {:>6}  {:>12.4}  {inputs}",
+                rank + 1,
+                sweep.metric.of(metrics),
+                metrics.net_profit,
+                metrics.trades,
+                metrics.max_drawdown,
+            ),
+            Err(err) => println!("{:>4}  error: {err}  {inputs}", rank + 1),
+        }
+    }
+    Ok(trials.first().is_some_and(|trial| trial.result.is_ok()))
+}
+
+/// `Title=start..end[:step]` or `Title=a,b,c`, as given to `--param`.
+fn parse_param(spec: &str) -> Result<(String, Space), String> {
+    let (title, values) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("expected `Title=values`, got `{spec}`"))?;
+    let space = match values.split_once("..") {
+        Some((start, rest)) => {
+            let (end, step) = rest.split_once(':').unwrap_or((rest, "1"));
+            let ints = (start.parse(), end.parse(), step.parse());
+            if let (Ok(start), Ok(end), Ok(step)) = ints {
+                Space::Int { start, end, step }
+            } else {
+                let float = |s: &str| {
+                    s.parse::<f64>()
+                        .map_err(|_| format!("`{s}` is not a number in `{spec}`"))
+                };
+                Space::Float {
+                    start: float(start)?,
+                    end: float(end)?,
+                    step: float(step)?,
+                }
+            }
+        }
+        None => Space::Choices(values.split(',').map(parse_choice).collect()),
+    };
+    Ok((title.to_string(), space))
+}
+
+/// A `--param` choice: a bool or number if it reads as one, else a string.
+fn parse_choice(choice: &str) -> InputValue {
+    if let Ok(b) = choice.parse() {
+        InputValue::Bool(b)
+    } else if let Ok(n) = choice.parse() {
+        InputValue::Int(n)
+    } else if let Ok(x) = choice.parse() {
+        InputValue::Float(x)
+    } else {
+        InputValue::Str(choice.to_string())
+    }
+}
+
+fn input_text(value: &InputValue) -> String {
+    match value {
+        InputValue::Bool(b) => b.to_string(),
+        InputValue::Int(n) => n.to_string(),
+        InputValue::Float(x) => x.to_string(),
+        InputValue::Str(s) => s.clone(),
+        InputValue::Color(c) => format!("{c:?}"),
+    }
+}
+
 fn report(file: &Path, diagnostics: &[Diagnostic]) {
     for diagnostic in diagnostics {
         println!("{}: {diagnostic}", file.display());
@@ -82,6 +82,14 @@ function start(command: string, context: vscode.ExtensionContext) {
       output.appendLine(`Language server failed to start: ${err}`)
     );
   context.subscriptions.push({ dispose: () => void client?.stop() });
+
+  // `pine` launch configurations run the same binary as a debug adapter.
+  context.subscriptions.push(
+    vscode.debug.registerDebugAdapterDescriptorFactory("pine", {
+      createDebugAdapterDescriptor: () =>
+        new vscode.DebugAdapterExecutable(command, ["dap"]),
+    })
+  );
 }
 
 async function promptDownload(context: vscode.ExtensionContext) {
@@ -16,11 +16,14 @@ mod tests {
     }
 
     /// Wraps the bar provider and serves mock request data our synthetic bars
-    /// don't carry — currently a fixed volume footprint (`// Footprint: <file>`)
-    /// so the `footprint.*` / `volume_row.*` accessors can be exercised.
+    /// don't carry — a fixed volume footprint (`// Footprint: <file>`) so the
+    /// `footprint.*` / `volume_row.*` accessors can be exercised, and one
+    /// exchange rate (`// CurrencyRate: <from> <to> <rate>`) for multi-currency
+    /// strategies.
     struct TestProvider {
         bars: StaticProvider,
         footprint: Option<Vec<FootprintRow>>,
+        currency_rate: Option<(String, String, f64)>,
     }
 
     impl DataProvider for TestProvider {
@@ -30,6 +33,25 @@ mod tests {
         fn footprint(&self, _tpr: f64, _va: f64, _imbalance: f64) -> Option<Vec<FootprintRow>> {
             self.footprint.clone()
         }
+        fn currency_rate(&self, from: &str, to: &str, _time: i64) -> Option<f64> {
+            let (base, quote, rate) = self.currency_rate.as_ref()?;
+            if (from, to) == (base.as_str(), quote.as_str()) {
+                Some(*rate)
+            } else if (from, to) == (quote.as_str(), base.as_str()) {
+                Some(1.0 / rate)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// `<from> <to> <rate>`, as given to `// CurrencyRate:`.
+    fn parse_currency_rate(spec: &str) -> Option<(String, String, f64)> {
+        let mut parts = spec.split_whitespace();
+        let from = parts.next()?.to_string();
+        let to = parts.next()?.to_string();
+        let rate = parts.next()?.parse().ok()?;
+        Some((from, to, rate))
     }
 
     fn load_footprint(name: &str) -> Vec<FootprintRow> {
@@ -176,6 +198,8 @@ mod tests {
         let provider = TestProvider {
             bars: load_test_data(data_file),
             footprint: directive::<String>(source, "// Footprint:").map(|f| load_footprint(&f)),
+            currency_rate: directive::<String>(source, "// CurrencyRate:")
+                .and_then(|spec| parse_currency_rate(&spec)),
         };
 
         // Use `// Timeframe:` to set a custom timeframe, defaults to 1 second